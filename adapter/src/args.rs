@@ -1,20 +1,30 @@
 use std::fmt::{Display, Formatter};
+use std::num::NonZeroUsize;
 use std::path::PathBuf;
 
 use clap::{Parser, ValueEnum};
+use nlwkn::WaterRightNo;
 
 /// NLWKN Water Right File Adapter
 #[derive(Debug, Parser)]
 #[command(version, about)]
 pub struct Args {
     /// Path to reports JSON file
-    pub reports_json: PathBuf,
+    #[arg(required_unless_present = "sort_order_coverage")]
+    pub reports_json: Option<PathBuf>,
 
     /// Language for the field names
     ///
-    /// `De` will use the names from the original reports
-    #[arg(value_enum, long, short, default_value = "en")]
-    pub lang: Lang,
+    /// `De` will use the names from the original reports.
+    /// Only the header is affected, values are kept as they are in the reports.
+    #[arg(
+        value_enum,
+        long = "header-lang",
+        short = 'l',
+        alias = "lang",
+        default_value = "en"
+    )]
+    pub header_lang: Lang,
 
     /// Output format
     #[arg(value_enum, long, short, default_value = "csv")]
@@ -22,7 +32,109 @@ pub struct Args {
 
     /// Output file path
     #[arg(long, short)]
-    pub out: Option<PathBuf>
+    pub out: Option<PathBuf>,
+
+    /// Only write the water right with this number as pretty-printed JSON
+    #[arg(long = "no", value_name = "WATER_RIGHT_NO", conflicts_with = "format")]
+    pub water_right_no: Option<WaterRightNo>,
+
+    /// Also write a data dictionary describing every field
+    ///
+    /// Written as JSON if the path ends with `.json`, otherwise as CSV.
+    #[arg(long)]
+    pub dictionary: Option<PathBuf>,
+
+    /// Also write the water rights as JSON with the german label next to every
+    /// value
+    ///
+    /// Every field becomes `{"value": ..., "labelDe": ...}`.
+    #[arg(long, value_name = "PATH")]
+    pub bilingual: Option<PathBuf>,
+
+    /// Also write a hash of the ordered columns to detect schema changes
+    #[arg(long, value_name = "PATH")]
+    pub schema_hash: Option<PathBuf>,
+
+    /// Also write an index with the byte offset and rows of every water right
+    ///
+    /// Only available for the `csv` format.
+    #[arg(long, value_name = "PATH")]
+    pub index: Option<PathBuf>,
+
+    /// Skip the water rights listed in the index of a prior output
+    ///
+    /// Reads an index written by `--index`, useful for incremental updates.
+    #[arg(long, value_name = "INDEX")]
+    pub only_missing: Option<PathBuf>,
+
+    /// Write one CSV per legal department instead of a single one
+    ///
+    /// Every CSV only has the columns used by its rows, the files are named
+    /// after the output path with the department inserted, e.g.
+    /// `reports.A.csv`.
+    /// Only available for the `csv` format.
+    #[arg(long, conflicts_with_all = ["index", "schema_hash"])]
+    pub split_by_department: bool,
+
+    /// Leave out the UTM coordinates of all usage locations
+    #[arg(long)]
+    pub exclude_coords: bool,
+
+    /// Rewrite all rates with a known measurement to m³ per day
+    ///
+    /// Rates with unknown measurements are kept as they are.
+    #[arg(long)]
+    pub normalize_units: bool,
+
+    /// Abort if less than this many bytes would remain free after writing
+    ///
+    /// The output is estimated to be at most twice the size of the reports.
+    #[arg(long, value_name = "BYTES", default_value = "0")]
+    pub min_free_bytes: u64,
+
+    /// Also read the pdf-only reports next to the reports JSON file
+    ///
+    /// For `reports.json` this is `pdf-only-reports.json` in the same
+    /// directory, as written by the parser.
+    #[arg(long)]
+    pub include_pdf_only: bool,
+
+    /// Add a column with the file each row is read from
+    #[arg(long, requires = "include_pdf_only")]
+    pub tag_source: bool,
+
+    /// Only write every Nth row of the flattened table
+    ///
+    /// Gives a small preview of a large dataset, the header keeps all columns.
+    #[arg(long, value_name = "N", conflicts_with = "water_right_no")]
+    pub sample: Option<NonZeroUsize>,
+
+    /// Add a geometry column with the UTM coordinates as WKT point
+    ///
+    /// Rows without both coordinates are left blank.
+    #[arg(long)]
+    pub wkt: bool,
+
+    /// Write every field of the water rights, empty ones as `null` or empty
+    /// collection, so every record has the same shape
+    ///
+    /// Only available for the `reports` and `jsonl` formats and `--no`.
+    #[arg(long)]
+    pub full_schema: bool,
+
+    /// Read the water rights from a CSV written by the adapter instead of a
+    /// reports JSON file
+    ///
+    /// The header has to be in the language of `--header-lang`, columns that
+    /// are no field of the water rights are reported and ignored.
+    #[arg(long, conflicts_with = "include_pdf_only")]
+    pub from_csv: bool,
+
+    /// Print the sort position of every column key and exit
+    ///
+    /// Fails if a key was added without a position in the column order.
+    #[arg(long)]
+    pub sort_order_coverage: bool
 }
 
 #[derive(Debug, Copy, Clone, PartialEq, Eq, ValueEnum)]
@@ -33,13 +145,41 @@ pub enum Lang {
 
 #[derive(Debug, Copy, Clone, PartialEq, Eq, ValueEnum)]
 pub enum Format {
-    Csv
+    Csv,
+
+    /// Only the rates in long form, one row per rate
+    RatesTidy,
+
+    /// Distinct units of all rates and quantities with their counts
+    Units,
+
+    /// Same rows as `csv` as an array of JSON objects with typed values
+    JsonTable,
+
+    /// The water rights as reports JSON, like the input but with options like
+    /// `--normalize-units` applied
+    Reports,
+
+    /// Like `reports` but with one water right per line, the exporter streams
+    /// these instead of loading them at once
+    Jsonl,
+
+    /// Same rows as `csv` as GeoJSON features, located by the coordinates
+    /// reprojected to WGS84
+    #[value(name = "geojson")]
+    GeoJson
 }
 
 impl Display for Format {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         match self {
-            Format::Csv => write!(f, "csv")
+            Format::Csv => write!(f, "csv"),
+            Format::RatesTidy => write!(f, "rates.csv"),
+            Format::Units => write!(f, "units.csv"),
+            Format::JsonTable => write!(f, "table.json"),
+            Format::Reports => write!(f, "adapted.json"),
+            Format::Jsonl => write!(f, "adapted.jsonl"),
+            Format::GeoJson => write!(f, "geojson")
         }
     }
 }
@@ -14,11 +14,20 @@ pub enum FlatTableKey<M> {
     Single(Cow<'static, str>)
 }
 
+/// List key constants together with their names.
+macro_rules! known_keys {
+    ($($name:ident),*) => {
+        [$((stringify!($name), FlatTableKey::$name)),*]
+    };
+}
+
 impl FlatTableKey<marker::Unselect> {
     pub const ACTIVE: FlatTableKey<marker::Unselect> = Self::from_str("active", "aktiv/inaktiv");
     pub const ADDRESS: FlatTableKey<marker::Unselect> = Self::from_str("address", "Adresse");
     pub const ANNOTATION: FlatTableKey<marker::Unselect> =
         Self::from_str("annotation", "Bemerkung");
+    pub const CADENZA_LEGAL_TITLE: FlatTableKey<marker::Unselect> =
+        Self::from_str("cadenza legal title", "Rechtstitel (cadenza)");
     pub const CATCHMENT_AREA_CODE: FlatTableKey<marker::Unselect> =
         Self::from_str("catchment area code", "Einzugsgebietskennzahl");
     pub const COUNTY: FlatTableKey<marker::Unselect> = Self::from_str("county", "Landkreis");
@@ -38,6 +47,7 @@ impl FlatTableKey<marker::Unselect> {
         Self::from_str("flood area", "Überschwemmungsgebiet");
     pub const FLUID_DISCHARGE: FlatTableKey<marker::Unselect> =
         Self::from_str("fluid discharge", "Ableitungsmenge");
+    pub const GEOMETRY: FlatTableKey<marker::Unselect> = Self::from_str("geometry", "Geometrie");
     pub const GRANTING_AUTHORITY: FlatTableKey<marker::Unselect> =
         Self::from_str("granting authority", "Erteilende Behörde");
     pub const GROUNDWATER_BODY: FlatTableKey<marker::Unselect> =
@@ -69,6 +79,10 @@ impl FlatTableKey<marker::Unselect> {
         Self::from_str("maintenance association", "Unterhaltungsverband");
     pub const MAP_EXCERPT: FlatTableKey<marker::Unselect> =
         Self::from_str("top. map 1:25000", "Top. Karte 1:25.000");
+    pub const MUNICIPALITY_DISTRICT: FlatTableKey<marker::Unselect> =
+        Self::from_str("municipality district", "Kreis (amtlich)");
+    pub const MUNICIPALITY_NAME: FlatTableKey<marker::Unselect> =
+        Self::from_str("municipality name", "Gemeinde (amtlich)");
     pub const MUNICIPAL_AREA: FlatTableKey<marker::Unselect> =
         Self::from_str("municipal area", "Gemeindegebiet");
     pub const NO: FlatTableKey<marker::Unselect> =
@@ -84,6 +98,10 @@ impl FlatTableKey<marker::Unselect> {
         Self::from_str("pumping rate", "Förderleistung");
     pub const RAIN_SUPPLEMENT: FlatTableKey<marker::Unselect> =
         Self::from_str("rain supplement", "Zusatzregen");
+    pub const RATE_PER: FlatTableKey<marker::Unselect> = Self::from_str("per", "pro");
+    pub const RATE_TYPE: FlatTableKey<marker::Unselect> = Self::from_str("rate type", "Mengenart");
+    pub const RATE_UNIT: FlatTableKey<marker::Unselect> = Self::from_str("unit", "Einheit");
+    pub const RATE_VALUE: FlatTableKey<marker::Unselect> = Self::from_str("value", "Wert");
     pub const REAL: FlatTableKey<marker::Unselect> = Self::from_str("real", "real/virtuell");
     pub const REGISTERING_AUTHORITY: FlatTableKey<marker::Unselect> =
         Self::from_str("registering authority", "eingetragen durch");
@@ -91,13 +109,14 @@ impl FlatTableKey<marker::Unselect> {
         Self::from_str("regulation citation", "Verordnungszitat");
     pub const RIVER_BASIN: FlatTableKey<marker::Unselect> =
         Self::from_str("river basin", "Flussgebiet");
-    const SORT_ORDER: [Self; 41] = [
+    const SORT_ORDER: [Self; 47] = [
         Self::NO,
         Self::HOLDER,
         Self::VALID_FROM,
         Self::VALID_UNTIL,
         Self::STATUS,
         Self::LEGAL_TITLE,
+        Self::CADENZA_LEGAL_TITLE,
         Self::WATER_AUTHORITY,
         Self::REGISTERING_AUTHORITY,
         Self::GRANTING_AUTHORITY,
@@ -117,6 +136,8 @@ impl FlatTableKey<marker::Unselect> {
         Self::LEGAL_PURPOSE,
         Self::MAP_EXCERPT,
         Self::MUNICIPAL_AREA,
+        Self::MUNICIPALITY_NAME,
+        Self::MUNICIPALITY_DISTRICT,
         Self::COUNTY,
         Self::LAND_RECORD,
         Self::PLOT,
@@ -127,15 +148,44 @@ impl FlatTableKey<marker::Unselect> {
         Self::RIVER_BASIN,
         Self::GROUNDWATER_BODY,
         Self::WATER_BODY,
+        Self::WATER_BODY_NORMALIZED,
         Self::FLOOD_AREA,
         Self::WATER_PROTECTION_AREA,
         Self::IRRIGATION_AREA,
         Self::UTM_EASTING,
         Self::UTM_NORTHING,
-        Self::ANNOTATION
+        Self::GEOMETRY,
+        Self::ANNOTATION,
+        Self::SOURCE
     ];
+    pub const SOURCE: FlatTableKey<marker::Unselect> = Self::from_str("source", "Quelle");
     pub const STATUS: FlatTableKey<marker::Unselect> = Self::from_str("status", "Zustand");
     pub const SUBJECT: FlatTableKey<marker::Unselect> = Self::from_str("subject", "Betreff");
+    /// Keys without a position in [`Self::SORT_ORDER`] on purpose.
+    ///
+    /// The rate keys are prefixes, their columns get the unit and period
+    /// appended and sort alphabetically after the fixed columns.
+    /// The columns of the tidy rates keep the order of their header.
+    /// The dam target and pH value columns have always been sorted
+    /// alphabetically between the rate columns, they stay there to keep the
+    /// column order of existing CSV files.
+    const UNSORTED: [Self; 15] = [
+        Self::FLUID_DISCHARGE,
+        Self::INJECTION_RATE,
+        Self::PUMPING_RATE,
+        Self::RAIN_SUPPLEMENT,
+        Self::WASTER_WATER_FLOW_VOLUME,
+        Self::WITHDRAWAL_RATE,
+        Self::RATE_PER,
+        Self::RATE_TYPE,
+        Self::RATE_UNIT,
+        Self::RATE_VALUE,
+        Self::DAM_TARGETS_DEFAULT,
+        Self::DAM_TARGETS_STEADY,
+        Self::DAM_TARGETS_MAX,
+        Self::PH_VALUES_MIN,
+        Self::PH_VALUES_MAX
+    ];
     pub const USAGE_LOCATION_NAME: FlatTableKey<marker::Unselect> =
         Self::from_str("usage location name", "Nutzungsort/Bezeichnung");
     pub const USAGE_LOCATION_NO: FlatTableKey<marker::Unselect> =
@@ -155,10 +205,117 @@ impl FlatTableKey<marker::Unselect> {
     pub const WATER_AUTHORITY: FlatTableKey<marker::Unselect> =
         Self::from_str("water authority", "Wasserbehörde");
     pub const WATER_BODY: FlatTableKey<marker::Unselect> = Self::from_str("water body", "Gewässer");
+    pub const WATER_BODY_NORMALIZED: FlatTableKey<marker::Unselect> =
+        Self::from_str("water body normalized", "Gewässer (normalisiert)");
     pub const WATER_PROTECTION_AREA: FlatTableKey<marker::Unselect> =
         Self::from_str("water protection area", "Wasserschutzgebiet");
     pub const WITHDRAWAL_RATE: FlatTableKey<marker::Unselect> =
         Self::from_str("withdrawal rate", "Entnahmemenge");
+
+    /// Every key constant with its name.
+    pub fn known_keys() -> &'static [(&'static str, Self)] {
+        const KNOWN_KEYS: &[(&str, FlatTableKey<marker::Unselect>)] = &known_keys!(
+            ACTIVE,
+            ADDRESS,
+            ANNOTATION,
+            CADENZA_LEGAL_TITLE,
+            CATCHMENT_AREA_CODE,
+            COUNTY,
+            DAM_TARGETS_DEFAULT,
+            DAM_TARGETS_MAX,
+            DAM_TARGETS_STEADY,
+            EU_SURVEY_AREA,
+            EXTERNAL_IDENTIFIER,
+            FILE_REFERENCE,
+            FLOOD_AREA,
+            FLUID_DISCHARGE,
+            GEOMETRY,
+            GRANTING_AUTHORITY,
+            GROUNDWATER_BODY,
+            HOLDER,
+            INITIALLY_GRANTED,
+            INJECTION_RATE,
+            IRRIGATION_AREA,
+            LAND_RECORD,
+            LAST_CHANGE,
+            LEGAL_DEPARTMENT_ABBREVIATION,
+            LEGAL_DEPARTMENT_DESCRIPTION,
+            LEGAL_PURPOSE,
+            LEGAL_TITLE,
+            MAINTENANCE_ASSOCIATION,
+            MAP_EXCERPT,
+            MUNICIPALITY_DISTRICT,
+            MUNICIPALITY_NAME,
+            MUNICIPAL_AREA,
+            NO,
+            PH_VALUES_MAX,
+            PH_VALUES_MIN,
+            PLOT,
+            PUMPING_RATE,
+            RAIN_SUPPLEMENT,
+            RATE_PER,
+            RATE_TYPE,
+            RATE_UNIT,
+            RATE_VALUE,
+            REAL,
+            REGISTERING_AUTHORITY,
+            REGULATION_CITATION,
+            RIVER_BASIN,
+            SOURCE,
+            STATUS,
+            SUBJECT,
+            USAGE_LOCATION_NAME,
+            USAGE_LOCATION_NO,
+            USAGE_LOCATION_SERIAL,
+            UTM_EASTING,
+            UTM_NORTHING,
+            VALID_FROM,
+            VALID_UNTIL,
+            WASTER_WATER_FLOW_VOLUME,
+            WATER_AUTHORITY,
+            WATER_BODY,
+            WATER_BODY_NORMALIZED,
+            WATER_PROTECTION_AREA,
+            WITHDRAWAL_RATE
+        );
+
+        KNOWN_KEYS
+    }
+
+    /// Sort coverage of every key constant by its name, see
+    /// [`SortCoverage`].
+    pub fn sort_order_coverage() -> Vec<(&'static str, Self, SortCoverage)> {
+        Self::known_keys()
+            .iter()
+            .map(|(name, key)| {
+                let coverage = match FlatTableKey::<marker::En>::from_unselect_ref(key).sort_index()
+                {
+                    Some(index) => SortCoverage::Sorted(index),
+                    None if Self::UNSORTED
+                        .iter()
+                        .any(|unsorted| unsorted.ref_en() == key.ref_en()) =>
+                    {
+                        SortCoverage::Unsorted
+                    }
+                    None => SortCoverage::Missing
+                };
+                (*name, key.clone(), coverage)
+            })
+            .collect()
+    }
+}
+
+/// Whether a key has a position in the column order.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SortCoverage {
+    /// Position in the sort order.
+    Sorted(usize),
+
+    /// Sorted alphabetically after the other columns on purpose.
+    Unsorted,
+
+    /// Sorted alphabetically as the key was added without a position.
+    Missing
 }
 
 impl<M> Clone for FlatTableKey<M> {
@@ -233,6 +390,19 @@ where
             .find_position(|&i| self == i)
             .map(|(i, _)| i)
     }
+
+    /// Key of a column name of a CSV header in the language of `M`.
+    ///
+    /// Names of key constants get the constant, so they keep their position
+    /// and translation, any other name becomes a single key.
+    pub fn from_header(name: &str) -> Self {
+        FlatTableKey::<marker::Unselect>::known_keys()
+            .iter()
+            .map(|(_, key)| Self::from_unselect_ref(key))
+            .find(|key| key.as_ref() == name)
+            .cloned()
+            .unwrap_or_else(|| Self::from(name.to_string()))
+    }
 }
 
 impl<M> From<String> for FlatTableKey<M> {
@@ -302,3 +472,62 @@ pub mod marker {
     pub struct En;
     pub struct De;
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn every_key_has_sort_position() {
+        let coverage = FlatTableKey::sort_order_coverage();
+        for (name, _, sort_coverage) in coverage.iter() {
+            assert_ne!(
+                *sort_coverage,
+                SortCoverage::Missing,
+                "{name} has no position in SORT_ORDER"
+            );
+        }
+
+        // every constant of this file has to be known to the coverage, otherwise
+        // a key missing in both would go unnoticed
+        let known_keys = FlatTableKey::known_keys();
+        let declared: Vec<&str> = include_str!("key.rs")
+            .lines()
+            .filter_map(|line| line.strip_prefix("    pub const "))
+            .filter_map(|line| line.split_once(": FlatTableKey"))
+            .map(|(name, _)| name)
+            .sorted()
+            .collect();
+        let known: Vec<&str> = known_keys.iter().map(|(name, _)| *name).sorted().collect();
+        assert_eq!(declared, known);
+
+        // every sorted and unsorted key has to be known to the coverage
+        assert_eq!(
+            known_keys.iter().map(|(name, _)| name).unique().count(),
+            known_keys.len()
+        );
+        assert_eq!(
+            known_keys.iter().map(|(_, key)| key.ref_en()).unique().count(),
+            known_keys.len()
+        );
+        for key in FlatTableKey::SORT_ORDER.iter().chain(FlatTableKey::UNSORTED.iter()) {
+            assert!(
+                known_keys.iter().any(|(_, known)| known.ref_en() == key.ref_en()),
+                "{} is not a known key",
+                key.ref_en()
+            );
+        }
+
+        let sorted = coverage
+            .iter()
+            .filter(|(.., sort_coverage)| matches!(sort_coverage, SortCoverage::Sorted(_)))
+            .count();
+        assert_eq!(sorted, FlatTableKey::SORT_ORDER.len());
+        for unsorted in FlatTableKey::UNSORTED.iter() {
+            assert_eq!(
+                FlatTableKey::<marker::En>::from_unselect_ref(unsorted).sort_index(),
+                None
+            );
+        }
+    }
+}
@@ -1,15 +1,22 @@
 use std::collections::{BTreeMap, BTreeSet};
 use std::fmt::Write;
+use std::num::NonZeroUsize;
 
 use itertools::Itertools;
 pub use key::*;
+use nlwkn::util::{round_coordinate, utm_to_wgs84, DEFAULT_COORDINATE_PRECISION};
 use nlwkn::{WaterRight, WaterRightNo};
 use rayon::prelude::*;
-
-use crate::flat_table::key::FlatTableKey;
-use crate::flat_table::value::FlatTableValue;
+use serde::ser::SerializeMap;
+use serde::{Serialize, Serializer};
+pub use tidy::{fmt_rates_tidy, RATES_TIDY_HEADER};
+pub use unflatten::UnknownColumns;
+pub use value::FlatTableValue;
 
 mod key;
+mod read;
+mod tidy;
+mod unflatten;
 mod util;
 mod value;
 
@@ -21,6 +28,22 @@ pub struct FlatTable<M> {
 pub type FlatTableRows<M> = Vec<FlatTableRow<M>>;
 pub type FlatTableRow<M> = BTreeMap<FlatTableKey<M>, FlatTableValue>;
 
+/// Position of the rows of a water right in a formatted CSV.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RowSpan {
+    /// Byte offset of the first row from the start of the CSV.
+    pub offset: usize,
+
+    /// First row, counted from 1 after the header.
+    pub first_row: usize,
+
+    /// Last row, inclusive.
+    pub last_row: usize
+}
+
+/// Rows of every water right in a formatted CSV, see [`FlatTable::fmt_csv`].
+pub type RowIndex = BTreeMap<WaterRightNo, RowSpan>;
+
 #[derive(Debug)]
 pub enum Progress {
     Flattened(WaterRightNo),
@@ -60,15 +83,196 @@ where
         FlatTable { values: rows, keys }
     }
 
-    pub fn fmt_csv<W>(&self, w: &mut W, notifier: impl Fn() + Send + Sync) -> std::fmt::Result
+    /// Read a table from a CSV written by [`fmt_csv`](Self::fmt_csv) with the
+    /// header in the language of `M`.
+    pub fn from_csv(csv: &str) -> anyhow::Result<Self> {
+        let (header, rows) = read::read_csv(csv)?;
+        let header: Vec<FlatTableKey<M>> =
+            header.iter().map(|name| FlatTableKey::from_header(name)).collect();
+        let keys: BTreeSet<FlatTableKey<M>> = header.iter().cloned().collect();
+        if keys.len() != header.len() {
+            return Err(anyhow::Error::msg("csv header has duplicate columns"));
+        }
+
+        let values = rows
+            .into_iter()
+            .map(|row| {
+                header
+                    .iter()
+                    .zip(row)
+                    .filter_map(|(key, value)| Some((key.clone(), value?)))
+                    .collect()
+            })
+            .collect();
+
+        Ok(FlatTable { values, keys })
+    }
+
+    /// Rebuild the water rights from the rows, the inverse of
+    /// [`from_water_rights_with_notifier`](Self::from_water_rights_with_notifier).
+    ///
+    /// Every field kept by flattening is restored, columns that are no field
+    /// of the water rights are returned separately.
+    /// Water rights without usage locations have no rows and are therefore
+    /// missing.
+    pub fn to_water_rights(&self) -> anyhow::Result<(Vec<WaterRight>, UnknownColumns)> {
+        unflatten::unflatten_rows(&self.values)
+    }
+
+    /// Number of rows in the table.
+    pub fn row_count(&self) -> usize {
+        self.values.len()
+    }
+
+    /// Keep only every `every`th row, starting with the first one.
+    ///
+    /// The columns are kept, so a sample has the same header as the full table.
+    pub fn sample(&mut self, every: NonZeroUsize) {
+        let mut i = 0;
+        self.values.retain(|_| {
+            let keep = i % every.get() == 0;
+            i += 1;
+            keep
+        });
+    }
+
+    /// Add a [WKT](wkt_point) geometry column from the UTM coordinates of every
+    /// row, rows without both coordinates are left blank.
+    pub fn add_wkt_geometry(&mut self) {
+        let easting_key = FlatTableKey::<M>::from_unselect(FlatTableKey::UTM_EASTING);
+        let northing_key = FlatTableKey::<M>::from_unselect(FlatTableKey::UTM_NORTHING);
+        let geometry_key = FlatTableKey::<M>::from_unselect(FlatTableKey::GEOMETRY);
+        for row in self.values.iter_mut() {
+            let coordinate = |key| match row.get(key) {
+                Some(FlatTableValue::U64(coordinate)) => Some(*coordinate),
+                _ => None
+            };
+            if let Some(point) = wkt_point(coordinate(&easting_key), coordinate(&northing_key)) {
+                row.insert(geometry_key.clone(), point.into());
+                self.keys.insert(geometry_key.clone());
+            }
+        }
+    }
+
+    /// Set `key` in every row to the tag of its water right, rows without a tag
+    /// are left as they are.
+    pub fn tag_rows<T>(
+        &mut self,
+        key: FlatTableKey<marker::Unselect>,
+        tag: impl Fn(WaterRightNo) -> Option<T>
+    ) where
+        T: Into<FlatTableValue>
+    {
+        let key = FlatTableKey::<M>::from_unselect(key);
+        let no_key = FlatTableKey::<M>::from_unselect(FlatTableKey::NO);
+        for row in self.values.iter_mut() {
+            let Some(FlatTableValue::U64(no)) = row.get(&no_key)
+            else {
+                continue;
+            };
+            if let Some(tag) = tag(*no) {
+                row.insert(key.clone(), tag.into());
+                self.keys.insert(key.clone());
+            }
+        }
+    }
+
+    /// Split the rows by their value of `key`, every part only has the columns
+    /// used by its rows.
+    ///
+    /// Rows without a value for `key` are dropped.
+    pub fn split_by(self, key: FlatTableKey<marker::Unselect>) -> BTreeMap<String, FlatTable<M>> {
+        let key = FlatTableKey::<M>::from_unselect(key);
+        let mut parts: BTreeMap<String, FlatTable<M>> = BTreeMap::new();
+        for row in self.values {
+            let part = match row.get(&key) {
+                Some(FlatTableValue::String(value)) => value.clone(),
+                Some(value) => value.to_string(),
+                None => continue
+            };
+
+            let part = parts.entry(part).or_insert_with(|| FlatTable {
+                values: FlatTableRows::new(),
+                keys: BTreeSet::new()
+            });
+            part.keys.extend(row.keys().cloned());
+            part.values.push(row);
+        }
+
+        parts
+    }
+
+    /// Write the table as a JSON array with an object per row, the values keep
+    /// their type.
+    ///
+    /// Columns without a value in a row are left out of its object.
+    pub fn fmt_json<W>(&self, w: &mut W) -> std::fmt::Result
+    where
+        W: Write
+    {
+        let rows: Vec<JsonRow<M>> = self.values.iter().map(JsonRow).collect();
+        let json = serde_json::to_string(&rows).map_err(|_| std::fmt::Error)?;
+        w.write_str(&json)
+    }
+
+    /// Write the table as GeoJSON `FeatureCollection` with a feature per row.
+    ///
+    /// The UTM coordinates are reprojected to a WGS84 `Point`, rows without
+    /// both coordinates get a `null` geometry instead of being left out.
+    /// The properties are the values of the row like in
+    /// [`fmt_json`](Self::fmt_json).
+    pub fn fmt_geojson<W>(&self, w: &mut W) -> std::fmt::Result
+    where
+        W: Write
+    {
+        let easting_key = FlatTableKey::<M>::from_unselect(FlatTableKey::UTM_EASTING);
+        let northing_key = FlatTableKey::<M>::from_unselect(FlatTableKey::UTM_NORTHING);
+        let features = self
+            .values
+            .iter()
+            .map(|row| {
+                let coordinate = |key| match row.get(key) {
+                    Some(FlatTableValue::U64(coordinate)) => Some(*coordinate),
+                    _ => None
+                };
+                GeoJsonFeature {
+                    kind: "Feature",
+                    geometry: geojson_point(coordinate(&easting_key), coordinate(&northing_key)),
+                    properties: JsonRow(row)
+                }
+            })
+            .collect();
+
+        let feature_collection = GeoJsonFeatureCollection {
+            kind: "FeatureCollection",
+            features
+        };
+        let json = serde_json::to_string(&feature_collection).map_err(|_| std::fmt::Error)?;
+        w.write_str(&json)
+    }
+
+    /// Hash of the ordered column keys, see [`schema_hash`].
+    pub fn schema_hash(&self) -> u64 {
+        schema_hash(self.keys.iter().map(AsRef::as_ref))
+    }
+
+    /// Write the table as CSV, returns where the rows of every water right
+    /// are.
+    pub fn fmt_csv<W>(
+        &self,
+        w: &mut W,
+        notifier: impl Fn() + Send + Sync
+    ) -> Result<RowIndex, std::fmt::Error>
     where
         W: Write
     {
         // TODO: replace this when `std` stabilized `intersperse`
+        let mut header = String::new();
         for key in Itertools::intersperse(self.keys.iter().map(AsRef::as_ref), ";") {
-            w.write_str(key)?;
+            header.push_str(key);
         }
-        writeln!(w)?;
+        writeln!(header)?;
+        w.write_str(&header)?;
 
         let rows: Vec<_> = self
             .values
@@ -94,10 +298,362 @@ where
             })
             .collect();
 
-        for row in rows {
-            w.write_str(&row)?;
+        let no_key = FlatTableKey::<M>::from_unselect(FlatTableKey::NO);
+        let mut index = RowIndex::new();
+        let mut offset = header.len();
+        for (i, (row, row_string)) in self.values.iter().zip(rows).enumerate() {
+            if let Some(FlatTableValue::U64(no)) = row.get(&no_key) {
+                index.entry(*no).and_modify(|span| span.last_row = i + 1).or_insert(RowSpan {
+                    offset,
+                    first_row: i + 1,
+                    last_row: i + 1
+                });
+            }
+
+            offset += row_string.len();
+            w.write_str(&row_string)?;
+        }
+
+        Ok(index)
+    }
+}
+
+/// Row serialized as JSON object with the columns in table order.
+struct JsonRow<'r, M>(&'r FlatTableRow<M>);
+
+impl<M> Serialize for JsonRow<'_, M>
+where
+    FlatTableKey<M>: AsRef<str>
+{
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer
+    {
+        let mut map = serializer.serialize_map(Some(self.0.len()))?;
+        for (key, value) in self.0 {
+            map.serialize_entry(key.as_ref(), value)?;
         }
+        map.end()
+    }
+}
+
+#[derive(Serialize)]
+#[serde(bound(serialize = "FlatTableKey<M>: AsRef<str>"))]
+struct GeoJsonFeatureCollection<'r, M> {
+    #[serde(rename = "type")]
+    kind: &'static str,
+    features: Vec<GeoJsonFeature<'r, M>>
+}
+
+#[derive(Serialize)]
+#[serde(bound(serialize = "FlatTableKey<M>: AsRef<str>"))]
+struct GeoJsonFeature<'r, M> {
+    #[serde(rename = "type")]
+    kind: &'static str,
+    geometry: Option<GeoJsonPoint>,
+    properties: JsonRow<'r, M>
+}
+
+#[derive(Debug, PartialEq, Serialize)]
+struct GeoJsonPoint {
+    #[serde(rename = "type")]
+    kind: &'static str,
+
+    /// Longitude and latitude in WGS84.
+    coordinates: [f64; 2]
+}
+
+/// Reproject UTM coordinates to a GeoJSON point, `None` unless both
+/// coordinates are present.
+fn geojson_point(easting: Option<u64>, northing: Option<u64>) -> Option<GeoJsonPoint> {
+    let (longitude, latitude) = utm_to_wgs84(easting?, northing?);
+    Some(GeoJsonPoint {
+        kind: "Point",
+        coordinates: [
+            round_coordinate(longitude, DEFAULT_COORDINATE_PRECISION),
+            round_coordinate(latitude, DEFAULT_COORDINATE_PRECISION)
+        ]
+    })
+}
+
+/// Render UTM coordinates as WKT point like the geometry of the exporter,
+/// `None` unless both coordinates are present.
+pub fn wkt_point(easting: Option<u64>, northing: Option<u64>) -> Option<String> {
+    Some(format!("POINT({} {})", easting?, northing?))
+}
+
+/// Deterministic hash of ordered column keys.
+///
+/// Uses 64-bit FNV-1a, as the hashers of `std` are not guaranteed to be stable
+/// across releases, with a unit separator between the keys.
+/// Downstream jobs can compare it to detect changes of the column set.
+pub fn schema_hash<'k>(keys: impl IntoIterator<Item = &'k str>) -> u64 {
+    const OFFSET_BASIS: u64 = 0xCBF29CE484222325;
+    const PRIME: u64 = 0x100000001B3;
+
+    let mut hash = OFFSET_BASIS;
+    for key in keys {
+        for byte in key.bytes().chain([0x1F]) {
+            hash ^= byte as u64;
+            hash = hash.wrapping_mul(PRIME);
+        }
+    }
+
+    hash
+}
+
+#[cfg(test)]
+mod tests {
+    use nlwkn::helper_types::SingleOrPair;
+    use nlwkn::{LandRecord, PHValues};
+
+    use super::*;
+
+    const REPORTS_JSON_PATH: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/test/reports.json");
+
+    fn csv<M>(water_rights: &[WaterRight]) -> String
+    where
+        FlatTableKey<M>: AsRef<str>,
+        M: Send + Sync
+    {
+        let flat_table: FlatTable<M> =
+            FlatTable::from_water_rights_with_notifier(water_rights, |_| ());
+        let mut csv = String::new();
+        flat_table.fmt_csv(&mut csv, || ()).unwrap();
+        csv
+    }
+
+    #[test]
+    fn header_lang_only_changes_header() {
+        let water_rights = nlwkn::load_water_rights(REPORTS_JSON_PATH).unwrap();
+        let en = csv::<marker::En>(&water_rights);
+        let de = csv::<marker::De>(&water_rights);
+
+        let (en_header, en_rows) = en.split_once('\n').unwrap();
+        let (de_header, de_rows) = de.split_once('\n').unwrap();
+
+        assert!(en_header.starts_with("water right no.;holder;"));
+        assert!(de_header.starts_with("Wasserrecht Nr.;Rechtsinhaber;"));
+        assert_eq!(en_rows, de_rows);
+        assert!(en_rows.contains("\"Körtke\""));
+    }
+
+    #[test]
+    fn schema_hash_works() {
+        let keys = ["water right no.", "holder", "valid from"];
+        assert_eq!(schema_hash(keys), schema_hash(keys));
+        assert_eq!(schema_hash(keys), 0x3354A67A31F0C3B8);
+        assert_ne!(
+            schema_hash(keys),
+            schema_hash(["water right no.", "holder"])
+        );
+        assert_ne!(
+            schema_hash(keys),
+            schema_hash(["water right no.", "holder", "valid from", "status"])
+        );
+        assert_ne!(schema_hash(["ab", "c"]), schema_hash(["a", "bc"]));
+
+        let water_rights = nlwkn::load_water_rights(REPORTS_JSON_PATH).unwrap();
+        let flat_table: FlatTable<marker::En> =
+            FlatTable::from_water_rights_with_notifier(&water_rights, |_| ());
+        let mut csv = String::new();
+        flat_table.fmt_csv(&mut csv, || ()).unwrap();
+        let header = csv.lines().next().unwrap();
+        assert_eq!(flat_table.schema_hash(), schema_hash(header.split(';')));
+    }
+
+    #[test]
+    fn json_keeps_value_types() {
+        let water_rights = nlwkn::load_water_rights(REPORTS_JSON_PATH).unwrap();
+        let flat_table: FlatTable<marker::En> =
+            FlatTable::from_water_rights_with_notifier(&water_rights, |_| ());
+        let mut json = String::new();
+        flat_table.fmt_json(&mut json).unwrap();
+
+        let rows: serde_json::Value = serde_json::from_str(&json).unwrap();
+        let row = &rows.as_array().unwrap()[0];
+        assert_eq!(row["water right no."], serde_json::json!(1101));
+        assert_eq!(row["holder"], serde_json::json!("Körtke"));
+        assert!(json.starts_with(r#"[{"water right no.":1101,"holder":"Körtke","#));
+    }
+
+    #[test]
+    fn sample_keeps_every_nth_row() {
+        let water_rights: Vec<_> = (1..=100)
+            .map(|no| {
+                let mut water_right =
+                    nlwkn::load_water_rights(REPORTS_JSON_PATH).unwrap().remove(0);
+                water_right.no = no;
+                water_right
+            })
+            .collect();
+        let mut flat_table: FlatTable<marker::En> =
+            FlatTable::from_water_rights_with_notifier(&water_rights, |_| ());
+        assert_eq!(flat_table.row_count(), 100);
+        let key_count = flat_table.keys.len();
+
+        flat_table.sample(NonZeroUsize::new(10).unwrap());
+        assert_eq!(flat_table.row_count(), 10);
+        assert_eq!(flat_table.keys.len(), key_count);
+        let nos: Vec<_> = flat_table
+            .values
+            .iter()
+            .map(|row| row[&FlatTableKey::from_unselect(FlatTableKey::NO)].to_string())
+            .collect();
+        assert_eq!(nos, [
+            "1", "11", "21", "31", "41", "51", "61", "71", "81", "91"
+        ]);
+
+        flat_table.sample(NonZeroUsize::new(1).unwrap());
+        assert_eq!(flat_table.row_count(), 10);
+    }
+
+    #[test]
+    fn wkt_geometry_works() {
+        assert_eq!(
+            wkt_point(Some(32603873), Some(5852015)).as_deref(),
+            Some("POINT(32603873 5852015)")
+        );
+        assert_eq!(wkt_point(Some(32603873), None), None);
+        assert_eq!(wkt_point(None, Some(5852015)), None);
+
+        // the second water right lacks the northing and stays blank
+        let mut water_rights = nlwkn::load_water_rights(REPORTS_JSON_PATH).unwrap();
+        let mut without_northing = nlwkn::load_water_rights(REPORTS_JSON_PATH).unwrap().remove(0);
+        without_northing.no = 1103;
+        for (_, usage_location) in without_northing.usage_locations_mut() {
+            usage_location.utm_northing = None;
+        }
+        water_rights.push(without_northing);
+
+        let mut flat_table: FlatTable<marker::En> =
+            FlatTable::from_water_rights_with_notifier(&water_rights, |_| ());
+        flat_table.add_wkt_geometry();
+        let mut csv = String::new();
+        flat_table.fmt_csv(&mut csv, || ()).unwrap();
+        let lines: Vec<_> = csv.lines().collect();
+        assert!(lines[0].contains(";utm northing;geometry;"));
+        assert!(lines[1].contains(";32603873;5852015;\"POINT(32603873 5852015)\";"));
+        assert!(lines[2].contains(";32603873;;;"));
+        assert!(!lines[2].contains("POINT"));
+    }
+
+    #[test]
+    fn geojson_works() {
+        let mut water_rights = nlwkn::load_water_rights(REPORTS_JSON_PATH).unwrap();
+        let mut without_coords = nlwkn::load_water_rights(REPORTS_JSON_PATH).unwrap().remove(0);
+        without_coords.no = 1103;
+        without_coords.exclude_coordinates();
+        water_rights.push(without_coords);
+
+        let geojson = |lang_de: bool| {
+            let mut geojson = String::new();
+            match lang_de {
+                false => {
+                    FlatTable::<marker::En>::from_water_rights_with_notifier(&water_rights, |_| ())
+                        .fmt_geojson(&mut geojson)
+                }
+                true => {
+                    FlatTable::<marker::De>::from_water_rights_with_notifier(&water_rights, |_| ())
+                        .fmt_geojson(&mut geojson)
+                }
+            }
+            .unwrap();
+            serde_json::from_str::<serde_json::Value>(&geojson).unwrap()
+        };
+
+        let en = geojson(false);
+        assert_eq!(en["type"], "FeatureCollection");
+        let features = en["features"].as_array().unwrap();
+        assert_eq!(features.len(), 2, "locations without coordinates are kept");
+        assert_eq!(features[0]["type"], "Feature");
+        assert_eq!(
+            features[0]["geometry"],
+            serde_json::json!({"type": "Point", "coordinates": [10.541013, 52.807907]})
+        );
+        assert_eq!(features[0]["properties"]["water right no."], 1101);
+        assert_eq!(features[0]["properties"]["holder"], "Körtke");
+        assert_eq!(features[1]["geometry"], serde_json::Value::Null);
+        assert_eq!(features[1]["properties"]["water right no."], 1103);
+
+        let de = geojson(true);
+        assert_eq!(de["features"][0]["geometry"], features[0]["geometry"]);
+        assert_eq!(de["features"][0]["properties"]["Rechtsinhaber"], "Körtke");
+    }
+
+    #[test]
+    fn csv_round_trip_keeps_water_rights() {
+        let pdf_only_path = REPORTS_JSON_PATH.replace("reports.json", "pdf-only-reports.json");
+        let mut water_rights = nlwkn::load_water_rights(REPORTS_JSON_PATH).unwrap();
+        water_rights.extend(nlwkn::load_water_rights(pdf_only_path).unwrap());
+
+        let water_right = &mut water_rights[0];
+        water_right.annotation = Some("Zeile 1\nZeile \"2\"; Ende".to_string());
+        let (_, usage_location) = water_right.usage_locations_mut().next().unwrap();
+        usage_location
+            .legal_purposes
+            .push(("E10".to_string(), "Beregnung, Frostschutz".to_string()));
+        usage_location.map_excerpt = Some(SingleOrPair::Pair(3528, "Wittingen".to_string()));
+        usage_location.catchment_area_code = Some(SingleOrPair::Single(5938));
+        usage_location.municipal_area = Some((3151025, "Sassenburg".to_string()));
+        usage_location.land_record = Some(
+            LandRecord {
+                district: "Bokel".to_string(),
+                field: 3
+            }
+            .into()
+        );
+        usage_location.dam_target_levels.default = Some((52.5, "m".to_string()).into());
+        usage_location.irrigation_area = Some((12.0, "ha".to_string()).into());
+        usage_location.ph_values = Some(PHValues {
+            min: Some(6),
+            max: Some(9)
+        });
+        usage_location
+            .injection_limits
+            .push(("Chlorid".to_string(), (250.0, "mg/l".to_string()).into()));
+
+        let round_trip = |csv: &str, lang_de: bool| {
+            let (water_rights, unknown_columns) = match lang_de {
+                false => FlatTable::<marker::En>::from_csv(csv).unwrap().to_water_rights(),
+                true => FlatTable::<marker::De>::from_csv(csv).unwrap().to_water_rights()
+            }
+            .unwrap();
+            assert!(unknown_columns.is_empty());
+            serde_json::to_value(water_rights).unwrap()
+        };
+        let expected = serde_json::to_value(&water_rights).unwrap();
+        assert_eq!(
+            round_trip(&csv::<marker::En>(&water_rights), false),
+            expected
+        );
+        assert_eq!(
+            round_trip(&csv::<marker::De>(&water_rights), true),
+            expected
+        );
+
+        let mut flat_table: FlatTable<marker::En> =
+            FlatTable::from_water_rights_with_notifier(&water_rights, |_| ());
+        flat_table.tag_rows(FlatTableKey::SOURCE, |_| Some("reports".to_string()));
+        let mut csv = String::new();
+        flat_table.fmt_csv(&mut csv, || ()).unwrap();
+        let (_, unknown_columns) =
+            FlatTable::<marker::En>::from_csv(&csv).unwrap().to_water_rights().unwrap();
+        assert_eq!(unknown_columns[&1102]["source"], ["reports"]);
+    }
+
+    #[test]
+    fn excluded_coordinates_are_absent() {
+        let mut water_rights = nlwkn::load_water_rights(REPORTS_JSON_PATH).unwrap();
+        assert!(csv::<marker::En>(&water_rights).contains("32603873"));
 
-        Ok(())
+        let affected: usize = water_rights.iter_mut().map(WaterRight::exclude_coordinates).sum();
+        assert_eq!(affected, 1);
+        let csv = csv::<marker::En>(&water_rights);
+        let header = csv.lines().next().unwrap();
+        assert!(!header.contains("utm"));
+        assert!(!csv.contains("32603873"));
+        assert!(!csv.contains("5852015"));
+        assert!(header.contains("county"));
     }
 }
@@ -0,0 +1,151 @@
+use std::iter::Peekable;
+use std::str::Chars;
+
+use crate::flat_table::value::FlatTableValue;
+
+/// Header and rows of a CSV, empty cells are `None`.
+pub type CsvCells = (Vec<String>, Vec<Vec<Option<FlatTableValue>>>);
+
+/// Split a CSV written by [`FlatTable::fmt_csv`](super::FlatTable::fmt_csv)
+/// into its header and rows.
+///
+/// Quoted cells are strings and may contain `;`, line breaks and doubled
+/// quotes, unquoted cells are read as booleans or numbers, empty cells are
+/// `None`.
+pub fn read_csv(csv: &str) -> anyhow::Result<CsvCells> {
+    let mut chars = csv.chars().peekable();
+
+    let mut header = Vec::new();
+    loop {
+        let (key, end) = read_raw(&mut chars);
+        header.push(key);
+        if end {
+            break;
+        }
+    }
+    if header.iter().all(String::is_empty) {
+        return Err(anyhow::Error::msg("csv has no header"));
+    }
+
+    let mut rows = Vec::new();
+    while chars.peek().is_some() {
+        let row_no = rows.len() + 1;
+        let mut row = Vec::with_capacity(header.len());
+        loop {
+            let (value, end) = match chars.peek() {
+                Some('"') => {
+                    let (value, end) = read_quoted(&mut chars)
+                        .map_err(|e| anyhow::Error::msg(format!("row {row_no}, {e}")))?;
+                    (Some(FlatTableValue::String(value)), end)
+                }
+                _ => {
+                    let (raw, end) = read_raw(&mut chars);
+                    (parse_unquoted(raw), end)
+                }
+            };
+            row.push(value);
+            if end {
+                break;
+            }
+        }
+
+        if row.len() != header.len() {
+            return Err(anyhow::Error::msg(format!(
+                "row {row_no} has {} values but the header has {} columns",
+                row.len(),
+                header.len()
+            )));
+        }
+        rows.push(row);
+    }
+
+    Ok((header, rows))
+}
+
+/// Read an unquoted cell, returns whether it ended the record.
+fn read_raw(chars: &mut Peekable<Chars>) -> (String, bool) {
+    let mut raw = String::new();
+    for c in chars.by_ref() {
+        match c {
+            ';' => return (raw, false),
+            '\n' => return (raw, true),
+            c => raw.push(c)
+        }
+    }
+
+    (raw, true)
+}
+
+/// Read a quoted cell, returns whether it ended the record.
+fn read_quoted(chars: &mut Peekable<Chars>) -> anyhow::Result<(String, bool)> {
+    chars.next();
+    let mut value = String::new();
+    loop {
+        match chars.next() {
+            Some('"') if chars.peek() == Some(&'"') => {
+                chars.next();
+                value.push('"');
+            }
+            Some('"') => break,
+            Some(c) => value.push(c),
+            None => return Err(anyhow::Error::msg("quoted value is not closed"))
+        }
+    }
+
+    match chars.next() {
+        Some(';') => Ok((value, false)),
+        Some('\n') | None => Ok((value, true)),
+        Some(c) => Err(anyhow::Error::msg(format!(
+            "unexpected {c:?} after quoted value {value:?}"
+        )))
+    }
+}
+
+/// Unquoted cells are written for booleans and numbers, anything else is kept
+/// as string.
+fn parse_unquoted(raw: String) -> Option<FlatTableValue> {
+    if raw.is_empty() {
+        return None;
+    }
+
+    Some(match raw.as_str() {
+        "true" => FlatTableValue::Bool(true),
+        "false" => FlatTableValue::Bool(false),
+        raw => match (raw.parse::<u64>(), raw.parse::<i64>(), raw.parse::<f64>()) {
+            (Ok(u), ..) => FlatTableValue::U64(u),
+            (_, Ok(i), _) => FlatTableValue::I64(i),
+            (.., Ok(f)) => FlatTableValue::F64(f),
+            _ => FlatTableValue::String(raw.to_string())
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn read_csv_works() {
+        let csv = "no;name;active;rate\n1101;\"a;\"\"b\"\"\nc\";true;2.5\n1102;;false;\n";
+        let (header, rows) = read_csv(csv).unwrap();
+        assert_eq!(header, ["no", "name", "active", "rate"]);
+        assert_eq!(rows.len(), 2);
+
+        let cells = |row: &[Option<FlatTableValue>]| -> Vec<Option<String>> {
+            row.iter().map(|value| value.as_ref().map(ToString::to_string)).collect()
+        };
+        assert!(matches!(rows[0][0], Some(FlatTableValue::U64(1101))));
+        assert!(matches!(&rows[0][1], Some(FlatTableValue::String(s)) if s == "a;\"b\"\nc"));
+        assert!(matches!(rows[0][2], Some(FlatTableValue::Bool(true))));
+        assert!(matches!(rows[0][3], Some(FlatTableValue::F64(_))));
+        assert_eq!(cells(&rows[1]), [
+            Some("1102".to_string()),
+            None,
+            Some("false".to_string()),
+            None
+        ]);
+
+        assert!(read_csv("no;name\n1101\n").is_err());
+        assert!(read_csv("no;name\n1101;\"open\n").is_err());
+    }
+}
@@ -0,0 +1,152 @@
+use std::fmt::Write;
+
+use itertools::Itertools;
+use nlwkn::helper_types::OrFallback;
+use nlwkn::{RateRecord, UsageLocation, WaterRight};
+
+use crate::flat_table::key::{marker, FlatTableKey};
+use crate::flat_table::value::FlatTableValue;
+
+/// Columns of [`fmt_rates_tidy`].
+pub const RATES_TIDY_HEADER: [FlatTableKey<marker::Unselect>; 8] = [
+    FlatTableKey::NO,
+    FlatTableKey::LEGAL_DEPARTMENT_ABBREVIATION,
+    FlatTableKey::USAGE_LOCATION_NO,
+    FlatTableKey::USAGE_LOCATION_SERIAL,
+    FlatTableKey::RATE_TYPE,
+    FlatTableKey::RATE_VALUE,
+    FlatTableKey::RATE_UNIT,
+    FlatTableKey::RATE_PER
+];
+
+/// Format the rates of all usage locations in long form as CSV.
+///
+/// Instead of a column per rate and time unit, every rate gets its own row
+/// with the water right, usage location and rate type identifying it.
+/// Rates that could not be parsed keep their raw text as value, their unit
+/// and time unit stay empty.
+pub fn fmt_rates_tidy<M, W>(water_rights: &[WaterRight], w: &mut W) -> std::fmt::Result
+where
+    FlatTableKey<M>: AsRef<str>,
+    W: Write
+{
+    // TODO: replace this when `std` stabilized `intersperse`
+    for key in Itertools::intersperse(
+        RATES_TIDY_HEADER.iter().map(|key| FlatTableKey::<M>::from_unselect_ref(key).as_ref()),
+        ";"
+    ) {
+        w.write_str(key)?;
+    }
+    writeln!(w)?;
+
+    for water_right in water_rights {
+        for department in water_right.legal_departments.values() {
+            for usage_location in department.usage_locations.iter() {
+                for (rate_type, rate_record) in rate_records(usage_location) {
+                    let rate_type = FlatTableKey::<M>::from_unselect_ref(&rate_type).as_ref();
+                    for rate in rate_record.iter() {
+                        write!(w, "{};{};", water_right.no, department.abbreviation)?;
+                        if let Some(no) = usage_location.no {
+                            write!(w, "{no}")?;
+                        }
+                        w.write_char(';')?;
+                        if let Some(serial) = usage_location.serial.as_ref() {
+                            write!(w, "{}", FlatTableValue::String(serial.clone()))?;
+                        }
+                        write!(w, ";{};", FlatTableValue::String(rate_type.to_string()))?;
+                        match rate {
+                            OrFallback::Expected(rate) => writeln!(
+                                w,
+                                "{};{};{}",
+                                rate.value,
+                                FlatTableValue::String(rate.unit.clone()),
+                                FlatTableValue::String(rate.per.to_string())
+                            )?,
+                            OrFallback::Fallback(raw) => {
+                                writeln!(w, "{};;", FlatTableValue::String(raw.clone()))?
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn rate_records(
+    usage_location: &UsageLocation
+) -> [(FlatTableKey<marker::Unselect>, &RateRecord); 6] {
+    [
+        (
+            FlatTableKey::WITHDRAWAL_RATE,
+            &usage_location.withdrawal_rates
+        ),
+        (FlatTableKey::PUMPING_RATE, &usage_location.pumping_rates),
+        (
+            FlatTableKey::INJECTION_RATE,
+            &usage_location.injection_rates
+        ),
+        (
+            FlatTableKey::WASTER_WATER_FLOW_VOLUME,
+            &usage_location.waste_water_flow_volume
+        ),
+        (
+            FlatTableKey::FLUID_DISCHARGE,
+            &usage_location.fluid_discharge
+        ),
+        (
+            FlatTableKey::RAIN_SUPPLEMENT,
+            &usage_location.rain_supplement
+        )
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use nlwkn::helper_types::{Duration, Rate};
+    use nlwkn::LegalDepartmentAbbreviation;
+
+    use super::*;
+
+    fn rate(value: f64, unit: &str, per: Duration) -> OrFallback<Rate<f64>> {
+        OrFallback::Expected(Rate {
+            value,
+            unit: unit.to_string(),
+            per
+        })
+    }
+
+    #[test]
+    fn rates_tidy_works() {
+        let mut usage_location = UsageLocation::new();
+        usage_location.no = Some(101);
+        usage_location.serial = Some("1".to_string());
+        usage_location.withdrawal_rates.insert(rate(2.5, "m³", Duration::Seconds(1.0)));
+        usage_location.withdrawal_rates.insert(rate(1500.0, "m³", Duration::Years(1.0)));
+        usage_location.withdrawal_rates.insert(OrFallback::Fallback("unbekannt".to_string()));
+        usage_location.pumping_rates.insert(rate(10.0, "l", Duration::Seconds(1.0)));
+
+        let water_right = WaterRight::builder(1101)
+            .add_usage_location(LegalDepartmentAbbreviation::A, usage_location)
+            .build();
+
+        let mut csv = String::new();
+        fmt_rates_tidy::<marker::En, _>(&[water_right], &mut csv).unwrap();
+        let mut lines = csv.lines();
+        assert_eq!(
+            lines.next().unwrap(),
+            "water right no.;legal department abbreviation;usage location no.;usage location \
+             serial no.;rate type;value;unit;per"
+        );
+        let mut rows: Vec<_> = lines.collect();
+        rows.sort();
+        assert_eq!(rows, [
+            r#"1101;A;101;"1";"pumping rate";10;"l";"s""#,
+            r#"1101;A;101;"1";"withdrawal rate";"unbekannt";;"#,
+            r#"1101;A;101;"1";"withdrawal rate";1500;"m³";"a""#,
+            r#"1101;A;101;"1";"withdrawal rate";2.5;"m³";"s""#
+        ]);
+    }
+}
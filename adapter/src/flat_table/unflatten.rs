@@ -0,0 +1,530 @@
+use std::collections::{BTreeMap, HashMap};
+
+use lazy_static::lazy_static;
+use nlwkn::helper_types::{OrFallback, Quantity, Rate, SingleOrPair};
+use nlwkn::util::parse_legal_purposes;
+use nlwkn::{
+    LandRecord, LegalDepartment, LegalDepartmentAbbreviation, PHValues, RateRecord, UsageLocation,
+    WaterRight, WaterRightNo
+};
+use regex::Regex;
+
+use crate::flat_table::key::{marker, FlatTableKey};
+use crate::flat_table::value::FlatTableValue;
+use crate::flat_table::FlatTableRow;
+
+/// Values of the columns that are no field of the water rights, like
+/// `source`, by water right and column, in the order of the rows.
+pub type UnknownColumns = BTreeMap<WaterRightNo, BTreeMap<String, Vec<String>>>;
+
+lazy_static! {
+    static ref STRING_NUM_RE: Regex =
+        Regex::new(r"^(?<string>\D+)\s*(?<num>\d+)$").expect("valid regex");
+}
+
+/// Rebuild the water rights from rows flattened by
+/// [`flatten_water_right`](super::util::flatten_water_right).
+///
+/// The rows are grouped by their water right number, every row becomes a
+/// usage location of its legal department.
+/// The water rights keep the order of their first row.
+pub fn unflatten_rows<'r, M>(
+    rows: impl IntoIterator<Item = &'r FlatTableRow<M>>
+) -> anyhow::Result<(Vec<WaterRight>, UnknownColumns)>
+where
+    FlatTableKey<M>: AsRef<str>,
+    M: 'r
+{
+    let no = FlatTableKey::NO;
+    let no_key = FlatTableKey::<M>::from_unselect_ref(&no);
+    let mut water_rights: Vec<WaterRight> = Vec::new();
+    let mut positions: HashMap<WaterRightNo, usize> = HashMap::new();
+    let mut unknown_columns = UnknownColumns::new();
+    let mut columns = HashMap::new();
+
+    for (i, row) in rows.into_iter().enumerate() {
+        let no = match row.get(no_key) {
+            Some(FlatTableValue::U64(no)) => *no,
+            Some(value) => {
+                return Err(anyhow::Error::msg(format!(
+                    "row {}, invalid water right no {value}",
+                    i + 1
+                )))
+            }
+            None => {
+                return Err(anyhow::Error::msg(format!(
+                    "row {} has no water right no",
+                    i + 1
+                )))
+            }
+        };
+
+        let position = *positions.entry(no).or_insert_with(|| {
+            water_rights.push(WaterRight::new(no));
+            water_rights.len() - 1
+        });
+        let unknown = unflatten_row(row, &mut water_rights[position], &mut columns)
+            .map_err(|e| anyhow::Error::msg(format!("row {}, {e}", i + 1)))?;
+        for (key, value) in unknown {
+            unknown_columns.entry(no).or_default().entry(key).or_default().push(value);
+        }
+    }
+
+    Ok((water_rights, unknown_columns))
+}
+
+/// Fields of a row besides the ones of its water right.
+struct RowFields {
+    usage_location: UsageLocation,
+    abbreviation: LegalDepartmentAbbreviation,
+    description: Option<String>
+}
+
+/// Sets the field of a column to the value of a row.
+type Setter = fn(&FlatTableValue, &mut WaterRight, &mut RowFields) -> anyhow::Result<()>;
+
+/// Rates of a usage location a rate column belongs to.
+type RatesOf = fn(&mut UsageLocation) -> &mut RateRecord;
+
+/// Key constants of the columns that are a field of the water rights.
+///
+/// Derived columns like `source` and `geometry` are no field.
+const FIELDS: &[(FlatTableKey<marker::Unselect>, Setter)] = &[
+    // the water right number is set by grouping the rows
+    (FlatTableKey::NO, |_, _, _| Ok(())),
+    (FlatTableKey::HOLDER, |v, wr, _| {
+        set(&mut wr.holder, text(v))
+    }),
+    (FlatTableKey::VALID_UNTIL, |v, wr, _| {
+        set(&mut wr.valid_until, text(v))
+    }),
+    (FlatTableKey::STATUS, |v, wr, _| {
+        set(&mut wr.status, text(v))
+    }),
+    (FlatTableKey::VALID_FROM, |v, wr, _| {
+        set(&mut wr.valid_from, text(v))
+    }),
+    (FlatTableKey::LEGAL_TITLE, |v, wr, _| {
+        set(&mut wr.legal_title, text(v))
+    }),
+    (FlatTableKey::CADENZA_LEGAL_TITLE, |v, wr, _| {
+        set(&mut wr.cadenza_legal_title, text(v))
+    }),
+    (FlatTableKey::WATER_AUTHORITY, |v, wr, _| {
+        set(&mut wr.water_authority, text(v))
+    }),
+    (FlatTableKey::REGISTERING_AUTHORITY, |v, wr, _| {
+        set(&mut wr.registering_authority, text(v))
+    }),
+    (FlatTableKey::GRANTING_AUTHORITY, |v, wr, _| {
+        set(&mut wr.granting_authority, text(v))
+    }),
+    (FlatTableKey::INITIALLY_GRANTED, |v, wr, _| {
+        set(&mut wr.initially_granted, text(v))
+    }),
+    (FlatTableKey::LAST_CHANGE, |v, wr, _| {
+        set(&mut wr.last_change, text(v))
+    }),
+    (FlatTableKey::FILE_REFERENCE, |v, wr, _| {
+        set(&mut wr.file_reference, text(v))
+    }),
+    (FlatTableKey::EXTERNAL_IDENTIFIER, |v, wr, _| {
+        set(&mut wr.external_identifier, text(v))
+    }),
+    (FlatTableKey::SUBJECT, |v, wr, _| {
+        set(&mut wr.subject, text(v))
+    }),
+    (FlatTableKey::ADDRESS, |v, wr, _| {
+        set(&mut wr.address, text(v))
+    }),
+    (FlatTableKey::ANNOTATION, |v, wr, _| {
+        set(&mut wr.annotation, text(v))
+    }),
+    (FlatTableKey::LEGAL_DEPARTMENT_ABBREVIATION, |v, _, row| {
+        row.abbreviation = match text(v).as_str() {
+            "?" => LegalDepartmentAbbreviation::Unknown,
+            other => other.parse()?
+        };
+        Ok(())
+    }),
+    (FlatTableKey::LEGAL_DEPARTMENT_DESCRIPTION, |v, _, row| {
+        set(&mut row.description, text(v))
+    }),
+    (FlatTableKey::USAGE_LOCATION_NO, |v, _, row| {
+        set(&mut row.usage_location.no, unsigned(v)?)
+    }),
+    (FlatTableKey::USAGE_LOCATION_SERIAL, |v, _, row| {
+        set(&mut row.usage_location.serial, text(v))
+    }),
+    (FlatTableKey::ACTIVE, |v, _, row| {
+        set(&mut row.usage_location.active, boolean(v)?)
+    }),
+    (FlatTableKey::REAL, |v, _, row| {
+        set(&mut row.usage_location.real, boolean(v)?)
+    }),
+    (FlatTableKey::USAGE_LOCATION_NAME, |v, _, row| {
+        set(&mut row.usage_location.name, text(v))
+    }),
+    (FlatTableKey::LEGAL_PURPOSE, |v, _, row| {
+        row.usage_location.legal_purposes = parse_legal_purposes(&text(v));
+        Ok(())
+    }),
+    (FlatTableKey::MAP_EXCERPT, |v, _, row| {
+        set(
+            &mut row.usage_location.map_excerpt,
+            parse_single_or_pair(&text(v))?
+        )
+    }),
+    (FlatTableKey::MUNICIPAL_AREA, |v, _, row| {
+        set(
+            &mut row.usage_location.municipal_area,
+            parse_code_pair(&text(v))?
+        )
+    }),
+    (FlatTableKey::MUNICIPALITY_NAME, |v, _, row| {
+        set(&mut row.usage_location.municipality_name, text(v))
+    }),
+    (FlatTableKey::MUNICIPALITY_DISTRICT, |v, _, row| {
+        set(&mut row.usage_location.municipality_district, text(v))
+    }),
+    (FlatTableKey::COUNTY, |v, _, row| {
+        set(&mut row.usage_location.county, text(v))
+    }),
+    (FlatTableKey::LAND_RECORD, |v, _, row| {
+        set(
+            &mut row.usage_location.land_record,
+            parse_land_record(text(v))
+        )
+    }),
+    (FlatTableKey::PLOT, |v, _, row| {
+        set(&mut row.usage_location.plot, text(v))
+    }),
+    (FlatTableKey::MAINTENANCE_ASSOCIATION, |v, _, row| {
+        set(
+            &mut row.usage_location.maintenance_association,
+            parse_code_pair(&text(v))?
+        )
+    }),
+    (FlatTableKey::EU_SURVEY_AREA, |v, _, row| {
+        set(
+            &mut row.usage_location.eu_survey_area,
+            parse_code_pair(&text(v))?
+        )
+    }),
+    (FlatTableKey::CATCHMENT_AREA_CODE, |v, _, row| {
+        set(
+            &mut row.usage_location.catchment_area_code,
+            parse_single_or_pair(&text(v))?
+        )
+    }),
+    (FlatTableKey::REGULATION_CITATION, |v, _, row| {
+        set(&mut row.usage_location.regulation_citation, text(v))
+    }),
+    (FlatTableKey::RIVER_BASIN, |v, _, row| {
+        set(&mut row.usage_location.river_basin, text(v))
+    }),
+    (FlatTableKey::GROUNDWATER_BODY, |v, _, row| {
+        set(&mut row.usage_location.groundwater_body, text(v))
+    }),
+    (FlatTableKey::WATER_BODY, |v, _, row| {
+        set(&mut row.usage_location.water_body, text(v))
+    }),
+    (FlatTableKey::WATER_BODY_NORMALIZED, |v, _, row| {
+        set(&mut row.usage_location.water_body_normalized, text(v))
+    }),
+    (FlatTableKey::FLOOD_AREA, |v, _, row| {
+        set(&mut row.usage_location.flood_area, text(v))
+    }),
+    (FlatTableKey::WATER_PROTECTION_AREA, |v, _, row| {
+        set(&mut row.usage_location.water_protection_area, text(v))
+    }),
+    (FlatTableKey::DAM_TARGETS_DEFAULT, |v, _, row| {
+        set(
+            &mut row.usage_location.dam_target_levels.default,
+            parse_quantity(&text(v))?
+        )
+    }),
+    (FlatTableKey::DAM_TARGETS_STEADY, |v, _, row| {
+        set(
+            &mut row.usage_location.dam_target_levels.steady,
+            parse_quantity(&text(v))?
+        )
+    }),
+    (FlatTableKey::DAM_TARGETS_MAX, |v, _, row| {
+        set(
+            &mut row.usage_location.dam_target_levels.max,
+            parse_quantity(&text(v))?
+        )
+    }),
+    (FlatTableKey::IRRIGATION_AREA, |v, _, row| {
+        set(
+            &mut row.usage_location.irrigation_area,
+            parse_quantity(&text(v))?
+        )
+    }),
+    (FlatTableKey::PH_VALUES_MIN, |v, _, row| {
+        let ph_values = row.usage_location.ph_values.get_or_insert(PHValues::new());
+        set(&mut ph_values.min, unsigned(v)?)
+    }),
+    (FlatTableKey::PH_VALUES_MAX, |v, _, row| {
+        let ph_values = row.usage_location.ph_values.get_or_insert(PHValues::new());
+        set(&mut ph_values.max, unsigned(v)?)
+    }),
+    (FlatTableKey::UTM_EASTING, |v, _, row| {
+        set(&mut row.usage_location.utm_easting, unsigned(v)?)
+    }),
+    (FlatTableKey::UTM_NORTHING, |v, _, row| {
+        set(&mut row.usage_location.utm_northing, unsigned(v)?)
+    })
+];
+
+/// Key constants of the rate columns, their columns get the unit and period
+/// appended, like `withdrawal rate m³/a`.
+const RATE_RECORDS: &[(FlatTableKey<marker::Unselect>, RatesOf)] = &[
+    (FlatTableKey::WITHDRAWAL_RATE, |ul| &mut ul.withdrawal_rates),
+    (FlatTableKey::PUMPING_RATE, |ul| &mut ul.pumping_rates),
+    (FlatTableKey::INJECTION_RATE, |ul| &mut ul.injection_rates),
+    (FlatTableKey::WASTER_WATER_FLOW_VOLUME, |ul| {
+        &mut ul.waste_water_flow_volume
+    }),
+    (FlatTableKey::FLUID_DISCHARGE, |ul| &mut ul.fluid_discharge),
+    (FlatTableKey::RAIN_SUPPLEMENT, |ul| &mut ul.rain_supplement)
+];
+
+/// What the values of a column are read into.
+#[derive(Clone, Copy)]
+enum Column<'k> {
+    Field(Setter),
+
+    /// Rates with the unit and period of the column, like `m³/a`.
+    Rates(RatesOf, &'k str),
+
+    /// No field, like `source` or the keys of dam targets and injection limits
+    /// taken from the reports.
+    Other
+}
+
+impl<'k> Column<'k> {
+    fn resolve<M>(key: &'k FlatTableKey<M>) -> Self
+    where
+        FlatTableKey<M>: AsRef<str>
+    {
+        let field =
+            FIELDS.iter().find(|(field, _)| FlatTableKey::<M>::from_unselect_ref(field) == key);
+        if let Some((_, setter)) = field {
+            return Column::Field(*setter);
+        }
+
+        for (prefix, rates_of) in RATE_RECORDS {
+            let prefix = FlatTableKey::<M>::from_unselect_ref(prefix).as_ref();
+            if let Some(unit) =
+                key.as_ref().strip_prefix(prefix).and_then(|unit| unit.strip_prefix(' '))
+            {
+                return Column::Rates(*rates_of, unit);
+            }
+        }
+
+        Column::Other
+    }
+}
+
+/// Add the usage location of a row to the water right, returns the columns
+/// that are no field of it.
+///
+/// `columns` keeps the resolved [`Column`] of every column name, so every
+/// column is only resolved once for all rows.
+fn unflatten_row<'r, M>(
+    row: &'r FlatTableRow<M>,
+    water_right: &mut WaterRight,
+    columns: &mut HashMap<&'r str, Column<'r>>
+) -> anyhow::Result<Vec<(String, String)>>
+where
+    FlatTableKey<M>: AsRef<str>
+{
+    let mut fields = RowFields {
+        usage_location: UsageLocation::new(),
+        abbreviation: LegalDepartmentAbbreviation::Unknown,
+        description: None
+    };
+    let mut remaining = Vec::new();
+
+    for (key, value) in row {
+        let column = *columns.entry(key.as_ref()).or_insert_with(|| Column::resolve(key));
+        let set = match column {
+            Column::Field(setter) => setter(value, water_right, &mut fields),
+            Column::Rates(rates_of, unit) => {
+                insert_rates(rates_of(&mut fields.usage_location), unit, &text(value))
+            }
+            Column::Other => {
+                remaining.push((key.as_ref().to_string(), value));
+                continue;
+            }
+        };
+        set.map_err(|e| anyhow::Error::msg(format!("column {:?}, {e}", key.as_ref())))?;
+    }
+
+    let RowFields {
+        mut usage_location,
+        abbreviation,
+        description
+    } = fields;
+
+    // keys of dam targets and injection limits are taken from the reports
+    let mut unknown = Vec::new();
+    for (key, value) in remaining {
+        let text = text(value);
+        match parse_quantity(&text) {
+            Ok(quantity) if key.starts_with("Stauziel") => {
+                usage_location.dam_target_levels.other.push((key, quantity))
+            }
+            Ok(quantity) if is_injection_limit_department(abbreviation) => {
+                usage_location.injection_limits.push((key, quantity))
+            }
+            _ => unknown.push((key, text))
+        }
+    }
+
+    let description = description.unwrap_or_else(|| abbreviation.description().to_string());
+    water_right
+        .legal_departments
+        .entry(abbreviation)
+        .or_insert_with(|| LegalDepartment::new(abbreviation, description))
+        .usage_locations
+        .push(usage_location);
+
+    Ok(unknown)
+}
+
+/// Departments whose usage locations may have "Erlaubniswert" entries with
+/// their own key, see `parse_allowance_value` of the parser.
+fn is_injection_limit_department(abbreviation: LegalDepartmentAbbreviation) -> bool {
+    use LegalDepartmentAbbreviation::*;
+
+    matches!(abbreviation, A | B | C | F)
+}
+
+fn set<T>(field: &mut Option<T>, value: T) -> anyhow::Result<()> {
+    *field = Some(value);
+    Ok(())
+}
+
+/// Inverse of
+/// [`insert_rate_record_into_row`](super::util::insert_rate_record_into_row),
+/// `unit` is the unit and period of the column like `m³/a`, `value` the rates
+/// of the column like `1500 m³, 2500 m³`.
+fn insert_rates(rate_record: &mut RateRecord, unit: &str, value: &str) -> anyhow::Result<()> {
+    let (measurement, per) = unit
+        .rsplit_once('/')
+        .ok_or_else(|| anyhow::Error::msg(format!("rate unit {unit:?} has no period")))?;
+    for rate in value.split(", ") {
+        let (value, _) = rate.split_once(' ').unwrap_or((rate, ""));
+        let rate: Rate<f64> = format!("{value} {measurement}/{per}").parse()?;
+        rate_record.insert(rate.into());
+    }
+
+    Ok(())
+}
+
+fn text(value: &FlatTableValue) -> String {
+    match value {
+        FlatTableValue::String(s) => s.clone(),
+        value => value.to_string()
+    }
+}
+
+fn unsigned(value: &FlatTableValue) -> anyhow::Result<u64> {
+    match value {
+        FlatTableValue::U64(u) => Ok(*u),
+        value => Err(anyhow::Error::msg(format!(
+            "expected a number, got {value}"
+        )))
+    }
+}
+
+fn boolean(value: &FlatTableValue) -> anyhow::Result<bool> {
+    match value {
+        FlatTableValue::Bool(b) => Ok(*b),
+        value => Err(anyhow::Error::msg(format!(
+            "expected a boolean, got {value}"
+        )))
+    }
+}
+
+/// Parse a quantity written like `2.5 m`.
+fn parse_quantity(value: &str) -> anyhow::Result<Quantity> {
+    let (value, unit) = value
+        .split_once(' ')
+        .ok_or_else(|| anyhow::Error::msg(format!("quantity {value:?} has no unit")))?;
+    Ok((value.parse()?, unit.to_string()).into())
+}
+
+/// Parse a code with its name like `3241001 Hannover`.
+fn parse_code_pair(value: &str) -> anyhow::Result<(u64, String)> {
+    let (code, name) = value
+        .split_once(' ')
+        .ok_or_else(|| anyhow::Error::msg(format!("{value:?} has no name after the code")))?;
+    Ok((code.parse()?, name.to_string()))
+}
+
+fn parse_single_or_pair(value: &str) -> anyhow::Result<SingleOrPair<u64, String>> {
+    Ok(match value.split_once(' ') {
+        Some((code, name)) => SingleOrPair::Pair(code.parse()?, name.to_string()),
+        None => SingleOrPair::Single(value.parse()?)
+    })
+}
+
+/// Land records are written as district and field without separator, like the
+/// reports after removing their spaces.
+fn parse_land_record(value: String) -> OrFallback<LandRecord> {
+    let land_record = STRING_NUM_RE.captures(&value).and_then(|captured| {
+        Some(LandRecord {
+            district: captured["string"].to_string(),
+            field: captured["num"].parse().ok()?
+        })
+    });
+
+    match land_record {
+        Some(land_record) => land_record.into(),
+        None => OrFallback::Fallback(value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unknown_columns_are_kept() {
+        let mut row = FlatTableRow::<marker::En>::new();
+        row.insert(FlatTableKey::from_header("water right no."), 1101u64.into());
+        row.insert(
+            FlatTableKey::from_header("source"),
+            "reports".to_string().into()
+        );
+        row.insert(
+            FlatTableKey::from_header("legal department abbreviation"),
+            "C".to_string().into()
+        );
+        row.insert(
+            FlatTableKey::from_header("Stauziel, bezogen auf Pegel X"),
+            "2.5 m".to_string().into()
+        );
+        row.insert(
+            FlatTableKey::from_header("comment"),
+            "checked".to_string().into()
+        );
+
+        let (water_rights, unknown_columns) = unflatten_rows([&row]).unwrap();
+        let usage_location =
+            &water_rights[0].legal_departments[&LegalDepartmentAbbreviation::C].usage_locations[0];
+        assert_eq!(usage_location.dam_target_levels.other.len(), 1);
+        assert_eq!(usage_location.dam_target_levels.other[0].1.value, 2.5);
+        assert_eq!(
+            unknown_columns[&1101],
+            BTreeMap::from([
+                ("comment".to_string(), vec!["checked".to_string()]),
+                ("source".to_string(), vec!["reports".to_string()])
+            ])
+        );
+    }
+}
@@ -1,6 +1,7 @@
 use std::format;
 use std::marker::PhantomData;
 
+use itertools::Itertools;
 use nlwkn::helper_types::OrFallback;
 use nlwkn::{LandRecord, LegalDepartment, RateRecord, UsageLocation, WaterRight};
 
@@ -32,13 +33,24 @@ pub fn insert_rate_record_into_row<M>(
         OrFallback::Fallback(_) => None,
         OrFallback::Expected(rate) => Some(rate)
     }) {
+        // rates with the same period may differ in their measurement, e.g. m³ and l
         let key: FlatTableKey<M> = FlatTableKey::Multiple {
             phantom: PhantomData,
-            de: format!("{}/{}", key.ref_de(), rate.per).into(),
-            en: format!("{}/{}", key.ref_en(), rate.per).into()
+            de: format!("{} {}/{}", key.ref_de(), rate.unit, rate.per).into(),
+            en: format!("{} {}/{}", key.ref_en(), rate.unit, rate.per).into()
         };
 
-        row.insert(key, format!("{} {}", rate.value, rate.unit).into());
+        // repeated allowances of the same kind share the column
+        let value = format!("{} {}", rate.value, rate.unit);
+        match row.get_mut(&key) {
+            Some(FlatTableValue::String(existing)) => {
+                existing.push_str(", ");
+                existing.push_str(&value);
+            }
+            _ => {
+                row.insert(key, value.into());
+            }
+        }
     }
 }
 
@@ -61,6 +73,7 @@ where
             status,
             valid_from,
             legal_title,
+            cadenza_legal_title,
             water_authority,
             registering_authority,
             granting_authority,
@@ -80,6 +93,11 @@ where
         insert_into_row(row, FlatTableKey::STATUS, status.clone());
         insert_into_row(row, FlatTableKey::VALID_FROM, valid_from.clone());
         insert_into_row(row, FlatTableKey::LEGAL_TITLE, legal_title.clone());
+        insert_into_row(
+            row,
+            FlatTableKey::CADENZA_LEGAL_TITLE,
+            cadenza_legal_title.clone()
+        );
         insert_into_row(row, FlatTableKey::WATER_AUTHORITY, water_authority.clone());
         insert_into_row(
             row,
@@ -154,9 +172,11 @@ where
         active,
         real,
         name,
-        legal_purpose,
+        legal_purposes,
         map_excerpt,
         municipal_area,
+        municipality_name,
+        municipality_district,
         county,
         land_record,
         plot,
@@ -171,6 +191,7 @@ where
         river_basin,
         groundwater_body,
         water_body,
+        water_body_normalized,
         flood_area,
         water_protection_area,
         dam_target_levels,
@@ -196,7 +217,12 @@ where
     insert_into_row(
         &mut row,
         FlatTableKey::LEGAL_PURPOSE,
-        legal_purpose.as_ref().map(|(code, name)| format!("{code} {name}"))
+        match legal_purposes.is_empty() {
+            true => None,
+            false => {
+                Some(legal_purposes.iter().map(|(code, name)| format!("{code} {name}")).join(", "))
+            }
+        }
     );
     insert_into_row(
         &mut row,
@@ -208,6 +234,16 @@ where
         FlatTableKey::MUNICIPAL_AREA,
         municipal_area.as_ref().map(|(code, name)| format!("{code} {name}"))
     );
+    insert_into_row(
+        &mut row,
+        FlatTableKey::MUNICIPALITY_NAME,
+        municipality_name.clone()
+    );
+    insert_into_row(
+        &mut row,
+        FlatTableKey::MUNICIPALITY_DISTRICT,
+        municipality_district.clone()
+    );
     insert_into_row(&mut row, FlatTableKey::COUNTY, county.clone());
 
     match land_record.as_ref() {
@@ -258,6 +294,11 @@ where
         groundwater_body.clone()
     );
     insert_into_row(&mut row, FlatTableKey::WATER_BODY, water_body.clone());
+    insert_into_row(
+        &mut row,
+        FlatTableKey::WATER_BODY_NORMALIZED,
+        water_body_normalized.clone()
+    );
     insert_into_row(&mut row, FlatTableKey::FLOOD_AREA, flood_area.clone());
     insert_into_row(
         &mut row,
@@ -297,6 +338,10 @@ where
         ph_values.as_ref().and_then(|v| v.max)
     );
 
+    for (key, quantity) in dam_target_levels.other.iter() {
+        row.insert(FlatTableKey::from(key.clone()), quantity.to_string().into());
+    }
+
     for (key, quantity) in injection_limits.iter() {
         row.insert(FlatTableKey::from(key.clone()), quantity.to_string().into());
     }
@@ -306,3 +351,47 @@ where
 
     row
 }
+
+#[cfg(test)]
+mod tests {
+    use nlwkn::helper_types::Rate;
+
+    use super::*;
+
+    #[test]
+    fn rates_with_different_measurements_keep_their_columns() {
+        let mut rate_record = RateRecord::new();
+        for rate in ["2 m³/d", "2000 l/d"] {
+            let rate: Rate<f64> = rate.parse().unwrap();
+            rate_record.insert(rate.into());
+        }
+        assert_eq!(rate_record.len(), 2);
+
+        let mut row = FlatTableRow::<marker::En>::new();
+        insert_rate_record_into_row(&mut row, FlatTableKey::WITHDRAWAL_RATE, &rate_record);
+        let columns: Vec<(&str, String)> =
+            row.iter().map(|(key, value)| (key.as_ref(), value.to_string())).collect();
+        assert_eq!(columns, [
+            ("withdrawal rate l/d", "\"2000 l\"".to_string()),
+            ("withdrawal rate m³/d", "\"2 m³\"".to_string())
+        ]);
+    }
+
+    #[test]
+    fn repeated_rates_share_their_column() {
+        let mut rate_record = RateRecord::new();
+        for rate in ["2500 m³/a", "1500 m³/a"] {
+            let rate: Rate<f64> = rate.parse().unwrap();
+            rate_record.insert(rate.into());
+        }
+
+        let mut row = FlatTableRow::<marker::En>::new();
+        insert_rate_record_into_row(&mut row, FlatTableKey::WITHDRAWAL_RATE, &rate_record);
+        let columns: Vec<(&str, String)> =
+            row.iter().map(|(key, value)| (key.as_ref(), value.to_string())).collect();
+        assert_eq!(columns, [(
+            "withdrawal rate m³/a",
+            "\"1500 m³, 2500 m³\"".to_string()
+        )]);
+    }
+}
@@ -1,6 +1,7 @@
 use std::fmt::{Display, Formatter};
 
 use itertools::Itertools;
+use serde::{Serialize, Serializer};
 
 pub enum FlatTableValue {
     String(String),
@@ -59,3 +60,20 @@ impl Display for FlatTableValue {
         }
     }
 }
+
+/// Values keep their type, numbers become JSON numbers, unlike the CSV cells of
+/// [`Display`].
+impl Serialize for FlatTableValue {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer
+    {
+        match self {
+            FlatTableValue::String(s) => serializer.serialize_str(s),
+            FlatTableValue::I64(i) => serializer.serialize_i64(*i),
+            FlatTableValue::U64(u) => serializer.serialize_u64(*u),
+            FlatTableValue::F64(f) => serializer.serialize_f64(*f),
+            FlatTableValue::Bool(b) => serializer.serialize_bool(*b)
+        }
+    }
+}
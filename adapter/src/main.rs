@@ -1,75 +1,359 @@
-use std::fs;
+use std::collections::{BTreeMap, BTreeSet};
+use std::ffi::OsString;
+use std::fmt::Write as _;
 use std::fs::File;
 use std::io::Write;
+use std::num::NonZeroUsize;
 use std::path::{Path, PathBuf};
 use std::sync::atomic::{AtomicUsize, Ordering};
+use std::{env, fs, io, process};
 
 use args::{Args, Format, Lang};
 use clap::Parser;
 use indicatif::ProgressBar;
 use lazy_static::lazy_static;
-use nlwkn::cli::{PROGRESS_STYLE, PROGRESS_UPDATE_INTERVAL, SPINNER_STYLE};
-use nlwkn::WaterRight;
+use nlwkn::cli::{self, PROGRESS_STYLE, PROGRESS_UPDATE_INTERVAL, SPINNER_STYLE};
+use nlwkn::data_dictionary::full_schema_water_rights;
+use nlwkn::disk_space::{check_free_space, estimate_output_size, SpaceCheck};
+use nlwkn::{WaterRight, WaterRightNo};
 
-use crate::flat_table::{FlatTable, Progress};
+use crate::flat_table::{FlatTable, FlatTableKey, Progress, RowIndex, SortCoverage};
 
 mod args;
 mod flat_table;
+mod units;
 
 lazy_static! {
     static ref PROGRESS: ProgressBar = ProgressBar::new_spinner();
 }
 
 fn main() {
+    if cli::print_completions::<Args, _, _>(
+        env!("CARGO_BIN_NAME"),
+        env::args_os(),
+        &mut io::stdout()
+    ) {
+        return;
+    }
+
     let Args {
         reports_json,
-        lang,
+        header_lang,
         format,
-        out
+        out,
+        water_right_no,
+        dictionary,
+        bilingual,
+        schema_hash,
+        index,
+        only_missing,
+        split_by_department,
+        exclude_coords,
+        normalize_units,
+        min_free_bytes,
+        include_pdf_only,
+        tag_source,
+        sample,
+        wkt,
+        full_schema,
+        from_csv,
+        sort_order_coverage
     } = Args::parse();
 
+    if sort_order_coverage {
+        print_sort_order_coverage();
+        return;
+    }
+    let reports_json = reports_json.expect("required unless --sort-order-coverage");
+
+    let csv_only_flag = match (index.is_some(), split_by_department) {
+        (true, _) => Some("--index"),
+        (_, true) => Some("--split-by-department"),
+        (false, false) => None
+    };
+    if let (Some(flag), false) = (csv_only_flag, format == Format::Csv) {
+        eprintln!(
+            "{} {flag} is only available for the csv format",
+            console::style("Error").red()
+        );
+        process::exit(1);
+    }
+
+    let flat_only_flag = match (sample.is_some(), wkt) {
+        (true, _) => Some("--sample"),
+        (_, true) => Some("--wkt"),
+        (false, false) => None
+    };
+    if let (Some(flag), Format::RatesTidy | Format::Units | Format::Reports | Format::Jsonl) =
+        (flat_only_flag, format)
+    {
+        eprintln!(
+            "{} {flag} is only available for the csv, json-table and geojson formats",
+            console::style("Error").red()
+        );
+        process::exit(1);
+    }
+
+    if full_schema && water_right_no.is_none() && !matches!(format, Format::Reports | Format::Jsonl)
+    {
+        eprintln!(
+            "{} --full-schema is only available for the reports and jsonl formats",
+            console::style("Error").red()
+        );
+        process::exit(1);
+    }
+
+    if let (Some(_), Format::Reports | Format::Jsonl) = (&schema_hash, format) {
+        eprintln!(
+            "{} --schema-hash is not available for the reports and jsonl formats, they have no \
+             columns",
+            console::style("Error").red()
+        );
+        process::exit(1);
+    }
+
     PROGRESS.enable_steady_tick(PROGRESS_UPDATE_INTERVAL);
 
-    PROGRESS.set_style(SPINNER_STYLE.clone());
-    PROGRESS.set_message("Reading reports file...");
-    let report_json_content =
-        fs::read_to_string(&reports_json).expect("could not read reports json");
+    let out = match (out, water_right_no) {
+        (Some(out), _) => out,
+        (None, Some(no)) => construct_out_path(reports_json.as_path(), &format!("{no}.json")),
+        (None, None) => construct_out_path(reports_json.as_path(), &format.to_string())
+    };
+
+    let mut input_size = fs::metadata(&reports_json).map(|m| m.len()).unwrap_or_default();
+    if include_pdf_only {
+        input_size +=
+            fs::metadata(pdf_only_path(&reports_json)).map(|m| m.len()).unwrap_or_default();
+    }
+    let out_dir = out.parent().filter(|dir| !dir.as_os_str().is_empty()).unwrap_or(Path::new("."));
+    match check_free_space(out_dir, estimate_output_size(input_size), min_free_bytes) {
+        Ok(SpaceCheck::Sufficient) => (),
+        Ok(check) => {
+            PROGRESS.finish_and_clear();
+            eprintln!("{} {check}", console::style("Error").red());
+            process::exit(1);
+        }
+        Err(err) => PROGRESS.println(format!(
+            "{} could not check free disk space, {err}",
+            console::style("Warning").yellow()
+        ))
+    }
 
-    let out = match out {
-        Some(out) => out,
-        None => construct_out_path(reports_json.as_path(), format)
+    PROGRESS.set_style(SPINNER_STYLE.clone());
+    PROGRESS.set_message("Loading reports...");
+    let mut water_rights = match (from_csv, header_lang) {
+        (false, _) => nlwkn::load_water_rights(&reports_json).expect("could not load reports json"),
+        (true, Lang::En) => read_csv_water_rights::<flat_table::marker::En>(&reports_json),
+        (true, Lang::De) => read_csv_water_rights::<flat_table::marker::De>(&reports_json)
     };
+    let mut pdf_only = BTreeSet::new();
+    if include_pdf_only {
+        let pdf_only_water_rights = nlwkn::load_water_rights(pdf_only_path(&reports_json))
+            .expect("could not load pdf-only reports json");
+        pdf_only.extend(pdf_only_water_rights.iter().map(|water_right| water_right.no));
+        water_rights.extend(pdf_only_water_rights);
+    }
+    let sources = tag_source.then_some(&pdf_only);
+
+    if let Some(only_missing) = only_missing {
+        let indexed = fs::read_to_string(&only_missing)
+            .map_err(anyhow::Error::from)
+            .and_then(|csv| indexed_water_rights(&csv));
+        let indexed = match indexed {
+            Ok(indexed) => indexed,
+            Err(err) => {
+                PROGRESS.finish_and_clear();
+                eprintln!(
+                    "{} could not read index {}, {err}",
+                    console::style("Error").red(),
+                    only_missing.display()
+                );
+                process::exit(1);
+            }
+        };
+
+        let total = water_rights.len();
+        water_rights.retain(|water_right| !indexed.contains(&water_right.no));
+        PROGRESS.println(format!(
+            "{} {} water rights already in the index",
+            console::style("Skipped").yellow(),
+            total - water_rights.len()
+        ));
+    }
+
+    if exclude_coords {
+        let affected: usize = water_rights.iter_mut().map(WaterRight::exclude_coordinates).sum();
+        PROGRESS.println(format!(
+            "{} coordinates of {affected} usage locations",
+            console::style("Excluded").yellow()
+        ));
+    }
+
+    if normalize_units {
+        let converted: usize = water_rights.iter_mut().map(units::normalize_rates).sum();
+        PROGRESS.println(format!(
+            "{} {converted} rates to {}/{}",
+            console::style("Normalized").yellow(),
+            units::CANONICAL_MEASUREMENT,
+            units::CANONICAL_PER
+        ));
+    }
+
+    if let Some(no) = water_right_no {
+        let water_right = match extract_water_right(water_rights, no) {
+            Ok(water_right) => water_right,
+            Err(err) => {
+                PROGRESS.finish_and_clear();
+                eprintln!("{} {err}", console::style("Error").red());
+                process::exit(1);
+            }
+        };
+        let json = match full_schema {
+            false => serde_json::to_string_pretty(&water_right),
+            true => full_schema_water_rights(std::slice::from_ref(&water_right))
+                .and_then(|water_rights| serde_json::to_string_pretty(&water_rights[0]))
+        }
+        .expect("could not serialize");
+        fs::write(&out, json).expect("could not write to out file");
+
+        PROGRESS.finish_and_clear();
+        println!(
+            "{} {no} {} {}",
+            console::style("Written water right").magenta(),
+            console::style("to").magenta(),
+            console::style(out.display()).green()
+        );
+        return;
+    }
 
-    PROGRESS.set_message("Parsing reports...");
-    let water_rights: Vec<WaterRight> =
-        serde_json::from_str(&report_json_content).expect("could not parse reports json");
+    if split_by_department {
+        let atomic_counter = AtomicUsize::default();
+        let csvs = match header_lang {
+            Lang::En => csv_by_department::<flat_table::marker::En>(
+                &water_rights,
+                sources,
+                sample,
+                wkt,
+                &atomic_counter
+            ),
+            Lang::De => csv_by_department::<flat_table::marker::De>(
+                &water_rights,
+                sources,
+                sample,
+                wkt,
+                &atomic_counter
+            )
+        };
+
+        PROGRESS.set_style(SPINNER_STYLE.clone());
+        PROGRESS.set_message("Saving results...");
+        let mut paths = Vec::with_capacity(csvs.len());
+        for (department, csv) in csvs {
+            let path = department_path(&out, &department);
+            fs::write(&path, csv).expect("could not write to out file");
+            paths.push(path);
+        }
+
+        PROGRESS.finish_and_clear();
+        for path in paths {
+            println!(
+                "{} {}",
+                console::style("Written results to").magenta(),
+                console::style(path.display()).green()
+            );
+        }
+        return;
+    }
 
     let mut out_file = File::create(&out).expect("could not create output file");
     let mut out_string = String::new();
 
     let atomic_counter = AtomicUsize::default();
-    match (format, lang) {
-        (Format::Csv, Lang::En) => {
-            let flat_table: FlatTable<flat_table::marker::En> =
-                flat_table::FlatTable::from_water_rights_with_notifier(
-                    water_rights.as_slice(),
-                    flatten_notifier(&atomic_counter, water_rights.len())
-                );
-            flat_table
-                .fmt_csv(&mut out_string, csv_notifier(&atomic_counter))
-                .expect("could not format csv");
-        }
-        (Format::Csv, Lang::De) => {
-            let flat_table: FlatTable<flat_table::marker::De> =
-                flat_table::FlatTable::from_water_rights_with_notifier(
-                    water_rights.as_slice(),
-                    flatten_notifier(&atomic_counter, water_rights.len())
-                );
-            flat_table
-                .fmt_csv(&mut out_string, csv_notifier(&atomic_counter))
-                .expect("could not format csv");
+    let (columns_hash, row_index) = match (format, header_lang) {
+        (Format::Csv, Lang::En) => write_csv::<flat_table::marker::En>(
+            &water_rights,
+            sources,
+            sample,
+            wkt,
+            &mut out_string,
+            &atomic_counter
+        ),
+        (Format::Csv, Lang::De) => write_csv::<flat_table::marker::De>(
+            &water_rights,
+            sources,
+            sample,
+            wkt,
+            &mut out_string,
+            &atomic_counter
+        ),
+        (Format::RatesTidy, Lang::En) => (
+            write_rates_tidy::<flat_table::marker::En>(&water_rights, &mut out_string),
+            RowIndex::new()
+        ),
+        (Format::RatesTidy, Lang::De) => (
+            write_rates_tidy::<flat_table::marker::De>(&water_rights, &mut out_string),
+            RowIndex::new()
+        ),
+        (Format::Units, _) => (write_units(&water_rights, &mut out_string), RowIndex::new()),
+        (Format::Reports, _) => {
+            out_string = match full_schema {
+                false => serde_json::to_string(&water_rights),
+                true => full_schema_water_rights(&water_rights)
+                    .and_then(|water_rights| serde_json::to_string(&water_rights))
+            }
+            .expect("could not serialize");
+            // no columns, rejected together with `--schema-hash` above
+            (0, RowIndex::new())
         }
-    }
+        (Format::Jsonl, _) => {
+            write_jsonl(&water_rights, full_schema, &mut out_string);
+            (0, RowIndex::new())
+        }
+        (Format::JsonTable, Lang::En) => (
+            write_json_table::<flat_table::marker::En>(
+                &water_rights,
+                sources,
+                sample,
+                wkt,
+                &mut out_string,
+                &atomic_counter
+            ),
+            RowIndex::new()
+        ),
+        (Format::JsonTable, Lang::De) => (
+            write_json_table::<flat_table::marker::De>(
+                &water_rights,
+                sources,
+                sample,
+                wkt,
+                &mut out_string,
+                &atomic_counter
+            ),
+            RowIndex::new()
+        ),
+        (Format::GeoJson, Lang::En) => (
+            write_geojson::<flat_table::marker::En>(
+                &water_rights,
+                sources,
+                sample,
+                wkt,
+                &mut out_string,
+                &atomic_counter
+            ),
+            RowIndex::new()
+        ),
+        (Format::GeoJson, Lang::De) => (
+            write_geojson::<flat_table::marker::De>(
+                &water_rights,
+                sources,
+                sample,
+                wkt,
+                &mut out_string,
+                &atomic_counter
+            ),
+            RowIndex::new()
+        )
+    };
 
     PROGRESS.set_style(SPINNER_STYLE.clone());
     PROGRESS.set_message("Saving results...");
@@ -81,22 +365,409 @@ fn main() {
         console::style("Written results to").magenta(),
         console::style(out.display()).green()
     );
+
+    if let Some(schema_hash) = schema_hash {
+        fs::write(&schema_hash, format!("{columns_hash:016x}\n"))
+            .expect("could not write schema hash");
+        println!(
+            "{} {}",
+            console::style("Written schema hash to").magenta(),
+            console::style(schema_hash.display()).green()
+        );
+    }
+
+    if let Some(index) = index {
+        fs::write(&index, fmt_row_index(&row_index)).expect("could not write index");
+        println!(
+            "{} {}",
+            console::style("Written index to").magenta(),
+            console::style(index.display()).green()
+        );
+    }
+
+    if let Some(dictionary) = dictionary {
+        write_dictionary(&dictionary);
+        println!(
+            "{} {}",
+            console::style("Written data dictionary to").magenta(),
+            console::style(dictionary.display()).green()
+        );
+    }
+
+    if let Some(bilingual) = bilingual {
+        write_bilingual(&bilingual, &water_rights);
+        println!(
+            "{} {}",
+            console::style("Written bilingual water rights to").magenta(),
+            console::style(bilingual.display()).green()
+        );
+    }
+}
+
+/// Rebuild the water rights from a CSV written by the adapter, the values of
+/// the columns that are no field of them are written next to the CSV, see
+/// [`UnknownColumns`](flat_table::UnknownColumns).
+fn read_csv_water_rights<M>(path: &Path) -> Vec<WaterRight>
+where
+    FlatTableKey<M>: AsRef<str>,
+    M: Send + Sync
+{
+    let water_rights = fs::read_to_string(path)
+        .map_err(anyhow::Error::from)
+        .and_then(|csv| FlatTable::<M>::from_csv(&csv))
+        .and_then(|flat_table| flat_table.to_water_rights());
+    let (water_rights, unknown_columns) = match water_rights {
+        Ok(water_rights) => water_rights,
+        Err(err) => {
+            PROGRESS.finish_and_clear();
+            eprintln!(
+                "{} could not read csv {}, {err}",
+                console::style("Error").red(),
+                path.display()
+            );
+            process::exit(1);
+        }
+    };
+
+    let columns: BTreeSet<&str> =
+        unknown_columns.values().flat_map(BTreeMap::keys).map(String::as_str).collect();
+    if !columns.is_empty() {
+        let unknown_columns_path = construct_out_path(path, "unknown-columns.json");
+        let content =
+            serde_json::to_string_pretty(&unknown_columns).expect("could not serialize columns");
+        fs::write(&unknown_columns_path, content).expect("could not write unknown columns");
+        PROGRESS.println(format!(
+            "{} columns {columns:?}, they are no field of the water rights, their values are in {}",
+            console::style("Ignored").yellow(),
+            unknown_columns_path.display()
+        ));
+    }
+
+    water_rights
+}
+
+/// Write the water rights with one per line into `out_string`, with
+/// `full_schema` every field is written, also the empty ones.
+fn write_jsonl(water_rights: &[WaterRight], full_schema: bool, out_string: &mut String) {
+    if !full_schema {
+        for water_right in water_rights {
+            out_string.push_str(&serde_json::to_string(water_right).expect("could not serialize"));
+            out_string.push('\n');
+        }
+        return;
+    }
+
+    let lines = match full_schema_water_rights(water_rights).expect("could not serialize") {
+        serde_json::Value::Array(water_rights) => water_rights,
+        _ => unreachable!("water rights are serialized as array")
+    };
+    for line in lines {
+        out_string.push_str(&serde_json::to_string(&line).expect("could not serialize"));
+        out_string.push('\n');
+    }
+}
+
+/// Write the water rights with german labels to `path`, see
+/// [`bilingual_water_rights`](nlwkn::data_dictionary::bilingual_water_rights).
+fn write_bilingual(path: &Path, water_rights: &[WaterRight]) {
+    let bilingual = nlwkn::data_dictionary::bilingual_water_rights(water_rights)
+        .expect("could not label water rights");
+    let content = serde_json::to_string(&bilingual).expect("could not serialize water rights");
+    fs::write(path, content).expect("could not write bilingual water rights");
+}
+
+/// Print the [`SortCoverage`] of every column key, exits with an error if a
+/// key is missing in the sort order.
+fn print_sort_order_coverage() {
+    let coverage = FlatTableKey::sort_order_coverage();
+    for (name, key, sort_coverage) in coverage.iter() {
+        let position = match sort_coverage {
+            SortCoverage::Sorted(index) => console::style(index.to_string()).green(),
+            SortCoverage::Unsorted => console::style("unsorted".to_string()).yellow(),
+            SortCoverage::Missing => console::style("missing".to_string()).red()
+        };
+        println!("{name:<30} {:<32} {position}", key.ref_en());
+    }
+
+    let missing = coverage
+        .iter()
+        .filter(|(.., sort_coverage)| *sort_coverage == SortCoverage::Missing)
+        .count();
+    if missing > 0 {
+        eprintln!(
+            "{} {missing} keys have no position in the sort order",
+            console::style("Error").red()
+        );
+        process::exit(1);
+    }
+}
+
+/// Write the data dictionary to `path`, as JSON if the extension is `json`,
+/// otherwise as CSV.
+fn write_dictionary(path: &Path) {
+    let dictionary = nlwkn::data_dictionary::data_dictionary();
+    let content = match path.extension().and_then(|ext| ext.to_str()) {
+        Some("json") => {
+            serde_json::to_string_pretty(&dictionary).expect("could not serialize dictionary")
+        }
+        _ => {
+            let quote = |s: &str| format!("\"{}\"", s.replace('"', "\"\""));
+            let mut csv = String::from("dataType;field;label;description\n");
+            for entry in dictionary {
+                writeln!(
+                    csv,
+                    "{};{};{};{}",
+                    quote(entry.data_type),
+                    quote(&entry.field),
+                    entry.label.as_deref().map(quote).unwrap_or_default(),
+                    entry.description.as_deref().map(quote).unwrap_or_default()
+                )
+                .expect("never fails on string");
+            }
+            csv
+        }
+    };
+
+    fs::write(path, content).expect("could not write data dictionary");
+}
+
+/// Path of the CSV of a legal department, `reports.csv` becomes
+/// `reports.A.csv`.
+fn department_path(out: &Path, department: &str) -> PathBuf {
+    let department = match department {
+        "?" => "unknown",
+        department => department
+    };
+
+    let mut file_name = out.file_stem().unwrap_or_default().to_owned();
+    file_name.push(".");
+    file_name.push(department);
+    if let Some(extension) = out.extension() {
+        file_name.push(".");
+        file_name.push(extension);
+    }
+    out.with_file_name(file_name)
+}
+
+/// Path of the pdf-only reports written by the parser next to
+/// `reports_json_path`.
+fn pdf_only_path(reports_json_path: &Path) -> PathBuf {
+    let mut file_name = OsString::from("pdf-only-");
+    file_name.push(reports_json_path.file_name().expect("`report_json` is no file path"));
+    reports_json_path.with_file_name(file_name)
+}
+
+/// Format the index of the water right rows as CSV.
+fn fmt_row_index(row_index: &RowIndex) -> String {
+    let mut csv = String::from("no;offset;firstRow;lastRow\n");
+    for (no, span) in row_index {
+        writeln!(
+            csv,
+            "{no};{};{};{}",
+            span.offset, span.first_row, span.last_row
+        )
+        .expect("never fails on string");
+    }
+    csv
+}
+
+/// Water right numbers of an index written by [`fmt_row_index`].
+fn indexed_water_rights(csv: &str) -> anyhow::Result<BTreeSet<WaterRightNo>> {
+    csv.lines()
+        .skip(1)
+        .filter(|line| !line.is_empty())
+        .map(|line| {
+            let no = line.split(';').next().unwrap_or_default();
+            no.parse().map_err(|_| anyhow::Error::msg(format!("invalid water right no {no:?}")))
+        })
+        .collect()
+}
+
+/// Write the water rights as CSV into `out_string`, returns the schema hash of
+/// the columns and the index of the rows.
+///
+/// With `pdf_only` every row is tagged with its source, water rights in it
+/// come from the pdf-only reports, all others from the reports.
+///
+/// The marker `M` only selects the language of the header, the values are the
+/// same for every marker.
+fn write_csv<M>(
+    water_rights: &[WaterRight],
+    pdf_only: Option<&BTreeSet<WaterRightNo>>,
+    sample: Option<NonZeroUsize>,
+    wkt: bool,
+    out_string: &mut String,
+    atomic_counter: &AtomicUsize
+) -> (u64, RowIndex)
+where
+    FlatTableKey<M>: AsRef<str>,
+    M: Send + Sync
+{
+    let flat_table: FlatTable<M> = flatten(water_rights, pdf_only, sample, wkt, atomic_counter);
+    let row_index =
+        flat_table.fmt_csv(out_string, csv_notifier(atomic_counter)).expect("could not format csv");
+    (flat_table.schema_hash(), row_index)
+}
+
+/// Write the water rights as JSON table into `out_string`, returns the schema
+/// hash of the columns.
+fn write_json_table<M>(
+    water_rights: &[WaterRight],
+    pdf_only: Option<&BTreeSet<WaterRightNo>>,
+    sample: Option<NonZeroUsize>,
+    wkt: bool,
+    out_string: &mut String,
+    atomic_counter: &AtomicUsize
+) -> u64
+where
+    FlatTableKey<M>: AsRef<str>,
+    M: Send + Sync
+{
+    let flat_table: FlatTable<M> = flatten(water_rights, pdf_only, sample, wkt, atomic_counter);
+    PROGRESS.set_style(SPINNER_STYLE.clone());
+    PROGRESS.set_message("Formatting JSON...");
+    flat_table.fmt_json(out_string).expect("could not format json");
+    flat_table.schema_hash()
+}
+
+/// Write the usage locations as GeoJSON into `out_string`, returns the schema
+/// hash of the properties.
+fn write_geojson<M>(
+    water_rights: &[WaterRight],
+    pdf_only: Option<&BTreeSet<WaterRightNo>>,
+    sample: Option<NonZeroUsize>,
+    wkt: bool,
+    out_string: &mut String,
+    atomic_counter: &AtomicUsize
+) -> u64
+where
+    FlatTableKey<M>: AsRef<str>,
+    M: Send + Sync
+{
+    let flat_table: FlatTable<M> = flatten(water_rights, pdf_only, sample, wkt, atomic_counter);
+    PROGRESS.set_style(SPINNER_STYLE.clone());
+    PROGRESS.set_message("Formatting GeoJSON...");
+    flat_table.fmt_geojson(out_string).expect("could not format geojson");
+    flat_table.schema_hash()
+}
+
+/// Format one CSV per legal department, keyed by the department abbreviation.
+///
+/// Every CSV only has the columns used by the rows of its department.
+fn csv_by_department<M>(
+    water_rights: &[WaterRight],
+    pdf_only: Option<&BTreeSet<WaterRightNo>>,
+    sample: Option<NonZeroUsize>,
+    wkt: bool,
+    atomic_counter: &AtomicUsize
+) -> BTreeMap<String, String>
+where
+    FlatTableKey<M>: AsRef<str>,
+    M: Send + Sync
+{
+    let flat_table: FlatTable<M> = flatten(water_rights, pdf_only, sample, wkt, atomic_counter);
+    let notifier = csv_notifier(atomic_counter);
+    flat_table
+        .split_by(FlatTableKey::LEGAL_DEPARTMENT_ABBREVIATION)
+        .into_iter()
+        .map(|(department, part)| {
+            let mut csv = String::new();
+            part.fmt_csv(&mut csv, &notifier).expect("could not format csv");
+            (department, csv)
+        })
+        .collect()
+}
+
+/// Flatten the water rights, with `pdf_only` every row is tagged with its
+/// source, with `sample` only every Nth row is kept and with `wkt` the
+/// coordinates are added as geometry.
+fn flatten<M>(
+    water_rights: &[WaterRight],
+    pdf_only: Option<&BTreeSet<WaterRightNo>>,
+    sample: Option<NonZeroUsize>,
+    wkt: bool,
+    atomic_counter: &AtomicUsize
+) -> FlatTable<M>
+where
+    FlatTableKey<M>: AsRef<str>,
+    M: Send + Sync
+{
+    let mut flat_table: FlatTable<M> = FlatTable::from_water_rights_with_notifier(
+        water_rights,
+        flatten_notifier(atomic_counter, water_rights.len())
+    );
+    if let Some(pdf_only) = pdf_only {
+        flat_table.tag_rows(FlatTableKey::SOURCE, |no| {
+            Some(match pdf_only.contains(&no) {
+                true => "pdf-only".to_string(),
+                false => "reports".to_string()
+            })
+        });
+    }
+    if wkt {
+        flat_table.add_wkt_geometry();
+    }
+    if let Some(sample) = sample {
+        let total = flat_table.row_count();
+        flat_table.sample(sample);
+        PROGRESS.println(format!(
+            "{} 1 of every {sample} rows, kept {} of {total} rows",
+            console::style("Sampled").yellow(),
+            flat_table.row_count()
+        ));
+    }
+    flat_table
+}
+
+/// Write the rates in long form into `out_string`, returns the schema hash of
+/// the columns.
+fn write_rates_tidy<M>(water_rights: &[WaterRight], out_string: &mut String) -> u64
+where
+    FlatTableKey<M>: AsRef<str>
+{
+    flat_table::fmt_rates_tidy::<M, _>(water_rights, out_string).expect("could not format csv");
+    flat_table::schema_hash(
+        flat_table::RATES_TIDY_HEADER
+            .iter()
+            .map(|key| FlatTableKey::<M>::from_unselect_ref(key).as_ref())
+    )
+}
+
+/// Write the distinct units into `out_string`, returns the schema hash of the
+/// columns.
+///
+/// The header is always English, as the columns have no counterpart in the
+/// reports.
+fn write_units(water_rights: &[WaterRight], out_string: &mut String) -> u64 {
+    units::fmt_unit_vocabulary(water_rights, out_string).expect("could not format csv");
+    flat_table::schema_hash(units::UNIT_VOCABULARY_HEADER)
 }
 
-fn construct_out_path(reports_json_path: &Path, format: Format) -> PathBuf {
+/// Take the water right with the number `no` out of `water_rights`.
+fn extract_water_right(
+    water_rights: Vec<WaterRight>,
+    no: WaterRightNo
+) -> anyhow::Result<WaterRight> {
+    water_rights
+        .into_iter()
+        .find(|water_right| water_right.no == no)
+        .ok_or_else(|| anyhow::Error::msg(format!("water right {no} is not in the reports")))
+}
+
+fn construct_out_path(reports_json_path: &Path, extension: &str) -> PathBuf {
     match (reports_json_path.parent(), reports_json_path.file_stem()) {
         (Some(parent), Some(file_stem)) => {
             let mut path_buf = PathBuf::from(parent);
             let mut file_name = file_stem.to_owned();
             file_name.push(".");
-            file_name.push(format.to_string());
+            file_name.push(extension);
             path_buf.push(file_name);
             path_buf
         }
         (None, Some(file_stem)) => {
             let mut file_name = file_stem.to_owned();
             file_name.push(".");
-            file_name.push(format.to_string());
+            file_name.push(extension);
             PathBuf::from(file_name)
         }
         (_, None) => panic!("`report_json` is no file path")
@@ -141,3 +812,139 @@ fn csv_notifier(atomic_counter: &AtomicUsize) -> impl Fn() + '_ {
 
     || PROGRESS.set_position(atomic_counter.fetch_add(1, Ordering::Relaxed) as u64)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const REPORTS_JSON_PATH: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/test/reports.json");
+
+    #[test]
+    fn pdf_only_rows_are_included() {
+        let reports_json = Path::new(REPORTS_JSON_PATH);
+        assert_eq!(
+            pdf_only_path(reports_json),
+            reports_json.with_file_name("pdf-only-reports.json")
+        );
+
+        let mut water_rights = nlwkn::load_water_rights(reports_json).unwrap();
+        let pdf_only_water_rights = nlwkn::load_water_rights(pdf_only_path(reports_json)).unwrap();
+        let pdf_only = BTreeSet::from_iter(pdf_only_water_rights.iter().map(|wr| wr.no));
+        water_rights.extend(pdf_only_water_rights);
+
+        let mut csv = String::new();
+        write_csv::<flat_table::marker::En>(
+            &water_rights,
+            Some(&pdf_only),
+            None,
+            false,
+            &mut csv,
+            &AtomicUsize::default()
+        );
+        let mut lines = csv.lines();
+        assert!(lines.next().unwrap().contains(";source;"));
+        let rows: Vec<_> = lines.collect();
+        assert_eq!(rows.len(), 2);
+        assert!(rows[0].starts_with("1101;") && rows[0].contains("\"reports\""));
+        assert!(rows[1].starts_with("1102;") && rows[1].contains("\"pdf-only\""));
+    }
+
+    #[test]
+    fn row_index_points_at_records() {
+        let reports_json = Path::new(REPORTS_JSON_PATH);
+        let mut water_rights = nlwkn::load_water_rights(reports_json).unwrap();
+        water_rights.extend(nlwkn::load_water_rights(pdf_only_path(reports_json)).unwrap());
+
+        let mut csv = String::new();
+        let (_, row_index) = write_csv::<flat_table::marker::En>(
+            &water_rights,
+            None,
+            None,
+            false,
+            &mut csv,
+            &AtomicUsize::default()
+        );
+        assert_eq!(row_index.len(), 2);
+
+        let lines: Vec<_> = csv.lines().collect();
+        for (no, span) in row_index.iter() {
+            assert!(csv[span.offset..].starts_with(&format!("{no};")));
+            let rows = &lines[span.first_row..=span.last_row];
+            assert!(rows.iter().all(|row| row.starts_with(&format!("{no};"))));
+        }
+        assert_eq!(row_index[&1102].offset, lines[0].len() + lines[1].len() + 2);
+
+        let index_csv = fmt_row_index(&row_index);
+        assert_eq!(
+            index_csv.lines().nth(1),
+            Some(format!("1101;{};1;1", lines[0].len() + 1).as_str())
+        );
+        assert_eq!(
+            indexed_water_rights(&index_csv).unwrap(),
+            BTreeSet::from([1101, 1102])
+        );
+        assert!(indexed_water_rights("no;offset;firstRow;lastRow\nabc;0;1;1\n").is_err());
+    }
+
+    #[test]
+    fn split_by_department_prunes_columns() {
+        let reports_json = Path::new(REPORTS_JSON_PATH);
+        let mut water_rights = nlwkn::load_water_rights(reports_json).unwrap();
+        water_rights.extend(nlwkn::load_water_rights(pdf_only_path(reports_json)).unwrap());
+
+        let csvs = csv_by_department::<flat_table::marker::En>(
+            &water_rights,
+            None,
+            None,
+            false,
+            &AtomicUsize::default()
+        );
+        assert_eq!(csvs.keys().collect::<Vec<_>>(), ["A", "E"]);
+        assert_eq!(
+            department_path(Path::new("data/reports.csv"), "A"),
+            Path::new("data/reports.A.csv")
+        );
+
+        let mut full = String::new();
+        write_csv::<flat_table::marker::En>(
+            &water_rights,
+            None,
+            None,
+            false,
+            &mut full,
+            &AtomicUsize::default()
+        );
+        let mut full_lines = full.lines();
+        let full_header: Vec<_> = full_lines.next().unwrap().split(';').collect();
+        let full_rows: Vec<_> = full_lines.collect();
+
+        for (department, csv) in csvs.iter() {
+            let lines: Vec<_> = csv.lines().collect();
+            assert_eq!(lines.len(), 2, "department {department}");
+            assert!(lines[1].contains(&format!(";\"{department}\";")));
+
+            // exactly the columns with a value in the full table are kept
+            let no = lines[1].split(';').next().unwrap();
+            let full_row = full_rows.iter().find(|row| row.starts_with(&format!("{no};"))).unwrap();
+            let used_columns: Vec<_> = full_header
+                .iter()
+                .zip(full_row.split(';'))
+                .filter(|(_, value)| !value.is_empty())
+                .map(|(key, _)| *key)
+                .collect();
+            assert_eq!(lines[0].split(';').collect::<Vec<_>>(), used_columns);
+        }
+        assert!(csvs["E"].lines().next().unwrap().split(';').count() < full_header.len());
+    }
+
+    #[test]
+    fn extract_water_right_works() {
+        let water_rights = nlwkn::load_water_rights(REPORTS_JSON_PATH).unwrap();
+        let water_right = extract_water_right(water_rights, 1101).unwrap();
+        assert_eq!(water_right.holder.as_deref(), Some("Körtke"));
+
+        let water_rights = nlwkn::load_water_rights(REPORTS_JSON_PATH).unwrap();
+        let err = extract_water_right(water_rights, 1102).unwrap_err();
+        assert_eq!(err.to_string(), "water right 1102 is not in the reports");
+    }
+}
@@ -0,0 +1,294 @@
+use std::collections::BTreeMap;
+use std::fmt::{Display, Formatter, Write};
+
+use nlwkn::helper_types::{Duration, OrFallback, Quantity, Rate};
+use nlwkn::{RateRecord, UsageLocation, WaterRight};
+
+use crate::flat_table::FlatTableValue;
+
+/// Header of [`fmt_unit_vocabulary`].
+pub const UNIT_VOCABULARY_HEADER: [&str; 3] = ["kind", "unit", "count"];
+
+/// Measurement all rates are normalized to by [`normalize_rates`].
+pub const CANONICAL_MEASUREMENT: &str = "m³";
+
+/// Period all rates are normalized to by [`normalize_rates`].
+pub const CANONICAL_PER: Duration = Duration::Days(1.0);
+
+/// Rate measurements with a known conversion and their amount of
+/// [`CANONICAL_MEASUREMENT`].
+pub const MEASUREMENT_FACTORS: &[(&str, f64)] = &[
+    ("m³", 1.0),
+    ("m3", 1.0),
+    ("cbm", 1.0),
+    ("Tsd. m³", 1000.0),
+    ("hl", 0.1),
+    ("l", 0.001)
+];
+
+/// Where a unit string was found.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum UnitKind {
+    /// Measurement of a rate, e.g. `m³` of `m³/a`.
+    RateMeasurement,
+
+    /// Time dimension of a rate, e.g. `a` of `m³/a`.
+    RatePer,
+
+    /// Unit of a quantity like the irrigation area or dam targets.
+    Quantity,
+
+    /// Rates that could not be parsed, counted by everything after the value.
+    UnparsedRate
+}
+
+impl Display for UnitKind {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            UnitKind::RateMeasurement => write!(f, "rate measurement"),
+            UnitKind::RatePer => write!(f, "rate per"),
+            UnitKind::Quantity => write!(f, "quantity"),
+            UnitKind::UnparsedRate => write!(f, "unparsed rate")
+        }
+    }
+}
+
+/// Count the distinct unit strings of all rates and quantities.
+///
+/// The units come from the free text of the reports, so this helps building
+/// conversion tables and spotting misspelled units.
+pub fn unit_vocabulary(water_rights: &[WaterRight]) -> BTreeMap<(UnitKind, String), usize> {
+    let mut vocabulary = BTreeMap::new();
+    let mut count =
+        |kind: UnitKind, unit: String| *vocabulary.entry((kind, unit)).or_insert(0) += 1;
+
+    let usage_locations = water_rights
+        .iter()
+        .flat_map(|water_right| water_right.legal_departments.values())
+        .flat_map(|department| department.usage_locations.iter());
+    for usage_location in usage_locations {
+        for rate in rate_records(usage_location).into_iter().flatten() {
+            match rate {
+                OrFallback::Expected(rate) => {
+                    count(UnitKind::RateMeasurement, rate.unit.clone());
+                    count(UnitKind::RatePer, rate.per.to_string());
+                }
+                OrFallback::Fallback(rate) => {
+                    let unit = rate.split_once(' ').map(|(_, unit)| unit).unwrap_or_default();
+                    count(UnitKind::UnparsedRate, unit.to_string());
+                }
+            }
+        }
+
+        for quantity in quantities(usage_location) {
+            count(UnitKind::Quantity, quantity.unit.clone());
+        }
+    }
+
+    vocabulary
+}
+
+/// Format the [`unit_vocabulary`] as CSV.
+pub fn fmt_unit_vocabulary<W>(water_rights: &[WaterRight], w: &mut W) -> std::fmt::Result
+where
+    W: Write
+{
+    writeln!(w, "{}", UNIT_VOCABULARY_HEADER.join(";"))?;
+    for ((kind, unit), count) in unit_vocabulary(water_rights) {
+        writeln!(
+            w,
+            "{};{};{count}",
+            FlatTableValue::String(kind.to_string()),
+            FlatTableValue::String(unit)
+        )?;
+    }
+
+    Ok(())
+}
+
+/// Rewrite the rates of every usage location to [`CANONICAL_MEASUREMENT`] per
+/// [`CANONICAL_PER`], returns the number of converted rates.
+///
+/// Rates with a measurement missing in [`MEASUREMENT_FACTORS`] and unparsed
+/// rates are kept as they are.
+/// Rates already canonical are kept next to the converted ones, a rate record
+/// may hold several rates with the same unit.
+pub fn normalize_rates(water_right: &mut WaterRight) -> usize {
+    let mut converted = 0;
+    for (_, usage_location) in water_right.usage_locations_mut() {
+        for rate_record in rate_records_mut(usage_location) {
+            *rate_record = std::mem::take(rate_record)
+                .into_iter()
+                .map(|rate| match &rate {
+                    OrFallback::Expected(expected) if !is_canonical(expected) => {
+                        match normalize_rate(expected) {
+                            Some(normalized) => {
+                                converted += 1;
+                                OrFallback::Expected(normalized)
+                            }
+                            None => rate
+                        }
+                    }
+                    _ => rate
+                })
+                .collect();
+        }
+    }
+
+    converted
+}
+
+/// Convert a rate to [`CANONICAL_MEASUREMENT`] per [`CANONICAL_PER`], `None`
+/// if its measurement has no known conversion.
+///
+/// Uses [`Duration::as_secs`], so periods of months and years are converted
+/// with 30 and 365 days.
+pub fn normalize_rate(rate: &Rate<f64>) -> Option<Rate<f64>> {
+    let (_, factor) = MEASUREMENT_FACTORS.iter().find(|(unit, _)| *unit == rate.unit)?;
+    let per_second = rate.value * factor / rate.per.as_secs();
+    Some(Rate {
+        value: per_second * CANONICAL_PER.as_secs(),
+        unit: CANONICAL_MEASUREMENT.to_string(),
+        per: CANONICAL_PER
+    })
+}
+
+fn is_canonical(rate: &Rate<f64>) -> bool {
+    rate.unit == CANONICAL_MEASUREMENT && rate.per == CANONICAL_PER
+}
+
+fn rate_records(usage_location: &UsageLocation) -> [&RateRecord; 6] {
+    [
+        &usage_location.withdrawal_rates,
+        &usage_location.pumping_rates,
+        &usage_location.injection_rates,
+        &usage_location.waste_water_flow_volume,
+        &usage_location.fluid_discharge,
+        &usage_location.rain_supplement
+    ]
+}
+
+fn rate_records_mut(usage_location: &mut UsageLocation) -> [&mut RateRecord; 6] {
+    [
+        &mut usage_location.withdrawal_rates,
+        &mut usage_location.pumping_rates,
+        &mut usage_location.injection_rates,
+        &mut usage_location.waste_water_flow_volume,
+        &mut usage_location.fluid_discharge,
+        &mut usage_location.rain_supplement
+    ]
+}
+
+fn quantities(usage_location: &UsageLocation) -> impl Iterator<Item = &Quantity> {
+    let dam_targets = &usage_location.dam_target_levels;
+    usage_location
+        .irrigation_area
+        .iter()
+        .chain(dam_targets.default.iter())
+        .chain(dam_targets.steady.iter())
+        .chain(dam_targets.max.iter())
+        .chain(dam_targets.other.iter().map(|(_, quantity)| quantity))
+        .chain(usage_location.injection_limits.iter().map(|(_, quantity)| quantity))
+}
+
+#[cfg(test)]
+mod tests {
+    use nlwkn::LegalDepartmentAbbreviation;
+
+    use super::*;
+
+    const REPORTS_JSON_PATH: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/test/reports.json");
+
+    #[test]
+    fn unit_vocabulary_counts_distinct_units() {
+        let mut water_rights = nlwkn::load_water_rights(REPORTS_JSON_PATH).unwrap();
+
+        let mut usage_location = UsageLocation::new();
+        let rate: Rate<f64> = "12 m³/h".parse().unwrap();
+        usage_location.pumping_rates.insert(rate.into());
+        usage_location.pumping_rates.insert(OrFallback::Fallback("3 m3/Tg".to_string()));
+        usage_location.irrigation_area = Some((20.0, "ha".to_string()).into());
+        usage_location
+            .injection_limits
+            .push(("Phosphor".to_string(), (2.0, "mg/l".to_string()).into()));
+        usage_location
+            .injection_limits
+            .push(("Stickstoff".to_string(), (4.0, "mg/l".to_string()).into()));
+        water_rights.push(
+            WaterRight::builder(1102)
+                .add_usage_location(LegalDepartmentAbbreviation::E, usage_location)
+                .build()
+        );
+
+        let vocabulary: Vec<_> = unit_vocabulary(&water_rights)
+            .into_iter()
+            .map(|((kind, unit), count)| (kind, unit, count))
+            .collect();
+        assert_eq!(vocabulary, [
+            (UnitKind::RateMeasurement, "m³".to_string(), 3),
+            (UnitKind::RatePer, "a".to_string(), 1),
+            (UnitKind::RatePer, "h".to_string(), 1),
+            (UnitKind::RatePer, "s".to_string(), 1),
+            (UnitKind::Quantity, "ha".to_string(), 1),
+            (UnitKind::Quantity, "mg/l".to_string(), 2),
+            (UnitKind::UnparsedRate, "m3/Tg".to_string(), 1)
+        ]);
+
+        let mut csv = String::new();
+        fmt_unit_vocabulary(&water_rights, &mut csv).unwrap();
+        assert!(csv.starts_with("kind;unit;count\n\"rate measurement\";\"m³\";3\n"));
+    }
+
+    #[test]
+    fn normalize_rates_works() {
+        let rate =
+            |rate: &str| -> OrFallback<Rate<f64>> { rate.parse::<Rate<f64>>().unwrap().into() };
+        let describe = |rate_record: &RateRecord| -> Vec<String> {
+            rate_record
+                .iter()
+                .map(|rate| match rate {
+                    OrFallback::Expected(rate) => {
+                        format!("{:.1} {}/{}", rate.value, rate.unit, rate.per)
+                    }
+                    OrFallback::Fallback(rate) => rate.clone()
+                })
+                .collect()
+        };
+
+        let mut usage_location = UsageLocation::new();
+        usage_location.withdrawal_rates.insert(rate("2 l/s"));
+        usage_location.withdrawal_rates.insert(rate("5 Ziegen/d"));
+        usage_location.withdrawal_rates.insert(OrFallback::Fallback("3 m3/Tg".to_string()));
+        usage_location.pumping_rates.insert(rate("100 m³/d"));
+        usage_location.pumping_rates.insert(rate("240 l/h"));
+        let mut water_right = WaterRight::builder(1102)
+            .add_usage_location(LegalDepartmentAbbreviation::E, usage_location)
+            .build();
+
+        assert_eq!(normalize_rates(&mut water_right), 2);
+        let (_, usage_location) = water_right.usage_locations().next().unwrap();
+        assert_eq!(describe(&usage_location.withdrawal_rates), [
+            "5.0 Ziegen/d",
+            "172.8 m³/d",
+            "3 m3/Tg"
+        ]);
+
+        // the canonical rate is kept next to the converted one
+        assert_eq!(describe(&usage_location.pumping_rates), [
+            "5.8 m³/d",
+            "100.0 m³/d"
+        ]);
+    }
+
+    #[test]
+    fn normalize_rate_leaves_unknown_units() {
+        let rate: Rate<f64> = "3 Ziegen/a".parse().unwrap();
+        assert!(normalize_rate(&rate).is_none());
+
+        let rate: Rate<f64> = "2 l/s".parse().unwrap();
+        let normalized = normalize_rate(&rate).unwrap();
+        assert_eq!(normalized.unit, "m³");
+        assert_eq!(normalized.per, Duration::Days(1.0));
+        assert!((normalized.value - 172.8).abs() < 1e-9);
+    }
+}
@@ -0,0 +1,51 @@
+//! Benchmarks flattening water rights into the rows of the adapter.
+//!
+//! The water right of `test/reports.json` is repeated under different numbers
+//! to get a representative amount of rows.
+
+use criterion::{criterion_group, criterion_main, Criterion};
+use nlwkn::WaterRight;
+
+#[allow(unused)]
+#[path = "../adapter/src/flat_table/mod.rs"]
+mod flat_table;
+
+use flat_table::{marker, FlatTable};
+
+const REPORTS_JSON_PATH: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/test/reports.json");
+const WATER_RIGHTS: u64 = 1000;
+
+fn water_rights() -> Vec<WaterRight> {
+    let json = std::fs::read_to_string(REPORTS_JSON_PATH).expect("fixture exists");
+    let reports: Vec<serde_json::Value> = serde_json::from_str(&json).expect("valid fixture");
+    let template = reports.into_iter().next().expect("fixture has a water right");
+
+    (0..WATER_RIGHTS)
+        .map(|no| {
+            let mut report = template.clone();
+            report["no"] = no.into();
+            serde_json::from_value(report).expect("valid water right")
+        })
+        .collect()
+}
+
+fn flatten(c: &mut Criterion) {
+    let water_rights = water_rights();
+
+    c.bench_function("flatten water rights", |b| {
+        b.iter(|| FlatTable::<marker::En>::from_water_rights_with_notifier(&water_rights, |_| ()))
+    });
+
+    let flat_table =
+        FlatTable::<marker::En>::from_water_rights_with_notifier(&water_rights, |_| ());
+    c.bench_function("format flat table csv", |b| {
+        b.iter(|| {
+            let mut csv = String::new();
+            flat_table.fmt_csv(&mut csv, || ()).expect("never fails on string");
+            csv
+        })
+    });
+}
+
+criterion_group!(benches, flatten);
+criterion_main!(benches);
@@ -0,0 +1,58 @@
+//! Benchmarks escaping values for the `COPY` statement of the exporter.
+//!
+//! The strings contain every character that needs escaping and are copied at
+//! the quotation depths of top level values, arrays and nested composites.
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+use indicatif::ProgressBar;
+use lazy_static::lazy_static;
+
+#[allow(unused)]
+#[path = "../exporter/src/export.rs"]
+mod export;
+#[allow(unused)]
+#[path = "../exporter/src/postgres_copy.rs"]
+mod postgres_copy;
+
+use postgres_copy::{IterPostgresCopy, PostgresCopy, PostgresCopyContext};
+
+lazy_static! {
+    // required by the `export` module
+    static ref PROGRESS: ProgressBar = ProgressBar::hidden();
+}
+
+const ANNOTATION: &str = "Entnahme aus dem \"Brunnen 1\" gemäß Bescheid vom 01.02.2003;\nAuflage \
+                          C:\\Auflagen\\3.pdf\r\nbeachten.";
+
+fn escaping(c: &mut Criterion) {
+    let mut group = c.benchmark_group("escape string");
+    for depth in 0..4 {
+        let ctx = PostgresCopyContext {
+            depth,
+            ..Default::default()
+        };
+        group.bench_with_input(BenchmarkId::from_parameter(depth), &ctx, |b, ctx| {
+            let mut buffer = Vec::with_capacity(1024);
+            b.iter(|| {
+                buffer.clear();
+                ANNOTATION.copy_to(&mut buffer, *ctx).expect("writing to vec never fails");
+            })
+        });
+    }
+    group.finish();
+
+    let annotations: Vec<String> = (0..16).map(|i| format!("{ANNOTATION} {i}")).collect();
+    c.bench_function("escape string array", |b| {
+        let mut buffer = Vec::with_capacity(16 * 1024);
+        b.iter(|| {
+            buffer.clear();
+            annotations
+                .iter()
+                .copy_to(&mut buffer, PostgresCopyContext::default())
+                .expect("writing to vec never fails");
+        })
+    });
+}
+
+criterion_group!(benches, escaping);
+criterion_main!(benches);
@@ -0,0 +1,128 @@
+//! Benchmarks extracting the key value pairs from a report.
+//!
+//! The report is generated in memory and resembles the layout of the Cadenza
+//! reports, keys are set in `F1` and values in `F2`.
+
+use criterion::{criterion_group, criterion_main, BatchSize, Criterion};
+use lopdf::content::{Content, Operation};
+use lopdf::{dictionary, Document, Object, Stream};
+
+#[allow(unused)]
+#[path = "../parser/src/intermediate/mod.rs"]
+mod intermediate;
+
+use intermediate::grouped_key_value::GroupedKeyValueRepr;
+use intermediate::key_value::KeyValueRepr;
+use intermediate::text_block::TextBlockRepr;
+
+const PAGES: usize = 8;
+const USAGE_LOCATIONS_PER_PAGE: usize = 4;
+
+const USAGE_LOCATION: &[(&str, &str)] = &[
+    ("Nutzungsort Lfd. Nr.:", "Brunnen 1 (aktiv, real)"),
+    ("Rechtszweck:", "E10 Beregnung"),
+    ("Landkreis:", "Gifhorn"),
+    ("Gemeindegebiet:", "Sassenburg"),
+    ("Gemarkung, Flur:", "Westerbeck, 3"),
+    ("Erlaubniswert:", "Entnahmemenge 1500 m³/a"),
+    ("UTM-Rechtswert:", "32603873"),
+    ("UTM-Hochwert:", "5852015")
+];
+
+fn text_block(font: &str, x: i64, y: i64, content: &str) -> [Operation; 4] {
+    [
+        Operation::new("BT", vec![]),
+        Operation::new("Tf", vec![Object::Name(font.into()), 9.into()]),
+        Operation::new("Tm", vec![
+            1.into(),
+            0.into(),
+            0.into(),
+            1.into(),
+            x.into(),
+            y.into(),
+        ]),
+        Operation::new("Tj", vec![Object::string_literal(content)])
+    ]
+}
+
+fn report() -> Document {
+    let mut document = Document::with_version("1.5");
+    let pages_id = document.new_object_id();
+
+    let mut kids = Vec::new();
+    for page in 0..PAGES {
+        let mut operations = Vec::new();
+        let mut y = 800;
+        if page == 0 {
+            operations.extend(text_block("F1", 50, y, "Wasserrecht Nr.:"));
+            operations.extend(text_block("F2", 200, y, "1101"));
+            operations.push(Operation::new("ET", vec![]));
+            y -= 12;
+            operations.extend(text_block("F1", 50, y, "Abteilung:"));
+            operations.extend(text_block("F2", 200, y, "E Entnahme von Grundwasser"));
+            operations.push(Operation::new("ET", vec![]));
+            y -= 12;
+        }
+
+        for _ in 0..USAGE_LOCATIONS_PER_PAGE {
+            for (key, value) in USAGE_LOCATION {
+                for (font, x, content) in [("F1", 50, key), ("F2", 200, value)] {
+                    operations.extend(text_block(font, x, y, content));
+                    operations.push(Operation::new("ET", vec![]));
+                }
+                y -= 12;
+            }
+        }
+
+        let content = Content { operations }.encode().expect("valid content");
+        let content_id = document.add_object(Stream::new(dictionary! {}, content));
+        kids.push(
+            document
+                .add_object(dictionary! {
+                    "Type" => "Page",
+                    "Parent" => pages_id,
+                    "Contents" => content_id
+                })
+                .into()
+        );
+    }
+
+    document.objects.insert(
+        pages_id,
+        Object::Dictionary(dictionary! {
+            "Type" => "Pages",
+            "Count" => PAGES as i64,
+            "Kids" => kids,
+            "MediaBox" => vec![0.into(), 0.into(), 595.into(), 842.into()]
+        })
+    );
+    let catalog_id = document.add_object(dictionary! {
+        "Type" => "Catalog",
+        "Pages" => pages_id
+    });
+    document.trailer.set("Root", catalog_id);
+    document
+}
+
+fn text_extraction(c: &mut Criterion) {
+    let document = report();
+
+    c.bench_function("text block extraction", |b| {
+        b.iter_batched(
+            || document.clone(),
+            |document| TextBlockRepr::try_from(document).expect("valid report"),
+            BatchSize::SmallInput
+        )
+    });
+
+    c.bench_function("key value grouping", |b| {
+        b.iter_batched(
+            || TextBlockRepr::try_from(document.clone()).expect("valid report"),
+            |text_block_repr| GroupedKeyValueRepr::from(KeyValueRepr::from(text_block_repr)),
+            BatchSize::SmallInput
+        )
+    });
+}
+
+criterion_group!(benches, text_extraction);
+criterion_main!(benches);
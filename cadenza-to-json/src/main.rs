@@ -0,0 +1,144 @@
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::{env, io};
+
+use clap::Parser;
+use nlwkn::cadenza::{CadenzaTable, CadenzaTableRow};
+use nlwkn::cli;
+
+/// NLWKN Cadenza Table to JSON Converter
+#[derive(Debug, Parser)]
+#[command(version, about)]
+struct Args {
+    /// Path to cadenza-provided xlsx file
+    xlsx_path: PathBuf,
+
+    /// Path of the written JSON file
+    out_path: PathBuf,
+
+    /// Only write these columns, separated by commas
+    ///
+    /// The columns are selected by their names in the table, like
+    /// `Wasserrecht Nr.` or `Rechtsinhaber`.
+    #[arg(long, value_delimiter = ',')]
+    columns: Option<Vec<String>>
+}
+
+fn main() -> anyhow::Result<()> {
+    if cli::print_completions::<Args, _, _>(
+        env!("CARGO_BIN_NAME"),
+        env::args_os(),
+        &mut io::stdout()
+    ) {
+        return Ok(());
+    }
+
+    let Args {
+        xlsx_path,
+        out_path,
+        columns
+    } = Args::parse();
+
+    let rows = cadenza_to_json(&xlsx_path, &out_path, columns.as_deref())?;
+    println!(
+        "{} {rows} rows to {}",
+        console::style("Written").magenta(),
+        console::style(out_path.display()).green()
+    );
+    Ok(())
+}
+
+/// Write the rows of the cadenza table as JSON array, the fields keep the
+/// column names of the table.
+/// If `columns` are given, only these are written.
+///
+/// Returns the number of written rows.
+fn cadenza_to_json(
+    xlsx_path: &Path,
+    out_path: &Path,
+    columns: Option<&[String]>
+) -> anyhow::Result<usize> {
+    let table = CadenzaTable::from_path(xlsx_path)?;
+    let selected = columns.map(|columns| select_columns(&table, columns)).transpose()?;
+    let mut writer = BufWriter::new(File::create(out_path)?);
+    match selected {
+        Some(selected) => serde_json::to_writer(&mut writer, &selected)?,
+        None => serde_json::to_writer(&mut writer, &table)?
+    }
+    writer.flush()?;
+    Ok(table.rows().len())
+}
+
+/// Keep only the `columns` of every row, unknown column names are an error.
+fn select_columns(
+    table: &CadenzaTable,
+    columns: &[String]
+) -> anyhow::Result<Vec<serde_json::Map<String, serde_json::Value>>> {
+    if let Some(unknown) =
+        columns.iter().find(|column| !CadenzaTableRow::COLUMNS.contains(&column.as_str()))
+    {
+        return Err(anyhow::Error::msg(format!(
+            "unknown column {unknown:?}, expected one of: {}",
+            CadenzaTableRow::COLUMNS.join(", ")
+        )));
+    }
+
+    table
+        .rows()
+        .iter()
+        .map(|row| {
+            let mut row = match serde_json::to_value(row)? {
+                serde_json::Value::Object(row) => row,
+                _ => unreachable!("rows are serialized as objects")
+            };
+            Ok(columns
+                .iter()
+                .map(|column| (column.clone(), row.remove(column).unwrap_or_default()))
+                .collect())
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+
+    const XLSX_PATH: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/test/short.xlsx");
+
+    #[test]
+    fn cadenza_to_json_works() {
+        let dir = tempfile::tempdir().unwrap();
+        let out_path = dir.path().join("cadenza.json");
+        let rows = cadenza_to_json(Path::new(XLSX_PATH), &out_path, None).unwrap();
+
+        let json: serde_json::Value =
+            serde_json::from_str(&fs::read_to_string(&out_path).unwrap()).unwrap();
+        let json = json.as_array().unwrap();
+        assert_eq!(json.len(), rows);
+        assert_eq!(json[0]["Wasserrecht Nr."], serde_json::json!(1101));
+        assert_eq!(json[0]["Rechtsinhaber"], serde_json::json!("Körtke"));
+        assert_eq!(json[0]["UTM-Rechtswert"], serde_json::json!(32603873));
+    }
+
+    #[test]
+    fn select_columns_works() {
+        let dir = tempfile::tempdir().unwrap();
+        let out_path = dir.path().join("cadenza.json");
+        let columns = ["Wasserrecht Nr.".to_string(), "Rechtsinhaber".to_string()];
+        cadenza_to_json(Path::new(XLSX_PATH), &out_path, Some(&columns)).unwrap();
+
+        let json: serde_json::Value =
+            serde_json::from_str(&fs::read_to_string(&out_path).unwrap()).unwrap();
+        assert_eq!(
+            json[0],
+            serde_json::json!({"Wasserrecht Nr.": 1101, "Rechtsinhaber": "Körtke"})
+        );
+
+        let table = CadenzaTable::from_path(Path::new(XLSX_PATH)).unwrap();
+        let err = select_columns(&table, &["Inhaber".to_string()]).unwrap_err();
+        assert!(err.to_string().contains("\"Inhaber\""));
+    }
+}
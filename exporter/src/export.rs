@@ -2,13 +2,23 @@
 //! 1. open transaction via [`PostgresClient::transaction`]
 //! 2. use [`Transaction::copy_in`] for [batch execution via STDIN](https://www.postgresql.org/docs/current/sql-copy.html)
 //! 3. use [`CopyInWriter`] to write rows
+//!
+//! [`CopyInWriter`]: postgres::CopyInWriter
 
-use std::io::Write;
+use std::borrow::Borrow;
+use std::collections::BTreeMap;
+use std::fmt::{Display, Formatter};
+use std::io::{self, Write};
+use std::iter;
+use std::num::NonZeroUsize;
 
+use anyhow::Context;
+use itertools::Itertools;
 use nlwkn::cli::{PROGRESS_STYLE, SPINNER_STYLE};
 use nlwkn::helper_types::Quantity;
 use nlwkn::{LegalDepartmentAbbreviation, UsageLocation, WaterRight, WaterRightNo};
 use postgres::{Client as PostgresClient, Transaction};
+use rayon::prelude::*;
 
 use crate::postgres_copy::{IterPostgresCopy, PostgresCopy, PostgresCopyContext};
 use crate::PROGRESS;
@@ -25,27 +35,360 @@ pub struct UtmPoint {
 
 pub struct IsoDate<'s>(pub &'s str);
 
+/// Phase of the export, attached as context to the errors of the export.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExportPhase {
+    /// Removing the existing water rights.
+    Truncate,
+
+    /// Removing the changed water rights before copying them again.
+    RemoveChanged,
+
+    /// Copying a water right.
+    WaterRight(WaterRightNo),
+
+    /// Copying a usage location, `row` counts the usage locations of all water
+    /// rights.
+    UsageLocation {
+        row: usize,
+        water_right_no: WaterRightNo
+    },
+
+    /// Finishing the copy into a table, the database checks the rows here.
+    FinishCopy(&'static str),
+
+    /// Committing the transaction.
+    Commit
+}
+
+impl Display for ExportPhase {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ExportPhase::Truncate => write!(f, "removing existing water rights"),
+            ExportPhase::RemoveChanged => write!(f, "removing changed water rights"),
+            ExportPhase::WaterRight(no) => write!(f, "copying water right {no}"),
+            ExportPhase::UsageLocation {
+                row,
+                water_right_no
+            } => write!(
+                f,
+                "copying usage location {row} of water right {water_right_no}"
+            ),
+            ExportPhase::FinishCopy(table) => write!(f, "finishing copy into {table}"),
+            ExportPhase::Commit => write!(f, "committing transaction")
+        }
+    }
+}
+
+/// Statement to clear all previously exported water rights.
+const TRUNCATE_QUERY: &str =
+    "TRUNCATE water_rights.usage_locations, water_rights.rights RESTART IDENTITY CASCADE";
+
+/// Query for the exported water rights and their date of change.
+const EXISTING_RIGHTS_QUERY: &str = "SELECT id::bigint, last_change::text FROM water_rights.rights";
+
+/// Statements to remove water rights by their numbers, the usage locations
+/// first as they reference the water rights.
+const REMOVE_RIGHTS_QUERIES: [&str; 2] = [
+    "DELETE FROM water_rights.usage_locations WHERE water_right = ANY($1::bigint[])",
+    "DELETE FROM water_rights.rights WHERE id = ANY($1::bigint[])"
+];
+
+/// Statement to copy the water rights.
+const COPY_RIGHTS_QUERY: &str =
+    "COPY water_rights.rights FROM STDIN WITH (FORMAT text, ENCODING 'utf8')";
+
+/// Statement to copy the usage locations, the id column uses its default.
+const COPY_USAGE_LOCATIONS_QUERY: &str = "COPY water_rights.usage_locations FROM STDIN WITH \
+                                          (FORMAT text, DEFAULT '@DEFAULT', ENCODING 'utf8')";
+
+/// Statement executed inside the transaction before copying any rows.
+fn pre_copy_query(replace_existing: bool) -> Option<&'static str> {
+    match replace_existing {
+        true => Some(TRUNCATE_QUERY),
+        false => None
+    }
+}
+
+/// Water rights to export, read again for every copied table.
+pub trait WaterRightSource {
+    type WaterRight: Borrow<WaterRight>;
+
+    /// Number of water rights, used for the progress.
+    fn len(&self) -> usize;
+
+    /// Read the water rights from the start.
+    fn read(&self) -> anyhow::Result<WaterRightIter<'_, Self::WaterRight>>;
+}
+
+pub type WaterRightIter<'s, W> = Box<dyn Iterator<Item = anyhow::Result<W>> + 's>;
+
+impl<'w> WaterRightSource for &'w [WaterRight] {
+    type WaterRight = &'w WaterRight;
+
+    fn len(&self) -> usize {
+        <[WaterRight]>::len(self)
+    }
+
+    fn read(&self) -> anyhow::Result<WaterRightIter<'_, Self::WaterRight>> {
+        Ok(Box::new(self.iter().map(Ok)))
+    }
+}
+
+/// Whether a water right is already exported.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Presence {
+    Missing,
+
+    /// Exported with a different date of change.
+    Changed,
+
+    Unchanged
+}
+
+/// Exported water rights with their date of change.
+pub type ExistingRights = BTreeMap<WaterRightNo, Option<String>>;
+
+/// Query the water rights already in the database.
+pub fn existing_water_rights(pg_client: &mut PostgresClient) -> anyhow::Result<ExistingRights> {
+    let mut existing = ExistingRights::new();
+    for row in pg_client.query(EXISTING_RIGHTS_QUERY, &[])? {
+        let no: i64 = row.try_get(0)?;
+        existing.insert(no as WaterRightNo, row.try_get(1)?);
+    }
+
+    Ok(existing)
+}
+
+/// Check if `water_right` is in `existing`, comparing the date of change.
+pub fn presence(water_right: &WaterRight, existing: &ExistingRights) -> Presence {
+    match existing.get(&water_right.no) {
+        None => Presence::Missing,
+        Some(last_change) if *last_change != water_right.last_change => Presence::Changed,
+        Some(_) => Presence::Unchanged
+    }
+}
+
+/// Export the water rights in a single transaction.
+///
+/// The water rights in `changed` are removed before, so they can be copied
+/// again.
+/// On failure the transaction is rolled back and the [`ExportPhase`] it failed
+/// in is logged and attached to the error.
 pub fn water_rights_to_pg(
     pg_client: &mut PostgresClient,
-    water_rights: &[WaterRight]
+    water_rights: &impl WaterRightSource,
+    replace_existing: bool,
+    changed: &[WaterRightNo],
+    flush_every: Option<NonZeroUsize>,
+    threads: Option<NonZeroUsize>,
+    ctx: PostgresCopyContext
 ) -> anyhow::Result<()> {
-    let mut transaction = pg_client.transaction()?;
-    copy_water_rights(&mut transaction, water_rights)?;
-    let usage_locations = water_rights
-        .iter()
-        .flat_map(|wr| {
-            wr.legal_departments
-                .values()
-                .flat_map(|ld| ld.usage_locations.iter().map(|ul| (wr.no, ld.abbreviation, ul)))
-        })
-        .collect();
-    copy_usage_locations(&mut transaction, usage_locations)?;
+    let transaction = pg_client.transaction()?;
+    let exported = export_in_transaction(
+        transaction,
+        water_rights,
+        replace_existing,
+        changed,
+        flush_every,
+        threads,
+        ctx
+    );
+
+    if let Err(err) = exported.as_ref() {
+        // the transaction is dropped without commit and therefore rolled back
+        let phase = err
+            .downcast_ref::<ExportPhase>()
+            .map(ToString::to_string)
+            .unwrap_or_else(|| "starting the export".to_string());
+        PROGRESS.println(format!(
+            "{} export failed while {phase}, rolled back transaction",
+            console::style("Error").red()
+        ));
+    }
+    exported
+}
+
+fn export_in_transaction(
+    mut transaction: Transaction,
+    water_rights: &impl WaterRightSource,
+    replace_existing: bool,
+    changed: &[WaterRightNo],
+    flush_every: Option<NonZeroUsize>,
+    threads: Option<NonZeroUsize>,
+    ctx: PostgresCopyContext
+) -> anyhow::Result<()> {
+    if let Some(query) = pre_copy_query(replace_existing) {
+        PROGRESS.set_style(SPINNER_STYLE.clone());
+        PROGRESS.set_message("Removing existing water rights...");
+        transaction.batch_execute(query).context(ExportPhase::Truncate)?;
+    }
+    if !changed.is_empty() {
+        PROGRESS.set_style(SPINNER_STYLE.clone());
+        PROGRESS.set_message("Removing changed water rights...");
+        let changed: Vec<i64> = changed.iter().map(|no| *no as i64).collect();
+        for query in REMOVE_RIGHTS_QUERIES {
+            transaction.execute(query, &[&changed]).context(ExportPhase::RemoveChanged)?;
+        }
+    }
+    copy_water_rights(&mut transaction, water_rights, flush_every, ctx)?;
+    copy_usage_locations(&mut transaction, water_rights, flush_every, threads, ctx)?;
     PROGRESS.set_style(SPINNER_STYLE.clone());
     PROGRESS.set_message("Committing transaction to database...");
-    transaction.commit()?;
+    transaction.commit().context(ExportPhase::Commit)?;
     Ok(())
 }
 
+/// Write the statements of [`water_rights_to_pg`] as an SQL dump.
+///
+/// The dump contains the same transaction and `COPY` data that would be sent
+/// to the database, so it can be applied later via `psql -f`.
+pub fn water_rights_to_sql(
+    writer: &mut impl Write,
+    water_rights: &impl WaterRightSource,
+    replace_existing: bool,
+    threads: Option<NonZeroUsize>,
+    ctx: PostgresCopyContext
+) -> anyhow::Result<()> {
+    writeln!(writer, "BEGIN;")?;
+    if let Some(query) = pre_copy_query(replace_existing) {
+        writeln!(writer, "{query};")?;
+    }
+
+    PROGRESS.set_style(PROGRESS_STYLE.clone());
+    PROGRESS.set_length(water_rights.len() as u64);
+    PROGRESS.set_message("Writing water rights...");
+    PROGRESS.set_prefix("📝");
+    PROGRESS.set_position(0);
+    writeln!(writer, "{COPY_RIGHTS_QUERY};")?;
+    write_water_rights(writer, water_rights.read()?, None, ctx)?;
+    writeln!(writer, "\\.")?;
+
+    PROGRESS.set_message("Writing usage locations...");
+    PROGRESS.set_position(0);
+    writeln!(writer, "{COPY_USAGE_LOCATIONS_QUERY};")?;
+    write_usage_locations(writer, water_rights.read()?, None, threads, ctx)?;
+    writeln!(writer, "\\.")?;
+
+    writeln!(writer, "COMMIT;")?;
+    Ok(())
+}
+
+/// Outcome of [`water_rights_dry_run`].
+#[derive(Debug, Default)]
+pub struct DryRun {
+    /// Formatted rows of the water rights.
+    pub rights: usize,
+
+    /// Formatted rows of the usage locations.
+    pub usage_locations: usize,
+
+    /// Water rights whose rows could not be formatted, the errors carry the
+    /// [`ExportPhase`] as context.
+    pub failures: Vec<anyhow::Error>
+}
+
+/// Format the `COPY` rows of [`water_rights_to_pg`] without a database.
+///
+/// The rows are written into the same `.tsv` files the `file-log` feature
+/// writes, `data/rights.dry-run.log.tsv` and
+/// `data/usage_locations.dry-run.log.tsv`.
+/// A water right whose rows cannot be formatted is left out of the files and
+/// collected in [`DryRun::failures`], the other ones are still checked.
+pub fn water_rights_dry_run(
+    water_rights: &impl WaterRightSource,
+    ctx: PostgresCopyContext
+) -> anyhow::Result<DryRun> {
+    let mut dry_run = DryRun::default();
+
+    PROGRESS.set_style(PROGRESS_STYLE.clone());
+    PROGRESS.set_length(water_rights.len() as u64);
+    PROGRESS.set_message("Formatting water rights...");
+    PROGRESS.set_prefix("🔍");
+    PROGRESS.set_position(0);
+    let mut log = log_through::LogThrough::new(io::sink(), "rights.dry-run")?.prepare_rights()?;
+    for water_right in water_rights.read()? {
+        let water_right = water_right?;
+        let water_right = water_right.borrow();
+        let mut rows = Vec::new();
+        match write_water_rights(&mut rows, iter::once(Ok(water_right)), None, ctx) {
+            Ok(()) => {
+                log.write_all(&rows)?;
+                dry_run.rights += 1;
+            }
+            Err(err) => dry_run.failures.push(err)
+        }
+    }
+    log.into_writer()?;
+
+    PROGRESS.set_message("Formatting usage locations...");
+    PROGRESS.set_position(0);
+    let mut log = log_through::LogThrough::new(io::sink(), "usage_locations.dry-run")?
+        .prepare_usage_locations()?;
+    for water_right in water_rights.read()? {
+        let water_right = water_right?;
+        let water_right = water_right.borrow();
+        let mut rows = Vec::new();
+        match write_usage_locations(&mut rows, iter::once(Ok(water_right)), None, None, ctx) {
+            Ok(()) => {
+                log.write_all(&rows)?;
+                dry_run.usage_locations += water_right.usage_locations().count();
+            }
+            Err(err) => dry_run.failures.push(err)
+        }
+    }
+    log.into_writer()?;
+
+    Ok(dry_run)
+}
+
+/// Write the `COPY` statements and rows of a single water right with all
+/// control characters escaped, to inspect the exact bytes sent for it.
+///
+/// Tabs separating the columns show up as `\t`, backslashes the copy format
+/// uses for escaping as `\\`.
+pub fn debug_copy_rows(
+    writer: &mut impl Write,
+    water_right: &WaterRight,
+    ctx: PostgresCopyContext
+) -> anyhow::Result<()> {
+    let mut rights = Vec::new();
+    write_water_rights(&mut rights, iter::once(Ok(water_right)), None, ctx)?;
+    let mut usage_locations = Vec::new();
+    write_usage_locations(
+        &mut usage_locations,
+        iter::once(Ok(water_right)),
+        None,
+        None,
+        ctx
+    )?;
+
+    for (query, rows) in [
+        (COPY_RIGHTS_QUERY, rights),
+        (COPY_USAGE_LOCATIONS_QUERY, usage_locations)
+    ] {
+        writeln!(writer, "{query};")?;
+        for line in String::from_utf8_lossy(&rows).lines() {
+            writeln!(writer, "{}", escape_control(line))?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Escape backslashes and control characters, other characters are kept.
+fn escape_control(line: &str) -> String {
+    let mut escaped = String::with_capacity(line.len());
+    for c in line.chars() {
+        match c {
+            '\\' => escaped.push_str("\\\\"),
+            c if c.is_control() => escaped.extend(c.escape_debug()),
+            c => escaped.push(c)
+        }
+    }
+    escaped
+}
+
 macro_rules! interleave_tabs {
     // Base case: when there's only one expression left, execute it without adding a tab after
     ($writer:expr; $expr:expr) => {
@@ -60,9 +403,28 @@ macro_rules! interleave_tabs {
     };
 }
 
+/// Flush the `writer` after every `flush_every` rows.
+///
+/// The [`CopyInWriter`] sends its buffer to the database on every flush, so
+/// this limits how many rows are buffered at once.
+/// `COPY FROM STDIN` accepts the data in arbitrary chunks, the flushes
+/// therefore don't change the copied rows.
+fn flush_periodically(
+    writer: &mut impl Write,
+    row: usize,
+    flush_every: Option<NonZeroUsize>
+) -> std::io::Result<()> {
+    match flush_every {
+        Some(flush_every) if (row + 1) % flush_every.get() == 0 => writer.flush(),
+        _ => Ok(())
+    }
+}
+
 fn copy_water_rights(
     transaction: &mut Transaction,
-    water_rights: &[WaterRight]
+    water_rights: &impl WaterRightSource,
+    flush_every: Option<NonZeroUsize>,
+    ctx: PostgresCopyContext
 ) -> anyhow::Result<()> {
     PROGRESS.set_style(PROGRESS_STYLE.clone());
     PROGRESS.set_length(water_rights.len() as u64);
@@ -71,19 +433,41 @@ fn copy_water_rights(
     PROGRESS.set_position(0);
 
     #[cfg_attr(feature = "file-log", allow(unused_mut))]
-    let mut writer = transaction.copy_in(
-        "
-            COPY water_rights.rights
-            FROM STDIN
-            WITH (
-                FORMAT text,
-                ENCODING 'utf8'
-            )
-        "
-    )?;
+    let mut writer = transaction.copy_in(COPY_RIGHTS_QUERY)?;
     #[cfg(feature = "file-log")]
-    let mut writer = log_through::LogThrough::new(writer, "rights.export").prepare_rights()?;
+    let mut writer = log_through::LogThrough::new(writer, "rights.export")?.prepare_rights()?;
+
+    write_water_rights(&mut writer, water_rights.read()?, flush_every, ctx)?;
+
+    #[cfg(feature = "file-log")]
+    let writer = writer.into_writer()?;
+    writer.finish().context(ExportPhase::FinishCopy("water_rights.rights"))?;
+    Ok(())
+}
+
+fn write_water_rights(
+    writer: &mut impl Write,
+    water_rights: impl Iterator<Item = anyhow::Result<impl Borrow<WaterRight>>>,
+    flush_every: Option<NonZeroUsize>,
+    ctx: PostgresCopyContext
+) -> anyhow::Result<()> {
+    for (row, water_right) in water_rights.enumerate() {
+        let water_right = water_right?;
+        let water_right = water_right.borrow();
+        let phase = ExportPhase::WaterRight(water_right.no);
+        write_water_right(writer, water_right, ctx).context(phase)?;
+        flush_periodically(writer, row, flush_every).context(phase)?;
+        PROGRESS.inc(1);
+    }
 
+    Ok(())
+}
+
+fn write_water_right(
+    mut writer: &mut impl Write,
+    water_right: &WaterRight,
+    ctx: PostgresCopyContext
+) -> anyhow::Result<()> {
     macro_rules! iso_date {
         ($iso_date_opt:expr) => {
             $iso_date_opt
@@ -93,129 +477,253 @@ fn copy_water_rights(
         };
     }
 
-    // PostgresCopyContext implements Copy,
-    // so this will be a new context for each call
-    let ctx = PostgresCopyContext::default();
-    for water_right in water_rights.iter() {
-        interleave_tabs! {
-            writer;
-            water_right.no.copy_to(&mut writer, ctx)?;
-            water_right.external_identifier.copy_to(&mut writer, ctx)?;
-            water_right.file_reference.copy_to(&mut writer, ctx)?;
-            water_right.legal_departments.keys().copy_to(&mut writer, ctx)?;
-            water_right.holder.copy_to(&mut writer, ctx)?;
-            water_right.address.copy_to(&mut writer, ctx)?;
-            water_right.subject.copy_to(&mut writer, ctx)?;
-            water_right.legal_title.copy_to(&mut writer, ctx)?;
-            water_right.status.copy_to(&mut writer, ctx)?;
-            iso_date!(water_right.valid_from)?;
-            iso_date!(water_right.valid_until)?;
-            iso_date!(water_right.initially_granted)?;
-            iso_date!(water_right.last_change)?;
-            water_right.water_authority.copy_to(&mut writer, ctx)?;
-            water_right.registering_authority.copy_to(&mut writer, ctx)?;
-            water_right.granting_authority.copy_to(&mut writer, ctx)?;
-            water_right.annotation.copy_to(&mut writer, ctx)?;
-        }
-        writeln!(writer)?;
-        PROGRESS.inc(1);
+    interleave_tabs! {
+        writer;
+        water_right.no.copy_to(&mut writer, ctx)?;
+        water_right.external_identifier.copy_to(&mut writer, ctx)?;
+        water_right.file_reference.copy_to(&mut writer, ctx)?;
+        water_right
+            .legal_departments
+            .keys()
+            .filter(|abbreviation| **abbreviation != LegalDepartmentAbbreviation::Unknown)
+            .copy_to(&mut writer, ctx)?;
+        water_right.holder.copy_to(&mut writer, ctx)?;
+        water_right.address.copy_to(&mut writer, ctx)?;
+        water_right.subject.copy_to(&mut writer, ctx)?;
+        water_right.legal_title.copy_to(&mut writer, ctx)?;
+        water_right.status.copy_to(&mut writer, ctx)?;
+        iso_date!(water_right.valid_from)?;
+        iso_date!(water_right.valid_until)?;
+        iso_date!(water_right.initially_granted)?;
+        iso_date!(water_right.last_change)?;
+        water_right.water_authority.copy_to(&mut writer, ctx)?;
+        water_right.registering_authority.copy_to(&mut writer, ctx)?;
+        water_right.granting_authority.copy_to(&mut writer, ctx)?;
+        water_right.annotation.copy_to(&mut writer, ctx)?;
     }
+    writeln!(writer)?;
 
-    #[cfg(feature = "file-log")]
-    let writer = writer.into_writer()?;
-    writer.finish()?;
     Ok(())
 }
 
+type UsageLocationRow<'ul> = (
+    WaterRightNo,
+    LegalDepartmentAbbreviation,
+    &'ul UsageLocation
+);
+
 fn copy_usage_locations(
     transaction: &mut Transaction,
-    usage_locations: Vec<(WaterRightNo, LegalDepartmentAbbreviation, &UsageLocation)>
+    water_rights: &impl WaterRightSource,
+    flush_every: Option<NonZeroUsize>,
+    threads: Option<NonZeroUsize>,
+    ctx: PostgresCopyContext
 ) -> anyhow::Result<()> {
     PROGRESS.set_style(PROGRESS_STYLE.clone());
-    PROGRESS.set_length(usage_locations.len() as u64);
+    PROGRESS.set_length(water_rights.len() as u64);
     PROGRESS.set_message("Copying usage locations...");
     PROGRESS.set_prefix("🐘");
     PROGRESS.set_position(0);
 
     #[cfg_attr(feature = "file-log", allow(unused_mut))]
-    let mut writer = transaction.copy_in(
-        "
-            COPY water_rights.usage_locations
-            FROM STDIN
-            WITH (
-                FORMAT text,
-                DEFAULT '@DEFAULT',
-                ENCODING 'utf8'
-            )
-        "
-    )?;
+    let mut writer = transaction.copy_in(COPY_USAGE_LOCATIONS_QUERY)?;
+    #[cfg(feature = "file-log")]
+    let mut writer = log_through::LogThrough::new(writer, "usage_locations.export")?
+        .prepare_usage_locations()?;
+
+    write_usage_locations(&mut writer, water_rights.read()?, flush_every, threads, ctx)?;
+
     #[cfg(feature = "file-log")]
-    let mut writer =
-        log_through::LogThrough::new(writer, "usage_locations.export").prepare_usage_locations()?;
-
-    let ctx = PostgresCopyContext::default();
-    for (no, lda, location) in usage_locations {
-        interleave_tabs! {
-            writer;
-            writer.write_all(b"@DEFAULT")?;
-            location.no.copy_to(&mut writer, ctx)?;
-            location.serial.copy_to(&mut writer, ctx)?;
-            no.copy_to(&mut writer, ctx)?;
-            lda.copy_to(&mut writer, ctx)?;
-            location.active.copy_to(&mut writer, ctx)?;
-            location.real.copy_to(&mut writer, ctx)?;
-            location.name.copy_to(&mut writer, ctx)?;
-            location.legal_purpose.copy_to(&mut writer, ctx)?;
-            location.map_excerpt.copy_to(&mut writer, ctx)?;
-            location.municipal_area.copy_to(&mut writer, ctx)?;
-            location.county.copy_to(&mut writer, ctx)?;
-            location.land_record.copy_to(&mut writer, ctx)?;
-            location.plot.copy_to(&mut writer, ctx)?;
-            location.maintenance_association.copy_to(&mut writer, ctx)?;
-            location.eu_survey_area.copy_to(&mut writer, ctx)?;
-            location.catchment_area_code.copy_to(&mut writer, ctx)?;
-            location.regulation_citation.copy_to(&mut writer, ctx)?;
-            location.withdrawal_rates.copy_to(&mut writer, ctx)?;
-            location.pumping_rates.copy_to(&mut writer, ctx)?;
-            location.injection_rates.copy_to(&mut writer, ctx)?;
-            location.waste_water_flow_volume.copy_to(&mut writer, ctx)?;
-            location.river_basin.copy_to(&mut writer, ctx)?;
-            location.groundwater_body.copy_to(&mut writer, ctx)?;
-            location.water_body.copy_to(&mut writer, ctx)?;
-            location.flood_area.copy_to(&mut writer, ctx)?;
-            location.water_protection_area.copy_to(&mut writer, ctx)?;
-            location.dam_target_levels.copy_to(&mut writer, ctx)?;
-            location.fluid_discharge.copy_to(&mut writer, ctx)?;
-            location.rain_supplement.copy_to(&mut writer, ctx)?;
-            location.irrigation_area.copy_to(&mut writer, ctx)?;
-            location.ph_values.copy_to(&mut writer, ctx)?;
-            location
-                .injection_limits
-                .iter()
-                .map(|(substance, quantity)| InjectionLimit {
-                    substance,
-                    quantity
+    let writer = writer.into_writer()?;
+    writer.finish().context(ExportPhase::FinishCopy("water_rights.usage_locations"))?;
+    Ok(())
+}
+
+/// Water rights read at once to format their usage locations on multiple
+/// threads.
+const PARALLEL_CHUNK_SIZE: usize = 256;
+
+/// Write the usage locations of the water rights, the progress counts the
+/// water rights.
+///
+/// With `threads` the rows are formatted on that many threads and then written
+/// in order, this produces the same bytes as writing them one after another.
+fn write_usage_locations(
+    writer: &mut impl Write,
+    water_rights: impl Iterator<Item = anyhow::Result<impl Borrow<WaterRight>>>,
+    flush_every: Option<NonZeroUsize>,
+    threads: Option<NonZeroUsize>,
+    ctx: PostgresCopyContext
+) -> anyhow::Result<()> {
+    if let Some(threads) = threads {
+        return write_usage_locations_parallel(writer, water_rights, flush_every, threads, ctx);
+    }
+
+    let mut row = 0;
+    for water_right in water_rights {
+        let water_right = water_right?;
+        let water_right = water_right.borrow();
+        warn_skipped_usage_locations(water_right);
+        for (abbreviation, usage_location) in exported_usage_locations(water_right) {
+            let phase = ExportPhase::UsageLocation {
+                row,
+                water_right_no: water_right.no
+            };
+            write_usage_location(writer, (water_right.no, abbreviation, usage_location), ctx)
+                .context(phase)?;
+            flush_periodically(writer, row, flush_every).context(phase)?;
+            row += 1;
+        }
+        PROGRESS.inc(1);
+    }
+
+    Ok(())
+}
+
+fn write_usage_locations_parallel<W: Borrow<WaterRight>>(
+    writer: &mut impl Write,
+    water_rights: impl Iterator<Item = anyhow::Result<W>>,
+    flush_every: Option<NonZeroUsize>,
+    threads: NonZeroUsize,
+    ctx: PostgresCopyContext
+) -> anyhow::Result<()> {
+    let pool = rayon::ThreadPoolBuilder::new().num_threads(threads.get()).build()?;
+
+    let mut row = 0;
+    for chunk in &water_rights.chunks(PARALLEL_CHUNK_SIZE) {
+        let chunk: Vec<W> = chunk.collect::<anyhow::Result<_>>()?;
+        let rows: Vec<UsageLocationRow> = chunk
+            .iter()
+            .map(Borrow::borrow)
+            .flat_map(|water_right: &WaterRight| {
+                exported_usage_locations(water_right).map(|(abbreviation, usage_location)| {
+                    (water_right.no, abbreviation, usage_location)
+                })
+            })
+            .collect();
+
+        // the context is `Copy`, every thread formats with its own copy
+        let buffers: Vec<anyhow::Result<Vec<u8>>> = pool.install(|| {
+            rows.par_iter()
+                .map(|usage_location_row| {
+                    let mut buffer = Vec::new();
+                    write_usage_location(&mut buffer, *usage_location_row, ctx)?;
+                    Ok(buffer)
                 })
-                .copy_to(&mut writer, ctx)?;
-            match (location.utm_easting, location.utm_northing) {
-                (Some(easting), Some(northing)) => Some(UtmPoint { easting, northing }),
-                _ => None
+                .collect()
+        });
+
+        let mut buffers = rows.iter().zip(buffers);
+        for water_right in chunk.iter().map(Borrow::borrow) {
+            warn_skipped_usage_locations(water_right);
+            for ((water_right_no, ..), buffer) in
+                buffers.by_ref().take(exported_usage_locations(water_right).count())
+            {
+                let phase = ExportPhase::UsageLocation {
+                    row,
+                    water_right_no: *water_right_no
+                };
+                writer.write_all(&buffer.context(phase)?).context(phase)?;
+                flush_periodically(writer, row, flush_every).context(phase)?;
+                row += 1;
             }
+            PROGRESS.inc(1);
+        }
+    }
+
+    Ok(())
+}
+
+/// Usage locations of a water right that can be exported.
+///
+/// The database enum has no unknown legal department, so usage locations of
+/// reports without a recognizable "Abteilung" are skipped like their department
+/// in the water rights table.
+fn exported_usage_locations(
+    water_right: &WaterRight
+) -> impl Iterator<Item = (LegalDepartmentAbbreviation, &UsageLocation)> {
+    water_right
+        .usage_locations()
+        .filter(|(abbreviation, _)| *abbreviation != LegalDepartmentAbbreviation::Unknown)
+}
+
+/// Print how many usage locations of the water right are skipped by
+/// [`exported_usage_locations`].
+fn warn_skipped_usage_locations(water_right: &WaterRight) {
+    let skipped =
+        water_right.usage_locations().count() - exported_usage_locations(water_right).count();
+    if skipped > 0 {
+        PROGRESS.println(format!(
+            "{} {skipped} usage locations of water right {} without a legal department",
+            console::style("Skipping").yellow(),
+            water_right.no
+        ));
+    }
+}
+
+fn write_usage_location(
+    mut writer: &mut impl Write,
+    (no, lda, location): UsageLocationRow,
+    ctx: PostgresCopyContext
+) -> anyhow::Result<()> {
+    interleave_tabs! {
+        writer;
+        writer.write_all(b"@DEFAULT")?;
+        location.no.copy_to(&mut writer, ctx)?;
+        location.serial.copy_to(&mut writer, ctx)?;
+        no.copy_to(&mut writer, ctx)?;
+        lda.copy_to(&mut writer, ctx)?;
+        location.active.copy_to(&mut writer, ctx)?;
+        location.real.copy_to(&mut writer, ctx)?;
+        location.name.copy_to(&mut writer, ctx)?;
+        // the database only holds a single legal purpose per usage location
+        location.legal_purposes.first().copy_to(&mut writer, ctx)?;
+        location.map_excerpt.copy_to(&mut writer, ctx)?;
+        location.municipal_area.copy_to(&mut writer, ctx)?;
+        location.county.copy_to(&mut writer, ctx)?;
+        location.land_record.copy_to(&mut writer, ctx)?;
+        location.plot.copy_to(&mut writer, ctx)?;
+        location.maintenance_association.copy_to(&mut writer, ctx)?;
+        location.eu_survey_area.copy_to(&mut writer, ctx)?;
+        location.catchment_area_code.copy_to(&mut writer, ctx)?;
+        location.regulation_citation.copy_to(&mut writer, ctx)?;
+        location.withdrawal_rates.copy_to(&mut writer, ctx)?;
+        location.pumping_rates.copy_to(&mut writer, ctx)?;
+        location.injection_rates.copy_to(&mut writer, ctx)?;
+        location.waste_water_flow_volume.copy_to(&mut writer, ctx)?;
+        location.river_basin.copy_to(&mut writer, ctx)?;
+        location.groundwater_body.copy_to(&mut writer, ctx)?;
+        location.water_body.copy_to(&mut writer, ctx)?;
+        location.flood_area.copy_to(&mut writer, ctx)?;
+        location.water_protection_area.copy_to(&mut writer, ctx)?;
+        location.dam_target_levels.copy_to(&mut writer, ctx)?;
+        location.fluid_discharge.copy_to(&mut writer, ctx)?;
+        location.rain_supplement.copy_to(&mut writer, ctx)?;
+        location.irrigation_area.copy_to(&mut writer, ctx)?;
+        location.ph_values.copy_to(&mut writer, ctx)?;
+        location
+            .injection_limits
+            .iter()
+            .map(|(substance, quantity)| InjectionLimit {
+                substance,
+                quantity
+            })
             .copy_to(&mut writer, ctx)?;
+        match (location.utm_easting, location.utm_northing) {
+            (Some(easting), Some(northing)) => Some(UtmPoint { easting, northing }),
+            _ => None
         }
-        writeln!(writer)?;
-        PROGRESS.inc(1);
+        .copy_to(&mut writer, ctx)?;
     }
+    writeln!(writer)?;
 
-    #[cfg(feature = "file-log")]
-    let writer = writer.into_writer()?;
-    writer.finish()?;
     Ok(())
 }
 
-#[cfg(feature = "file-log")]
+/// Writer logging everything written through it into a `.tsv` file in the
+/// `data` directory, used by the `file-log` feature and the dry run.
 mod log_through {
-    use std::fs::File;
+    use std::fs::{self, File};
     use std::io;
     use std::io::Write;
 
@@ -228,11 +736,12 @@ mod log_through {
     where
         T: io::Write
     {
-        pub fn new(writer: T, filename: &str) -> Self {
-            Self {
+        pub fn new(writer: T, filename: &str) -> io::Result<Self> {
+            fs::create_dir_all("data")?;
+            Ok(Self {
                 writer,
-                file: File::create(format!("data/{filename}.log.tsv")).unwrap()
-            }
+                file: File::create(format!("data/{filename}.log.tsv"))?
+            })
         }
 
         pub fn into_writer(mut self) -> io::Result<T> {
@@ -328,3 +837,299 @@ mod log_through {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn truncate_only_when_replacing() {
+        assert_eq!(pre_copy_query(false), None);
+        assert_eq!(
+            pre_copy_query(true),
+            Some(
+                "TRUNCATE water_rights.usage_locations, water_rights.rights RESTART IDENTITY \
+                 CASCADE"
+            )
+        );
+    }
+
+    /// Writer recording the written bytes and how often it was flushed.
+    #[derive(Default)]
+    struct FlushRecorder {
+        bytes: Vec<u8>,
+        flushes: usize
+    }
+
+    impl Write for FlushRecorder {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.bytes.write(buf)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            self.flushes += 1;
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn flushing_does_not_change_output() {
+        let reports_json = concat!(env!("CARGO_MANIFEST_DIR"), "/test/reports.json");
+        let mut water_rights = nlwkn::load_water_rights(reports_json).unwrap();
+        water_rights.extend(nlwkn::load_water_rights(reports_json).unwrap());
+
+        let ctx = PostgresCopyContext::default();
+        let write = |flush_every: Option<usize>| {
+            let flush_every = flush_every.and_then(NonZeroUsize::new);
+            let mut recorder = FlushRecorder::default();
+            write_water_rights(&mut recorder, water_rights.iter().map(Ok), flush_every, ctx)
+                .unwrap();
+            write_usage_locations(
+                &mut recorder,
+                water_rights.iter().map(Ok),
+                flush_every,
+                None,
+                ctx
+            )
+            .unwrap();
+            recorder
+        };
+
+        let unflushed = write(None);
+        assert_eq!(unflushed.flushes, 0);
+        for flush_every in [1, 2, 3] {
+            let flushed = write(Some(flush_every));
+            assert_eq!(flushed.bytes, unflushed.bytes, "flush every {flush_every}");
+        }
+        assert_eq!(write(Some(1)).flushes, 4);
+    }
+
+    #[test]
+    fn parallel_output_matches_serial() {
+        let reports_json = concat!(env!("CARGO_MANIFEST_DIR"), "/test/reports.json");
+        let reports = std::fs::read_to_string(reports_json).unwrap();
+        // more water rights than fit into a single chunk
+        let water_rights: Vec<WaterRight> = (0..PARALLEL_CHUNK_SIZE * 2 + 3)
+            .map(|no| {
+                let mut water_rights: Vec<WaterRight> = serde_json::from_str(&reports).unwrap();
+                water_rights[0].no = no as WaterRightNo;
+                water_rights.remove(0)
+            })
+            .collect();
+
+        let ctx = PostgresCopyContext::default();
+        let write = |threads: Option<usize>| {
+            let mut bytes = Vec::new();
+            let threads = threads.and_then(NonZeroUsize::new);
+            write_usage_locations(&mut bytes, water_rights.iter().map(Ok), None, threads, ctx)
+                .unwrap();
+            bytes
+        };
+
+        let serial = write(None);
+        assert_eq!(
+            String::from_utf8_lossy(&serial).lines().count(),
+            water_rights.len()
+        );
+        for threads in [1, 2, 4] {
+            assert!(write(Some(threads)) == serial, "{threads} threads");
+        }
+
+        // the row that no longer fits into the writer fails
+        let capacity = serial.len() / 2;
+        let failing_row = serial
+            .split_inclusive(|byte| *byte == b'\n')
+            .scan(0, |end, row| {
+                *end += row.len();
+                Some(*end)
+            })
+            .take_while(|end| *end <= capacity)
+            .count();
+        let mut writer = FailingWriter { capacity };
+        let err = write_usage_locations(
+            &mut writer,
+            water_rights.iter().map(Ok),
+            None,
+            NonZeroUsize::new(2),
+            ctx
+        )
+        .unwrap_err();
+        let Some(ExportPhase::UsageLocation { row, .. }) = err.downcast_ref::<ExportPhase>()
+        else {
+            panic!("error should be attributed to a usage location, got {err:?}");
+        };
+        assert_eq!(*row, failing_row);
+    }
+
+    #[test]
+    fn sql_dump_has_copy_blocks() {
+        let reports_json = concat!(env!("CARGO_MANIFEST_DIR"), "/test/reports.json");
+        let water_rights = nlwkn::load_water_rights(reports_json).unwrap();
+
+        let mut dump = Vec::new();
+        water_rights_to_sql(
+            &mut dump,
+            &water_rights.as_slice(),
+            true,
+            None,
+            PostgresCopyContext::default()
+        )
+        .unwrap();
+        let dump = String::from_utf8(dump).unwrap();
+        let lines: Vec<&str> = dump.lines().collect();
+
+        assert_eq!(lines.len(), 9);
+        assert_eq!(lines[0], "BEGIN;");
+        assert_eq!(lines[1], format!("{TRUNCATE_QUERY};"));
+        assert_eq!(lines[2], format!("{COPY_RIGHTS_QUERY};"));
+        assert!(lines[3].starts_with("1101\t"));
+        assert_eq!(lines[4], "\\.");
+        assert_eq!(lines[5], format!("{COPY_USAGE_LOCATIONS_QUERY};"));
+        assert!(lines[6].starts_with("@DEFAULT\t"));
+        assert_eq!(lines[7], "\\.");
+        assert_eq!(lines[8], "COMMIT;");
+
+        let mut rows = Vec::new();
+        write_water_rights(
+            &mut rows,
+            water_rights.iter().map(Ok),
+            None,
+            PostgresCopyContext::default()
+        )
+        .unwrap();
+        assert_eq!(format!("{}\n", lines[3]).as_bytes(), rows.as_slice());
+    }
+
+    /// Writer failing once more than `capacity` bytes are written.
+    struct FailingWriter {
+        capacity: usize
+    }
+
+    impl Write for FailingWriter {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            match self.capacity.checked_sub(buf.len()) {
+                Some(capacity) => {
+                    self.capacity = capacity;
+                    Ok(buf.len())
+                }
+                None => Err(std::io::Error::new(
+                    std::io::ErrorKind::Other,
+                    "forced failure"
+                ))
+            }
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn errors_are_attributed_to_phase() {
+        let reports_json = concat!(env!("CARGO_MANIFEST_DIR"), "/test/reports.json");
+        let water_rights = nlwkn::load_water_rights(reports_json).unwrap();
+        let ctx = PostgresCopyContext::default();
+
+        let mut writer = FailingWriter { capacity: 10 };
+        let err =
+            write_water_rights(&mut writer, water_rights.iter().map(Ok), None, ctx).unwrap_err();
+        assert_eq!(
+            err.downcast_ref::<ExportPhase>(),
+            Some(&ExportPhase::WaterRight(1101))
+        );
+
+        let mut writer = FailingWriter { capacity: 10 };
+        let err = write_usage_locations(&mut writer, water_rights.iter().map(Ok), None, None, ctx)
+            .unwrap_err();
+        assert_eq!(
+            err.downcast_ref::<ExportPhase>(),
+            Some(&ExportPhase::UsageLocation {
+                row: 0,
+                water_right_no: 1101
+            })
+        );
+        assert_eq!(
+            err.to_string(),
+            "copying usage location 0 of water right 1101"
+        );
+        assert_eq!(err.root_cause().to_string(), "forced failure");
+    }
+
+    #[test]
+    fn unknown_legal_departments_are_skipped() {
+        let reports_json = concat!(env!("CARGO_MANIFEST_DIR"), "/test/reports.json");
+        let mut water_right = nlwkn::load_water_rights(reports_json).unwrap().remove(0);
+        let ctx = PostgresCopyContext::default();
+        let write = |water_right: &WaterRight, threads: Option<usize>| {
+            let mut bytes = Vec::new();
+            let threads = threads.and_then(NonZeroUsize::new);
+            write_usage_locations(&mut bytes, iter::once(Ok(water_right)), None, threads, ctx)
+                .unwrap();
+            bytes
+        };
+        assert!(!write(&water_right, None).is_empty());
+
+        let departments = std::mem::take(&mut water_right.legal_departments);
+        for mut department in departments.into_values() {
+            department.abbreviation = LegalDepartmentAbbreviation::Unknown;
+            water_right.legal_departments.insert(LegalDepartmentAbbreviation::Unknown, department);
+        }
+        assert!(write(&water_right, None).is_empty());
+        assert!(write(&water_right, Some(2)).is_empty());
+    }
+
+    #[test]
+    fn debug_copy_rows_escapes_control_characters() {
+        let reports_json = concat!(env!("CARGO_MANIFEST_DIR"), "/test/reports.json");
+        let mut water_right = nlwkn::load_water_rights(reports_json).unwrap().remove(0);
+        water_right.annotation = Some("erste Zeile\nzweite Zeile".to_string());
+
+        let mut out = Vec::new();
+        debug_copy_rows(&mut out, &water_right, PostgresCopyContext::default()).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        let lines: Vec<&str> = out.lines().collect();
+
+        assert_eq!(lines.len(), 4);
+        assert_eq!(lines[0], format!("{COPY_RIGHTS_QUERY};"));
+        assert!(lines[1].starts_with(r"1101\t1/1\t6630-01-1610\t{A}\tKörtke\t"));
+        assert!(lines[1].ends_with(r"\tLandkreis Gifhorn\terste Zeile\\nzweite Zeile"));
+        assert!(!lines[1].contains('\t'));
+        assert_eq!(lines[2], format!("{COPY_USAGE_LOCATIONS_QUERY};"));
+        assert!(lines[3].starts_with(r"@DEFAULT\t101\t1101-01\t1101\tA\t"));
+    }
+
+    #[test]
+    fn presence_compares_date_of_change() {
+        let water_right = |no: WaterRightNo, last_change: Option<&str>| {
+            let mut water_right = WaterRight::new(no);
+            water_right.last_change = last_change.map(str::to_string);
+            water_right
+        };
+        let existing = ExistingRights::from([
+            (1101, Some("2023-05-02".to_string())),
+            (1102, None),
+            (1103, Some("2023-05-02".to_string()))
+        ]);
+
+        assert_eq!(
+            presence(&water_right(1101, Some("2023-05-02")), &existing),
+            Presence::Unchanged
+        );
+        assert_eq!(
+            presence(&water_right(1102, None), &existing),
+            Presence::Unchanged
+        );
+        assert_eq!(
+            presence(&water_right(1103, Some("2024-01-10")), &existing),
+            Presence::Changed
+        );
+        assert_eq!(
+            presence(&water_right(1102, Some("2024-01-10")), &existing),
+            Presence::Changed
+        );
+        assert_eq!(
+            presence(&water_right(1104, None), &existing),
+            Presence::Missing
+        );
+    }
+}
@@ -1,17 +1,31 @@
+use std::borrow::Borrow;
+use std::error::Error;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::num::NonZeroUsize;
 use std::path::PathBuf;
 use std::str::FromStr;
-use std::{env, fs};
+use std::time::Duration;
+use std::{env, io, thread};
 
+use anyhow::Context;
 use clap::Parser;
 use indicatif::ProgressBar;
 use lazy_static::lazy_static;
-use nlwkn::cli::{PROGRESS_UPDATE_INTERVAL, SPINNER_STYLE};
-use nlwkn::WaterRight;
-use postgres::{Client as PostgresClient, NoTls};
+use nlwkn::cli::{self, PROGRESS_UPDATE_INTERVAL, SPINNER_STYLE};
+use nlwkn::{WaterRight, WaterRightNo};
+use postgres::error::SqlState;
+use postgres::{Client as PostgresClient, Config as PostgresConfig, NoTls};
 use static_toml::static_toml;
 
+use crate::export::{DryRun, Presence, WaterRightSource};
+use crate::postgres_copy::PostgresCopyContext;
+use crate::stream::StreamedReports;
+
 mod export;
 mod postgres_copy;
+mod sql_check;
+mod stream;
 
 const INIT_QUERY: &str = include_str!("../../target/resources/init.sql");
 
@@ -28,8 +42,94 @@ lazy_static! {
 #[command(version, about)]
 struct Args {
     /// Path to reports JSON file
+    ///
+    /// Files ending in `.jsonl` are read with one water right per line and
+    /// streamed instead of loaded at once.
     pub reports_json: PathBuf,
 
+    /// Read the reports JSON one water right at a time
+    ///
+    /// Only the water right being copied is held in memory, but the file is
+    /// read again for every copied table.
+    #[arg(long)]
+    pub stream: bool,
+
+    /// Remove all existing water rights before exporting
+    ///
+    /// This truncates the tables inside the export transaction and therefore
+    /// requires `--yes`.
+    #[arg(long, requires = "yes")]
+    pub replace_existing: bool,
+
+    /// Confirm destructive operations like `--replace-existing`
+    #[arg(long)]
+    pub yes: bool,
+
+    /// Send the buffered rows to the database every N rows
+    ///
+    /// Without this, rows are sent whenever the internal buffer runs full.
+    #[arg(long, value_name = "N")]
+    pub flush_every: Option<NonZeroUsize>,
+
+    /// Format the rows of the usage locations on N threads
+    ///
+    /// The rows are still sent in order, by default they are formatted on
+    /// the main thread.
+    #[arg(long, value_name = "N")]
+    pub threads: Option<NonZeroUsize>,
+
+    /// Leave out the UTM coordinates of all usage locations
+    ///
+    /// The locations are exported without geometry.
+    #[arg(long)]
+    pub exclude_coords: bool,
+
+    /// Write the statements into an SQL dump instead of a database
+    ///
+    /// The dump can be applied later via `psql -f`.
+    #[arg(long, value_name = "PATH")]
+    pub sql_out: Option<PathBuf>,
+
+    /// Write empty collections as `{}` instead of NULL
+    ///
+    /// Useful if array columns are constrained to be `NOT NULL`.
+    #[arg(long)]
+    pub compact_arrays: bool,
+
+    /// Retry connecting to the database up to N times on network failures
+    ///
+    /// The wait between the attempts doubles, starting at one second.
+    #[arg(long, value_name = "N", default_value = "3")]
+    pub connect_retries: u32,
+
+    /// Only export water rights that are not in the database yet
+    ///
+    /// Water rights whose date of change differs from the exported one are
+    /// replaced.
+    #[arg(long, conflicts_with_all = ["replace_existing", "sql_out"])]
+    pub only_missing: bool,
+
+    /// Only print the copied rows of the water right with this number
+    ///
+    /// Nothing is exported, the rows are printed with escaped control
+    /// characters to inspect the exact bytes of a failing import.
+    #[arg(
+        long = "no",
+        value_name = "WATER_RIGHT_NO",
+        conflicts_with_all = ["replace_existing", "sql_out", "only_missing"]
+    )]
+    pub water_right_no: Option<WaterRightNo>,
+
+    /// Only format the rows and check the initialization, without a database
+    ///
+    /// The rows are written as `.tsv` files into the `data` directory, fails
+    /// if any water right cannot be formatted.
+    #[arg(
+        long,
+        conflicts_with_all = ["replace_existing", "sql_out", "only_missing", "water_right_no"]
+    )]
+    pub dry_run: bool,
+
     #[clap(flatten)]
     pub pg_args: PostgresArgs
 }
@@ -54,41 +154,261 @@ struct PostgresArgs {
 }
 
 fn main() -> anyhow::Result<()> {
+    if cli::print_completions::<Args, _, _>(
+        env!("CARGO_BIN_NAME"),
+        env::args_os(),
+        &mut io::stdout()
+    ) {
+        return Ok(());
+    }
+
     let Args {
         reports_json,
+        stream,
+        replace_existing,
+        yes: _,
+        flush_every,
+        threads,
+        exclude_coords,
+        sql_out,
+        compact_arrays,
+        connect_retries,
+        only_missing,
+        water_right_no,
+        dry_run,
         pg_args
     } = Args::parse();
+    let ctx = PostgresCopyContext {
+        compact_arrays,
+        ..Default::default()
+    };
 
     PROGRESS.enable_steady_tick(PROGRESS_UPDATE_INTERVAL);
-
     PROGRESS.set_style(SPINNER_STYLE.clone());
+    if replace_existing {
+        PROGRESS.println(format!(
+            "{} existing water rights will be replaced",
+            console::style("Warning").yellow()
+        ));
+    }
+
+    if let Some(format) = stream::stream_format(&reports_json, stream) {
+        if only_missing {
+            return Err(anyhow::Error::msg(
+                "--only-missing is not supported for streamed reports"
+            ));
+        }
+
+        PROGRESS.set_message("Counting reports...");
+        let reports = StreamedReports::open(reports_json, format, exclude_coords)?;
+        if exclude_coords {
+            PROGRESS.println(format!(
+                "{} coordinates of all usage locations",
+                console::style("Excluding").yellow()
+            ));
+        }
+        if let Some(no) = water_right_no {
+            return print_copy_rows(&reports, no, ctx);
+        }
+        if dry_run {
+            return check_copy_rows(&reports, ctx);
+        }
+
+        let target = export_target(sql_out, pg_args, connect_retries)?;
+        return export(
+            target,
+            &reports,
+            replace_existing,
+            &[],
+            flush_every,
+            threads,
+            ctx
+        );
+    }
+
+    PROGRESS.set_message("Loading reports...");
+    let mut water_rights = nlwkn::load_water_rights(reports_json)?;
+    if exclude_coords {
+        let affected: usize = water_rights.iter_mut().map(WaterRight::exclude_coordinates).sum();
+        PROGRESS.println(format!(
+            "{} coordinates of {affected} usage locations",
+            console::style("Excluded").yellow()
+        ));
+    }
+    if let Some(no) = water_right_no {
+        return print_copy_rows(&water_rights.as_slice(), no, ctx);
+    }
+    if dry_run {
+        return check_copy_rows(&water_rights.as_slice(), ctx);
+    }
+
+    let mut target = export_target(sql_out, pg_args, connect_retries)?;
+    let mut changed = Vec::new();
+    if let (true, ExportTarget::Postgres(pg_client)) = (only_missing, &mut target) {
+        PROGRESS.set_message("Querying existing water rights...");
+        let existing = export::existing_water_rights(pg_client)?;
+        let total = water_rights.len();
+        water_rights.retain(
+            |water_right| match export::presence(water_right, &existing) {
+                Presence::Missing => true,
+                Presence::Changed => {
+                    changed.push(water_right.no);
+                    true
+                }
+                Presence::Unchanged => false
+            }
+        );
+        PROGRESS.println(format!(
+            "{} {} water rights already in the database, {} changed ones are replaced",
+            console::style("Skipped").yellow(),
+            total - water_rights.len(),
+            changed.len()
+        ));
+    }
+
+    export(
+        target,
+        &water_rights.as_slice(),
+        replace_existing,
+        &changed,
+        flush_every,
+        threads,
+        ctx
+    )
+}
+
+/// Where the water rights are exported to.
+enum ExportTarget {
+    SqlDump(PathBuf),
+    Postgres(Box<PostgresClient>)
+}
+
+fn export_target(
+    sql_out: Option<PathBuf>,
+    pg_args: PostgresArgs,
+    connect_retries: u32
+) -> anyhow::Result<ExportTarget> {
+    if let Some(sql_out) = sql_out {
+        return Ok(ExportTarget::SqlDump(sql_out));
+    }
+
     PROGRESS.set_message("Setting up postgres client...");
-    let mut pg_client = setup_pg_client(pg_args)?;
-    PROGRESS.set_message("Initializing database...");
-    pg_client.batch_execute(INIT_QUERY)?;
+    let pg_config = pg_config(pg_args);
+    let pg_client = with_connect_retries(connect_retries, || {
+        let mut pg_client = pg_config.connect(NoTls)?;
+        PROGRESS.set_message("Initializing database...");
+        pg_client.batch_execute(INIT_QUERY)?;
+        Ok(pg_client)
+    })?;
+    Ok(ExportTarget::Postgres(Box::new(pg_client)))
+}
+
+fn export(
+    target: ExportTarget,
+    water_rights: &impl WaterRightSource,
+    replace_existing: bool,
+    changed: &[WaterRightNo],
+    flush_every: Option<NonZeroUsize>,
+    threads: Option<NonZeroUsize>,
+    ctx: PostgresCopyContext
+) -> anyhow::Result<()> {
+    match target {
+        ExportTarget::SqlDump(sql_out) => {
+            let mut writer = BufWriter::new(File::create(&sql_out)?);
+            writeln!(writer, "{INIT_QUERY}")?;
+            export::water_rights_to_sql(&mut writer, water_rights, replace_existing, threads, ctx)?;
+            writer.flush()?;
+
+            PROGRESS.finish_and_clear();
+            println!(
+                "{} {}",
+                console::style("Written SQL dump to").magenta(),
+                console::style(sql_out.display()).green()
+            );
+        }
+        ExportTarget::Postgres(mut pg_client) => {
+            export::water_rights_to_pg(
+                &mut pg_client,
+                water_rights,
+                replace_existing,
+                changed,
+                flush_every,
+                threads,
+                ctx
+            )?;
+
+            PROGRESS.finish_and_clear();
+            println!(
+                "{}",
+                console::style("Successfully exported water rights to database").green()
+            );
+        }
+    }
+
+    Ok(())
+}
+
+/// Print the copied rows of a single water right, see
+/// [`export::debug_copy_rows`].
+fn print_copy_rows(
+    water_rights: &impl WaterRightSource,
+    no: WaterRightNo,
+    ctx: PostgresCopyContext
+) -> anyhow::Result<()> {
+    let water_right = water_rights
+        .read()?
+        .find(|water_right| match water_right {
+            Ok(water_right) => water_right.borrow().no == no,
+            Err(_) => true
+        })
+        .ok_or_else(|| anyhow::Error::msg(format!("water right {no} is not in the reports")))??;
+
+    PROGRESS.finish_and_clear();
+    export::debug_copy_rows(&mut io::stdout().lock(), water_right.borrow(), ctx)
+}
 
-    PROGRESS.set_message("Reading reports file...");
-    let water_rights = fs::read_to_string(reports_json)?;
-    PROGRESS.set_message("Parsing reports...");
-    let water_rights: Vec<WaterRight> = serde_json::from_str(&water_rights)?;
-    export::water_rights_to_pg(&mut pg_client, &water_rights)?;
+/// Check the initialization and format the copied rows without a database,
+/// see [`export::water_rights_dry_run`].
+fn check_copy_rows(
+    water_rights: &impl WaterRightSource,
+    ctx: PostgresCopyContext
+) -> anyhow::Result<()> {
+    PROGRESS.set_message("Checking initialization...");
+    let statements = sql_check::check_sql(INIT_QUERY).context("initialization is malformed")?;
 
+    let DryRun {
+        rights,
+        usage_locations,
+        failures
+    } = export::water_rights_dry_run(water_rights, ctx)?;
     PROGRESS.finish_and_clear();
+
+    for failure in failures.iter() {
+        eprintln!("{} {failure:#}", console::style("Error").red());
+    }
+    if !failures.is_empty() {
+        return Err(anyhow::Error::msg(format!(
+            "{} water rights could not be formatted",
+            failures.len()
+        )));
+    }
+
     println!(
-        "{}",
-        console::style("Successfully exported water rights to database").green()
+        "{} {statements} statements of the initialization, {rights} water rights and \
+         {usage_locations} usage locations",
+        console::style("Checked").green()
     );
     Ok(())
 }
 
-fn setup_pg_client(
+fn pg_config(
     PostgresArgs {
         user,
         password,
         host,
         port
     }: PostgresArgs
-) -> anyhow::Result<PostgresClient> {
+) -> PostgresConfig {
     let mut pg_config = PostgresClient::configure();
     pg_config.application_name(concat!(env!("CARGO_PKG_NAME"), "/", env!("CARGO_BIN_NAME")));
     pg_config.dbname(CONFIG.postgres.database);
@@ -100,5 +420,72 @@ fn setup_pg_client(
         .and_then(|v| u16::from_str(&v).ok())
         .or(port)
         .map(|v| pg_config.port(v));
-    Ok(pg_config.connect(NoTls)?)
+    pg_config
+}
+
+/// Run `connect` again while it fails with a transient error, at most
+/// `retries` times.
+fn with_connect_retries<T>(
+    retries: u32,
+    mut connect: impl FnMut() -> Result<T, postgres::Error>
+) -> anyhow::Result<T> {
+    let mut wait = Duration::from_secs(1);
+    let mut attempt = 0;
+    loop {
+        let err = match connect() {
+            Ok(connected) => return Ok(connected),
+            Err(err) => err
+        };
+
+        let is_io = err.source().map(|source| source.is::<io::Error>()).unwrap_or(false);
+        if attempt >= retries || !is_transient(is_io, err.code()) {
+            return Err(err.into());
+        }
+
+        attempt += 1;
+        PROGRESS.println(format!(
+            "{} could not connect to database, {err}, retry {attempt}/{retries} in {} seconds",
+            console::style("Warning").yellow(),
+            wait.as_secs()
+        ));
+        thread::sleep(wait);
+        wait *= 2;
+    }
+}
+
+/// Check if a failed connection attempt is worth retrying.
+///
+/// Network failures surface as IO errors without a SQL state.
+/// The server reports temporary failures with the connection exception class
+/// `08` or with `57P03` while starting up.
+/// Other failures, like a wrong password, will not resolve by retrying.
+fn is_transient(is_io: bool, code: Option<&SqlState>) -> bool {
+    match code {
+        Some(code) => code.code().starts_with("08") || *code == SqlState::CANNOT_CONNECT_NOW,
+        None => is_io
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn is_transient_works() {
+        assert!(is_transient(true, None));
+        assert!(is_transient(false, Some(&SqlState::CONNECTION_FAILURE)));
+        assert!(is_transient(
+            false,
+            Some(&SqlState::SQLCLIENT_UNABLE_TO_ESTABLISH_SQLCONNECTION)
+        ));
+        assert!(is_transient(false, Some(&SqlState::CANNOT_CONNECT_NOW)));
+
+        assert!(!is_transient(false, None));
+        assert!(!is_transient(false, Some(&SqlState::INVALID_PASSWORD)));
+        assert!(!is_transient(
+            false,
+            Some(&SqlState::INVALID_AUTHORIZATION_SPECIFICATION)
+        ));
+        assert!(!is_transient(false, Some(&SqlState::UNDEFINED_TABLE)));
+    }
 }
@@ -5,16 +5,6 @@ use nlwkn::{DamTargets, LandRecord, LegalDepartmentAbbreviation, PHValues, RateR
 
 use crate::export::{InjectionLimit, IsoDate, UtmPoint};
 
-/// Simple macro to make calling an expression n times simpler, also allows the
-/// use of [`?`](https://doc.rust-lang.org/std/result/index.html#the-question-mark-operator-).
-macro_rules! repeat {
-    ($range:expr, $expr:expr) => {
-        for _ in $range {
-            $expr;
-        }
-    };
-}
-
 pub trait PostgresCopy {
     /// Write `self` on a writer for the `COPY` instruction from PostgreSQL.
     ///
@@ -26,6 +16,20 @@ pub trait PostgresCopy {
     fn copy_to<W: io::Write>(&self, writer: &mut W, ctx: PostgresCopyContext) -> io::Result<()>;
 }
 
+/// Composite type of the Postgres DB.
+///
+/// A composite has to write exactly as many fields as the database type has,
+/// otherwise the values end up in the wrong fields without any error.
+/// Implementors of [`PostgresCopy`] pass their type to `composite!`, which
+/// checks the amount of written fields against `ARITY` in debug builds.
+pub trait CompositeType {
+    /// Name of the type in the database.
+    const NAME: &'static str;
+
+    /// Amount of fields of the type in the database.
+    const ARITY: usize;
+}
+
 /// Separate trait of [`PostgresCopy`] to avoid upstream implementation
 /// conflicts.
 pub trait IterPostgresCopy {
@@ -41,7 +45,11 @@ pub trait IterPostgresCopy {
 pub struct PostgresCopyContext {
     pub depth: usize,
     pub in_composite: bool,
-    pub in_array: bool
+    pub in_array: bool,
+
+    /// Write empty collections as `{}` instead of `\N`, for array columns
+    /// that are `NOT NULL`.
+    pub compact_arrays: bool
 }
 
 impl PostgresCopyContext {
@@ -54,9 +62,13 @@ impl PostgresCopyContext {
     }
 
     /// Marks context as inside a composite.
+    ///
+    /// A composite inside an array is the innermost container of its fields,
+    /// so they are no longer marked as inside an array.
     pub fn composite(self) -> Self {
         Self {
             in_composite: true,
+            in_array: false,
             ..self
         }
     }
@@ -99,19 +111,18 @@ impl PostgresCopyContext {
 /// But escaping is not done by adding more backslashes, but by placing more
 /// quotation marks. But these doubled quotation marks need to be escaped, so we
 /// get a sequence like this `\\"\\"`.
+/// Every further depth escapes the quotation marks and backslashes of the
+/// depth below with a backslash again, see [`write_escaped`].
 fn quoted<F, W>(write_op: F, writer: &mut W, ctx: PostgresCopyContext) -> io::Result<()>
 where
     F: FnOnce(&mut W, PostgresCopyContext) -> io::Result<()>,
     W: io::Write
 {
-    let quote = |writer: &mut W, ctx: PostgresCopyContext| {
-        match ctx.depth {
-            0 => (),
-            1 => write!(writer, r#"""#)?,
-            2 => write!(writer, r#"\\""#)?,
-            d => repeat!(1..d, write!(writer, r#"\\""#)?)
-        }
-        Ok::<_, io::Error>(())
+    let quote = |writer: &mut W, ctx: PostgresCopyContext| match ctx.depth {
+        0 => Ok(()),
+        // on depth 2 the quote may be inside an array, which cannot read doubled quotes
+        d @ (1 | 2) => write_escaped(writer, '"', d - 1, false),
+        d => write_escaped(writer, '"', d - 1, true)
     };
 
     quote(writer, ctx)?;
@@ -121,6 +132,38 @@ where
     Ok(())
 }
 
+/// Write a single character that is inside `depth` quotes.
+///
+/// Each quote level escapes quotation marks and backslashes with a backslash
+/// before the level around it escapes them again, on depth 0 the `COPY` text
+/// escapes backslashes and control characters.
+/// With `double_quotes` the innermost level escapes quotation marks by doubling
+/// them instead, this is only understood by composites.
+fn write_escaped<W: io::Write>(
+    writer: &mut W,
+    c: char,
+    depth: usize,
+    double_quotes: bool
+) -> io::Result<()> {
+    match (depth, c) {
+        (0, '\\') => writer.write_all(br"\\"),
+        (0, '\n') => writer.write_all(br"\n"),
+        (0, '\r') => writer.write_all(br"\r"),
+        (0, '\t') => writer.write_all(br"\t"),
+        (0, c) => write!(writer, "{c}"),
+        (d, '"') if double_quotes => {
+            write_escaped(writer, '"', d - 1, false)?;
+            write_escaped(writer, '"', d - 1, false)
+        }
+        (d, '"' | '\\') => {
+            write_escaped(writer, '\\', d - 1, false)?;
+            write_escaped(writer, c, d - 1, false)
+        }
+        (d, '\n' | '\r' | '\t') => write_escaped(writer, c, d - 1, false),
+        (_, c) => write!(writer, "{c}")
+    }
+}
+
 pub struct Null;
 
 impl PostgresCopy for Null {
@@ -141,8 +184,10 @@ where
 {
     fn copy_to(self, writer: &mut impl io::Write, ctx: PostgresCopyContext) -> io::Result<()> {
         let mut iter = self.peekable();
-        if iter.peek().is_none() {
-            return Null.copy_to(writer, ctx);
+        match (iter.peek().is_none(), ctx.compact_arrays) {
+            (true, true) => return write!(writer, "{{}}"),
+            (true, false) => return Null.copy_to(writer, ctx),
+            (false, _) => ()
         }
 
         write!(writer, "{{")?;
@@ -175,27 +220,20 @@ impl_postgres_copy!(bool);
 
 impl PostgresCopy for str {
     fn copy_to<W: io::Write>(&self, writer: &mut W, ctx: PostgresCopyContext) -> io::Result<()> {
-        let inner = |w: &mut W, ctx: PostgresCopyContext| {
+        // strings in arrays need to be quoted just like composites
+        let ctx = match ctx.in_array && ctx.depth == 0 {
+            true => ctx.deepen(),
+            false => ctx
+        };
+
+        let inner = |w: &mut W, inner_ctx: PostgresCopyContext| {
             // this needs custom escaping as postgres demands certain rules
             // https://www.postgresql.org/docs/current/sql-copy.html#id-1.9.3.55.9.2
 
             // the depth here is always increased by one as quoted will push the depth
-            let d = ctx.depth;
+            let depth = inner_ctx.depth - 1;
             for c in self.chars() {
-                match c {
-                    '"' if d <= 1 => write!(w, r#"""#),
-                    '"' => {
-                        // same double backslash as in `quoted`
-                        repeat!(2..d, w.write_all(br"\\")?);
-                        write!(w, r#"""#)?;
-                        repeat!(2..d, w.write_all(br"\\")?);
-                        write!(w, r#"""#)
-                    }
-                    '\\' => write!(w, r"\"),
-                    '\n' => write!(w, r"\n"),
-                    '\r' => write!(w, r"\r"),
-                    _ => write!(w, "{c}")
-                }?;
+                write_escaped(w, c, depth, !ctx.in_array)?;
             }
             Ok(())
         };
@@ -236,9 +274,9 @@ where
 {
     fn copy_to<W: io::Write>(&self, writer: &mut W, ctx: PostgresCopyContext) -> io::Result<()> {
         write!(writer, "{{")?;
-        self.0.copy_to(writer, ctx)?;
+        self.0.copy_to(writer, ctx.array())?;
         write!(writer, ",")?;
-        self.1.copy_to(writer, ctx)?;
+        self.1.copy_to(writer, ctx.array())?;
         write!(writer, "}}")?;
         Ok(())
     }
@@ -264,24 +302,45 @@ macro_rules! composite {
             Ok(())
         }, $writer, ctx)?;
     }};
+
+    // Same as above but checks the amount of elements against the composite type
+    ($writer:expr, $ctx:expr, $composite:ty => ($first:expr, $($rest:expr),* $(,)?)) => {{
+        debug_assert_eq!(
+            1 $(+ { stringify!($rest); 1 })*,
+            <$composite as CompositeType>::ARITY,
+            "arity of `{}` drifted",
+            <$composite as CompositeType>::NAME
+        );
+        composite!($writer, $ctx, ($first, $($rest),*));
+    }};
 }
 
 /// Represents the `water_rights.injection_limit` in the Postgres DB.
 impl PostgresCopy for (String, Quantity) {
     fn copy_to<W: io::Write>(&self, writer: &mut W, ctx: PostgresCopyContext) -> io::Result<()> {
-        composite!(writer, ctx, (self.0, self.1));
+        composite!(writer, ctx, Self => (self.0, self.1));
         Ok(())
     }
 }
 
+impl CompositeType for (String, Quantity) {
+    const ARITY: usize = 2;
+    const NAME: &'static str = "injection_limit";
+}
+
 /// Represents the `water_rights.numeric_keyed_value` in the Postgres DB.
 impl PostgresCopy for (u64, String) {
     fn copy_to<W: io::Write>(&self, writer: &mut W, ctx: PostgresCopyContext) -> io::Result<()> {
-        composite!(writer, ctx, (self.0, &self.1));
+        composite!(writer, ctx, Self => (self.0, &self.1));
         Ok(())
     }
 }
 
+impl CompositeType for (u64, String) {
+    const ARITY: usize = 2;
+    const NAME: &'static str = "numeric_keyed_value";
+}
+
 impl PostgresCopy for UtmPoint {
     fn copy_to<W: io::Write>(&self, writer: &mut W, _ctx: PostgresCopyContext) -> io::Result<()> {
         let UtmPoint { easting, northing } = self;
@@ -296,11 +355,16 @@ impl PostgresCopy for SingleOrPair<u64, String> {
             SingleOrPair::Single(key) => (key, None),
             SingleOrPair::Pair(key, name) => (key, Some(name))
         };
-        composite!(writer, ctx, (key, name));
+        composite!(writer, ctx, Self => (key, name));
         Ok(())
     }
 }
 
+impl CompositeType for SingleOrPair<u64, String> {
+    const ARITY: usize = 2;
+    const NAME: &'static str = "numeric_keyed_value";
+}
+
 impl PostgresCopy for Quantity {
     fn copy_to<W: io::Write>(&self, writer: &mut W, ctx: PostgresCopyContext) -> io::Result<()> {
         composite!(writer, ctx, (self.value, self.unit));
@@ -310,11 +374,16 @@ impl PostgresCopy for Quantity {
 
 impl PostgresCopy for Rate<f64> {
     fn copy_to<W: io::Write>(&self, writer: &mut W, ctx: PostgresCopyContext) -> io::Result<()> {
-        composite!(writer, ctx, (self.value, self.unit, self.per));
+        composite!(writer, ctx, Self => (self.value, self.unit, self.per));
         Ok(())
     }
 }
 
+impl CompositeType for Rate<f64> {
+    const ARITY: usize = 3;
+    const NAME: &'static str = "rate";
+}
+
 impl PostgresCopy for RateRecord {
     fn copy_to<W: io::Write>(&self, writer: &mut W, ctx: PostgresCopyContext) -> io::Result<()> {
         self.iter()
@@ -346,14 +415,20 @@ impl PostgresCopy for Duration {
 
 impl PostgresCopy for DamTargets {
     fn copy_to<W: io::Write>(&self, writer: &mut W, ctx: PostgresCopyContext) -> io::Result<()> {
+        // the composite type has no column for `other` reference bases yet
         if self.default.is_none() && self.steady.is_none() && self.max.is_none() {
             return Null.copy_to(writer, ctx);
         }
-        composite!(writer, ctx, (self.default, self.steady, self.max));
+        composite!(writer, ctx, Self => (self.default, self.steady, self.max));
         Ok(())
     }
 }
 
+impl CompositeType for DamTargets {
+    const ARITY: usize = 3;
+    const NAME: &'static str = "dam_targets";
+}
+
 impl PostgresCopy for OrFallback<LandRecord> {
     fn copy_to<W: io::Write>(&self, writer: &mut W, ctx: PostgresCopyContext) -> io::Result<()> {
         match self {
@@ -374,7 +449,12 @@ impl PostgresCopy for LegalDepartmentAbbreviation {
             LegalDepartmentAbbreviation::E => write!(writer, "E"),
             LegalDepartmentAbbreviation::F => write!(writer, "F"),
             LegalDepartmentAbbreviation::K => write!(writer, "K"),
-            LegalDepartmentAbbreviation::L => write!(writer, "L")
+            LegalDepartmentAbbreviation::L => write!(writer, "L"),
+            // the database enum has no unknown department, those are skipped
+            LegalDepartmentAbbreviation::Unknown => Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "unknown legal department cannot be exported"
+            ))
         }
     }
 }
@@ -397,11 +477,16 @@ impl PostgresCopy for PHValues {
 
 impl<'il> PostgresCopy for InjectionLimit<'il> {
     fn copy_to<W: io::Write>(&self, writer: &mut W, ctx: PostgresCopyContext) -> io::Result<()> {
-        composite!(writer, ctx, (self.substance, self.quantity));
+        composite!(writer, ctx, Self => (self.substance, self.quantity));
         Ok(())
     }
 }
 
+impl CompositeType for InjectionLimit<'_> {
+    const ARITY: usize = 2;
+    const NAME: &'static str = "injection_limit";
+}
+
 impl PostgresCopy for IsoDate<'_> {
     fn copy_to<W: io::Write>(&self, writer: &mut W, _ctx: PostgresCopyContext) -> io::Result<()> {
         match self.0 {
@@ -414,9 +499,17 @@ impl PostgresCopy for IsoDate<'_> {
 #[cfg(test)]
 mod tests {
 
+    use std::io;
     use std::io::Write;
 
-    use crate::postgres_copy::{quoted, PostgresCopy, PostgresCopyContext};
+    use nlwkn::helper_types::{OrFallback, Quantity, Rate, SingleOrPair};
+    use nlwkn::{DamTargets, PHValues, RateRecord};
+    use proptest::prelude::*;
+
+    use crate::export::InjectionLimit;
+    use crate::postgres_copy::{
+        quoted, CompositeType, IterPostgresCopy, Null, PostgresCopy, PostgresCopyContext
+    };
 
     fn ctx_depth(depth: usize) -> PostgresCopyContext {
         PostgresCopyContext {
@@ -521,4 +614,341 @@ mod tests {
         }
         assert_eq!(buffer, r#"\\"some \\"\\"quoted\\"\\" text\\""#, "depth 2");
     }
+
+    fn copy_to_string(value: impl PostgresCopy, ctx: PostgresCopyContext) -> String {
+        let mut buffer = Vec::new();
+        value.copy_to(&mut buffer, ctx).unwrap();
+        String::from_utf8(buffer).unwrap()
+    }
+
+    /// Count the top-level fields of a composite copied at depth 0.
+    fn composite_arity(value: impl PostgresCopy) -> usize {
+        let copied = copy_to_string(value, PostgresCopyContext::default());
+        let fields = copied
+            .strip_prefix('(')
+            .and_then(|copied| copied.strip_suffix(')'))
+            .unwrap_or_else(|| panic!("{copied:?} is no composite"));
+
+        let mut arity = 1;
+        let mut depth = 0;
+        let mut in_quotes = false;
+        for c in fields.chars() {
+            match (c, in_quotes) {
+                ('"', _) => in_quotes = !in_quotes,
+                ('(', false) => depth += 1,
+                (')', false) => depth -= 1,
+                (',', false) if depth == 0 => arity += 1,
+                _ => ()
+            }
+        }
+
+        arity
+    }
+
+    fn assert_arity<T: PostgresCopy + CompositeType>(value: T) {
+        assert_eq!(composite_arity(&value), T::ARITY, "arity of `{}`", T::NAME);
+    }
+
+    #[test]
+    fn composites_have_expected_arity() {
+        let quantity = || Quantity {
+            value: 3.0,
+            unit: "mg/l".to_string()
+        };
+        let substance = "Phosphor, gesamt".to_string();
+        assert_arity((substance.clone(), quantity()));
+        assert_arity(InjectionLimit {
+            substance: &substance,
+            quantity: &quantity()
+        });
+
+        assert_arity((3151025, "Sassenburg".to_string()));
+        assert_arity(SingleOrPair::Single(3151025));
+        assert_arity(SingleOrPair::Pair(3151025, "Sassenburg".to_string()));
+
+        assert_arity("2.5 m³/s".parse::<Rate<f64>>().unwrap());
+
+        let mut dam_targets = DamTargets::default();
+        dam_targets.steady = Some(quantity());
+        assert_arity(dam_targets);
+    }
+
+    #[test]
+    fn empty_rate_record_copy_to_works() {
+        let empty = RateRecord::new();
+        assert_eq!(
+            copy_to_string(&empty, PostgresCopyContext::default()),
+            r"\N"
+        );
+
+        let ctx = PostgresCopyContext {
+            compact_arrays: true,
+            ..Default::default()
+        };
+        assert_eq!(copy_to_string(&empty, ctx), "{}");
+
+        let mut filled = RateRecord::new();
+        filled.insert(OrFallback::Expected("2.5 m³/s".parse().unwrap()));
+        assert_eq!(
+            copy_to_string(&filled, ctx),
+            copy_to_string(&filled, PostgresCopyContext::default())
+        );
+    }
+
+    #[test]
+    fn dam_targets_copy_to_works() {
+        let empty = DamTargets::default();
+        assert_eq!(copy_to_string(&empty, ctx_depth(0)), r"\N", "all none");
+
+        let mut partial = DamTargets::default();
+        partial.steady = Some(Quantity {
+            value: 1.5,
+            unit: "m".to_string()
+        });
+        assert_eq!(
+            copy_to_string(&partial, ctx_depth(0)),
+            r#"(,"(1.5,\\"m\\")",)"#,
+            "only steady"
+        );
+    }
+
+    #[test]
+    fn ph_values_copy_to_works() {
+        let ph_values = |min, max| PHValues { min, max };
+        assert_eq!(
+            copy_to_string(ph_values(Some(6), Some(9)), ctx_depth(0)),
+            "[6,9]"
+        );
+        assert_eq!(
+            copy_to_string(ph_values(Some(6), None), ctx_depth(0)),
+            "[6,infinity)"
+        );
+        assert_eq!(
+            copy_to_string(ph_values(None, Some(9)), ctx_depth(0)),
+            "(-infinity,9]"
+        );
+        assert_eq!(
+            copy_to_string(ph_values(None, None), ctx_depth(0)),
+            "(-infinity,infinity)"
+        );
+    }
+
+    /// Field of a composite generated by the round trip test.
+    #[derive(Debug, Clone, PartialEq)]
+    enum Field {
+        Null,
+        Text(String),
+        Composite(Box<Pair>)
+    }
+
+    #[derive(Debug, Clone, PartialEq)]
+    struct Pair(Field, Field);
+
+    /// Column of a row generated by the round trip test.
+    #[derive(Debug, Clone, PartialEq)]
+    enum Column {
+        Text(Option<String>),
+        Composite(Pair),
+        Array(Vec<Pair>),
+        TextArray(String, String)
+    }
+
+    impl PostgresCopy for Field {
+        fn copy_to<W: Write>(&self, writer: &mut W, ctx: PostgresCopyContext) -> io::Result<()> {
+            match self {
+                Field::Null => Null.copy_to(writer, ctx),
+                Field::Text(text) => text.copy_to(writer, ctx),
+                Field::Composite(pair) => pair.copy_to(writer, ctx)
+            }
+        }
+    }
+
+    impl PostgresCopy for Pair {
+        fn copy_to<W: Write>(&self, writer: &mut W, ctx: PostgresCopyContext) -> io::Result<()> {
+            composite!(writer, ctx, (self.0, self.1));
+            Ok(())
+        }
+    }
+
+    impl PostgresCopy for Column {
+        fn copy_to<W: Write>(&self, writer: &mut W, ctx: PostgresCopyContext) -> io::Result<()> {
+            match self {
+                Column::Text(text) => text.copy_to(writer, ctx),
+                Column::Composite(pair) => pair.copy_to(writer, ctx),
+                Column::Array(pairs) => pairs.iter().copy_to(writer, ctx),
+                Column::TextArray(first, second) => (first, second).copy_to(writer, ctx)
+            }
+        }
+    }
+
+    /// Split a line of the `COPY` text into its unescaped columns, `None` for
+    /// `\N`.
+    fn parse_copy_line(line: &str) -> Vec<Option<String>> {
+        let line = line.strip_suffix('\n').expect("line should end with a newline");
+        line.split('\t')
+            .map(|column| match column {
+                r"\N" => None,
+                column => {
+                    let mut unescaped = String::new();
+                    let mut chars = column.chars();
+                    while let Some(c) = chars.next() {
+                        unescaped.push(match c {
+                            '\\' => match chars.next().expect("escaped character") {
+                                'n' => '\n',
+                                'r' => '\r',
+                                't' => '\t',
+                                c => c
+                            },
+                            c => c
+                        });
+                    }
+                    Some(unescaped)
+                }
+            })
+            .collect()
+    }
+
+    /// Split the literal of a composite or array into its fields, like
+    /// `record_in` and `array_in` of Postgres do.
+    ///
+    /// Returns every field with whether it was quoted.
+    fn split_literal(literal: &str, open: char, close: char) -> Vec<(String, bool)> {
+        let inner = literal
+            .strip_prefix(open)
+            .and_then(|literal| literal.strip_suffix(close))
+            .unwrap_or_else(|| panic!("{literal:?} should be enclosed in {open}{close}"));
+
+        let mut fields = Vec::new();
+        let mut chars = inner.chars().peekable();
+        loop {
+            let mut field = String::new();
+            let mut quoted = false;
+            let mut in_quotes = false;
+            let mut more = false;
+            while let Some(c) = chars.next() {
+                match (c, in_quotes) {
+                    (',', false) => {
+                        more = true;
+                        break;
+                    }
+                    ('\\', _) => field.push(chars.next().expect("escaped character")),
+                    // only composites read doubled quotes
+                    ('"', true) if open == '(' && chars.peek() == Some(&'"') => {
+                        chars.next();
+                        field.push('"');
+                    }
+                    ('"', _) => {
+                        in_quotes = !in_quotes;
+                        quoted = true;
+                    }
+                    (c, _) => field.push(c)
+                }
+            }
+            assert!(!in_quotes, "unterminated quote in {literal:?}");
+            fields.push((field, quoted));
+            if !more {
+                return fields;
+            }
+        }
+    }
+
+    fn parse_composite(literal: &str) -> Vec<Option<String>> {
+        split_literal(literal, '(', ')')
+            .into_iter()
+            .map(|(field, quoted)| (quoted || !field.is_empty()).then_some(field))
+            .collect()
+    }
+
+    fn parse_array(literal: &str) -> Vec<Option<String>> {
+        split_literal(literal, '{', '}')
+            .into_iter()
+            .map(|(field, quoted)| (quoted || field != "NULL").then_some(field))
+            .collect()
+    }
+
+    fn decode_field(like: &Field, text: Option<String>) -> Field {
+        match (like, text) {
+            (_, None) => Field::Null,
+            (Field::Composite(pair), Some(text)) => {
+                Field::Composite(Box::new(decode_pair(pair, &text)))
+            }
+            (_, Some(text)) => Field::Text(text)
+        }
+    }
+
+    fn decode_pair(like: &Pair, literal: &str) -> Pair {
+        let [first, second]: [Option<String>; 2] = parse_composite(literal)
+            .try_into()
+            .unwrap_or_else(|fields| panic!("{literal:?} should have 2 fields, got {fields:?}"));
+        Pair(decode_field(&like.0, first), decode_field(&like.1, second))
+    }
+
+    /// Decode a column of the `COPY` text using `like` to know its type.
+    fn decode_column(like: &Column, text: Option<String>) -> Column {
+        match like {
+            Column::Text(_) => Column::Text(text),
+            Column::Composite(pair) => Column::Composite(decode_pair(pair, &text.unwrap())),
+            Column::Array(pairs) => {
+                let elements = parse_array(&text.unwrap());
+                assert_eq!(elements.len(), pairs.len());
+                Column::Array(
+                    pairs
+                        .iter()
+                        .zip(elements)
+                        .map(|(pair, element)| decode_pair(pair, &element.unwrap()))
+                        .collect()
+                )
+            }
+            Column::TextArray(..) => match parse_array(&text.unwrap()).as_slice() {
+                [Some(first), Some(second)] => Column::TextArray(first.clone(), second.clone()),
+                elements => panic!("expected 2 elements, got {elements:?}")
+            }
+        }
+    }
+
+    fn text() -> impl Strategy<Value = String> {
+        r#"[a-zß ,(){}"\\\t\n\r]{0,6}"#
+    }
+
+    fn pair() -> impl Strategy<Value = Pair> {
+        let leaf = prop_oneof![Just(Field::Null), text().prop_map(Field::Text)];
+        let field = leaf.prop_recursive(3, 8, 2, |inner| {
+            (inner.clone(), inner)
+                .prop_map(|(first, second)| Field::Composite(Box::new(Pair(first, second))))
+        });
+        (field.clone(), field).prop_map(|(first, second)| Pair(first, second))
+    }
+
+    fn column() -> impl Strategy<Value = Column> {
+        prop_oneof![
+            proptest::option::of(text()).prop_map(Column::Text),
+            pair().prop_map(Column::Composite),
+            proptest::collection::vec(pair(), 1..3).prop_map(Column::Array),
+            (text(), text()).prop_map(|(first, second)| Column::TextArray(first, second))
+        ]
+    }
+
+    proptest! {
+        #[test]
+        fn copy_text_round_trips(columns in proptest::collection::vec(column(), 1..4)) {
+            let mut line = Vec::new();
+            for (i, column) in columns.iter().enumerate() {
+                if i > 0 {
+                    line.write_all(b"\t").unwrap();
+                }
+                column.copy_to(&mut line, PostgresCopyContext::default()).unwrap();
+            }
+            line.write_all(b"\n").unwrap();
+            let line = String::from_utf8(line).unwrap();
+
+            let parsed = parse_copy_line(&line);
+            prop_assert_eq!(parsed.len(), columns.len(), "columns of {:?}", line);
+            let decoded: Vec<Column> = columns
+                .iter()
+                .zip(parsed)
+                .map(|(column, text)| decode_column(column, text))
+                .collect();
+            prop_assert_eq!(decoded, columns, "copy text {:?}", line);
+        }
+    }
 }
@@ -0,0 +1,173 @@
+//! Structural check of SQL without a database.
+//!
+//! This is no SQL parser, it only finds the mistakes that break a whole
+//! script, like unclosed quotes or unbalanced parentheses, which is enough to
+//! catch a broken `init.sql` before connecting to a database.
+
+/// Check that quotes, dollar quotes and comments are closed and that the
+/// parentheses of every statement are balanced.
+///
+/// Returns the number of statements, a last statement without `;` counts as
+/// well.
+pub fn check_sql(sql: &str) -> anyhow::Result<usize> {
+    let line_of = |index: usize| sql[..index].matches('\n').count() + 1;
+    let unclosed = |what: &str, start: usize| {
+        anyhow::Error::msg(format!(
+            "{what} opened in line {} is not closed",
+            line_of(start)
+        ))
+    };
+
+    let mut statements = 0;
+    let mut in_statement = false;
+    let mut open_parens = Vec::new();
+    let mut index = 0;
+    while let Some(c) = sql[index..].chars().next() {
+        let rest = &sql[index..];
+        let start = index;
+        index += c.len_utf8();
+
+        match c {
+            '-' if rest.starts_with("--") => {
+                index = rest.find('\n').map(|end| start + end).unwrap_or(sql.len());
+                continue;
+            }
+            '/' if rest.starts_with("/*") => {
+                index =
+                    start + block_comment_len(rest).ok_or_else(|| unclosed("comment", start))?;
+                continue;
+            }
+            '\'' | '"' => {
+                index = start + quoted_len(rest, c).ok_or_else(|| unclosed("quote", start))?;
+            }
+            '$' => {
+                if let Some(tag) = dollar_quote_tag(rest) {
+                    let end = rest[tag.len()..]
+                        .find(tag)
+                        .ok_or_else(|| unclosed(&format!("dollar quote {tag}"), start))?;
+                    index = start + tag.len() + end + tag.len();
+                }
+            }
+            '(' => open_parens.push(start),
+            ')' if open_parens.pop().is_none() => {
+                return Err(anyhow::Error::msg(format!(
+                    "unexpected ')' in line {}",
+                    line_of(start)
+                )));
+            }
+            ';' => {
+                if let Some(open) = open_parens.last() {
+                    return Err(unclosed("parenthesis", *open));
+                }
+                if in_statement {
+                    statements += 1;
+                }
+                in_statement = false;
+                continue;
+            }
+            _ => ()
+        }
+
+        in_statement |= !c.is_whitespace();
+    }
+
+    if let Some(open) = open_parens.last() {
+        return Err(unclosed("parenthesis", *open));
+    }
+    if in_statement {
+        statements += 1;
+    }
+
+    Ok(statements)
+}
+
+/// Length of a comment starting with `/*`, comments nest in postgres.
+fn block_comment_len(sql: &str) -> Option<usize> {
+    let mut depth = 0;
+    let mut index = 0;
+    while index < sql.len() {
+        let rest = &sql[index..];
+        if rest.starts_with("/*") {
+            depth += 1;
+            index += 2;
+        }
+        else if rest.starts_with("*/") {
+            depth -= 1;
+            index += 2;
+            if depth == 0 {
+                return Some(index);
+            }
+        }
+        else {
+            index += rest.chars().next()?.len_utf8();
+        }
+    }
+
+    None
+}
+
+/// Length of a string or identifier starting with `quote`, doubled quotes
+/// are escaped.
+fn quoted_len(sql: &str, quote: char) -> Option<usize> {
+    let mut chars = sql.char_indices().skip(1).peekable();
+    while let Some((index, c)) = chars.next() {
+        if c != quote {
+            continue;
+        }
+        match chars.peek() {
+            Some((_, next)) if *next == quote => {
+                chars.next();
+            }
+            _ => return Some(index + c.len_utf8())
+        }
+    }
+
+    None
+}
+
+/// Tag of a dollar quote like `$$` or `$body$` at the start of `sql`,
+/// parameters like `$1` are no dollar quotes.
+fn dollar_quote_tag(sql: &str) -> Option<&str> {
+    let name_len =
+        sql[1..].find(|c: char| !(c.is_alphanumeric() || c == '_')).unwrap_or(sql.len() - 1);
+    let name = &sql[1..1 + name_len];
+    if name.starts_with(|c: char| c.is_ascii_digit()) || !sql[1 + name_len..].starts_with('$') {
+        return None;
+    }
+
+    Some(&sql[..name_len + 2])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn check_sql_works() {
+        let sql = r#"
+            -- schema for the water rights; with a comment
+            CREATE SCHEMA IF NOT EXISTS water_rights;
+            /* nested /* comment; */ ( */
+            CREATE TABLE water_rights.rights (
+                id bigint PRIMARY KEY,
+                "holder; name" text DEFAULT 'it''s (open'
+            );
+            CREATE FUNCTION f() RETURNS int AS $body$ SELECT 1; ) $body$ LANGUAGE sql;
+            SELECT $1::int, $$;$$
+        "#;
+        assert_eq!(check_sql(sql).unwrap(), 4);
+        assert_eq!(check_sql("").unwrap(), 0);
+        assert_eq!(check_sql(" ; ;").unwrap(), 0);
+
+        let error = check_sql("SELECT 1;\nCREATE TABLE t (id int;").unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "parenthesis opened in line 2 is not closed"
+        );
+        assert!(check_sql("SELECT 1);").is_err());
+        assert!(check_sql("SELECT 'open;").is_err());
+        assert!(check_sql("SELECT \"open;").is_err());
+        assert!(check_sql("SELECT $x$ open;").is_err());
+        assert!(check_sql("/* open").is_err());
+    }
+}
@@ -0,0 +1,160 @@
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+use std::path::{Path, PathBuf};
+
+use nlwkn::WaterRight;
+
+use crate::export::{WaterRightIter, WaterRightSource};
+
+/// How the water rights are laid out in a streamed reports file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StreamFormat {
+    /// JSONL with one water right per line.
+    Lines,
+
+    /// Reports JSON with an array of water rights, as written by the parser.
+    Array
+}
+
+/// Reports read one water right at a time for every copied table instead of
+/// being loaded at once.
+pub struct StreamedReports {
+    path: PathBuf,
+    format: StreamFormat,
+    count: usize,
+    exclude_coords: bool
+}
+
+impl StreamedReports {
+    /// Open a reports file and count its water rights.
+    ///
+    /// JSONL water rights are only validated while they are read, arrays are
+    /// read completely for counting and therefore validated right away.
+    pub fn open(
+        path: impl AsRef<Path>,
+        format: StreamFormat,
+        exclude_coords: bool
+    ) -> anyhow::Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        let mut count = 0;
+        match format {
+            StreamFormat::Lines => {
+                for line in BufReader::new(File::open(&path)?).lines() {
+                    if !line?.trim().is_empty() {
+                        count += 1;
+                    }
+                }
+            }
+            StreamFormat::Array => {
+                for water_right in nlwkn::stream_water_rights_array(&path)? {
+                    water_right?;
+                    count += 1;
+                }
+            }
+        }
+
+        Ok(StreamedReports {
+            path,
+            format,
+            count,
+            exclude_coords
+        })
+    }
+}
+
+impl WaterRightSource for StreamedReports {
+    type WaterRight = WaterRight;
+
+    fn len(&self) -> usize {
+        self.count
+    }
+
+    fn read(&self) -> anyhow::Result<WaterRightIter<'_, Self::WaterRight>> {
+        let exclude_coords = self.exclude_coords;
+        let water_rights: WaterRightIter<'_, WaterRight> = match self.format {
+            StreamFormat::Lines => Box::new(nlwkn::stream_water_rights(&self.path)?),
+            StreamFormat::Array => Box::new(nlwkn::stream_water_rights_array(&self.path)?)
+        };
+        let water_rights = water_rights.map(move |water_right| {
+            let mut water_right = water_right?;
+            if exclude_coords {
+                water_right.exclude_coordinates();
+            }
+            Ok(water_right)
+        });
+        Ok(Box::new(water_rights))
+    }
+}
+
+/// Check if a reports file should be streamed and how.
+///
+/// Files ending in `.jsonl` are always streamed, other reports only with
+/// `--stream`.
+pub fn stream_format(path: &Path, stream: bool) -> Option<StreamFormat> {
+    match path.extension().map(|extension| extension == "jsonl").unwrap_or(false) {
+        true => Some(StreamFormat::Lines),
+        false => stream.then_some(StreamFormat::Array)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+    use crate::export;
+    use crate::postgres_copy::PostgresCopyContext;
+
+    #[test]
+    fn streamed_dump_matches_loaded_dump() {
+        let reports_json = concat!(env!("CARGO_MANIFEST_DIR"), "/test/reports.json");
+        let mut water_rights = nlwkn::load_water_rights(reports_json).unwrap();
+        water_rights.extend(
+            nlwkn::load_water_rights(concat!(
+                env!("CARGO_MANIFEST_DIR"),
+                "/test/pdf-only-reports.json"
+            ))
+            .unwrap()
+        );
+
+        let dir = tempfile::tempdir().unwrap();
+        let jsonl_path = dir.path().join("reports.jsonl");
+        let mut jsonl = String::new();
+        for water_right in water_rights.iter() {
+            jsonl.push_str(&serde_json::to_string(water_right).unwrap());
+            jsonl.push('\n');
+        }
+        fs::write(&jsonl_path, jsonl).unwrap();
+        let json_path = dir.path().join("reports.json");
+        fs::write(&json_path, serde_json::to_string(&water_rights).unwrap()).unwrap();
+        let reports = StreamedReports::open(&jsonl_path, StreamFormat::Lines, true).unwrap();
+        assert_eq!(reports.len(), 2);
+        assert_eq!(stream_format(&jsonl_path, false), Some(StreamFormat::Lines));
+        assert_eq!(stream_format(Path::new(reports_json), false), None);
+        assert_eq!(
+            stream_format(Path::new(reports_json), true),
+            Some(StreamFormat::Array)
+        );
+
+        let ctx = PostgresCopyContext::default();
+        let mut streamed = Vec::new();
+        export::water_rights_to_sql(&mut streamed, &reports, false, None, ctx).unwrap();
+
+        let reports = StreamedReports::open(&json_path, StreamFormat::Array, true).unwrap();
+        assert_eq!(reports.len(), 2);
+        let mut streamed_array = Vec::new();
+        export::water_rights_to_sql(&mut streamed_array, &reports, false, None, ctx).unwrap();
+
+        water_rights.iter_mut().for_each(|water_right| {
+            water_right.exclude_coordinates();
+        });
+        let mut loaded = Vec::new();
+        export::water_rights_to_sql(&mut loaded, &water_rights.as_slice(), false, None, ctx)
+            .unwrap();
+        assert_eq!(
+            String::from_utf8(streamed),
+            String::from_utf8(loaded.clone())
+        );
+        assert_eq!(String::from_utf8(streamed_array), String::from_utf8(loaded));
+    }
+}
@@ -0,0 +1,125 @@
+use std::time::{Duration, Instant};
+
+use parking_lot::Mutex;
+
+/// Token bucket limiting the requests per second sent to cadenza.
+///
+/// The bucket holds up to one second worth of requests, so a burst after an
+/// idle phase is sent immediately and every later request waits for its
+/// token.
+/// Every request takes a token when it asks for one, even if it has to wait
+/// for it, this keeps the limiter fair between concurrent tasks without
+/// holding the lock while waiting.
+#[derive(Debug)]
+pub struct RateLimiter {
+    max_rps: Option<f64>,
+    bucket: Mutex<Bucket>
+}
+
+#[derive(Debug)]
+struct Bucket {
+    /// Available tokens, negative if requests are waiting for their token.
+    tokens: f64,
+    refilled_at: Instant
+}
+
+impl RateLimiter {
+    /// Limiter for at most `max_rps` requests per second, `None` does not
+    /// limit at all.
+    pub fn new(max_rps: Option<f64>) -> Self {
+        Self::starting_at(max_rps, Instant::now())
+    }
+
+    fn starting_at(max_rps: Option<f64>, now: Instant) -> Self {
+        RateLimiter {
+            max_rps,
+            bucket: Mutex::new(Bucket {
+                tokens: max_rps.map(capacity).unwrap_or_default(),
+                refilled_at: now
+            })
+        }
+    }
+
+    /// Wait until the next request may be sent.
+    pub async fn acquire(&self) {
+        let wait = self.reserve(Instant::now());
+        if !wait.is_zero() {
+            tokio::time::sleep(wait).await;
+        }
+    }
+
+    /// Take a token at `now`, returns how long to wait until it is available.
+    fn reserve(&self, now: Instant) -> Duration {
+        let Some(max_rps) = self.max_rps
+        else {
+            return Duration::ZERO;
+        };
+
+        let mut bucket = self.bucket.lock();
+        let elapsed = now.saturating_duration_since(bucket.refilled_at).as_secs_f64();
+        bucket.tokens = (bucket.tokens + elapsed * max_rps).min(capacity(max_rps));
+        bucket.refilled_at = bucket.refilled_at.max(now);
+        bucket.tokens -= 1.0;
+
+        match bucket.tokens < 0.0 {
+            true => Duration::from_secs_f64(-bucket.tokens / max_rps),
+            false => Duration::ZERO
+        }
+    }
+}
+
+/// Tokens the bucket holds at most, at least one to send any request.
+fn capacity(max_rps: f64) -> f64 {
+    max_rps.max(1.0)
+}
+
+/// Parse the requests per second of `--max-rps`.
+pub fn parse_max_rps(max_rps: &str) -> Result<f64, String> {
+    match max_rps.trim().parse::<f64>() {
+        Ok(max_rps) if max_rps.is_finite() && max_rps > 0.0 => Ok(max_rps),
+        Ok(_) => Err("requests per second must be positive".to_string()),
+        Err(err) => Err(err.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn millis(duration: Duration) -> u128 {
+        duration.as_millis()
+    }
+
+    #[test]
+    fn rate_limiter_throttles_burst() {
+        let start = Instant::now();
+        let limiter = RateLimiter::starting_at(Some(2.0), start);
+
+        // the first two requests use the full bucket, the others wait in turn
+        let waits: Vec<u128> = (0..5).map(|_| millis(limiter.reserve(start))).collect();
+        assert_eq!(waits, [0, 0, 500, 1000, 1500]);
+
+        // once the waiting requests are sent, the bucket refills up to its capacity
+        let later = start + Duration::from_secs(5);
+        let waits: Vec<u128> = (0..3).map(|_| millis(limiter.reserve(later))).collect();
+        assert_eq!(waits, [0, 0, 500]);
+
+        let slow = RateLimiter::starting_at(Some(0.5), start);
+        assert_eq!(millis(slow.reserve(start)), 0);
+        assert_eq!(millis(slow.reserve(start)), 2000);
+        assert_eq!(millis(slow.reserve(start + Duration::from_secs(1))), 3000);
+
+        let unlimited = RateLimiter::starting_at(None, start);
+        assert!((0..100).all(|_| unlimited.reserve(start).is_zero()));
+    }
+
+    #[test]
+    fn parse_max_rps_works() {
+        assert_eq!(parse_max_rps("2"), Ok(2.0));
+        assert_eq!(parse_max_rps("0.5"), Ok(0.5));
+        assert!(parse_max_rps("0").is_err());
+        assert!(parse_max_rps("-1").is_err());
+        assert!(parse_max_rps("inf").is_err());
+        assert!(parse_max_rps("fast").is_err());
+    }
+}
@@ -1,24 +1,34 @@
 use std::cmp::Ordering;
-use std::collections::BTreeSet;
+use std::collections::hash_map::RandomState;
+use std::collections::{BTreeMap, BTreeSet};
+use std::hash::{BuildHasher, Hasher};
 use std::path::{Path, PathBuf};
-use std::time::Duration;
-use std::{fs, io};
+use std::process::ExitCode;
+use std::time::{Duration, Instant};
+use std::{env, fs, io};
 
+use chrono::{Local, NaiveDate};
 use clap::Parser;
-use console::{Alignment, Color};
+use console::{Alignment, Color, Term};
 use indicatif::ProgressBar;
 use nlwkn::cadenza::{CadenzaTable, CadenzaTableRow};
-use nlwkn::cli::{progress_message, ProgressBarGuard, PRINT_PADDING};
+use nlwkn::cli::{self, progress_message, ProgressBarGuard, PRINT_PADDING};
 use nlwkn::WaterRightNo;
 use reqwest::redirect::Policy;
 use thiserror::Error;
 
+use crate::limit::RateLimiter;
 use crate::req::FetchReportUrlError;
+use crate::session::JSessionId;
 use crate::tor::start_socks_proxy;
+use crate::watch::{WatchSchedule, WatchTargets};
 
 // mod browse;
+mod limit;
 mod req;
+mod session;
 mod tor;
+mod watch;
 
 static_toml::static_toml! {
     static CONFIG = include_toml!("config.toml");
@@ -38,20 +48,237 @@ struct Args {
 
     /// Ignore already downloaded files
     #[clap(long)]
-    force: bool
+    force: bool,
+
+    /// Skip the confirmation for large fetches
+    #[clap(long)]
+    yes: bool,
+
+    /// Download into a `YYYY-MM-DD` subdirectory of the reports directory
+    #[clap(long)]
+    date_subdir: bool,
+
+    /// Keep running and fetch new and changed water rights every interval
+    ///
+    /// The xlsx file is read again on every cycle.
+    #[clap(long, requires = "xlsx_path", conflicts_with = "water_right_no")]
+    watch: bool,
+
+    /// Only fetch water rights with a usage location in this county, can be
+    /// repeated
+    #[clap(long, value_name = "COUNTY", conflicts_with = "water_right_no")]
+    county: Vec<String>,
+
+    /// Only fetch water rights with a legal department starting with this, like
+    /// `Entnahme`
+    #[clap(long, value_name = "PREFIX", conflicts_with = "water_right_no")]
+    legal_department: Option<String>,
+
+    /// Interval between the cycles of `--watch`, like `30m`, `6h` or `1d`
+    #[clap(long, value_parser = watch::parse_interval, default_value = "1d")]
+    interval: Duration,
+
+    /// Base of the exponential backoff between retries, overrides
+    /// `cadenza.backoff_base` of the config
+    #[clap(long)]
+    backoff_base: Option<u64>,
+
+    /// Longest wait in seconds between retries, overrides `cadenza.backoff_cap`
+    /// of the config
+    #[clap(long)]
+    backoff_cap: Option<u64>,
+
+    /// Fraction every wait between retries is randomly stretched or shortened
+    /// by, overrides `cadenza.backoff_jitter` of the config
+    #[clap(long, value_name = "FRACTION")]
+    backoff_jitter: Option<f64>,
+
+    /// Send at most this many requests per second to cadenza
+    ///
+    /// Counts every request, including the steps to find the url of a report.
+    /// Without this, requests are sent as fast as cadenza responds.
+    #[clap(long, value_parser = limit::parse_max_rps)]
+    max_rps: Option<f64>
+}
+
+/// Amount of reports from which on a fetch needs to be confirmed.
+const LARGE_FETCH_THRESHOLD: usize = 50_000;
+
+/// Rough time a single report takes to fetch, used for estimations.
+const ESTIMATED_FETCH_DURATION: Duration = Duration::from_secs(8);
+
+#[derive(Debug, PartialEq, Eq)]
+enum FetchConfirmation {
+    NotNeeded,
+    Prompt,
+    Refuse
+}
+
+/// Decide whether fetching `count` reports needs a confirmation.
+///
+/// Without a user attending the terminal no prompt can be answered, so large
+/// fetches are refused unless `--yes` was passed.
+fn fetch_confirmation(count: usize, yes: bool, interactive: bool) -> FetchConfirmation {
+    match (count > LARGE_FETCH_THRESHOLD, yes, interactive) {
+        (false, _, _) | (true, true, _) => FetchConfirmation::NotNeeded,
+        (true, false, true) => FetchConfirmation::Prompt,
+        (true, false, false) => FetchConfirmation::Refuse
+    }
+}
+
+/// Wait between the retries of a report.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Backoff {
+    base: u64,
+    cap: Duration,
+
+    /// Fraction of the wait it is randomly changed by, between 0 and 1.
+    jitter: f64
+}
+
+impl Backoff {
+    fn new(base: u64, cap_secs: u64, jitter: f64) -> Result<Self, String> {
+        match (base, cap_secs, jitter) {
+            (0, _, _) => Err("backoff base must be at least 1".to_string()),
+            (_, 0, _) => Err("backoff cap must be at least 1 second".to_string()),
+            (_, _, jitter) if !(0.0..=1.0).contains(&jitter) => {
+                Err("backoff jitter must be between 0 and 1".to_string())
+            }
+            (base, cap_secs, jitter) => Ok(Backoff {
+                base,
+                cap: Duration::from_secs(cap_secs),
+                jitter
+            })
+        }
+    }
+
+    /// Backoff from the config with the overrides of the command line.
+    fn from_args(args: &Args) -> Result<Self, String> {
+        let from_config = |key: &str, value: i64| {
+            u64::try_from(value).map_err(|_| format!("cadenza.{key} must not be negative"))
+        };
+        let base = match args.backoff_base {
+            Some(base) => base,
+            None => from_config("backoff_base", CONFIG.cadenza.backoff_base)?
+        };
+        let cap = match args.backoff_cap {
+            Some(cap) => cap,
+            None => from_config("backoff_cap", CONFIG.cadenza.backoff_cap)?
+        };
+        let jitter = args.backoff_jitter.unwrap_or(CONFIG.cadenza.backoff_jitter);
+        Backoff::new(base, cap, jitter)
+    }
+
+    /// Wait before the `retry`th retry, `base ^ retry` seconds up to the cap.
+    fn wait(&self, retry: u32) -> Duration {
+        Duration::from_secs(self.base.saturating_pow(retry)).min(self.cap)
+    }
+
+    /// [`wait`](Self::wait) changed randomly by up to the jitter, still at most
+    /// the cap.
+    ///
+    /// Without jitter all workers failing at once would also retry at once.
+    fn jittered_wait(&self, retry: u32) -> Duration {
+        self.with_jitter(self.wait(retry), random_fraction())
+    }
+
+    /// Stretch or shorten `wait` by the jitter, `random` between 0 and 1 picks
+    /// the change, 0.5 keeps the wait as it is.
+    fn with_jitter(&self, wait: Duration, random: f64) -> Duration {
+        let factor = 1.0 + self.jitter * (2.0 * random - 1.0);
+        wait.mul_f64(factor).min(self.cap)
+    }
+}
+
+/// Random number between 0 and 1, good enough to spread out retries.
+///
+/// Every [`RandomState`] is seeded differently, hashing nothing with it yields
+/// a random number without pulling in a random number generator.
+fn random_fraction() -> f64 {
+    let random = RandomState::new().build_hasher().finish();
+    (random >> 11) as f64 / (1u64 << 53) as f64
 }
 
 #[tokio::main]
-async fn main() {
+async fn main() -> ExitCode {
+    if cli::print_completions::<Args, _, _>(
+        env!("CARGO_BIN_NAME"),
+        env::args_os(),
+        &mut io::stdout()
+    ) {
+        return ExitCode::SUCCESS;
+    }
+
     let args = Args::parse();
+    let backoff = match Backoff::from_args(&args) {
+        Ok(backoff) => backoff,
+        Err(err) => {
+            eprintln!("{} {err}", console::style("Error").red());
+            return ExitCode::FAILURE;
+        }
+    };
     let _proxy_handle = tokio::spawn(start_socks_proxy());
-
-    let to_fetch = match (args.water_right_no, args.xlsx_path) {
+    let limiter = RateLimiter::new(args.max_rps);
+
+    let cadenza_table = match args.xlsx_path.as_deref() {
+        Some(xlsx_path) => match read_cadenza_table(xlsx_path, &args) {
+            Ok(cadenza_table) => Some(cadenza_table),
+            Err(err) => {
+                eprintln!(
+                    "{} could not parse table, {err:#}",
+                    console::style("Error").red()
+                );
+                return ExitCode::FAILURE;
+            }
+        },
+        None => None
+    };
+    let to_fetch = match (args.water_right_no, &cadenza_table) {
         (Some(no), _) => vec![no],
-        (None, Some(xlsx_path)) => collect_no_from_cadenza_table(&xlsx_path),
+        (None, Some(cadenza_table)) => cadenza_table.rows().iter().map(|row| row.no).collect(),
         (None, None) => unreachable!("handled by clap")
     };
 
+    if to_fetch.is_empty() {
+        match args.county.is_empty() && args.legal_department.is_none() {
+            true => eprintln!(
+                "{} the table contains no water rights, is this the correct xlsx file?",
+                console::style("Warning").yellow()
+            ),
+            false => eprintln!(
+                "{} no water rights of the table match --county and --legal-department",
+                console::style("Warning").yellow()
+            )
+        }
+        return ExitCode::FAILURE;
+    }
+
+    let estimated_hours = (ESTIMATED_FETCH_DURATION * to_fetch.len() as u32).as_secs() / 3600;
+    match fetch_confirmation(to_fetch.len(), args.yes, console::user_attended()) {
+        FetchConfirmation::NotNeeded => (),
+        FetchConfirmation::Prompt => {
+            eprint!(
+                "{} about to fetch {} reports, this takes roughly {estimated_hours} hours. \
+                 Continue? [y/N] ",
+                console::style("Warning").yellow(),
+                to_fetch.len()
+            );
+            let answer = Term::stderr().read_line().unwrap_or_default();
+            if !matches!(answer.trim(), "y" | "Y" | "yes") {
+                return ExitCode::FAILURE;
+            }
+        }
+        FetchConfirmation::Refuse => {
+            eprintln!(
+                "{} about to fetch {} reports, this takes roughly {estimated_hours} hours, pass \
+                 --yes to confirm",
+                console::style("Error").red(),
+                to_fetch.len()
+            );
+            return ExitCode::FAILURE;
+        }
+    }
+
     let client = reqwest::ClientBuilder::new()
         .proxy(
             reqwest::Proxy::http(format!("socks5://localhost:{}", *tor::SOCKS_PORT).as_str())
@@ -68,14 +295,112 @@ async fn main() {
         }
     }
 
-    fs::create_dir_all(CONFIG.data.reports).expect("could not create necessary directories");
+    let mut session = load_session(&client, &limiter).await;
+
+    if let (true, Some(xlsx_path), Some(cadenza_table)) =
+        (args.watch, args.xlsx_path.as_deref(), cadenza_table)
+    {
+        watch(
+            xlsx_path,
+            cadenza_table,
+            &client,
+            &limiter,
+            backoff,
+            session,
+            &args
+        )
+        .await;
+    }
+
+    let FetchSummary { unfetched, .. } = fetch_reports(
+        to_fetch,
+        &BTreeSet::new(),
+        &client,
+        &limiter,
+        backoff,
+        &mut session,
+        &args
+    )
+    .await;
+    save_session(session.as_ref());
+    match unfetched.is_empty() {
+        false => println!(
+            "{}, could not fetch: {}",
+            console::style("Fetching done").magenta(),
+            unfetched.iter().map(|no| no.to_string()).collect::<Vec<String>>().join(", ")
+        ),
+        true => println!("{}", console::style("Fetched all reports").magenta())
+    }
+
+    ExitCode::SUCCESS
+}
+
+/// Session saved by a prior run, if cadenza still accepts it.
+///
+/// A session cadenza rejects is discarded, the first report then starts a new
+/// one.
+async fn load_session(client: &reqwest::Client, limiter: &RateLimiter) -> Option<JSessionId> {
+    let session_path = Path::new(CONFIG.data.session);
+    let session = JSessionId::load(session_path)?;
+
+    let _pb = ProgressBarGuard::new_wait_spinner("Checking saved session...");
+    if req::probe_session(&session, client, limiter).await {
+        return Some(session);
+    }
+
+    if let Err(e) = JSessionId::discard(session_path) {
+        eprintln!(
+            "{} could not discard expired session, {e}",
+            console::style("Warning").yellow()
+        );
+    }
+    None
+}
+
+/// Save the current session for the next run, failing to do so only costs a
+/// new session.
+fn save_session(session: Option<&JSessionId>) {
+    let session_path = Path::new(CONFIG.data.session);
+    let saved = match session {
+        Some(session) => session.save(session_path),
+        None => JSessionId::discard(session_path)
+    };
+    if let Err(e) = saved {
+        eprintln!(
+            "{} could not save session, {e}",
+            console::style("Warning").yellow()
+        );
+    }
+}
+
+/// Outcome of [`fetch_reports`].
+#[derive(Debug, Default)]
+struct FetchSummary {
+    fetched: usize,
+    unfetched: Vec<WaterRightNo>
+}
+
+/// Fetch the reports of `to_fetch` that are not downloaded yet.
+///
+/// Reports in `refetch` are fetched again even if already downloaded.
+async fn fetch_reports(
+    to_fetch: Vec<WaterRightNo>,
+    refetch: &BTreeSet<WaterRightNo>,
+    client: &reqwest::Client,
+    limiter: &RateLimiter,
+    backoff: Backoff,
+    session: &mut Option<JSessionId>,
+    args: &Args
+) -> FetchSummary {
+    let reports_dir = reports_dir(args.date_subdir.then(|| Local::now().date_naive()));
+    fs::create_dir_all(&reports_dir).expect("could not create necessary directories");
 
     let mut fetched_reports = match args.force {
         true => BTreeSet::new(),
         false => {
             let _pb = ProgressBarGuard::new_wait_spinner("Fetching already downloaded reports...");
             BTreeSet::from_iter(
-                find_fetched_reports()
+                find_fetched_reports(&reports_dir)
                     .expect("could not find already fetched reports")
                     .iter()
                     .copied()
@@ -83,7 +408,9 @@ async fn main() {
         }
     };
 
-    let mut unfetched_reports = Vec::new();
+    fetched_reports.retain(|no| !refetch.contains(no));
+
+    let mut summary = FetchSummary::default();
 
     let progress = ProgressBar::new(to_fetch.len() as u64)
         .with_style(nlwkn::cli::PROGRESS_STYLE.clone())
@@ -106,12 +433,13 @@ async fn main() {
         progress.tick();
 
         for retry in 1..=(CONFIG.cadenza.retries as u32) {
-            let fetched = fetch(water_right_no, &client).await;
+            let fetched = fetch(water_right_no, client, limiter, session, &reports_dir).await;
             match fetched {
                 Ok(_) => {
                     progress_message(&progress, "Fetched", Color::Green, water_right_no);
                     progress.inc(1);
                     fetched_reports.insert(water_right_no);
+                    summary.fetched += 1;
                     continue 'wr_loop;
                 }
 
@@ -126,6 +454,16 @@ async fn main() {
                     continue 'wr_loop;
                 }
 
+                // every attempt starts a new session, no need to wait for that
+                Err(FetchError::ReportUrl(FetchReportUrlError::SessionExpired)) => {
+                    progress_message(
+                        &progress,
+                        "Warning",
+                        Color::Yellow,
+                        format!("session expired while fetching {water_right_no}, retrying")
+                    );
+                }
+
                 Err(err) => {
                     progress_message(
                         &progress,
@@ -134,18 +472,18 @@ async fn main() {
                         format!("failed to fetch, {err}")
                     );
 
-                    // use quadratic backoff for wait until retry
-                    let wait = 2u64.pow(retry);
+                    let wait = backoff.jittered_wait(retry);
                     progress.println(format!(
-                        "{}  will try again in {wait} seconds...",
-                        console::pad_str("", PRINT_PADDING, Alignment::Right, None)
+                        "{}  will try again in {} seconds...",
+                        console::pad_str("", PRINT_PADDING, Alignment::Right, None),
+                        wait.as_secs()
                     ));
-                    tokio::time::sleep(Duration::from_secs(wait)).await;
+                    tokio::time::sleep(wait).await;
                 }
             }
         }
 
-        unfetched_reports.push(water_right_no);
+        summary.unfetched.push(water_right_no);
         progress_message(
             &progress,
             "Warning",
@@ -156,13 +494,74 @@ async fn main() {
     }
 
     progress.finish_and_clear();
-    match unfetched_reports.is_empty() {
-        false => println!(
-            "{}, could not fetch: {}",
-            console::style("Fetching done").magenta(),
-            unfetched_reports.iter().map(|no| no.to_string()).collect::<Vec<String>>().join(", ")
-        ),
-        true => println!("{}", console::style("Fetched all reports").magenta())
+    summary
+}
+
+/// Run [`fetch_reports`] every `--interval` for new and changed water rights.
+///
+/// The first cycle uses the already read `cadenza_table`, every later one
+/// reads the xlsx file again, so it may be replaced between the cycles.
+/// If the file cannot be read, e.g. while it is being replaced, the table of
+/// the last cycle is used again.
+/// Reports that could not be fetched are tried again in the next cycle.
+async fn watch(
+    xlsx_path: &Path,
+    mut cadenza_table: CadenzaTable,
+    client: &reqwest::Client,
+    limiter: &RateLimiter,
+    backoff: Backoff,
+    mut session: Option<JSessionId>,
+    args: &Args
+) -> ! {
+    let mut schedule = WatchSchedule::new(args.interval, Instant::now());
+    let mut previous = BTreeMap::new();
+    let mut cycle = 0;
+
+    loop {
+        cycle += 1;
+        if cycle > 1 {
+            match read_cadenza_table(xlsx_path, args) {
+                Ok(read) => cadenza_table = read,
+                Err(err) => eprintln!(
+                    "{} could not parse table, using the one of the last cycle, {err:#}",
+                    console::style("Warning").yellow()
+                )
+            }
+        }
+        let current: Vec<_> =
+            cadenza_table.rows().iter().map(|row| (row.no, row.date_of_change.clone())).collect();
+
+        let WatchTargets { to_fetch, changed } = watch::watch_targets(&current, &previous);
+        let new = to_fetch.len() - changed.len();
+        let FetchSummary { fetched, unfetched } = fetch_reports(
+            to_fetch,
+            &changed,
+            client,
+            limiter,
+            backoff,
+            &mut session,
+            args
+        )
+        .await;
+        // watching never exits cleanly, keep the session after every cycle
+        save_session(session.as_ref());
+
+        previous = BTreeMap::from_iter(current);
+        for no in unfetched.iter() {
+            previous.remove(no);
+        }
+
+        let wait = schedule.wait(Instant::now());
+        let next_cycle = Local::now() + chrono::Duration::from_std(wait).unwrap_or_default();
+        println!(
+            "{} {cycle}, {new} new, {} changed, {fetched} fetched, {} could not be fetched, next \
+             cycle at {}",
+            console::style("Finished cycle").magenta(),
+            changed.len(),
+            unfetched.len(),
+            next_cycle.format("%Y-%m-%d %H:%M")
+        );
+        tokio::time::sleep(wait).await;
     }
 }
 
@@ -178,23 +577,53 @@ enum FetchError {
     Write(#[from] io::Error)
 }
 
-async fn fetch(water_right_no: WaterRightNo, client: &reqwest::Client) -> Result<(), FetchError> {
-    let report_link = req::fetch_report_url(water_right_no, client).await?;
+async fn fetch(
+    water_right_no: WaterRightNo,
+    client: &reqwest::Client,
+    limiter: &RateLimiter,
+    session: &mut Option<JSessionId>,
+    reports_dir: &Path
+) -> Result<(), FetchError> {
+    let report_link = req::fetch_report_url(water_right_no, client, limiter, session).await?;
+    limiter.acquire().await;
     let pdf_bytes = client.get(&report_link).send().await?.bytes().await?;
     fs::write(
-        format!("{}/rep{}.pdf", CONFIG.data.reports, water_right_no),
+        reports_dir.join(format!("rep{water_right_no}.pdf")),
         pdf_bytes
     )?;
 
     Ok(())
 }
 
-fn collect_no_from_cadenza_table(xlsx_path: &Path) -> Vec<WaterRightNo> {
+/// Directory the reports are written to, with a date the reports are put into
+/// a `YYYY-MM-DD` subdirectory of it.
+fn reports_dir(date: Option<NaiveDate>) -> PathBuf {
+    let mut reports_dir = PathBuf::from(CONFIG.data.reports);
+    if let Some(date) = date {
+        reports_dir.push(date.format("%Y-%m-%d").to_string());
+    }
+
+    reports_dir
+}
+
+/// Read the table with the rows selected by `--county` and
+/// `--legal-department`, sorted and with every water right only once.
+fn read_cadenza_table(xlsx_path: &Path, args: &Args) -> anyhow::Result<CadenzaTable> {
     let mut cadenza_table = {
         let _pb = ProgressBarGuard::new_wait_spinner("Parsing table...");
-        CadenzaTable::from_path(xlsx_path).expect("could not parse table")
+        CadenzaTable::from_path(xlsx_path)?
     };
 
+    // filter before deduplicating, the kept row of a water right may be in
+    // another county than the others
+    if !args.county.is_empty() {
+        let counties: Vec<&str> = args.county.iter().map(String::as_str).collect();
+        cadenza_table.filter_by_county(&counties);
+    }
+    if let Some(prefix) = args.legal_department.as_deref() {
+        cadenza_table.filter_by_legal_department_prefix(prefix);
+    }
+
     {
         let _pb = ProgressBarGuard::new_wait_spinner("Sorting table...");
         cadenza_table.sort_by(sort_cadenza_table);
@@ -205,7 +634,7 @@ fn collect_no_from_cadenza_table(xlsx_path: &Path) -> Vec<WaterRightNo> {
         cadenza_table.dedup_by(dedup_cadenza_table);
     }
 
-    cadenza_table.rows().iter().map(|row| row.no).collect()
+    Ok(cadenza_table)
 }
 
 fn sort_cadenza_table(a: &CadenzaTableRow, b: &CadenzaTableRow) -> Ordering {
@@ -240,14 +669,22 @@ fn dedup_cadenza_table(a: &mut CadenzaTableRow, b: &mut CadenzaTableRow) -> bool
     a.no == b.no
 }
 
-fn find_fetched_reports() -> anyhow::Result<Vec<WaterRightNo>> {
+fn find_fetched_reports(reports_dir: &Path) -> anyhow::Result<Vec<WaterRightNo>> {
     let mut fetched_reports: Vec<WaterRightNo> = Vec::new();
 
-    let report_dir_iter = fs::read_dir(CONFIG.data.reports)?;
+    let report_dir_iter = fs::read_dir(reports_dir)?;
     for item in report_dir_iter {
         let item = item?;
         let file_name = item.file_name();
-        let file_name = file_name.to_string_lossy();
+        let Some(file_name) = file_name.to_str()
+        else {
+            eprintln!(
+                "{} file name {:?} is not valid UTF-8, will be ignored",
+                console::style("Warning").yellow(),
+                file_name.to_string_lossy()
+            );
+            continue;
+        };
         if !file_name.ends_with(".pdf") || !file_name.starts_with("rep") {
             continue;
         }
@@ -265,3 +702,88 @@ fn find_fetched_reports() -> anyhow::Result<Vec<WaterRightNo>> {
 
     Ok(fetched_reports)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fetch_confirmation_works() {
+        use FetchConfirmation::*;
+
+        assert_eq!(fetch_confirmation(10, false, false), NotNeeded);
+        assert_eq!(
+            fetch_confirmation(LARGE_FETCH_THRESHOLD, false, false),
+            NotNeeded
+        );
+        assert_eq!(
+            fetch_confirmation(LARGE_FETCH_THRESHOLD + 1, true, false),
+            NotNeeded
+        );
+        assert_eq!(
+            fetch_confirmation(LARGE_FETCH_THRESHOLD + 1, false, true),
+            Prompt
+        );
+        assert_eq!(
+            fetch_confirmation(LARGE_FETCH_THRESHOLD + 1, false, false),
+            Refuse
+        );
+    }
+
+    #[test]
+    fn backoff_works() {
+        let backoff = Backoff::new(2, 1024, 0.0).unwrap();
+        assert_eq!(backoff.wait(1), Duration::from_secs(2));
+        assert_eq!(backoff.wait(3), Duration::from_secs(8));
+        assert_eq!(backoff.wait(10), Duration::from_secs(1024));
+        assert_eq!(backoff.wait(11), Duration::from_secs(1024));
+
+        let backoff = Backoff::new(3, 60, 0.0).unwrap();
+        assert_eq!(backoff.wait(1), Duration::from_secs(3));
+        assert_eq!(backoff.wait(3), Duration::from_secs(27));
+        assert_eq!(backoff.wait(4), Duration::from_secs(60));
+        assert_eq!(backoff.wait(u32::MAX), Duration::from_secs(60));
+
+        let backoff = Backoff::new(1, 60, 0.0).unwrap();
+        assert_eq!(backoff.wait(5), Duration::from_secs(1));
+
+        assert!(Backoff::new(0, 60, 0.0).is_err());
+        assert!(Backoff::new(2, 0, 0.0).is_err());
+        assert!(Backoff::new(2, 60, 1.5).is_err());
+        assert!(Backoff::new(2, 60, -0.25).is_err());
+    }
+
+    #[test]
+    fn backoff_jitter_works() {
+        let backoff = Backoff::new(2, 60, 0.25).unwrap();
+        let wait = backoff.wait(3);
+        assert_eq!(backoff.with_jitter(wait, 0.0), Duration::from_secs(6));
+        assert_eq!(backoff.with_jitter(wait, 0.5), Duration::from_secs(8));
+        assert_eq!(backoff.with_jitter(wait, 1.0), Duration::from_secs(10));
+
+        // the jitter never exceeds the cap
+        assert_eq!(
+            backoff.with_jitter(backoff.wait(10), 1.0),
+            Duration::from_secs(60)
+        );
+
+        for _ in 0..100 {
+            let wait = backoff.jittered_wait(3);
+            assert!(wait >= Duration::from_secs(6) && wait <= Duration::from_secs(10));
+        }
+
+        let backoff = Backoff::new(2, 60, 0.0).unwrap();
+        assert_eq!(backoff.jittered_wait(3), Duration::from_secs(8));
+    }
+
+    #[test]
+    fn reports_dir_works() {
+        assert_eq!(reports_dir(None), PathBuf::from(CONFIG.data.reports));
+
+        let date = NaiveDate::from_ymd_opt(2024, 3, 7).unwrap();
+        assert_eq!(
+            reports_dir(Some(date)),
+            PathBuf::from(CONFIG.data.reports).join("2024-03-07")
+        );
+    }
+}
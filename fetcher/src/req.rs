@@ -4,14 +4,26 @@ use regex::Regex;
 use reqwest::header::ToStrError;
 use thiserror::Error;
 
+use crate::limit::RateLimiter;
+use crate::session::JSessionId;
+
 static CADENZA_ROOT: &str = crate::CONFIG.cadenza.root;
 static CADENZA_URL: &str = crate::CONFIG.cadenza.url;
 const USER_AGENT: &str =
     "Mozilla/5.0 (Windows NT 10.0; Win64; x64; rv:109.0) Gecko/20100101 Firefox/115.0";
 
+/// Markers of the page cadenza responds with if the session expired between
+/// the steps of [`fetch_report_url`].
+const SESSION_EXPIRED_MARKERS: &[&str] = &[
+    "ViewExpiredException",
+    "Sitzung ist abgelaufen",
+    "session has expired"
+];
+
 lazy_static! {
+    // the download url may be percent-encoded, depending on the cadenza node
     static ref REPORT_URL_RE: Regex =
-        Regex::new(r"\?file=rep(?<report_id>\d+)\.pdf").expect("valid regex");
+        Regex::new(r"(?i)(?:\?|%3F)file(?:=|%3D)rep(?<report_id>\d+)\.pdf").expect("valid regex");
 }
 
 #[derive(Debug, Error)]
@@ -40,6 +52,9 @@ pub enum FetchReportUrlError {
     #[error("finish response has not 'Location' header")]
     FinishNoLocation,
 
+    #[error("session expired before the report url was fetched")]
+    SessionExpired,
+
     #[error("cadenza has no results for this request")]
     NoResults,
 
@@ -47,15 +62,28 @@ pub enum FetchReportUrlError {
     NoReportFileId
 }
 
+/// Find the download url of the report of a water right.
+///
+/// The command is sent within `session` if there is one, the session cadenza
+/// responds with is stored in `session` for the next report.
+/// If the session expired or no session could be read from the response,
+/// `session` is left empty, so the next attempt starts a new one.
 pub async fn fetch_report_url(
     water_right_no: WaterRightNo,
-    client: &reqwest::Client
+    client: &reqwest::Client,
+    limiter: &RateLimiter,
+    session: &mut Option<JSessionId>
 ) -> Result<String, FetchReportUrlError> {
+    let session_path = match session.take() {
+        Some(session) => format!(";jsessionid={}", session.as_str()),
+        None => String::new()
+    };
     let command_url = format!(
-        "{CADENZA_URL}commands.xhtml?ShowLegacy.RepositoryItem.Id=FIS-W.WBE.wbe/\
+        "{CADENZA_URL}commands.xhtml{session_path}?ShowLegacy.RepositoryItem.Id=FIS-W.WBE.wbe/\
          wbe_net_wasserrecht.cwf&ShowLegacy.RepositoryItem.Value='{water_right_no}'&ShowLegacy.\
          RepositoryItem.Attribute=wbe_net_wasserrecht.wasserrecht_nr"
     );
+    limiter.acquire().await;
     let command_res = client.get(command_url).header("User-Agent", USER_AGENT).send().await?;
     match command_res.status().as_u16() {
         302 => (),
@@ -69,8 +97,10 @@ pub async fn fetch_report_url(
         .split(";jsessionid=")
         .nth(1)
         .ok_or(FetchReportUrlError::CommandNoSessionId)?;
+    *session = Some(JSessionId::new(j_session_id));
 
     let wait_cweb_url = format!("{CADENZA_URL}wait.cweb;jsessionid={j_session_id}");
+    limiter.acquire().await;
     let wait_cweb_res = client.get(wait_cweb_url).header("User-Agent", USER_AGENT).send().await?;
     match wait_cweb_res.status().as_u16() {
         302 => (),
@@ -80,6 +110,7 @@ pub async fn fetch_report_url(
     let finished_url =
         wait_cweb_res.headers().get("Location").ok_or(FetchReportUrlError::WaitCwebNoLocation)?;
     let finished_url = format!("{CADENZA_ROOT}{}", finished_url.to_str()?);
+    limiter.acquire().await;
     let finished_res = client.get(&finished_url).header("User-Agent", USER_AGENT).send().await?;
     let download_url = match finished_res.headers().get("Location") {
         Some(location) => location.to_str()?,
@@ -88,17 +119,90 @@ pub async fn fetch_report_url(
                 Ok(body) if body.contains("Die Abfrage liefert keine Ergebnisse.") => {
                     Err(FetchReportUrlError::NoResults)
                 }
+                Ok(body) if is_session_expired(&body) => {
+                    *session = None;
+                    Err(FetchReportUrlError::SessionExpired)
+                }
                 _ => Err(FetchReportUrlError::FinishNoLocation)
             }
         }
     };
 
-    let captured =
-        REPORT_URL_RE.captures(download_url).ok_or(FetchReportUrlError::NoReportFileId)?;
-    let report_id = &captured["report_id"];
+    let report_id = report_file_id(download_url).ok_or(FetchReportUrlError::NoReportFileId)?;
     let report_url = format!(
         "{CADENZA_URL}/pages/download/get;jsessionid={j_session_id}?file=rep{report_id}.pdf&\
          mimetype=application/pdf"
     );
     Ok(report_url)
 }
+
+/// Check if cadenza still accepts a session, e.g. one saved by a prior run.
+///
+/// Cadenza redirects the wait page of a valid session, any other response or
+/// a failed request means the session should be discarded.
+pub async fn probe_session(
+    session: &JSessionId,
+    client: &reqwest::Client,
+    limiter: &RateLimiter
+) -> bool {
+    let probe_url = format!("{CADENZA_URL}wait.cweb;jsessionid={}", session.as_str());
+    limiter.acquire().await;
+    match client.get(probe_url).header("User-Agent", USER_AGENT).send().await {
+        Ok(res) => res.status().as_u16() == 302,
+        Err(_) => false
+    }
+}
+
+/// Check if cadenza responded with its expired session page instead of a
+/// redirect.
+fn is_session_expired(body: &str) -> bool {
+    SESSION_EXPIRED_MARKERS.iter().any(|marker| body.contains(marker))
+}
+
+/// Id of the report file in a download url, which may be percent-encoded.
+fn report_file_id(download_url: &str) -> Option<&str> {
+    let captured = REPORT_URL_RE.captures(download_url)?;
+    captured.name("report_id").map(|report_id| report_id.as_str())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SESSION_EXPIRED_BODY: &str = r#"<!DOCTYPE html>
+<html xmlns="http://www.w3.org/1999/xhtml">
+<head><title>Cadenza Web - Fehler</title></head>
+<body>
+  <div class="error-page">
+    <h1>Ihre Sitzung ist abgelaufen.</h1>
+    <p>Bitte starten Sie die Anwendung neu.</p>
+    <!-- javax.faces.application.ViewExpiredException -->
+  </div>
+</body>
+</html>"#;
+
+    #[test]
+    fn is_session_expired_works() {
+        assert!(is_session_expired(SESSION_EXPIRED_BODY));
+        assert!(!is_session_expired(
+            "<html><body>Die Abfrage liefert keine Ergebnisse.</body></html>"
+        ));
+        assert!(!is_session_expired(""));
+    }
+
+    #[test]
+    fn report_file_id_works() {
+        assert_eq!(
+            report_file_id("/cadenza/pages/download/get?file=rep4711.pdf&mimetype=application/pdf"),
+            Some("4711")
+        );
+        assert_eq!(
+            report_file_id("/cadenza/pages/download/get%3Ffile%3Drep4711.pdf"),
+            Some("4711")
+        );
+        assert_eq!(
+            report_file_id("/cadenza/pages/download/get?file=map4711.png"),
+            None
+        );
+    }
+}
@@ -0,0 +1,72 @@
+use std::path::Path;
+use std::{fs, io};
+
+/// Session id of cadenza, the `jsessionid` of the request urls.
+///
+/// The session is kept between the runs of the fetcher in a small file, see
+/// `data.session` of the config, so a restarted crawl continues the session
+/// of the interrupted one instead of starting a new one.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct JSessionId(String);
+
+impl JSessionId {
+    pub fn new(id: impl Into<String>) -> Self {
+        JSessionId(id.into())
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+
+    /// Read the session id a prior run saved to `path`.
+    ///
+    /// A missing, unreadable or empty file has no session id.
+    pub fn load(path: &Path) -> Option<JSessionId> {
+        let id = fs::read_to_string(path).ok()?;
+        let id = id.trim();
+        match id.is_empty() {
+            true => None,
+            false => Some(JSessionId::new(id))
+        }
+    }
+
+    /// Save the session id to `path` for the next run.
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(path, &self.0)
+    }
+
+    /// Remove a saved session id that cadenza no longer accepts.
+    pub fn discard(path: &Path) -> io::Result<()> {
+        match fs::remove_file(path) {
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(()),
+            res => res
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn session_round_trip_works() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("session").join(".jsessionid");
+        JSessionId::discard(&path).unwrap();
+        assert_eq!(JSessionId::load(&path), None);
+
+        let session = JSessionId::new("A1B2C3D4.node1");
+        session.save(&path).unwrap();
+        assert_eq!(JSessionId::load(&path), Some(session));
+
+        JSessionId::discard(&path).unwrap();
+        assert_eq!(JSessionId::load(&path), None);
+
+        fs::write(&path, " \n").unwrap();
+        assert_eq!(JSessionId::load(&path), None);
+        JSessionId::discard(&path).unwrap();
+    }
+}
@@ -0,0 +1,153 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::time::{Duration, Instant};
+
+use nlwkn::WaterRightNo;
+
+/// Schedule of the watch mode.
+///
+/// Cycles start every `interval`, a cycle taking longer than that is followed
+/// by the next one immediately and the schedule continues from there, missed
+/// cycles are not caught up.
+#[derive(Debug)]
+pub struct WatchSchedule {
+    interval: Duration,
+    next: Instant
+}
+
+impl WatchSchedule {
+    /// Schedule for a first cycle started at `start`.
+    pub fn new(interval: Duration, start: Instant) -> Self {
+        WatchSchedule {
+            interval,
+            next: start + interval
+        }
+    }
+
+    /// Time to wait at `now` until the next cycle starts.
+    pub fn wait(&mut self, now: Instant) -> Duration {
+        let start = self.next.max(now);
+        self.next = start + self.interval;
+        start - now
+    }
+}
+
+/// Water rights a watch cycle needs to fetch.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct WatchTargets {
+    /// New and changed water rights in table order.
+    pub to_fetch: Vec<WaterRightNo>,
+
+    /// Water rights whose "Änderungsdatum" differs from the last cycle, their
+    /// reports are fetched again even if already downloaded.
+    pub changed: BTreeSet<WaterRightNo>
+}
+
+/// Compare the water rights of the current table with the ones of the last
+/// cycle.
+///
+/// `current` contains the water right numbers with their "Änderungsdatum" in
+/// table order, `previous` the ones from the last cycle.
+pub fn watch_targets(
+    current: &[(WaterRightNo, Option<String>)],
+    previous: &BTreeMap<WaterRightNo, Option<String>>
+) -> WatchTargets {
+    let mut targets = WatchTargets::default();
+    for (no, date_of_change) in current {
+        match previous.get(no) {
+            None => targets.to_fetch.push(*no),
+            Some(previous) if previous != date_of_change => {
+                targets.to_fetch.push(*no);
+                targets.changed.insert(*no);
+            }
+            Some(_) => ()
+        }
+    }
+
+    targets
+}
+
+/// Parse an interval like `90s`, `30m`, `6h` or `1d`.
+pub fn parse_interval(interval: &str) -> Result<Duration, String> {
+    let interval = interval.trim();
+    let unit_start = interval
+        .find(|c: char| !c.is_ascii_digit())
+        .ok_or_else(|| format!("interval {interval:?} has no unit, use s, m, h or d"))?;
+    let (amount, unit) = interval.split_at(unit_start);
+    let amount: u64 = amount.parse().map_err(|_| format!("interval {interval:?} has no amount"))?;
+
+    let unit_secs = match unit {
+        "s" => 1,
+        "m" => 60,
+        "h" => 60 * 60,
+        "d" => 60 * 60 * 24,
+        unit => return Err(format!("unknown unit {unit:?}, use s, m, h or d"))
+    };
+    match amount.checked_mul(unit_secs) {
+        Some(0) => Err("interval must not be zero".to_string()),
+        Some(secs) => Ok(Duration::from_secs(secs)),
+        None => Err(format!("interval {interval:?} is too long"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MINUTE: Duration = Duration::from_secs(60);
+    const HOUR: Duration = Duration::from_secs(60 * 60);
+
+    #[test]
+    fn watch_schedule_works() {
+        let mut clock = Instant::now();
+        let mut schedule = WatchSchedule::new(HOUR, clock);
+
+        // first cycle takes 10 minutes
+        clock += 10 * MINUTE;
+        let wait = schedule.wait(clock);
+        assert_eq!(wait, 50 * MINUTE);
+        clock += wait;
+
+        // second cycle overruns the interval, the third starts immediately
+        clock += 2 * HOUR + 30 * MINUTE;
+        assert_eq!(schedule.wait(clock), Duration::ZERO);
+
+        // the schedule continues from the delayed cycle
+        clock += MINUTE;
+        assert_eq!(schedule.wait(clock), 59 * MINUTE);
+    }
+
+    #[test]
+    fn watch_targets_works() {
+        let date = |date: &str| Some(date.to_string());
+        let first = [(3, date("2024-01-01")), (1, None), (2, date("2024-01-01"))];
+        let targets = watch_targets(&first, &BTreeMap::new());
+        assert_eq!(targets.to_fetch, vec![3, 1, 2]);
+        assert!(targets.changed.is_empty());
+
+        let previous = BTreeMap::from_iter(first.clone());
+        assert_eq!(watch_targets(&first, &previous), WatchTargets::default());
+
+        let second = [
+            (3, date("2024-02-01")),
+            (1, None),
+            (4, None),
+            (2, date("2024-01-01"))
+        ];
+        let targets = watch_targets(&second, &previous);
+        assert_eq!(targets.to_fetch, vec![3, 4]);
+        assert_eq!(targets.changed, BTreeSet::from([3]));
+    }
+
+    #[test]
+    fn parse_interval_works() {
+        assert_eq!(parse_interval("90s"), Ok(Duration::from_secs(90)));
+        assert_eq!(parse_interval("30m"), Ok(30 * MINUTE));
+        assert_eq!(parse_interval("6h"), Ok(6 * HOUR));
+        assert_eq!(parse_interval("1d"), Ok(24 * HOUR));
+        assert!(parse_interval("6").is_err());
+        assert!(parse_interval("h").is_err());
+        assert!(parse_interval("0h").is_err());
+        assert!(parse_interval("2w").is_err());
+        assert!(parse_interval("999999999999999999d").is_err());
+    }
+}
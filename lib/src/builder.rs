@@ -0,0 +1,132 @@
+use crate::{
+    LegalDepartment, LegalDepartmentAbbreviation, UsageLocation, WaterRight, WaterRightNo
+};
+
+/// Fluent builder for [`WaterRight`]s.
+///
+/// Usage locations are added with their legal department, the departments
+/// are created on demand and always stored under their own abbreviation.
+///
+/// ```
+/// use nlwkn::builder::WaterRightBuilder;
+/// use nlwkn::{LegalDepartmentAbbreviation, UsageLocation};
+///
+/// let water_right = WaterRightBuilder::new(1101)
+///     .holder("Körtke")
+///     .status("aktiv")
+///     .add_usage_location(LegalDepartmentAbbreviation::A, UsageLocation::new())
+///     .build();
+/// assert_eq!(water_right.legal_departments.len(), 1);
+/// ```
+#[derive(Debug)]
+pub struct WaterRightBuilder(WaterRight);
+
+macro_rules! setters {
+    ($($field:ident),* $(,)?) => {$(
+        pub fn $field(mut self, $field: impl Into<String>) -> Self {
+            self.0.$field = Some($field.into());
+            self
+        }
+    )*};
+}
+
+impl WaterRightBuilder {
+    setters!(
+        holder,
+        valid_until,
+        status,
+        valid_from,
+        legal_title,
+        cadenza_legal_title,
+        water_authority,
+        registering_authority,
+        granting_authority,
+        initially_granted,
+        last_change,
+        file_reference,
+        external_identifier,
+        subject,
+        address,
+        annotation
+    );
+
+    pub fn new(water_right_no: WaterRightNo) -> Self {
+        WaterRightBuilder(WaterRight::new(water_right_no))
+    }
+
+    /// Add a usage location to the legal department `department`.
+    ///
+    /// If the water right has no such department yet, it is created with the
+    /// [default description](LegalDepartmentAbbreviation::description).
+    pub fn add_usage_location(
+        mut self,
+        department: LegalDepartmentAbbreviation,
+        location: UsageLocation
+    ) -> Self {
+        self.0
+            .legal_departments
+            .entry(department)
+            .or_insert_with(|| {
+                LegalDepartment::new(department, department.description().to_string())
+            })
+            .usage_locations
+            .push(location);
+        self
+    }
+
+    pub fn build(self) -> WaterRight {
+        self.0
+    }
+}
+
+impl WaterRight {
+    /// Start building a water right, see [`WaterRightBuilder`].
+    pub fn builder(water_right_no: WaterRightNo) -> WaterRightBuilder {
+        WaterRightBuilder::new(water_right_no)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::validate_water_rights;
+
+    #[test]
+    fn builder_works() {
+        let usage_location = |serial: &str| {
+            let mut usage_location = UsageLocation::new();
+            usage_location.serial = Some(serial.to_string());
+            usage_location
+        };
+
+        let water_right = WaterRight::builder(1101)
+            .holder("Körtke")
+            .file_reference("6630-01-1610")
+            .add_usage_location(LegalDepartmentAbbreviation::A, usage_location("1"))
+            .add_usage_location(LegalDepartmentAbbreviation::B, usage_location("2"))
+            .add_usage_location(LegalDepartmentAbbreviation::A, usage_location("3"))
+            .build();
+
+        assert_eq!(water_right.no, 1101);
+        assert_eq!(water_right.holder.as_deref(), Some("Körtke"));
+        assert_eq!(water_right.file_reference.as_deref(), Some("6630-01-1610"));
+        assert_eq!(water_right.status, None);
+
+        let department_a = &water_right.legal_departments[&LegalDepartmentAbbreviation::A];
+        assert_eq!(department_a.abbreviation, LegalDepartmentAbbreviation::A);
+        assert_eq!(
+            department_a
+                .usage_locations
+                .iter()
+                .map(|ul| ul.serial.as_deref().unwrap())
+                .collect::<Vec<_>>(),
+            ["1", "3"]
+        );
+
+        let department_b = &water_right.legal_departments[&LegalDepartmentAbbreviation::B];
+        assert_eq!(department_b.usage_locations.len(), 1);
+        assert!(department_b.description.starts_with("Einbringen und Einleiten"));
+
+        validate_water_rights(&[water_right]).unwrap();
+    }
+}
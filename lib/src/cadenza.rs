@@ -2,16 +2,18 @@ use std::cmp::Ordering;
 use std::hash::{Hash, Hasher};
 use std::path::Path;
 
-use calamine::{DataType, RangeDeserializerBuilder, Reader, Xlsx};
-use serde::{Deserialize, Deserializer};
+use calamine::{DataType, DeError, RangeDeserializerBuilder, Reader, Xlsx};
+use serde::de::Error;
+use serde::{Deserialize, Deserializer, Serialize};
 
-use crate::util::StringOption;
+use crate::util::{is_thousands_separated, StringOption, UTM_ZERO_POLICY};
 use crate::WaterRightNo;
 
-#[derive(Debug)]
+#[derive(Debug, Serialize)]
+#[serde(transparent)]
 pub struct CadenzaTable(Vec<CadenzaTableRow>);
 
-#[derive(Debug, Deserialize, Eq)]
+#[derive(Debug, Deserialize, Serialize, Eq)]
 #[cfg_attr(test, derive(Default))]
 #[serde(deny_unknown_fields)]
 pub struct CadenzaTableRow {
@@ -89,27 +91,70 @@ pub struct CadenzaTableRow {
     #[serde(rename = "Wasserschutzgebiet")]
     pub water_protection_area: Option<String>,
 
-    #[serde(rename = "UTM-Rechtswert", deserialize_with = "zero_as_none")]
+    #[serde(rename = "UTM-Rechtswert", deserialize_with = "utm_coordinate")]
     pub utm_easting: Option<u64>,
 
-    #[serde(rename = "UTM-Hochwert", deserialize_with = "zero_as_none")]
+    #[serde(rename = "UTM-Hochwert", deserialize_with = "utm_coordinate")]
     pub utm_northing: Option<u64>
 }
 
+/// Row of the table that could not be read, see
+/// [`CadenzaTable::from_path_skipping_bad_rows`].
+#[derive(Debug)]
+pub struct BadRow {
+    /// Number of the row in the sheet, counted from 1 like in spreadsheet
+    /// programs.
+    pub row: u32,
+    pub error: DeError
+}
+
 impl CadenzaTable {
     pub fn from_path(path: &Path) -> anyhow::Result<CadenzaTable> {
+        let (rows, _) = Self::read_rows(path, false)?;
+        Ok(CadenzaTable(rows))
+    }
+
+    /// Like [`Self::from_path`] but rows that cannot be read, e.g. with text in
+    /// a numeric column, are skipped and returned instead of failing the whole
+    /// table.
+    pub fn from_path_skipping_bad_rows(path: &Path) -> anyhow::Result<(CadenzaTable, Vec<BadRow>)> {
+        let (rows, bad_rows) = Self::read_rows(path, true)?;
+        Ok((CadenzaTable(rows), bad_rows))
+    }
+
+    fn read_rows(
+        path: &Path,
+        skip_bad_rows: bool
+    ) -> anyhow::Result<(Vec<CadenzaTableRow>, Vec<BadRow>)> {
         let mut workbook: Xlsx<_> = calamine::open_workbook(path)?;
         let worksheets = workbook.worksheets();
         let (_, range) = worksheets.first().ok_or(anyhow::Error::msg("workbook empty"))?;
+        // the header is the first row of the range, the start is 0-based
+        let first_row = range.start().map(|(row, _)| row).unwrap_or_default() + 2;
         let iter = RangeDeserializerBuilder::new().has_headers(true).from_range(range)?;
-        let rows: Result<Vec<CadenzaTableRow>, _> = iter.collect();
-        Ok(CadenzaTable(rows?))
+
+        let mut rows = Vec::new();
+        let mut bad_rows = Vec::new();
+        for (row, result) in (first_row..).zip(iter) {
+            match result {
+                Ok(table_row) => rows.push(table_row),
+                Err(error) if skip_bad_rows => bad_rows.push(BadRow { row, error }),
+                Err(error) => return Err(error.into())
+            }
+        }
+
+        Ok((rows, bad_rows))
     }
 
     pub fn rows(&self) -> &Vec<CadenzaTableRow> {
         &self.0
     }
 
+    /// Whether the table has no data rows, e.g. a sheet with only headers.
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
     pub fn sort_by<F>(&mut self, compare: F)
     where
         F: FnMut(&CadenzaTableRow, &CadenzaTableRow) -> Ordering
@@ -125,6 +170,37 @@ impl CadenzaTable {
         self.0.dedup_by(same_bucket);
     }
 
+    /// Keep only the rows for which `keep` returns `true`, in their order.
+    pub fn retain<F>(&mut self, keep: F)
+    where
+        F: FnMut(&CadenzaTableRow) -> bool
+    {
+        self.0.retain(keep);
+    }
+
+    /// Keep only the rows of usage locations in one of the `counties`.
+    ///
+    /// The counties are compared ignoring case and surrounding whitespace,
+    /// rows without a county are removed.
+    pub fn filter_by_county(&mut self, counties: &[&str]) {
+        let counties: Vec<String> =
+            counties.iter().map(|county| county.trim().to_lowercase()).collect();
+        self.retain(|row| match row.county.as_deref() {
+            Some(county) => counties.contains(&county.trim().to_lowercase()),
+            None => false
+        });
+    }
+
+    /// Keep only the rows whose legal department starts with `prefix`,
+    /// ignoring case, e.g. `"Entnahme"`.
+    ///
+    /// The legal department is the description of the "Rechtsabteilung", not
+    /// its abbreviation, which is unreliable in the table.
+    pub fn filter_by_legal_department_prefix(&mut self, prefix: &str) {
+        let prefix = prefix.to_lowercase();
+        self.retain(|row| row.legal_department.to_lowercase().starts_with(&prefix));
+    }
+
     pub fn sanitize(&mut self) {
         #[allow(deprecated)]
         for row in self.0.iter_mut() {
@@ -152,6 +228,37 @@ impl CadenzaTable {
     }
 }
 
+impl CadenzaTableRow {
+    /// Names of the columns in the table, in the order of the fields.
+    pub const COLUMNS: [&'static str; 25] = [
+        "Wasserrecht Nr.",
+        "Rechtsinhaber",
+        "Gültig Bis",
+        "Zustand",
+        "Gültig Ab",
+        "Rechtsabteilungen",
+        "Rechtstitel",
+        "Wasserbehoerde",
+        "Erteilende Behoerde",
+        "Aenderungsdatum",
+        "Aktenzeichen",
+        "Externe Kennung",
+        "Betreff",
+        "Adresse",
+        "Nutzungsort Nr.",
+        "Nutzungsort",
+        "Rechtsabteilung",
+        "Rechtszweck",
+        "Landkreis",
+        "Flussgebiet",
+        "Grundwasserkörper",
+        "Überschwemmungsgebiet",
+        "Wasserschutzgebiet",
+        "UTM-Rechtswert",
+        "UTM-Hochwert"
+    ];
+}
+
 impl PartialEq for CadenzaTableRow {
     fn eq(&self, other: &Self) -> bool {
         self.no == other.no && self.usage_location_no == other.usage_location_no
@@ -164,25 +271,74 @@ impl Hash for CadenzaTableRow {
     }
 }
 
+/// Deserialize a date cell formatted as `YYYY-MM-DD`.
+///
+/// Cells that are no dates, like the text `unbefristet`, are kept verbatim.
 fn deserialize_date<'de, D>(deserializer: D) -> Result<Option<String>, D::Error>
 where
     D: Deserializer<'de>
 {
-    let float: calamine::Data = calamine::Data::deserialize(deserializer)?;
-    Ok(Some(
-        float.as_date().ok_or(serde::de::Error::custom("cannot convert to date"))?.to_string()
-    ))
+    let data: calamine::Data = calamine::Data::deserialize(deserializer)?;
+    if let Some(date) = data.as_date() {
+        return Ok(Some(date.to_string()));
+    }
+
+    match data {
+        calamine::Data::Empty => Ok(None),
+        calamine::Data::String(s) => Ok(Some(s)),
+        other => Ok(Some(other.to_string()))
+    }
 }
 
-fn zero_as_none<'de, D>(deserializer: D) -> Result<Option<u64>, D::Error>
+/// Deserialize a numeric cell, `0` is kept as a regular value.
+///
+/// Besides numeric cells this also accepts cells formatted as strings with
+/// thousands separators, e.g. `32.603.873` or `32 603 873`.
+fn number_cell<'de, D>(deserializer: D) -> Result<Option<u64>, D::Error>
 where
     D: Deserializer<'de>
 {
-    let option: Option<u64> = Option::deserialize(deserializer)?;
-    match option {
-        Some(0) => Ok(None),
-        Some(x) => Ok(Some(x)),
-        None => Ok(None)
+    let data: Option<calamine::Data> = Option::deserialize(deserializer)?;
+    match data {
+        None | Some(calamine::Data::Empty) => Ok(None),
+        Some(calamine::Data::Int(i)) => {
+            Ok(Some(u64::try_from(i).map_err(|_| {
+                D::Error::custom(format!("negative number: {i}"))
+            })?))
+        }
+        Some(calamine::Data::Float(f)) if f >= 0.0 && f.fract() == 0.0 => Ok(Some(f as u64)),
+        Some(calamine::Data::String(s)) => parse_separated_number(&s).map_err(D::Error::custom),
+        Some(other) => Err(D::Error::custom(format!("expected a number, got {other}")))
+    }
+}
+
+/// Deserialize a UTM coordinate cell like [`number_cell`], applying the
+/// [`UTM_ZERO_POLICY`].
+fn utm_coordinate<'de, D>(deserializer: D) -> Result<Option<u64>, D::Error>
+where
+    D: Deserializer<'de>
+{
+    Ok(number_cell(deserializer)?.and_then(|number| UTM_ZERO_POLICY.apply(number)))
+}
+
+/// Parse a number that may contain spaces, dots or apostrophes as thousands
+/// separators.
+///
+/// Dots are only read as thousands separators like in [`parse_decimal`], so
+/// decimals like `"5852015.5"` are rejected instead of losing their dot.
+///
+/// [`parse_decimal`]: crate::util::parse_decimal
+fn parse_separated_number(s: &str) -> Result<Option<u64>, String> {
+    let invalid = || format!("invalid number: {s:?}");
+    let digits: String = s.chars().filter(|c| !c.is_whitespace() && *c != '\'').collect();
+    if digits.contains('.') && !is_thousands_separated(&digits) {
+        return Err(invalid());
+    }
+
+    let digits = digits.replace('.', "");
+    match digits.is_empty() {
+        true => Ok(None),
+        false => digits.parse().map(Some).map_err(|_| invalid())
     }
 }
 
@@ -193,6 +349,9 @@ mod tests {
     use super::*;
 
     const XLSX_PATH: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/test/cadenza.xlsx");
+    const HEADER_ONLY_XLSX_PATH: &str =
+        concat!(env!("CARGO_MANIFEST_DIR"), "/test/header-only.xlsx");
+    const BAD_ROW_XLSX_PATH: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/test/bad-row.xlsx");
 
     #[allow(deprecated)]
     #[test]
@@ -234,6 +393,48 @@ mod tests {
         assert_eq!(rows[0], first_row);
     }
 
+    #[test]
+    fn bad_rows_are_skipped() {
+        let xlsx_path = Path::new(BAD_ROW_XLSX_PATH);
+        assert!(CadenzaTable::from_path(xlsx_path).is_err());
+
+        let (table, bad_rows) = CadenzaTable::from_path_skipping_bad_rows(xlsx_path).unwrap();
+        assert_eq!(table.rows().len(), 9);
+        assert!(!table.rows().iter().any(|row| row.no == 1303 && row.usage_location_no == 103));
+        assert_eq!(table.rows()[0].no, 1101);
+        assert_eq!(bad_rows.len(), 1);
+        assert_eq!(bad_rows[0].row, 3);
+        assert!(bad_rows[0].error.to_string().contains("103a"));
+    }
+
+    #[test]
+    fn header_only_is_empty() {
+        let xlsx_path = Path::new(HEADER_ONLY_XLSX_PATH);
+        let table = CadenzaTable::from_path(xlsx_path).unwrap();
+        assert!(table.is_empty());
+    }
+
+    #[test]
+    fn columns_match_fields() {
+        let row = serde_json::to_value(CadenzaTableRow::default()).unwrap();
+        let mut fields: Vec<&str> = row.as_object().unwrap().keys().map(String::as_str).collect();
+        let mut columns = CadenzaTableRow::COLUMNS.to_vec();
+        fields.sort();
+        columns.sort();
+        assert_eq!(fields, columns);
+    }
+
+    #[test]
+    fn sanitize_strips_bom() {
+        let mut table = CadenzaTable(vec![CadenzaTableRow {
+            no: 1101,
+            rights_holder: Some("\u{FEFF}Körtke ".to_string()),
+            ..Default::default()
+        }]);
+        table.sanitize();
+        assert_eq!(table.rows()[0].rights_holder.as_deref(), Some("Körtke"));
+    }
+
     #[test]
     fn sort_works() {
         let a = CadenzaTableRow {
@@ -261,4 +462,93 @@ mod tests {
             assert_eq!(*i, r.no);
         }
     }
+
+    #[test]
+    fn filter_works() {
+        let row = |no, county: Option<&str>, legal_department: &str| CadenzaTableRow {
+            no,
+            county: county.map(ToString::to_string),
+            legal_department: legal_department.to_string(),
+            ..Default::default()
+        };
+        let table = || {
+            CadenzaTable(vec![
+                row(1, Some("Gifhorn"), "Entnahme von Wasser"),
+                row(1, Some("Aurich"), "Einbringen und Einleiten von Stoffen"),
+                row(2, Some(" aurich "), "Entnahme von Wasser"),
+                row(3, None, "Entnahme von Wasser"),
+                row(4, Some("Leer"), "Aufstauen und Absenken"),
+            ])
+        };
+        let nos = |table: &CadenzaTable| table.rows().iter().map(|row| row.no).collect::<Vec<_>>();
+
+        let mut by_county = table();
+        by_county.filter_by_county(&["Aurich", "Leer"]);
+        assert_eq!(nos(&by_county), [1, 2, 4]);
+
+        let mut by_department = table();
+        by_department.filter_by_legal_department_prefix("entnahme");
+        assert_eq!(nos(&by_department), [1, 2, 3]);
+
+        let mut retained = table();
+        retained.retain(|row| row.no > 2);
+        assert_eq!(nos(&retained), [3, 4]);
+    }
+
+    #[test]
+    fn utm_coordinate_accepts_separators() {
+        #[derive(Debug, Deserialize)]
+        struct Utm {
+            #[serde(deserialize_with = "utm_coordinate")]
+            easting: Option<u64>
+        }
+
+        let parse = |json: &str| serde_json::from_str::<Utm>(json).map(|utm| utm.easting);
+        assert_eq!(parse(r#"{"easting": 32603873}"#).unwrap(), Some(32603873));
+        assert_eq!(
+            parse(r#"{"easting": "32.603.873"}"#).unwrap(),
+            Some(32603873)
+        );
+        assert_eq!(
+            parse(r#"{"easting": "32 603 873"}"#).unwrap(),
+            Some(32603873)
+        );
+        assert_eq!(parse(r#"{"easting": "0"}"#).unwrap(), None);
+        assert_eq!(parse(r#"{"easting": null}"#).unwrap(), None);
+        assert!(parse(r#"{"easting": "32,60x"}"#).is_err());
+        assert!(parse(r#"{"easting": "5852015.5"}"#).is_err());
+        assert!(parse(r#"{"easting": "32.6038.73"}"#).is_err());
+    }
+
+    #[test]
+    fn number_cell_keeps_zero() {
+        #[derive(Debug, Deserialize)]
+        struct Cells {
+            #[serde(deserialize_with = "number_cell")]
+            serial: Option<u64>,
+            #[serde(deserialize_with = "utm_coordinate")]
+            easting: Option<u64>
+        }
+
+        let cells: Cells = serde_json::from_str(r#"{"serial": 0, "easting": 0}"#).unwrap();
+        assert_eq!(cells.serial, Some(0));
+        assert_eq!(cells.easting, None);
+    }
+
+    #[test]
+    fn deserialize_date_keeps_text() {
+        #[derive(Debug, Deserialize)]
+        struct Dates {
+            #[serde(deserialize_with = "deserialize_date")]
+            valid_from: Option<String>,
+            #[serde(deserialize_with = "deserialize_date")]
+            valid_until: Option<String>
+        }
+
+        let dates: Dates =
+            serde_json::from_str(r#"{"valid_from": 45000.0, "valid_until": "unbefristet"}"#)
+                .unwrap();
+        assert_eq!(dates.valid_from.as_deref(), Some("2023-03-15"));
+        assert_eq!(dates.valid_until.as_deref(), Some("unbefristet"));
+    }
 }
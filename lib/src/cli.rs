@@ -1,7 +1,11 @@
 use std::borrow::Cow;
+use std::ffi::OsString;
 use std::fmt::Display;
+use std::io::Write;
 use std::time::Duration;
 
+use clap::{Arg, Command, CommandFactory};
+use clap_complete::Shell;
 use console::Alignment;
 use indicatif::{ProgressBar, ProgressStyle};
 use lazy_static::lazy_static;
@@ -48,6 +52,38 @@ pub fn progress_message<M, S>(
     progress.println(format!("{keyword} {msg}"))
 }
 
+/// Print the shell completions of the binary if it was called with the hidden
+/// `completions <shell>` subcommand, returns whether they were printed.
+///
+/// Call this with [`std::env::args_os`] before parsing the arguments, so the
+/// required arguments of `A` do not have to be given.
+pub fn print_completions<A, I, T>(bin_name: &str, args: I, writer: &mut impl Write) -> bool
+where
+    A: CommandFactory,
+    I: IntoIterator<Item = T>,
+    T: Into<OsString> + Clone
+{
+    let completions = Command::new("completions")
+        .hide(true)
+        .arg(Arg::new("shell").required(true).value_parser(clap::value_parser!(Shell)));
+    let matches = A::command()
+        .subcommand(completions)
+        .args_conflicts_with_subcommands(true)
+        .subcommand_negates_reqs(true)
+        .try_get_matches_from(args);
+    let shell = match matches.as_ref().map(|matches| matches.subcommand()) {
+        Ok(Some(("completions", matches))) => matches.get_one::<Shell>("shell"),
+        _ => return false
+    };
+
+    let Some(shell) = shell.copied()
+    else {
+        return false;
+    };
+    clap_complete::generate(shell, &mut A::command(), bin_name, writer);
+    true
+}
+
 pub struct ProgressBarGuard {
     pub progress_bar: ProgressBar,
     finish_message: Option<String>
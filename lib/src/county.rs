@@ -0,0 +1,211 @@
+//! Coarse check whether coordinates lie within the county of a usage
+//! location.
+//!
+//! Every district of Niedersachsen is approximated by its bounding box in
+//! WGS84, a point outside the box of its stated county most likely comes from
+//! a parse error.
+//! The boxes overlap at the district borders, so points near a border may
+//! pass for the neighbouring district too.
+
+use std::fmt::{Display, Formatter};
+
+use crate::municipality::DISTRICTS;
+use crate::util::utm_to_wgs84;
+
+/// Bounding box in WGS84 degrees.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BoundingBox {
+    pub min_longitude: f64,
+    pub min_latitude: f64,
+    pub max_longitude: f64,
+    pub max_latitude: f64
+}
+
+impl BoundingBox {
+    const fn new(
+        min_longitude: f64,
+        min_latitude: f64,
+        max_longitude: f64,
+        max_latitude: f64
+    ) -> Self {
+        BoundingBox {
+            min_longitude,
+            min_latitude,
+            max_longitude,
+            max_latitude
+        }
+    }
+
+    pub fn contains(&self, longitude: f64, latitude: f64) -> bool {
+        (self.min_longitude..=self.max_longitude).contains(&longitude) &&
+            (self.min_latitude..=self.max_latitude).contains(&latitude)
+    }
+}
+
+/// Tolerance in degrees added around every bounding box, roughly 5 km.
+pub const COUNTY_TOLERANCE: f64 = 0.05;
+
+/// Bounding boxes of the districts by their key, see [`DISTRICTS`].
+///
+/// The boxes are rounded outwards to two decimals and include the islands of
+/// the coastal districts.
+pub const COUNTY_BOUNDS: &[(u64, BoundingBox)] = &[
+    (3101, BoundingBox::new(10.41, 52.19, 10.62, 52.35)),
+    (3102, BoundingBox::new(10.21, 52.02, 10.50, 52.21)),
+    (3103, BoundingBox::new(10.62, 52.33, 10.92, 52.50)),
+    (3151, BoundingBox::new(10.25, 52.33, 11.01, 52.85)),
+    (3153, BoundingBox::new(10.05, 51.73, 10.73, 52.10)),
+    (3154, BoundingBox::new(10.72, 52.06, 11.13, 52.45)),
+    (3155, BoundingBox::new(9.55, 51.58, 10.20, 51.95)),
+    (3157, BoundingBox::new(10.05, 52.20, 10.42, 52.46)),
+    (3158, BoundingBox::new(10.35, 51.95, 10.95, 52.25)),
+    (3159, BoundingBox::new(9.62, 51.29, 10.70, 51.85)),
+    (3241, BoundingBox::new(9.35, 52.18, 10.15, 52.70)),
+    (3251, BoundingBox::new(8.30, 52.40, 9.05, 53.05)),
+    (3252, BoundingBox::new(9.05, 51.90, 9.65, 52.20)),
+    (3254, BoundingBox::new(9.65, 51.90, 10.30, 52.30)),
+    (3255, BoundingBox::new(9.35, 51.70, 9.90, 52.05)),
+    (3256, BoundingBox::new(8.85, 52.40, 9.45, 52.85)),
+    (3257, BoundingBox::new(8.95, 52.15, 9.45, 52.45)),
+    (3351, BoundingBox::new(9.75, 52.45, 10.65, 52.95)),
+    (3352, BoundingBox::new(8.30, 53.40, 9.10, 53.95)),
+    (3353, BoundingBox::new(9.65, 53.15, 10.35, 53.48)),
+    (3354, BoundingBox::new(10.60, 52.82, 11.60, 53.25)),
+    (3355, BoundingBox::new(10.10, 53.00, 11.00, 53.40)),
+    (3356, BoundingBox::new(8.60, 53.10, 9.10, 53.35)),
+    (3357, BoundingBox::new(8.95, 52.95, 9.75, 53.60)),
+    (3358, BoundingBox::new(9.35, 52.70, 10.20, 53.20)),
+    (3359, BoundingBox::new(9.05, 53.30, 9.85, 53.75)),
+    (3360, BoundingBox::new(10.20, 52.75, 10.95, 53.15)),
+    (3361, BoundingBox::new(8.95, 52.75, 9.55, 53.15)),
+    (3401, BoundingBox::new(8.55, 53.00, 8.72, 53.10)),
+    (3402, BoundingBox::new(7.05, 53.30, 7.30, 53.42)),
+    (3403, BoundingBox::new(8.15, 53.08, 8.32, 53.20)),
+    (3404, BoundingBox::new(7.95, 52.20, 8.15, 52.34)),
+    (3405, BoundingBox::new(8.00, 53.48, 8.18, 53.60)),
+    (3451, BoundingBox::new(7.75, 53.05, 8.25, 53.35)),
+    (3452, BoundingBox::new(6.90, 53.30, 7.70, 53.75)),
+    (3453, BoundingBox::new(7.55, 52.70, 8.25, 53.20)),
+    (3454, BoundingBox::new(6.95, 52.30, 7.85, 53.15)),
+    (3455, BoundingBox::new(7.75, 53.30, 8.20, 53.82)),
+    (3456, BoundingBox::new(6.65, 52.25, 7.30, 52.65)),
+    (3457, BoundingBox::new(6.60, 52.95, 7.80, 53.65)),
+    (3458, BoundingBox::new(8.00, 52.75, 8.65, 53.15)),
+    (3459, BoundingBox::new(7.65, 52.00, 8.50, 52.75)),
+    (3460, BoundingBox::new(8.00, 52.45, 8.50, 52.90)),
+    (3461, BoundingBox::new(8.15, 53.05, 8.65, 53.65)),
+    (3462, BoundingBox::new(7.45, 53.45, 7.95, 53.80))
+];
+
+/// Result of [`check_county_coordinates`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CountyCheck {
+    Inside,
+    Outside,
+
+    /// The county is not a district of Niedersachsen.
+    UnknownCounty
+}
+
+impl Display for CountyCheck {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CountyCheck::Inside => write!(f, "inside"),
+            CountyCheck::Outside => write!(f, "outside"),
+            CountyCheck::UnknownCounty => write!(f, "in an unknown county")
+        }
+    }
+}
+
+/// Bounding boxes of all districts with the name of a county.
+///
+/// Prefixes like "Landkreis" or "Stadt" and the case are ignored, names
+/// shared by a city and its district, like "Osnabrück", yield both boxes.
+pub fn county_bounds(county: &str) -> Vec<BoundingBox> {
+    let normalize = |name: &str| {
+        let name = name.trim();
+        [
+            "Landkreis ",
+            "Kreisfreie Stadt ",
+            "Stadt ",
+            "Region ",
+            "LK "
+        ]
+        .iter()
+        .find_map(|prefix| name.strip_prefix(prefix))
+        .unwrap_or(name)
+        .trim()
+        .to_lowercase()
+    };
+    let county = normalize(county);
+
+    let keys: Vec<u64> = DISTRICTS
+        .iter()
+        .filter(|(_, name)| normalize(name) == county)
+        .map(|(key, _)| *key)
+        .collect();
+
+    COUNTY_BOUNDS.iter().filter(|(key, _)| keys.contains(key)).map(|(_, bounds)| *bounds).collect()
+}
+
+/// Check if UTM coordinates lie within the bounding box of a county, widened
+/// by [`COUNTY_TOLERANCE`].
+pub fn check_county_coordinates(county: &str, easting: u64, northing: u64) -> CountyCheck {
+    let bounds = county_bounds(county);
+    if bounds.is_empty() {
+        return CountyCheck::UnknownCounty;
+    }
+
+    let (longitude, latitude) = utm_to_wgs84(easting, northing);
+    let inside = bounds.iter().any(|bounds| {
+        let widened = BoundingBox::new(
+            bounds.min_longitude - COUNTY_TOLERANCE,
+            bounds.min_latitude - COUNTY_TOLERANCE,
+            bounds.max_longitude + COUNTY_TOLERANCE,
+            bounds.max_latitude + COUNTY_TOLERANCE
+        );
+        widened.contains(longitude, latitude)
+    });
+
+    match inside {
+        true => CountyCheck::Inside,
+        false => CountyCheck::Outside
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn every_district_has_bounds() {
+        for (key, name) in DISTRICTS {
+            assert!(
+                COUNTY_BOUNDS.iter().any(|(bounds_key, _)| bounds_key == key),
+                "{name} has no bounds"
+            );
+        }
+    }
+
+    #[test]
+    fn check_county_coordinates_works() {
+        // 10.541013, 52.807907
+        assert_eq!(
+            check_county_coordinates("Gifhorn", 32603873, 5852015),
+            CountyCheck::Inside
+        );
+        assert_eq!(
+            check_county_coordinates("Landkreis Gifhorn", 32603873, 5852015),
+            CountyCheck::Inside
+        );
+        assert_eq!(
+            check_county_coordinates("Emsland", 32603873, 5852015),
+            CountyCheck::Outside
+        );
+        assert_eq!(
+            check_county_coordinates("Nirgendwo", 32603873, 5852015),
+            CountyCheck::UnknownCounty
+        );
+        assert_eq!(county_bounds("Osnabrück").len(), 2);
+    }
+}
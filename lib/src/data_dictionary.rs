@@ -0,0 +1,379 @@
+use std::collections::HashMap;
+
+use serde::Serialize;
+use serde_json::{json, Value};
+
+use crate::util::FieldDoc;
+use crate::{DamTargets, LandRecord, LegalDepartment, PHValues, UsageLocation, WaterRight};
+
+/// A single field of the data structs, described for users of the output.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DataDictionaryEntry {
+    /// Name of the struct containing the field.
+    pub data_type: &'static str,
+
+    /// Name of the field as serialized.
+    pub field: String,
+
+    /// Original german label of the field in the reports.
+    pub label: Option<String>,
+
+    /// Further description of the field.
+    pub description: Option<String>
+}
+
+/// Build the data dictionary of all data structs from their field docs.
+pub fn data_dictionary() -> Vec<DataDictionaryEntry> {
+    let data_types: [(&str, &[FieldDoc]); 6] = [
+        ("WaterRight", WaterRight::FIELD_DOCS),
+        ("LegalDepartment", LegalDepartment::FIELD_DOCS),
+        ("UsageLocation", UsageLocation::FIELD_DOCS),
+        ("LandRecord", LandRecord::FIELD_DOCS),
+        ("PHValues", PHValues::FIELD_DOCS),
+        ("DamTargets", DamTargets::FIELD_DOCS)
+    ];
+
+    data_types
+        .into_iter()
+        .flat_map(|(data_type, field_docs)| {
+            field_docs.iter().map(move |field_doc| {
+                let (label, description) = split_doc(field_doc.doc);
+                DataDictionaryEntry {
+                    data_type,
+                    field: field_doc
+                        .rename
+                        .map(ToString::to_string)
+                        .unwrap_or_else(|| to_camel_case(field_doc.field)),
+                    label,
+                    description
+                }
+            })
+        })
+        .collect()
+}
+
+/// How a data struct is nested into the field of another one.
+#[derive(Debug, Clone, Copy)]
+enum Nesting {
+    /// The field contains the struct itself.
+    Single,
+
+    /// The field contains an array or map of the struct.
+    Each
+}
+
+/// Data structs nested into fields of other data structs.
+const NESTED_DATA_TYPES: &[(&str, &str, &str, Nesting)] = &[
+    (
+        "WaterRight",
+        "legalDepartments",
+        "LegalDepartment",
+        Nesting::Each
+    ),
+    (
+        "LegalDepartment",
+        "usageLocations",
+        "UsageLocation",
+        Nesting::Each
+    ),
+    ("UsageLocation", "landRecord", "LandRecord", Nesting::Single),
+    ("UsageLocation", "pHValues", "PHValues", Nesting::Single),
+    (
+        "UsageLocation",
+        "damTargetLevels",
+        "DamTargets",
+        Nesting::Single
+    )
+];
+
+/// Empty value of a field that is skipped when empty instead of being `None`.
+#[derive(Debug, Clone, Copy)]
+enum Empty {
+    Array,
+
+    /// A data struct whose fields are all skipped.
+    Object
+}
+
+/// Fields skipped when empty, every other skipped field is `null`.
+const EMPTY_FIELDS: &[(&str, &str, Empty)] = &[
+    ("UsageLocation", "legalPurposes", Empty::Array),
+    ("UsageLocation", "withdrawalRates", Empty::Array),
+    ("UsageLocation", "pumpingRates", Empty::Array),
+    ("UsageLocation", "injectionRates", Empty::Array),
+    ("UsageLocation", "wasteWaterFlowVolume", Empty::Array),
+    ("UsageLocation", "damTargetLevels", Empty::Object),
+    ("UsageLocation", "fluidDischarge", Empty::Array),
+    ("UsageLocation", "rainSupplement", Empty::Array),
+    ("UsageLocation", "injectionLimits", Empty::Array),
+    ("DamTargets", "other", Empty::Array)
+];
+
+/// Serialize water rights with every field, also the empty ones.
+///
+/// The reports leave out fields without a value, here they are `null` or, for
+/// collections, empty, so every record has the same shape for schema-strict
+/// consumers.
+/// Nested data structs, like the usage locations, are filled the same way.
+pub fn full_schema_water_rights(water_rights: &[WaterRight]) -> serde_json::Result<Value> {
+    let mut fields: HashMap<&str, Vec<String>> = HashMap::new();
+    for entry in data_dictionary() {
+        fields.entry(entry.data_type).or_default().push(entry.field);
+    }
+
+    let mut value = serde_json::to_value(water_rights)?;
+    if let Value::Array(water_rights) = &mut value {
+        for water_right in water_rights {
+            fill_data_type(water_right, "WaterRight", &fields);
+        }
+    }
+
+    Ok(value)
+}
+
+fn fill_data_type(value: &mut Value, data_type: &str, fields: &HashMap<&str, Vec<String>>) {
+    // fallback values like an unparsed land record are no data struct
+    let Value::Object(values) = value
+    else {
+        return;
+    };
+
+    for field in fields.get(data_type).into_iter().flatten() {
+        let empty = EMPTY_FIELDS
+            .iter()
+            .find(|(parent, parent_field, _)| *parent == data_type && parent_field == field);
+        let empty = match empty {
+            Some((.., Empty::Array)) => Value::Array(Vec::new()),
+            Some((.., Empty::Object)) => Value::Object(Default::default()),
+            None => Value::Null
+        };
+        values.entry(field.as_str()).or_insert(empty);
+    }
+
+    for (field, value) in values.iter_mut() {
+        let nested = NESTED_DATA_TYPES
+            .iter()
+            .find(|(parent, parent_field, ..)| *parent == data_type && parent_field == field);
+        match (nested, &mut *value) {
+            (Some((.., nested, Nesting::Single)), value) => fill_data_type(value, nested, fields),
+            (Some((.., nested, Nesting::Each)), Value::Array(items)) => {
+                items.iter_mut().for_each(|item| fill_data_type(item, nested, fields))
+            }
+            (Some((.., nested, Nesting::Each)), Value::Object(items)) => {
+                items.values_mut().for_each(|item| fill_data_type(item, nested, fields))
+            }
+            _ => ()
+        }
+    }
+}
+
+/// Serialize water rights with the german label next to every value.
+///
+/// Every field keeps its english name but its value is replaced by
+/// `{"value": ..., "labelDe": ...}`, `labelDe` is `null` for fields without a
+/// label in the reports.
+/// Nested data structs, like the usage locations, are labeled the same way.
+pub fn bilingual_water_rights(water_rights: &[WaterRight]) -> serde_json::Result<Value> {
+    let labels: HashMap<(&str, String), Option<String>> = data_dictionary()
+        .into_iter()
+        .map(|entry| ((entry.data_type, entry.field), entry.label))
+        .collect();
+
+    let mut value = serde_json::to_value(water_rights)?;
+    if let Value::Array(water_rights) = &mut value {
+        for water_right in water_rights {
+            label_fields(water_right, "WaterRight", &labels);
+        }
+    }
+
+    Ok(value)
+}
+
+fn label_fields(
+    value: &mut Value,
+    data_type: &str,
+    labels: &HashMap<(&str, String), Option<String>>
+) {
+    // fallback values like an unparsed land record are no data struct
+    let Value::Object(fields) = value
+    else {
+        return;
+    };
+
+    for (field, value) in fields.iter_mut() {
+        let nested = NESTED_DATA_TYPES
+            .iter()
+            .find(|(parent, parent_field, ..)| *parent == data_type && parent_field == field);
+        match (nested, &mut *value) {
+            (Some((.., nested, Nesting::Single)), value) => label_fields(value, nested, labels),
+            (Some((.., nested, Nesting::Each)), Value::Array(items)) => {
+                items.iter_mut().for_each(|item| label_fields(item, nested, labels))
+            }
+            (Some((.., nested, Nesting::Each)), Value::Object(items)) => {
+                items.values_mut().for_each(|item| label_fields(item, nested, labels))
+            }
+            _ => ()
+        }
+
+        let label = labels.get(&(data_type, field.clone())).cloned().flatten();
+        *value = json!({
+            "value": value.take(),
+            "labelDe": label
+        });
+    }
+}
+
+/// Split doc lines into the quoted german label and the remaining description.
+fn split_doc(doc: &[&str]) -> (Option<String>, Option<String>) {
+    let mut lines = doc.iter().map(|line| line.trim()).filter(|line| !line.is_empty());
+    let mut description: Vec<&str> = Vec::new();
+
+    let label = match lines.next() {
+        Some(first) if first.starts_with('"') => match first[1..].split_once('"') {
+            Some((label, rest)) => {
+                description.push(rest.trim());
+                Some(label.to_string())
+            }
+            None => {
+                description.push(first);
+                None
+            }
+        },
+        Some(first) => {
+            description.push(first);
+            None
+        }
+        None => None
+    };
+
+    description.extend(lines);
+    let description = description.into_iter().filter(|line| !line.is_empty()).collect::<Vec<_>>();
+    let description = match description.is_empty() {
+        true => None,
+        false => Some(description.join(" "))
+    };
+
+    (label, description)
+}
+
+fn to_camel_case(snake_case: &str) -> String {
+    let mut camel_case = String::with_capacity(snake_case.len());
+    let mut upper_next = false;
+    for c in snake_case.chars() {
+        match (c, upper_next) {
+            ('_', _) => upper_next = true,
+            (c, true) => {
+                camel_case.extend(c.to_uppercase());
+                upper_next = false;
+            }
+            (c, false) => camel_case.push(c)
+        }
+    }
+
+    camel_case
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn data_dictionary_works() {
+        let dictionary = data_dictionary();
+
+        let legal_departments = dictionary
+            .iter()
+            .find(|entry| entry.data_type == "WaterRight" && entry.field == "legalDepartments")
+            .unwrap();
+        assert_eq!(legal_departments.label, None);
+        assert!(legal_departments.description.as_ref().unwrap().starts_with("The usage locations"));
+
+        let holder = dictionary.iter().find(|entry| entry.field == "holder").unwrap();
+        assert_eq!(holder.label.as_deref(), Some("Rechtsinhaber"));
+        assert_eq!(holder.description, None);
+
+        let ph_values = dictionary.iter().find(|entry| entry.field == "pHValues").unwrap();
+        assert_eq!(ph_values.label.as_deref(), Some("pH-Werte"));
+
+        let injection_limits =
+            dictionary.iter().find(|entry| entry.field == "injectionLimits").unwrap();
+        assert_eq!(injection_limits.label.as_deref(), Some("Erlaubniswert"));
+        assert_eq!(
+            injection_limits.description.as_deref(),
+            Some("for legal department B")
+        );
+    }
+
+    #[test]
+    fn full_schema_water_rights_works() {
+        let reports_json = concat!(env!("CARGO_MANIFEST_DIR"), "/test/reports.json");
+        let water_rights = crate::load_water_rights(reports_json).unwrap();
+        let compact = serde_json::to_value(&water_rights).unwrap();
+        let full = full_schema_water_rights(&water_rights).unwrap();
+
+        let keys = |value: &Value| -> Vec<String> {
+            let mut keys: Vec<String> = value.as_object().unwrap().keys().cloned().collect();
+            keys.sort();
+            keys
+        };
+        let fields = |data_type: &str| -> Vec<String> {
+            let mut fields: Vec<String> = data_dictionary()
+                .into_iter()
+                .filter(|entry| entry.data_type == data_type)
+                .map(|entry| entry.field)
+                .collect();
+            fields.sort();
+            fields
+        };
+
+        assert_eq!(compact[0].get("subject"), None);
+        assert_eq!(full[0]["subject"], Value::Null);
+        assert_eq!(keys(&full[0]), fields("WaterRight"));
+        assert!(keys(&compact[0]).len() < keys(&full[0]).len());
+
+        let compact_location = &compact[0]["legalDepartments"]["A"]["usageLocations"][0];
+        let full_location = &full[0]["legalDepartments"]["A"]["usageLocations"][0];
+        assert_eq!(compact_location.get("pumpingRates"), None);
+        assert_eq!(full_location["pumpingRates"], json!([]));
+        assert_eq!(full_location["landRecord"], Value::Null);
+        assert_eq!(keys(full_location), fields("UsageLocation"));
+        assert_eq!(
+            keys(&full_location["damTargetLevels"]),
+            fields("DamTargets")
+        );
+        assert_eq!(full_location["damTargetLevels"]["other"], json!([]));
+
+        // the values of the compact output are kept
+        for (field, value) in compact_location.as_object().unwrap() {
+            assert_eq!(&full_location[field], value, "{field}");
+        }
+
+        // the full output reads back into the same water rights
+        let read_back: Vec<WaterRight> = serde_json::from_value(full).unwrap();
+        assert_eq!(serde_json::to_value(read_back).unwrap(), compact);
+    }
+
+    #[test]
+    fn bilingual_water_rights_works() {
+        let reports_json = concat!(env!("CARGO_MANIFEST_DIR"), "/test/reports.json");
+        let water_rights = crate::load_water_rights(reports_json).unwrap();
+        let bilingual = bilingual_water_rights(&water_rights).unwrap();
+
+        assert_eq!(
+            bilingual[0]["holder"],
+            json!({"value": "Körtke", "labelDe": "Rechtsinhaber"})
+        );
+        assert_eq!(
+            bilingual[0]["no"],
+            json!({"value": 1101, "labelDe": "Wasserrecht Nr."})
+        );
+
+        let usage_location =
+            &bilingual[0]["legalDepartments"]["value"]["A"]["usageLocations"]["value"][0];
+        assert_eq!(
+            usage_location["county"],
+            json!({"value": "Gifhorn", "labelDe": "Landkreis"})
+        );
+    }
+}
@@ -0,0 +1,94 @@
+//! Best-effort check of the free disk space before writing large outputs.
+//!
+//! Running out of space in the middle of a write leaves half-written outputs,
+//! so the tools estimate their output size upfront and abort early instead.
+
+use std::fmt::{Display, Formatter};
+use std::io;
+use std::path::Path;
+
+/// Outputs are assumed to be at most this many times as large as their input.
+///
+/// Flat tables repeat the water right for every usage location and are
+/// therefore larger than the nested JSON they are made from.
+pub const OUTPUT_SIZE_FACTOR: u64 = 2;
+
+/// Result of [`check_space`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SpaceCheck {
+    /// Enough space is left after writing.
+    Sufficient,
+
+    /// Writing would leave less than the minimum free bytes.
+    Insufficient { required: u64, available: u64 }
+}
+
+impl Display for SpaceCheck {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SpaceCheck::Sufficient => write!(f, "sufficient disk space"),
+            SpaceCheck::Insufficient {
+                required,
+                available
+            } => write!(
+                f,
+                "insufficient disk space, {required} bytes required but only {available} bytes \
+                 available"
+            )
+        }
+    }
+}
+
+/// Estimate the size of an output from the size of its input.
+pub fn estimate_output_size(input_size: u64) -> u64 {
+    input_size.saturating_mul(OUTPUT_SIZE_FACTOR)
+}
+
+/// Check if writing `estimated_output` bytes leaves at least `min_free_bytes`
+/// of the `available` bytes free.
+pub fn check_space(available: u64, estimated_output: u64, min_free_bytes: u64) -> SpaceCheck {
+    let required = estimated_output.saturating_add(min_free_bytes);
+    match available >= required {
+        true => SpaceCheck::Sufficient,
+        false => SpaceCheck::Insufficient {
+            required,
+            available
+        }
+    }
+}
+
+/// Check the space available in the file system of `dir`, see
+/// [`check_space`].
+///
+/// Fails if the available space cannot be determined, callers should then
+/// continue without the check.
+pub fn check_free_space(
+    dir: &Path,
+    estimated_output: u64,
+    min_free_bytes: u64
+) -> io::Result<SpaceCheck> {
+    let available = fs2::available_space(dir)?;
+    Ok(check_space(available, estimated_output, min_free_bytes))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn check_space_works() {
+        assert_eq!(estimate_output_size(1000), 2000);
+        assert_eq!(estimate_output_size(u64::MAX), u64::MAX);
+
+        assert_eq!(check_space(3000, 2000, 1000), SpaceCheck::Sufficient);
+        assert_eq!(check_space(3000, 2000, 0), SpaceCheck::Sufficient);
+        assert_eq!(check_space(2999, 2000, 1000), SpaceCheck::Insufficient {
+            required: 3000,
+            available: 2999
+        });
+        assert_eq!(check_space(100, u64::MAX, 1), SpaceCheck::Insufficient {
+            required: u64::MAX,
+            available: 100
+        });
+    }
+}
@@ -9,7 +9,7 @@ use serde::de::{DeserializeOwned, Error};
 use serde::{Deserialize, Deserializer, Serialize, Serializer};
 use serde_json::Value;
 
-use crate::util::Near;
+use crate::util::{parse_decimal, Near};
 
 #[derive(Debug)]
 pub struct Rate<T> {
@@ -23,7 +23,7 @@ where
     T: PartialEq
 {
     fn eq(&self, other: &Self) -> bool {
-        self.per == other.per && self.value == other.value
+        self.per == other.per && self.unit == other.unit && self.value == other.value
     }
 }
 
@@ -31,7 +31,7 @@ impl<T> Eq for Rate<T> where T: PartialEq {}
 
 impl<T> PartialOrd<Self> for Rate<T>
 where
-    T: PartialEq
+    T: PartialOrd
 {
     fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
         Some(self.cmp(other))
@@ -40,10 +40,16 @@ where
 
 impl<T> Ord for Rate<T>
 where
-    T: PartialEq
+    T: PartialOrd
 {
     fn cmp(&self, other: &Self) -> Ordering {
-        self.per.cmp(&other.per)
+        // the unit and value are compared too, otherwise a rate record could not hold
+        // rates with the same period but different measurements, or repeated allowances
+        // of the same kind
+        self.per
+            .cmp(&other.per)
+            .then_with(|| self.unit.cmp(&other.unit))
+            .then_with(|| self.value.partial_cmp(&other.value).unwrap_or(Ordering::Equal))
     }
 }
 
@@ -92,13 +98,13 @@ impl FromStr for Rate<f64> {
         let unit =
             split.next().ok_or_else(|| anyhow::Error::msg(format!("rate has no unit: {s}")))?;
 
-        let value: f64 = value.parse()?;
+        let value = parse_decimal(value)?;
 
         let unit_capture = UNIT_RE.captures(unit).ok_or(anyhow::Error::msg(format!(
             "unit {unit:?} has invalid format"
         )))?;
         let measurement = unit_capture["measurement"].to_string();
-        let factor: f64 = unit_capture["factor"].parse().unwrap_or(1f64);
+        let factor = parse_decimal(&unit_capture["factor"]).unwrap_or(1f64);
         let time = match &unit_capture["time"] {
             "s" => Duration::Seconds(factor),
             "m" | "min" => Duration::Minutes(factor),
@@ -407,4 +413,26 @@ mod tests {
         assert_eq!(serde_json::from_str::<T>(SINGLE_SER).unwrap(), SINGLE_DE);
         assert_eq!(serde_json::from_str::<T>(PAIR_SER).unwrap(), PAIR_DE);
     }
+
+    #[test]
+    fn rate_from_str_reads_decimal_commas() {
+        let rate: Rate<f64> = "0,5 m³/s".parse().unwrap();
+        assert_eq!(rate.value, 0.5);
+        assert_eq!(rate.unit, "m³");
+        assert!(matches!(rate.per, Duration::Seconds(f) if f == 1.0));
+
+        let rate: Rate<f64> = "1.200,00 m³/a".parse().unwrap();
+        assert_eq!(rate.value, 1200.0);
+        assert!(matches!(rate.per, Duration::Years(f) if f == 1.0));
+
+        let rate: Rate<f64> = "12.5 l/s".parse().unwrap();
+        assert_eq!(rate.value, 12.5);
+        assert_eq!(rate.unit, "l");
+
+        let rate: Rate<f64> = "0.125 m³/s".parse().unwrap();
+        assert_eq!(rate.value, 0.125);
+
+        let rate: Rate<f64> = "3 m³/0,5h".parse().unwrap();
+        assert!(matches!(rate.per, Duration::Hours(f) if f == 0.5));
+    }
 }
@@ -1,18 +1,28 @@
-use std::collections::{BTreeSet, HashMap};
+use std::collections::{BTreeSet, HashMap, HashSet};
 use std::error::Error;
 use std::fmt::{Display, Formatter};
+use std::fs;
+use std::io::{self, BufRead, BufReader, Read};
+use std::path::Path;
 use std::str::FromStr;
 
+use flate2::read::GzDecoder;
 use helper_types::*;
-use serde::{Deserialize, Serialize};
+use serde::{Deserialize, Deserializer, Serialize};
 use serde_with::skip_serializing_none;
 
 use crate::util::data_structs;
 
+pub mod builder;
 pub mod cadenza;
 pub mod cli;
+pub mod county;
+pub mod data_dictionary;
+pub mod disk_space;
 pub mod helper_types;
+pub mod municipality;
 pub mod util;
+pub mod water_body;
 
 pub type WaterRightNo = u64;
 
@@ -41,6 +51,11 @@ data_structs! {
         /// "Rechtstitel"
         legal_title?: String,
 
+        /// "Rechtstitel"
+        /// of the cadenza table, only set if it differs from the one of the
+        /// report
+        cadenza_legal_title?: String,
+
         /// "Wasserbehörde"
         water_authority?: String,
 
@@ -74,7 +89,7 @@ data_structs! {
         /// departments.
         /// This map holds all legal departments available in a water right and
         /// their corresponding usage locations.
-        legal_departments: HashMap<LegalDepartmentAbbreviation, LegalDepartment>,
+        legal_departments: HashMap<LegalDepartmentAbbreviation, LegalDepartment> = HashMap::new(),
 
         /// "Bemerkung"
         annotation?: String,
@@ -82,7 +97,7 @@ data_structs! {
 
     /// The water rights are split into different departments.
     #[serde(rename_all = "camelCase")]
-    struct LegalDepartment {
+    struct LegalDepartment: fn from_fields {
         /// "Abteilungsbezeichnung"
         description: String,
 
@@ -90,7 +105,7 @@ data_structs! {
         abbreviation: LegalDepartmentAbbreviation,
 
         /// "Nutzungsorte"
-        usage_locations: Vec<UsageLocation>,
+        usage_locations: Vec<UsageLocation> = Vec::new(),
     }
 
     /// A single water right may have multiple usage locations.
@@ -115,7 +130,16 @@ data_structs! {
         name?: String,
 
         /// "Rechtszweck"
-        legal_purpose?: (String, String),
+        ///
+        /// Usually a single `(code, description)` pair, some usage locations
+        /// list multiple purposes.
+        #[serde(
+            skip_serializing_if = "Vec::is_empty",
+            default,
+            alias = "legalPurpose",
+            deserialize_with = "deserialize_legal_purposes"
+        )]
+        legal_purposes: Vec<(String, String)> = Vec::new(),
 
         /// "Top. Karte 1:25.000"
         #[serde(alias = "topMap1:25000")]
@@ -124,6 +148,12 @@ data_structs! {
         /// "Gemeindegebiet"
         municipal_area?: (u64, String),
 
+        /// Official name of the municipal area, looked up from its key
+        municipality_name?: String,
+
+        /// District of the municipal area, looked up from its key
+        municipality_district?: String,
+
         /// "Landkreis"
         county?: String,
 
@@ -152,7 +182,7 @@ data_structs! {
             default,
             alias = "withdrawalRate"
         )]
-        withdrawal_rates: RateRecord,
+        withdrawal_rates: RateRecord = RateRecord::new(),
 
         /// "Förderleistung"
         #[serde(
@@ -160,7 +190,7 @@ data_structs! {
             default,
             alias = "pumpingRate"
         )]
-        pumping_rates: RateRecord,
+        pumping_rates: RateRecord = RateRecord::new(),
 
         /// "Einleitungsmenge"
         #[serde(
@@ -168,11 +198,11 @@ data_structs! {
             default,
             alias = "injectionRate"
         )]
-        injection_rates: RateRecord,
+        injection_rates: RateRecord = RateRecord::new(),
 
         /// "Abwasservolumenstrom"
         #[serde(skip_serializing_if = "RateRecord::is_empty", default)]
-        waste_water_flow_volume: RateRecord,
+        waste_water_flow_volume: RateRecord = RateRecord::new(),
 
         /// "Flussgebiet"
         river_basin?: String,
@@ -183,6 +213,9 @@ data_structs! {
         /// "Gewässer"
         water_body?: String,
 
+        /// Canonical name of the water body, looked up from its known variants
+        water_body_normalized?: String,
+
         /// "Überschwemmungsgebiet"
         flood_area?: String,
 
@@ -191,15 +224,15 @@ data_structs! {
 
         /// "Stauziele"
         #[serde(skip_serializing_if = "DamTargets::is_empty", default)]
-        dam_target_levels: DamTargets,
+        dam_target_levels: DamTargets = DamTargets::new(),
 
         /// "Ableitungsmenge"
         #[serde(skip_serializing_if = "RateRecord::is_empty", default)]
-        fluid_discharge: RateRecord,
+        fluid_discharge: RateRecord = RateRecord::new(),
 
         /// "Zusatzregen"
         #[serde(skip_serializing_if = "RateRecord::is_empty", default)]
-        rain_supplement: RateRecord,
+        rain_supplement: RateRecord = RateRecord::new(),
 
         /// "Beregnungsfläche"
         irrigation_area?: Quantity,
@@ -214,7 +247,7 @@ data_structs! {
             default,
             alias = "injectionLimit"
         )]
-        injection_limits: Vec<(String, Quantity)>,
+        injection_limits: Vec<(String, Quantity)> = Vec::new(),
 
         /// "UTM-Rechtswert"
         utm_easting?: u64,
@@ -251,79 +284,127 @@ data_structs! {
 
         /// "Höchststau"
         max?: Quantity,
+
+        /// Dam targets with other reference bases, e.g. "Stauziel, bezogen auf
+        /// Pegel X", keyed by their full description
+        #[serde(skip_serializing_if = "Vec::is_empty", default)]
+        other: Vec<(String, Quantity)> = Vec::new(),
     }
 }
 
 impl WaterRight {
-    pub fn new(water_right_no: WaterRightNo) -> Self {
-        WaterRight {
-            no: water_right_no,
-            holder: None,
-            valid_until: None,
-            status: None,
-            valid_from: None,
-            legal_title: None,
-            water_authority: None,
-            registering_authority: None,
-            granting_authority: None,
-            initially_granted: None,
-            last_change: None,
-            file_reference: None,
-            external_identifier: None,
-            subject: None,
-            address: None,
-            legal_departments: Default::default(),
-            annotation: None
+    /// All usage locations of the water right with the legal department they
+    /// belong to.
+    ///
+    /// The legal departments are visited in no particular order, the usage
+    /// locations of a department in their order.
+    pub fn usage_locations(
+        &self
+    ) -> impl Iterator<Item = (LegalDepartmentAbbreviation, &UsageLocation)> {
+        self.legal_departments.values().flat_map(|department| {
+            department
+                .usage_locations
+                .iter()
+                .map(|usage_location| (department.abbreviation, usage_location))
+        })
+    }
+
+    /// Mutable variant of [`WaterRight::usage_locations`].
+    pub fn usage_locations_mut(
+        &mut self
+    ) -> impl Iterator<Item = (LegalDepartmentAbbreviation, &mut UsageLocation)> {
+        self.legal_departments.values_mut().flat_map(|department| {
+            let abbreviation = department.abbreviation;
+            department
+                .usage_locations
+                .iter_mut()
+                .map(move |usage_location| (abbreviation, usage_location))
+        })
+    }
+
+    /// Remove the UTM coordinates of every usage location.
+    ///
+    /// Used for public releases that must not contain precise locations,
+    /// county and municipal area are kept.
+    /// Returns the number of usage locations that had coordinates.
+    pub fn exclude_coordinates(&mut self) -> usize {
+        let mut affected = 0;
+        for (_, usage_location) in self.usage_locations_mut() {
+            let easting = usage_location.utm_easting.take();
+            let northing = usage_location.utm_northing.take();
+            if easting.is_some() || northing.is_some() {
+                affected += 1;
+            }
+        }
+
+        affected
+    }
+
+    /// Mean of the UTM coordinates of all usage locations as easting and
+    /// northing.
+    ///
+    /// Usage locations without [`UsageLocation::utm_coordinates`] are
+    /// ignored, if none has coordinates, there is no centroid.
+    pub fn centroid(&self) -> Option<(f64, f64)> {
+        let (count, easting, northing) = self
+            .usage_locations()
+            .filter_map(|(_, usage_location)| usage_location.utm_coordinates())
+            .fold(
+                (0u64, 0.0, 0.0),
+                |(count, sum_e, sum_n), (easting, northing)| {
+                    (count + 1, sum_e + easting as f64, sum_n + northing as f64)
+                }
+            );
+
+        match count {
+            0 => None,
+            count => Some((easting / count as f64, northing / count as f64))
         }
     }
 }
 
 impl LegalDepartment {
     pub fn new(abbreviation: LegalDepartmentAbbreviation, description: String) -> Self {
-        LegalDepartment {
-            description,
-            abbreviation,
-            usage_locations: vec![]
-        }
+        LegalDepartment::from_fields(description, abbreviation)
+    }
+
+    /// Sort the usage locations by their serial, e.g. `1101-02` before
+    /// `1101-10`, then by their number.
+    ///
+    /// The numeric parts of the serials are compared as numbers, usage
+    /// locations without a serial come last.
+    pub fn sort_usage_locations(&mut self) {
+        self.usage_locations.sort_by_cached_key(|usage_location| {
+            let serial = usage_location.serial.as_ref().map(|serial| {
+                let numbers: Vec<u64> = serial
+                    .split(|c: char| !c.is_ascii_digit())
+                    .filter_map(|part| part.parse().ok())
+                    .collect();
+                (numbers, serial.clone())
+            });
+            (serial.is_none(), serial, usage_location.no)
+        });
     }
 }
 
 impl UsageLocation {
-    pub fn new() -> Self {
-        UsageLocation {
-            no: None,
-            serial: None,
-            active: None,
-            real: None,
-            name: None,
-            legal_purpose: None,
-            map_excerpt: None,
-            municipal_area: None,
-            county: None,
-            land_record: None,
-            plot: None,
-            maintenance_association: None,
-            eu_survey_area: None,
-            catchment_area_code: None,
-            regulation_citation: None,
-            withdrawal_rates: Default::default(),
-            pumping_rates: Default::default(),
-            injection_rates: Default::default(),
-            waste_water_flow_volume: Default::default(),
-            river_basin: None,
-            groundwater_body: None,
-            water_body: None,
-            flood_area: None,
-            water_protection_area: None,
-            dam_target_levels: DamTargets::default(),
-            fluid_discharge: Default::default(),
-            rain_supplement: Default::default(),
-            irrigation_area: None,
-            ph_values: None,
-            injection_limits: Default::default(),
-            utm_easting: None,
-            utm_northing: None
-        }
+    /// UTM easting and northing of the usage location.
+    ///
+    /// Usage locations without both coordinates have no position, a coordinate
+    /// of zero counts as missing, see [`util::UTM_ZERO_POLICY`].
+    pub fn utm_coordinates(&self) -> Option<(u64, u64)> {
+        let easting = self.utm_easting.and_then(|easting| util::UTM_ZERO_POLICY.apply(easting))?;
+        let northing =
+            self.utm_northing.and_then(|northing| util::UTM_ZERO_POLICY.apply(northing))?;
+        Some((easting, northing))
+    }
+
+    /// Latitude and longitude in WGS84 degrees of the
+    /// [`UsageLocation::utm_coordinates`], see [`util::utm_to_wgs84`].
+    pub fn to_lat_lon(&self) -> Option<(f64, f64)> {
+        let (easting, northing) = self.utm_coordinates()?;
+        let (longitude, latitude) = util::utm_to_wgs84(easting, northing);
+        Some((latitude, longitude))
     }
 }
 
@@ -353,7 +434,10 @@ pub enum LegalDepartmentAbbreviation {
     K,
 
     /// "Fischereirechte"
-    L
+    L,
+
+    /// Usage locations of reports without a recognizable "Abteilung"
+    Unknown
 }
 
 impl Display for LegalDepartmentAbbreviation {
@@ -366,13 +450,39 @@ impl Display for LegalDepartmentAbbreviation {
             LegalDepartmentAbbreviation::E => 'E',
             LegalDepartmentAbbreviation::F => 'F',
             LegalDepartmentAbbreviation::K => 'K',
-            LegalDepartmentAbbreviation::L => 'L'
+            LegalDepartmentAbbreviation::L => 'L',
+            LegalDepartmentAbbreviation::Unknown => '?'
         };
 
         write!(f, "{char}")
     }
 }
 
+impl LegalDepartmentAbbreviation {
+    /// Description of the legal department as used in the reports.
+    pub fn description(&self) -> &'static str {
+        match self {
+            LegalDepartmentAbbreviation::A => {
+                "Entnahme von Wasser oder Entnahmen fester Stoffe aus oberirdischen Gewässern"
+            }
+            LegalDepartmentAbbreviation::B => {
+                "Einbringen und Einleiten von Stoffen in oberirdische und Küstengewässer"
+            }
+            LegalDepartmentAbbreviation::C => "Aufstauen und Absenken oberirdischer Gewässer",
+            LegalDepartmentAbbreviation::D => "Andere Einwirkung auf oberirdische Gewässer",
+            LegalDepartmentAbbreviation::E => {
+                "Entnahme, Zutageförderung, Zutageleiten und Ableiten von Grundwasser"
+            }
+            LegalDepartmentAbbreviation::F => {
+                "Andere Nutzungen und Einwirkungen auf das Grundwasser"
+            }
+            LegalDepartmentAbbreviation::K => "Zwangsrechte",
+            LegalDepartmentAbbreviation::L => "Fischereirechte",
+            LegalDepartmentAbbreviation::Unknown => "Unbekannte Abteilung"
+        }
+    }
+}
+
 #[derive(Debug)]
 pub struct ParseLegalDepartmentError(String);
 
@@ -404,8 +514,466 @@ impl FromStr for LegalDepartmentAbbreviation {
 
 pub type RateRecord = BTreeSet<OrFallback<Rate<f64>>>;
 
+/// Deserialize legal purposes from either a list of pairs or a single pair.
+///
+/// Older reports only stored a single `(code, description)` pair as
+/// `legalPurpose`.
+fn deserialize_legal_purposes<'de, D>(deserializer: D) -> Result<Vec<(String, String)>, D::Error>
+where
+    D: Deserializer<'de>
+{
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum LegalPurposes {
+        Single((String, String)),
+        Multiple(Vec<(String, String)>)
+    }
+
+    Ok(match LegalPurposes::deserialize(deserializer)? {
+        LegalPurposes::Single(pair) => vec![pair],
+        LegalPurposes::Multiple(pairs) => pairs
+    })
+}
+
+/// Magic bytes at the start of every gzip stream.
+const GZIP_MAGIC: [u8; 2] = [0x1F, 0x8B];
+
+/// Load water rights from a reports JSON file as written by the parser.
+///
+/// Gzip compressed files are detected by their magic bytes and decompressed
+/// transparently.
+/// After parsing, the water rights are checked for duplicate numbers and
+/// legal departments that are stored under a different abbreviation than
+/// their own.
+pub fn load_water_rights(path: impl AsRef<Path>) -> anyhow::Result<Vec<WaterRight>> {
+    let bytes = fs::read(path)?;
+    let json = match bytes.starts_with(&GZIP_MAGIC) {
+        true => {
+            let mut json = String::new();
+            GzDecoder::new(bytes.as_slice()).read_to_string(&mut json)?;
+            json
+        }
+        false => String::from_utf8(bytes)?
+    };
+
+    let water_rights: Vec<WaterRight> = serde_json::from_str(&json)?;
+    validate_water_rights(&water_rights)?;
+    Ok(water_rights)
+}
+
+/// Read water rights from a JSONL file with one water right per line, one at
+/// a time.
+///
+/// Unlike [`load_water_rights`] only the current water right is held in
+/// memory, blank lines are skipped.
+/// Every water right is checked like in [`load_water_rights`] when it is read,
+/// so the iterator may fail after yielding some water rights.
+pub fn stream_water_rights(
+    path: impl AsRef<Path>
+) -> anyhow::Result<impl Iterator<Item = anyhow::Result<WaterRight>>> {
+    let lines = BufReader::new(fs::File::open(path)?).lines();
+    let mut seen = HashSet::new();
+    let water_rights = lines
+        .enumerate()
+        .filter(|(_, line)| !matches!(line, Ok(line) if line.trim().is_empty()))
+        .map(move |(i, line)| {
+            let water_right: WaterRight = serde_json::from_str(&line?)
+                .map_err(|err| anyhow::Error::msg(format!("line {}, {err}", i + 1)))?;
+            validate_water_right(&water_right, &mut seen)?;
+            Ok(water_right)
+        });
+    Ok(water_rights)
+}
+
+/// Read water rights from a reports JSON file, one element of its array at a
+/// time.
+///
+/// This reads the same files as [`load_water_rights`], including gzip
+/// compressed ones, but like [`stream_water_rights`] only the current water
+/// right is held in memory and every water right is checked when it is read.
+pub fn stream_water_rights_array(
+    path: impl AsRef<Path>
+) -> anyhow::Result<impl Iterator<Item = anyhow::Result<WaterRight>>> {
+    let mut file = BufReader::new(fs::File::open(path)?);
+    let reader: Box<dyn BufRead> = match file.fill_buf()?.starts_with(&GZIP_MAGIC) {
+        true => Box::new(BufReader::new(GzDecoder::new(file))),
+        false => Box::new(file)
+    };
+
+    let mut elements = JsonArrayElements {
+        reader,
+        read: 0,
+        done: false
+    };
+    let mut seen = HashSet::new();
+    let water_rights = std::iter::from_fn(move || {
+        let water_right = elements.next()?;
+        if water_right.is_err() {
+            elements.done = true;
+        }
+        Some(water_right.and_then(|water_right| {
+            validate_water_right(&water_right, &mut seen)?;
+            Ok(water_right)
+        }))
+    });
+    Ok(water_rights)
+}
+
+/// Elements of a JSON array read from `reader` one at a time.
+struct JsonArrayElements<R> {
+    reader: R,
+    read: usize,
+    done: bool
+}
+
+impl<R: BufRead> JsonArrayElements<R> {
+    /// Skip whitespace and return the next byte without consuming it.
+    fn peek(&mut self) -> io::Result<Option<u8>> {
+        loop {
+            match self.reader.fill_buf()?.first().copied() {
+                Some(byte) if byte.is_ascii_whitespace() => self.reader.consume(1),
+                byte => return Ok(byte)
+            }
+        }
+    }
+
+    fn expect(&mut self, expected: &[u8]) -> anyhow::Result<u8> {
+        match self.peek()? {
+            Some(byte) if expected.contains(&byte) => {
+                self.reader.consume(1);
+                Ok(byte)
+            }
+            found => Err(anyhow::Error::msg(format!(
+                "expected one of {:?} after {} water rights, found {:?}",
+                String::from_utf8_lossy(expected),
+                self.read,
+                found.map(char::from)
+            )))
+        }
+    }
+
+    fn next_element(&mut self) -> anyhow::Result<Option<WaterRight>> {
+        let end = match self.read {
+            0 => {
+                self.expect(b"[")?;
+                self.peek()? == Some(b']')
+            }
+            _ => self.expect(b",]")? == b']'
+        };
+        if end {
+            if self.read == 0 {
+                self.expect(b"]")?;
+            }
+            return match self.peek()? {
+                None => Ok(None),
+                Some(_) => Err(anyhow::Error::msg(
+                    "trailing characters after the water rights"
+                ))
+            };
+        }
+
+        let mut deserializer = serde_json::Deserializer::from_reader(&mut self.reader);
+        let water_right = WaterRight::deserialize(&mut deserializer)
+            .map_err(|err| anyhow::Error::msg(format!("water right {}, {err}", self.read + 1)))?;
+        self.read += 1;
+        Ok(Some(water_right))
+    }
+}
+
+impl<R: BufRead> Iterator for JsonArrayElements<R> {
+    type Item = anyhow::Result<WaterRight>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+
+        let element = self.next_element().transpose();
+        self.done = !matches!(element, Some(Ok(_)));
+        element
+    }
+}
+
+fn validate_water_rights(water_rights: &[WaterRight]) -> anyhow::Result<()> {
+    let mut seen = HashSet::with_capacity(water_rights.len());
+    for water_right in water_rights {
+        validate_water_right(water_right, &mut seen)?;
+    }
+
+    Ok(())
+}
+
+/// Check a water right against the numbers of the water rights `seen` before.
+fn validate_water_right(
+    water_right: &WaterRight,
+    seen: &mut HashSet<WaterRightNo>
+) -> anyhow::Result<()> {
+    if !seen.insert(water_right.no) {
+        return Err(anyhow::Error::msg(format!(
+            "water right {} appears more than once",
+            water_right.no
+        )));
+    }
+
+    for (abbreviation, department) in water_right.legal_departments.iter() {
+        if *abbreviation != department.abbreviation {
+            return Err(anyhow::Error::msg(format!(
+                "water right {} stores legal department {} under {abbreviation}",
+                water_right.no, department.abbreviation
+            )));
+        }
+    }
+
+    Ok(())
+}
+
 impl DamTargets {
     pub fn is_empty(&self) -> bool {
-        self.steady.is_none() && self.max.is_none() && self.default.is_none()
+        self.steady.is_none() &&
+            self.max.is_none() &&
+            self.default.is_none() &&
+            self.other.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use flate2::write::GzEncoder;
+    use flate2::Compression;
+
+    use super::*;
+
+    const REPORTS_JSON_PATH: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/test/reports.json");
+
+    fn usage_location(easting: Option<u64>, northing: Option<u64>) -> UsageLocation {
+        let mut usage_location = UsageLocation::new();
+        usage_location.utm_easting = easting;
+        usage_location.utm_northing = northing;
+        usage_location
+    }
+
+    #[test]
+    fn load_water_rights_works() {
+        let water_rights = load_water_rights(REPORTS_JSON_PATH).unwrap();
+        assert_eq!(water_rights.len(), 1);
+
+        let water_right = &water_rights[0];
+        assert_eq!(water_right.no, 1101);
+        assert_eq!(water_right.holder.as_deref(), Some("Körtke"));
+
+        let department = &water_right.legal_departments[&LegalDepartmentAbbreviation::A];
+        assert_eq!(department.usage_locations.len(), 1);
+        assert_eq!(department.usage_locations[0].withdrawal_rates.len(), 2);
+    }
+
+    #[test]
+    fn load_water_rights_detects_gzip() {
+        let json = fs::read(REPORTS_JSON_PATH).unwrap();
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(&json).unwrap();
+
+        let dir = tempfile::tempdir().unwrap();
+        let gz_path = dir.path().join("reports.json.gz");
+        fs::write(&gz_path, encoder.finish().unwrap()).unwrap();
+        let water_rights = load_water_rights(&gz_path).unwrap();
+
+        assert_eq!(water_rights.len(), 1);
+        assert_eq!(water_rights[0].no, 1101);
+    }
+
+    #[test]
+    fn stream_water_rights_works() {
+        let water_right = load_water_rights(REPORTS_JSON_PATH).unwrap().remove(0);
+        let line = serde_json::to_string(&water_right).unwrap();
+        let other = serde_json::to_string(&WaterRight::new(1102)).unwrap();
+
+        let dir = tempfile::tempdir().unwrap();
+        let jsonl_path = dir.path().join("reports.jsonl");
+        fs::write(&jsonl_path, format!("{line}\n\n{other}\n{line}\n")).unwrap();
+        let streamed: Vec<_> = stream_water_rights(&jsonl_path).unwrap().collect();
+
+        assert_eq!(streamed.len(), 3);
+        let first = streamed[0].as_ref().unwrap();
+        assert_eq!(first.holder.as_deref(), Some("Körtke"));
+        assert_eq!(first.usage_locations().count(), 1);
+        assert_eq!(streamed[1].as_ref().unwrap().no, 1102);
+        assert!(streamed[2].is_err());
+    }
+
+    #[test]
+    fn stream_water_rights_array_works() {
+        let json = fs::read_to_string(REPORTS_JSON_PATH).unwrap();
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(json.as_bytes()).unwrap();
+        let dir = tempfile::tempdir().unwrap();
+        let gz_path = dir.path().join("reports.json.gz");
+        fs::write(&gz_path, encoder.finish().unwrap()).unwrap();
+        let streamed: Vec<_> = stream_water_rights_array(&gz_path).unwrap().collect();
+        assert_eq!(streamed.len(), 1);
+        let first = streamed[0].as_ref().unwrap();
+        assert_eq!(first.holder.as_deref(), Some("Körtke"));
+        assert_eq!(first.usage_locations().count(), 1);
+
+        let stream = |json: &str| {
+            let json_path = dir.path().join("reports.json");
+            fs::write(&json_path, json).unwrap();
+            stream_water_rights_array(&json_path)
+                .unwrap()
+                .map(|water_right| water_right.map(|water_right| water_right.no).ok())
+                .collect::<Vec<_>>()
+        };
+        let a = serde_json::to_string(&WaterRight::new(1101)).unwrap();
+        let b = serde_json::to_string(&WaterRight::new(1102)).unwrap();
+        assert_eq!(stream(" [ ] \n"), []);
+        assert_eq!(stream(&format!("[{a}, {b}]")), [Some(1101), Some(1102)]);
+        assert_eq!(stream(&format!("[{a},{a},{b}]")), [
+            Some(1101),
+            None,
+            Some(1102)
+        ]);
+        assert_eq!(stream(&format!("[{a} {b}]")), [Some(1101), None]);
+        assert_eq!(stream(&format!("[{a}] []")), [Some(1101), None]);
+        assert_eq!(stream(&a), [None]);
+    }
+
+    #[test]
+    fn legal_purposes_accept_single_pair() {
+        let single: UsageLocation =
+            serde_json::from_str(r#"{"legalPurpose": ["A70", "Speisung von Teichen"]}"#).unwrap();
+        assert_eq!(single.legal_purposes, [(
+            "A70".to_string(),
+            "Speisung von Teichen".to_string()
+        )]);
+
+        let multiple: UsageLocation = serde_json::from_str(
+            r#"{"legalPurposes": [["A70", "Speisung von Teichen"], ["E10", "Beregnung"]]}"#
+        )
+        .unwrap();
+        assert_eq!(multiple.legal_purposes.len(), 2);
+    }
+
+    #[test]
+    fn validation_rejects_duplicates() {
+        let water_rights = [WaterRight::new(1), WaterRight::new(1)];
+        assert!(validate_water_rights(&water_rights).is_err());
+    }
+
+    #[test]
+    fn sort_usage_locations_works() {
+        let usage_location = |serial: Option<&str>, no: Option<u64>| {
+            let mut usage_location = UsageLocation::new();
+            usage_location.serial = serial.map(ToString::to_string);
+            usage_location.no = no;
+            usage_location
+        };
+
+        let mut department = LegalDepartment::new(LegalDepartmentAbbreviation::A, String::new());
+        department.usage_locations = vec![
+            usage_location(None, Some(1)),
+            usage_location(Some("1101-10"), Some(2)),
+            usage_location(Some("1101-02"), Some(4)),
+            usage_location(Some("1101-02"), Some(3)),
+            usage_location(Some("999-01"), None),
+        ];
+        department.sort_usage_locations();
+
+        let sorted: Vec<_> = department
+            .usage_locations
+            .iter()
+            .map(|usage_location| (usage_location.serial.as_deref(), usage_location.no))
+            .collect();
+        assert_eq!(sorted, [
+            (Some("999-01"), None),
+            (Some("1101-02"), Some(3)),
+            (Some("1101-02"), Some(4)),
+            (Some("1101-10"), Some(2)),
+            (None, Some(1))
+        ]);
+    }
+
+    #[test]
+    fn usage_locations_works() {
+        let usage_location = |serial: &str| {
+            let mut usage_location = UsageLocation::new();
+            usage_location.serial = Some(serial.to_string());
+            usage_location
+        };
+
+        let mut water_right = WaterRight::new(1101);
+        for (abbreviation, serials) in [
+            (
+                LegalDepartmentAbbreviation::A,
+                ["1101-01", "1101-02"].as_slice()
+            ),
+            (LegalDepartmentAbbreviation::E, ["1101-03"].as_slice())
+        ] {
+            let mut department = LegalDepartment::new(abbreviation, String::new());
+            department.usage_locations = serials.iter().copied().map(usage_location).collect();
+            water_right.legal_departments.insert(abbreviation, department);
+        }
+
+        let mut tagged: Vec<_> = water_right
+            .usage_locations()
+            .map(|(abbreviation, usage_location)| {
+                (abbreviation, usage_location.serial.as_deref().unwrap())
+            })
+            .collect();
+        tagged.sort();
+        assert_eq!(tagged, [
+            (LegalDepartmentAbbreviation::A, "1101-01"),
+            (LegalDepartmentAbbreviation::A, "1101-02"),
+            (LegalDepartmentAbbreviation::E, "1101-03")
+        ]);
+
+        for (abbreviation, usage_location) in water_right.usage_locations_mut() {
+            usage_location.name = Some(abbreviation.to_string());
+        }
+        let department_e = &water_right.legal_departments[&LegalDepartmentAbbreviation::E];
+        assert_eq!(department_e.usage_locations[0].name.as_deref(), Some("E"));
+        assert_eq!(water_right.usage_locations().count(), 3);
+    }
+
+    #[test]
+    fn centroid_works() {
+        let water_right = |usage_locations: Vec<UsageLocation>| {
+            let mut water_right = WaterRight::new(1101);
+            let mut department =
+                LegalDepartment::new(LegalDepartmentAbbreviation::A, String::new());
+            department.usage_locations = usage_locations;
+            water_right.legal_departments.insert(LegalDepartmentAbbreviation::A, department);
+            water_right
+        };
+
+        let multiple = water_right(vec![
+            usage_location(Some(32603873), Some(5852015)),
+            usage_location(Some(32603875), Some(5852021)),
+            usage_location(Some(32700000), None),
+            usage_location(Some(0), Some(5852015)),
+            usage_location(None, None),
+        ]);
+        assert_eq!(multiple.centroid(), Some((32603874.0, 5852018.0)));
+
+        let single = water_right(vec![usage_location(Some(32603873), Some(5852015))]);
+        assert_eq!(single.centroid(), Some((32603873.0, 5852015.0)));
+
+        let without_coordinates = water_right(vec![usage_location(None, Some(5852015))]);
+        assert_eq!(without_coordinates.centroid(), None);
+        assert_eq!(WaterRight::new(1101).centroid(), None);
+    }
+
+    #[test]
+    fn to_lat_lon_works() {
+        // near Gifhorn
+        let (latitude, longitude) =
+            usage_location(Some(32603873), Some(5852015)).to_lat_lon().unwrap();
+        assert!((52.80..52.82).contains(&latitude), "{latitude}");
+        assert!((10.53..10.55).contains(&longitude), "{longitude}");
+
+        assert_eq!(usage_location(Some(32603873), None).to_lat_lon(), None);
+        assert_eq!(usage_location(None, Some(5852015)).to_lat_lon(), None);
+        assert_eq!(usage_location(Some(0), Some(5852015)).to_lat_lon(), None);
+        assert_eq!(usage_location(Some(32603873), Some(0)).to_lat_lon(), None);
     }
 }
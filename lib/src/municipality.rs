@@ -0,0 +1,268 @@
+//! Lookup of official municipality keys ("Amtlicher Gemeindeschlüssel").
+//!
+//! The "Gemeindegebiet" of a usage location is a `(key, name)` pair.
+//! The key consists of the state, the district and the municipality, e.g.
+//! `03241001` for Hannover in the "Region Hannover" (`03241`).
+//! The reports drop the leading zero, so the keys are handled as numbers here.
+
+use std::collections::BTreeMap;
+use std::fmt::{Display, Formatter};
+
+use crate::UsageLocation;
+
+/// Districts ("Landkreise" and "kreisfreie Städte") of Niedersachsen by their
+/// key.
+pub const DISTRICTS: &[(u64, &str)] = &[
+    (3101, "Braunschweig"),
+    (3102, "Salzgitter"),
+    (3103, "Wolfsburg"),
+    (3151, "Gifhorn"),
+    (3153, "Goslar"),
+    (3154, "Helmstedt"),
+    (3155, "Northeim"),
+    (3157, "Peine"),
+    (3158, "Wolfenbüttel"),
+    (3159, "Göttingen"),
+    (3241, "Region Hannover"),
+    (3251, "Diepholz"),
+    (3252, "Hameln-Pyrmont"),
+    (3254, "Hildesheim"),
+    (3255, "Holzminden"),
+    (3256, "Nienburg (Weser)"),
+    (3257, "Schaumburg"),
+    (3351, "Celle"),
+    (3352, "Cuxhaven"),
+    (3353, "Harburg"),
+    (3354, "Lüchow-Dannenberg"),
+    (3355, "Lüneburg"),
+    (3356, "Osterholz"),
+    (3357, "Rotenburg (Wümme)"),
+    (3358, "Heidekreis"),
+    (3359, "Stade"),
+    (3360, "Uelzen"),
+    (3361, "Verden"),
+    (3401, "Delmenhorst"),
+    (3402, "Emden"),
+    (3403, "Oldenburg (Oldb)"),
+    (3404, "Osnabrück"),
+    (3405, "Wilhelmshaven"),
+    (3451, "Ammerland"),
+    (3452, "Aurich"),
+    (3453, "Cloppenburg"),
+    (3454, "Emsland"),
+    (3455, "Friesland"),
+    (3456, "Grafschaft Bentheim"),
+    (3457, "Leer"),
+    (3458, "Oldenburg"),
+    (3459, "Osnabrück"),
+    (3460, "Vechta"),
+    (3461, "Wesermarsch"),
+    (3462, "Wittmund")
+];
+
+/// Municipalities embedded into [`MunicipalityLookup::embedded`].
+///
+/// Only the district-free cities and Hannover are embedded, the full official
+/// table can be added via [`MunicipalityLookup::extend_from_csv`].
+pub const MUNICIPALITIES: &[(u64, &str)] = &[
+    (3101000, "Braunschweig, Stadt"),
+    (3102000, "Salzgitter, Stadt"),
+    (3103000, "Wolfsburg, Stadt"),
+    (3241001, "Hannover, Landeshauptstadt"),
+    (3401000, "Delmenhorst, Stadt"),
+    (3402000, "Emden, Stadt"),
+    (3403000, "Oldenburg (Oldenburg), Stadt"),
+    (3404000, "Osnabrück, Stadt"),
+    (3405000, "Wilhelmshaven, Stadt")
+];
+
+/// Result of [`MunicipalityLookup::enrich`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MunicipalityCheck {
+    /// The name in the report matches the official one.
+    Matching,
+
+    /// The name in the report differs from the official one.
+    Mismatch { official: String },
+
+    /// The district is known but the municipality is not in the lookup.
+    UnknownMunicipality,
+
+    /// The key does not belong to any district of Niedersachsen.
+    UnknownKey
+}
+
+impl Display for MunicipalityCheck {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MunicipalityCheck::Matching => write!(f, "matching"),
+            MunicipalityCheck::Mismatch { official } => write!(f, "officially named {official:?}"),
+            MunicipalityCheck::UnknownMunicipality => write!(f, "an unknown municipality"),
+            MunicipalityCheck::UnknownKey => write!(f, "an unknown key")
+        }
+    }
+}
+
+/// Lookup of municipality names and districts by their key.
+#[derive(Debug, Clone)]
+pub struct MunicipalityLookup {
+    municipalities: BTreeMap<u64, String>,
+    districts: BTreeMap<u64, String>
+}
+
+impl MunicipalityLookup {
+    /// Lookup with the embedded [`DISTRICTS`] and [`MUNICIPALITIES`].
+    pub fn embedded() -> Self {
+        let owned = |table: &[(u64, &str)]| {
+            table.iter().map(|(key, name)| (*key, name.to_string())).collect()
+        };
+
+        MunicipalityLookup {
+            municipalities: owned(MUNICIPALITIES),
+            districts: owned(DISTRICTS)
+        }
+    }
+
+    /// Add municipalities from a CSV with the key and the name separated by
+    /// `;`, lines without a numeric key, like a header, are skipped.
+    ///
+    /// Entries of the CSV replace embedded ones with the same key.
+    pub fn extend_from_csv(&mut self, csv: &str) -> anyhow::Result<()> {
+        for line in csv.lines() {
+            let Some((key, name)) = line.split_once(';')
+            else {
+                continue;
+            };
+            let Ok(key) = key.trim().trim_matches('"').parse::<u64>()
+            else {
+                continue;
+            };
+
+            let name = name.trim().trim_matches('"');
+            if name.is_empty() {
+                return Err(anyhow::Error::msg(format!(
+                    "municipality {key} has no name"
+                )));
+            }
+            self.municipalities.insert(key, name.to_string());
+        }
+
+        Ok(())
+    }
+
+    /// All municipalities of the lookup by their key.
+    pub fn municipalities(&self) -> &BTreeMap<u64, String> {
+        &self.municipalities
+    }
+
+    pub fn municipality(&self, key: u64) -> Option<&str> {
+        self.municipalities.get(&key).map(String::as_str)
+    }
+
+    /// District of a municipality key.
+    pub fn district(&self, key: u64) -> Option<&str> {
+        self.districts.get(&(key / 1000)).map(String::as_str)
+    }
+
+    /// Fill the official municipality name and district of a usage location
+    /// from its "Gemeindegebiet".
+    ///
+    /// Returns `None` if the usage location has no "Gemeindegebiet".
+    pub fn enrich(&self, usage_location: &mut UsageLocation) -> Option<MunicipalityCheck> {
+        let (key, name) = usage_location.municipal_area.as_ref()?;
+
+        let Some(district) = self.district(*key)
+        else {
+            return Some(MunicipalityCheck::UnknownKey);
+        };
+        usage_location.municipality_district = Some(district.to_string());
+
+        let Some(official) = self.municipality(*key)
+        else {
+            return Some(MunicipalityCheck::UnknownMunicipality);
+        };
+        let check = match names_match(name, official) {
+            true => MunicipalityCheck::Matching,
+            false => MunicipalityCheck::Mismatch {
+                official: official.to_string()
+            }
+        };
+        usage_location.municipality_name = Some(official.to_string());
+
+        Some(check)
+    }
+}
+
+/// Compare names ignoring case and suffixes like ", Stadt".
+fn names_match(report: &str, official: &str) -> bool {
+    let normalize = |name: &str| name.split(',').next().unwrap_or_default().trim().to_lowercase();
+
+    normalize(report) == normalize(official)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn usage_location(key: u64, name: &str) -> UsageLocation {
+        let mut usage_location = UsageLocation::new();
+        usage_location.municipal_area = Some((key, name.to_string()));
+        usage_location
+    }
+
+    #[test]
+    fn enrich_works() {
+        let lookup = MunicipalityLookup::embedded();
+
+        let mut hannover = usage_location(3241001, "Hannover");
+        assert_eq!(
+            lookup.enrich(&mut hannover),
+            Some(MunicipalityCheck::Matching)
+        );
+        assert_eq!(
+            hannover.municipality_name.as_deref(),
+            Some("Hannover, Landeshauptstadt")
+        );
+        assert_eq!(
+            hannover.municipality_district.as_deref(),
+            Some("Region Hannover")
+        );
+
+        let mut mismatch = usage_location(3241001, "Laatzen");
+        assert_eq!(
+            lookup.enrich(&mut mismatch),
+            Some(MunicipalityCheck::Mismatch {
+                official: "Hannover, Landeshauptstadt".to_string()
+            })
+        );
+
+        let mut unknown = usage_location(9999999, "Nirgendwo");
+        assert_eq!(
+            lookup.enrich(&mut unknown),
+            Some(MunicipalityCheck::UnknownKey)
+        );
+        assert_eq!(unknown.municipality_name, None);
+        assert_eq!(unknown.municipality_district, None);
+
+        assert_eq!(lookup.enrich(&mut UsageLocation::new()), None);
+    }
+
+    #[test]
+    fn extend_from_csv_works() {
+        let mut lookup = MunicipalityLookup::embedded();
+        let mut sassenburg = usage_location(3151025, "Sassenburg");
+        assert_eq!(
+            lookup.enrich(&mut sassenburg),
+            Some(MunicipalityCheck::UnknownMunicipality)
+        );
+        assert_eq!(sassenburg.municipality_district.as_deref(), Some("Gifhorn"));
+
+        lookup.extend_from_csv("key;name\n03151025;\"Sassenburg\"\n").unwrap();
+        assert_eq!(
+            lookup.enrich(&mut sassenburg),
+            Some(MunicipalityCheck::Matching)
+        );
+        assert_eq!(sassenburg.municipality_name.as_deref(), Some("Sassenburg"));
+        assert!(lookup.extend_from_csv("03151025;").is_err());
+    }
+}
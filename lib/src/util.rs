@@ -1,12 +1,21 @@
+use std::fmt::{Display, Formatter};
+use std::num::ParseFloatError;
+use std::ops::RangeInclusive;
+
+use lazy_static::lazy_static;
+use regex::Regex;
+use serde::Serialize;
+
 macro_rules! data_structs {
     {$(
         $(#[$struct_attr:meta])*
-        struct $struct:ident {
+        struct $struct:ident $(: fn $new:ident)? {
             $(
-                $(#[$field_attr:meta])*
+                $(#[$($field_attr:tt)*])*
                 $field:ident
                 $(? $([$($_:tt)* $optional:tt])?)?:
-                $type:ty,
+                $type:ty
+                $(= $default:expr)?,
             )*
         }
     )+} => {
@@ -16,36 +25,154 @@ macro_rules! data_structs {
             $(#[$struct_attr])*
             pub struct $struct {
                 $(
-                    $(#[$field_attr])*
+                    $(#[$($field_attr)*])*
                     pub $field:
                         $($(if $optional)? Option<)?
                             $type
                         $($(if $optional)? >)?
                 ),*
             }
+
+            impl $struct {
+                /// Documentation of every field, see [`FieldDoc`](crate::util::FieldDoc).
+                pub const FIELD_DOCS: &'static [$crate::util::FieldDoc] = &[$(
+                    $crate::util::FieldDoc {
+                        field: stringify!($field),
+                        rename: $crate::util::field_rename!($([$($field_attr)*])*),
+                        doc: $crate::util::field_doc!([] $([$($field_attr)*])*)
+                    }
+                ),*];
+
+                $crate::util::data_struct_new!(
+                    $struct ($($new)? new) [] []
+                    $([$field ($(optional $(if $optional)?)?) ($type) ($($default)?)])*
+                );
+            }
         )+
     }
 }
 
+/// Generates the `new` function of a data struct.
+///
+/// Optional fields start as `None` and fields with a `= default` get their
+/// default, all other fields become parameters in their order.
+/// A struct declared as `struct Name: fn name { .. }` gets the function under
+/// that name instead, e.g. to keep a `new` with other parameters.
+macro_rules! data_struct_new {
+    (
+        $struct:ident $name:tt [$($params:tt)*] [$($init:tt)*]
+        [$field:ident ($($_:tt)?) ($type:ty) ($default:expr)] $($rest:tt)*
+    ) => {
+        $crate::util::data_struct_new!(
+            $struct $name [$($params)*] [$($init)* $field: $default,] $($rest)*
+        );
+    };
+    (
+        $struct:ident $name:tt [$($params:tt)*] [$($init:tt)*]
+        [$field:ident (optional) ($type:ty) ()] $($rest:tt)*
+    ) => {
+        $crate::util::data_struct_new!(
+            $struct $name [$($params)*] [$($init)* $field: None,] $($rest)*
+        );
+    };
+    (
+        $struct:ident $name:tt [$($params:tt)*] [$($init:tt)*]
+        [$field:ident () ($type:ty) ()] $($rest:tt)*
+    ) => {
+        $crate::util::data_struct_new!(
+            $struct $name [$($params)* $field: $type,] [$($init)* $field,] $($rest)*
+        );
+    };
+    (
+        $struct:ident ($new:ident $($_default:ident)?)
+        [$($param:ident: $param_type:ty,)*] [$($init:tt)*]
+    ) => {
+        #[allow(clippy::new_without_default)]
+        pub fn $new($($param: $param_type),*) -> Self {
+            $struct { $($init)* }
+        }
+    };
+}
+
+/// Collects the `#[doc = "..."]` attributes of a field into a slice.
+macro_rules! field_doc {
+    ([$($acc:expr),*]) => {
+        &[$($acc),*]
+    };
+    ([$($acc:expr),*] [doc = $doc:literal] $($rest:tt)*) => {
+        $crate::util::field_doc!([$($acc,)* $doc] $($rest)*)
+    };
+    ([$($acc:expr),*] [$($_:tt)*] $($rest:tt)*) => {
+        $crate::util::field_doc!([$($acc),*] $($rest)*)
+    };
+}
+
+/// Finds an explicit `#[serde(rename = "...")]` attribute of a field.
+macro_rules! field_rename {
+    () => {
+        None
+    };
+    ([serde(rename = $rename:literal)] $($rest:tt)*) => {
+        Some($rename)
+    };
+    ([$($_:tt)*] $($rest:tt)*) => {
+        $crate::util::field_rename!($($rest)*)
+    };
+}
+
+pub(crate) use data_struct_new;
 pub(crate) use data_structs;
+pub(crate) use field_doc;
+pub(crate) use field_rename;
+
+/// Documentation of a single field of the data structs.
+///
+/// Generated by `data_structs!` from the doc comments of the fields.
+#[derive(Debug)]
+pub struct FieldDoc {
+    /// Name of the field in Rust.
+    pub field: &'static str,
+
+    /// Name of the field if explicitly renamed for serialization.
+    pub rename: Option<&'static str>,
+
+    /// Every line of the doc comment.
+    pub doc: &'static [&'static str]
+}
 
 pub trait StringOption {
+    /// Remove [stray characters](is_stray_char) and surrounding whitespace,
+    /// empty strings and `-` become `None`.
     fn sanitize(self) -> Option<String>;
 }
 
 impl StringOption for Option<String> {
     fn sanitize(self) -> Option<String> {
-        match self.as_ref() {
-            None => None,
-            Some(s) => match s.trim() {
-                "" | "-" => None,
-                s_trim if s_trim == s => self,
-                s_trim => Some(s_trim.to_owned())
-            }
+        let s = match self {
+            Some(s) if s.contains(is_stray_char) => s.replace(is_stray_char, ""),
+            Some(s) => s,
+            None => return None
+        };
+
+        match s.trim() {
+            "" | "-" => None,
+            s_trim if s_trim.len() == s.len() => Some(s),
+            s_trim => Some(s_trim.to_owned())
         }
     }
 }
 
+/// Check if a character is invisible and only ends up in strings through
+/// encoding issues, like a byte order mark or a zero-width space.
+///
+/// Control characters are included, except for line breaks and tabs.
+pub fn is_stray_char(c: char) -> bool {
+    matches!(
+        c,
+        '\u{FEFF}' | '\u{200B}' | '\u{200C}' | '\u{200D}' | '\u{2060}'
+    ) || (c.is_control() && !matches!(c, '\n' | '\r' | '\t'))
+}
+
 pub trait OptionUpdate<T: Clone> {
     fn update_if_none(&mut self, other: Option<T>);
     fn update_if_none_clone(&mut self, other: Option<&T>);
@@ -77,13 +204,42 @@ impl<T: Clone> OptionUpdate<T> for Option<T> {
     }
 }
 
-pub fn zero_is_none(value: u64) -> Option<u64> {
-    match value {
-        0 => None,
-        _ => Some(value)
+/// How `0` is treated in a numeric field.
+///
+/// The Cadenza table uses `0` for values that are not set, but other numbers,
+/// like usage location numbers, can legitimately be `0`.
+/// Therefore every field that drops a `0` has to opt in explicitly.
+///
+/// Fields treating `0` as missing:
+/// - "UTM-Rechtswert" and "UTM-Hochwert", see [`UTM_ZERO_POLICY`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ZeroPolicy {
+    /// `0` is a regular value.
+    Valid,
+
+    /// `0` is a sentinel for a missing value.
+    Missing
+}
+
+impl ZeroPolicy {
+    pub fn apply(self, value: u64) -> Option<u64> {
+        match (self, value) {
+            (ZeroPolicy::Missing, 0) => None,
+            _ => Some(value)
+        }
     }
 }
 
+/// Zero policy of the UTM coordinates, a coordinate of `0` is never a real
+/// location.
+pub const UTM_ZERO_POLICY: ZeroPolicy = ZeroPolicy::Missing;
+
+/// Shorthand for [`ZeroPolicy::Missing`], prefer a named policy like
+/// [`UTM_ZERO_POLICY`] to document why `0` is dropped.
+pub fn zero_is_none(value: u64) -> Option<u64> {
+    ZeroPolicy::Missing.apply(value)
+}
+
 pub trait Near {
     type Rhs;
     const THRESHOLD: f64;
@@ -100,3 +256,416 @@ impl Near for f64 {
         (self - other).abs() < Self::THRESHOLD
     }
 }
+
+/// Default amount of decimals reprojected coordinates are rounded to.
+///
+/// Six decimals of a degree are roughly 0.1 m, which is way below the
+/// precision of the UTM values the coordinates are derived from.
+pub const DEFAULT_COORDINATE_PRECISION: u32 = 6;
+
+/// Round a reprojected coordinate to `decimals` decimal places.
+///
+/// Floating point reprojections differ slightly between runs and platforms,
+/// rounding them keeps emitted coordinates stable and diffs meaningful.
+pub fn round_coordinate(value: f64, decimals: u32) -> f64 {
+    let factor = 10f64.powi(decimals as i32);
+    (value * factor).round() / factor
+}
+
+/// Parse a decimal number in german or english notation.
+///
+/// The reports write decimals mostly with a comma, like `"1,5"` or
+/// `"1.234,5"`.
+/// If the value contains a comma, dots are read as thousands separators and
+/// the comma as decimal separator, otherwise the value is parsed as is, so
+/// `"12.5"` stays 12.5 and `"0.125"` stays 0.125.
+/// Without a comma a dot is always a decimal point, `"1.500"` is therefore
+/// 1.5 and not 1500 as in german notation, the reports write thousands with a
+/// decimal comma like `"1.500,00"`.
+pub fn parse_decimal(value: &str) -> Result<f64, ParseFloatError> {
+    match value.contains(',') {
+        true => value.replace('.', "").replacen(',', ".", 1).parse(),
+        false => value.parse()
+    }
+}
+
+/// Whether `value` is an integer with dots between groups of three digits,
+/// like `"1.500"` or `"5.852.015"`.
+pub fn is_thousands_separated(value: &str) -> bool {
+    THOUSANDS_SEPARATED_RE.is_match(value)
+}
+
+/// Reproject ETRS89 / UTM zone 32N coordinates to WGS84, returns longitude
+/// and latitude in degrees.
+///
+/// The `32` zone prefix of the eastings in the reports is ignored.
+/// ETRS89 and WGS84 differ by less than a meter, so no datum shift is applied
+/// and the inverse transverse Mercator projection of the GRS80 ellipsoid is
+/// used as is.
+pub fn utm_to_wgs84(easting: u64, northing: u64) -> (f64, f64) {
+    const SEMI_MAJOR_AXIS: f64 = 6_378_137.0;
+    const FLATTENING: f64 = 1.0 / 298.257_222_101;
+    const SCALE: f64 = 0.9996;
+    const FALSE_EASTING: f64 = 500_000.0;
+    const CENTRAL_MERIDIAN: f64 = 9.0;
+
+    let e2 = FLATTENING * (2.0 - FLATTENING);
+    let ep2 = e2 / (1.0 - e2);
+    let x = (easting % 1_000_000) as f64 - FALSE_EASTING;
+    let y = northing as f64;
+
+    // footpoint latitude of the meridian arc
+    let mu = y /
+        SCALE /
+        (SEMI_MAJOR_AXIS * (1.0 - e2 / 4.0 - 3.0 * e2.powi(2) / 64.0 - 5.0 * e2.powi(3) / 256.0));
+    let e1 = (1.0 - (1.0 - e2).sqrt()) / (1.0 + (1.0 - e2).sqrt());
+    let phi1 = mu +
+        (3.0 * e1 / 2.0 - 27.0 * e1.powi(3) / 32.0) * (2.0 * mu).sin() +
+        (21.0 * e1.powi(2) / 16.0 - 55.0 * e1.powi(4) / 32.0) * (4.0 * mu).sin() +
+        (151.0 * e1.powi(3) / 96.0) * (6.0 * mu).sin() +
+        (1097.0 * e1.powi(4) / 512.0) * (8.0 * mu).sin();
+
+    let (sin1, cos1, tan1) = (phi1.sin(), phi1.cos(), phi1.tan());
+    let c1 = ep2 * cos1.powi(2);
+    let t1 = tan1.powi(2);
+    let n1 = SEMI_MAJOR_AXIS / (1.0 - e2 * sin1.powi(2)).sqrt();
+    let r1 = SEMI_MAJOR_AXIS * (1.0 - e2) / (1.0 - e2 * sin1.powi(2)).powf(1.5);
+    let d = x / (n1 * SCALE);
+
+    let latitude =
+        phi1 - (n1 * tan1 / r1) *
+            (d.powi(2) / 2.0 -
+                (5.0 + 3.0 * t1 + 10.0 * c1 - 4.0 * c1.powi(2) - 9.0 * ep2) * d.powi(4) / 24.0 +
+                (61.0 + 90.0 * t1 + 298.0 * c1 + 45.0 * t1.powi(2) -
+                    252.0 * ep2 -
+                    3.0 * c1.powi(2)) *
+                    d.powi(6) /
+                    720.0);
+    let longitude = (d - (1.0 + 2.0 * t1 + c1) * d.powi(3) / 6.0 +
+        (5.0 - 2.0 * c1 + 28.0 * t1 - 3.0 * c1.powi(2) + 8.0 * ep2 + 24.0 * t1.powi(2)) *
+            d.powi(5) /
+            120.0) /
+        cos1;
+
+    (
+        CENTRAL_MERIDIAN + longitude.to_degrees(),
+        latitude.to_degrees()
+    )
+}
+
+/// Plausible UTM eastings of Niedersachsen in zone 32N, including the `32`
+/// zone prefix used by the reports.
+pub const NIEDERSACHSEN_UTM_EASTING: RangeInclusive<u64> = 32_250_000..=32_800_000;
+
+/// Plausible UTM northings of Niedersachsen in zone 32N.
+pub const NIEDERSACHSEN_UTM_NORTHING: RangeInclusive<u64> = 5_650_000..=6_000_000;
+
+/// Result of [`check_utm_coordinates`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum CoordinateCheck {
+    Plausible,
+    OutOfRange,
+    Swapped
+}
+
+impl Display for CoordinateCheck {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CoordinateCheck::Plausible => write!(f, "plausible"),
+            CoordinateCheck::OutOfRange => write!(f, "out of range"),
+            CoordinateCheck::Swapped => write!(f, "swapped")
+        }
+    }
+}
+
+/// Check if UTM coordinates can lie within Niedersachsen.
+///
+/// Coordinates that would be plausible after swapping easting and northing
+/// are reported as [`CoordinateCheck::Swapped`].
+pub fn check_utm_coordinates(easting: u64, northing: u64) -> CoordinateCheck {
+    let in_range = |easting, northing| {
+        NIEDERSACHSEN_UTM_EASTING.contains(&easting) &&
+            NIEDERSACHSEN_UTM_NORTHING.contains(&northing)
+    };
+
+    match (in_range(easting, northing), in_range(northing, easting)) {
+        (true, _) => CoordinateCheck::Plausible,
+        (false, true) => CoordinateCheck::Swapped,
+        (false, false) => CoordinateCheck::OutOfRange
+    }
+}
+
+/// Normalize a file reference ("Aktenzeichen") like `6630-01-1610`.
+///
+/// File references are groups of digits separated by `-`, the PDFs sometimes
+/// contain other dashes or whitespace around them.
+/// Returns `None` if the reference does not follow that pattern, callers
+/// should then keep the raw value.
+pub fn normalize_file_reference(file_reference: &str) -> Option<String> {
+    let is_separator = |c: char| matches!(c, '-' | '–' | '—') || c.is_whitespace();
+
+    let groups: Vec<&str> =
+        file_reference.trim().split(is_separator).filter(|group| !group.is_empty()).collect();
+    let well_formed =
+        groups.len() >= 2 && groups.iter().all(|group| group.chars().all(|c| c.is_ascii_digit()));
+
+    well_formed.then(|| groups.join("-"))
+}
+
+/// Normalize an annotation ("Bemerkung"), see [`strip_annotation_prefix`].
+pub fn normalize_annotation(annotation: Option<String>) -> Option<String> {
+    strip_annotation_prefix(&annotation?)
+}
+
+/// Remove the label the reports sometimes repeat in front of an annotation.
+///
+/// For `"Bemerkung: Grundwasserentnahme"` the label and surrounding
+/// whitespace are removed, annotations without the label are only trimmed.
+/// Returns `None` if nothing remains, e.g. for a bare `"Bemerkung:"`.
+pub fn strip_annotation_prefix(annotation: &str) -> Option<String> {
+    let annotation = annotation.trim();
+    let annotation = annotation.strip_prefix("Bemerkung:").unwrap_or(annotation).trim();
+
+    match annotation.is_empty() {
+        true => None,
+        false => Some(annotation.to_string())
+    }
+}
+
+lazy_static! {
+    static ref LEGAL_PURPOSE_CODE_RE: Regex = Regex::new(r"^[A-Z]\d+$").expect("valid regex");
+    static ref THOUSANDS_SEPARATED_RE: Regex =
+        Regex::new(r"^\d{1,3}(\.\d{3})+$").expect("valid regex");
+}
+
+/// Parse the "Rechtszweck" into `(code, description)` pairs.
+///
+/// Multiple purposes are listed one after another, every purpose starts with
+/// its code, e.g. `A70 Speisung von Teichen E10 Beregnung`, or
+/// `A70 Speisung, Fischzucht, E10 Beregnung` as the adapter joins them.
+/// A `,` in front of the next code is removed, one within a description is
+/// kept.
+/// If the value does not start with a code, the first word is used as the code
+/// like for a single purpose.
+pub fn parse_legal_purposes(value: &str) -> Vec<(String, String)> {
+    let mut words = value.split_whitespace();
+    let Some(first) = words.next()
+    else {
+        return Vec::new();
+    };
+
+    let mut purposes = vec![(first.to_string(), String::new())];
+    for word in words {
+        let (_, description) = purposes.last_mut().expect("always has one purpose");
+        match LEGAL_PURPOSE_CODE_RE.is_match(word) && !description.is_empty() {
+            true => {
+                if description.ends_with(',') {
+                    description.pop();
+                }
+                purposes.push((word.to_string(), String::new()));
+            }
+            false => {
+                if !description.is_empty() {
+                    description.push(' ');
+                }
+                description.push_str(word);
+            }
+        }
+    }
+
+    purposes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    data_structs! {
+        struct DefaultsExample {
+            /// "Nummer"
+            no: u64,
+
+            name?: String,
+
+            tags: Vec<String> = vec!["new".to_string()],
+
+            limit?: u32 = Some(10),
+
+            label: String,
+        }
+
+        struct NamedExample: fn from_fields {
+            no: u64,
+        }
+    }
+
+    #[test]
+    fn data_structs_generate_new() {
+        let example = DefaultsExample::new(1101, "Teich".to_string());
+        assert_eq!(example.no, 1101);
+        assert_eq!(example.name, None);
+        assert_eq!(example.tags, ["new"]);
+        assert_eq!(example.limit, Some(10));
+        assert_eq!(example.label, "Teich");
+        let fields: Vec<_> = DefaultsExample::FIELD_DOCS.iter().map(|doc| doc.field).collect();
+        assert_eq!(fields, ["no", "name", "tags", "limit", "label"]);
+
+        assert_eq!(NamedExample::from_fields(1101).no, 1101);
+        assert_eq!(NamedExample::FIELD_DOCS[0].field, "no");
+    }
+
+    #[test]
+    fn check_utm_coordinates_works() {
+        assert_eq!(
+            check_utm_coordinates(32603873, 5852015),
+            CoordinateCheck::Plausible
+        );
+        assert_eq!(
+            check_utm_coordinates(603873, 5852015),
+            CoordinateCheck::OutOfRange
+        );
+        assert_eq!(
+            check_utm_coordinates(5852015, 32603873),
+            CoordinateCheck::Swapped
+        );
+    }
+
+    #[test]
+    fn zero_policy_works() {
+        assert_eq!(ZeroPolicy::Valid.apply(0), Some(0));
+        assert_eq!(ZeroPolicy::Valid.apply(7), Some(7));
+        assert_eq!(ZeroPolicy::Missing.apply(0), None);
+        assert_eq!(ZeroPolicy::Missing.apply(7), Some(7));
+        assert_eq!(UTM_ZERO_POLICY.apply(0), None);
+    }
+
+    #[test]
+    fn utm_to_wgs84_works() {
+        let round = |(longitude, latitude): (f64, f64)| {
+            (
+                round_coordinate(longitude, DEFAULT_COORDINATE_PRECISION),
+                round_coordinate(latitude, DEFAULT_COORDINATE_PRECISION)
+            )
+        };
+
+        assert_eq!(
+            round(utm_to_wgs84(32603873, 5852015)),
+            (10.541013, 52.807907)
+        );
+        assert_eq!(round(utm_to_wgs84(603873, 5852015)), (10.541013, 52.807907));
+        assert_eq!(round(utm_to_wgs84(32500000, 5800000)), (9.0, 52.350293));
+        assert_eq!(
+            round(utm_to_wgs84(32250000, 5650000)),
+            (5.440761, 50.947317)
+        );
+        assert_eq!(
+            round(utm_to_wgs84(32800000, 6000000)),
+            (13.584882, 54.06068)
+        );
+    }
+
+    #[test]
+    fn round_coordinate_works() {
+        let rounded = round_coordinate(52.37502845612834, DEFAULT_COORDINATE_PRECISION);
+        assert_eq!(rounded, 52.375028);
+        assert_eq!(rounded.to_string(), "52.375028");
+
+        assert_eq!(round_coordinate(9.7320104999999, 2), 9.73);
+        assert_eq!(round_coordinate(-9.7320105, 0), -10.0);
+    }
+
+    #[test]
+    fn parse_decimal_works() {
+        assert_eq!(parse_decimal("12.5"), Ok(12.5));
+        assert_eq!(parse_decimal("1500"), Ok(1500.0));
+        assert_eq!(parse_decimal("0.125"), Ok(0.125));
+        assert_eq!(parse_decimal("12.345"), Ok(12.345));
+        assert_eq!(parse_decimal("1.5000"), Ok(1.5));
+        assert_eq!(parse_decimal("0,5"), Ok(0.5));
+        assert_eq!(parse_decimal("1.234,5"), Ok(1234.5));
+        assert_eq!(parse_decimal("1.200.000,00"), Ok(1_200_000.0));
+        assert!(parse_decimal("1,2,3").is_err());
+        assert!(parse_decimal("eins").is_err());
+    }
+
+    #[test]
+    fn normalize_file_reference_works() {
+        let normalized = Some("6630-01-1610".to_string());
+        assert_eq!(normalize_file_reference("6630-01-1610"), normalized);
+        assert_eq!(normalize_file_reference(" 6630 - 01 -1610\n"), normalized);
+        assert_eq!(normalize_file_reference("6630–01–1610"), normalized);
+        assert_eq!(normalize_file_reference("6630 01 1610"), normalized);
+
+        assert_eq!(normalize_file_reference("6630/01/1610"), None);
+        assert_eq!(normalize_file_reference("66.30-01-1610 a"), None);
+        assert_eq!(normalize_file_reference("6630"), None);
+        assert_eq!(normalize_file_reference(""), None);
+    }
+
+    #[test]
+    fn normalize_annotation_works() {
+        let normalize = |annotation: &str| normalize_annotation(Some(annotation.to_string()));
+        let expected = Some("Grundwasserentnahme".to_string());
+        assert_eq!(normalize("Bemerkung: Grundwasserentnahme"), expected);
+        assert_eq!(normalize(" Bemerkung:  Grundwasserentnahme\n"), expected);
+        assert_eq!(normalize("Grundwasserentnahme "), expected);
+
+        assert_eq!(normalize("Bemerkung:"), None);
+        assert_eq!(normalize(" Bemerkung: "), None);
+        assert_eq!(normalize(""), None);
+        assert_eq!(normalize_annotation(None), None);
+
+        assert_eq!(
+            normalize("Siehe Bemerkung: Antrag"),
+            Some("Siehe Bemerkung: Antrag".to_string())
+        );
+    }
+
+    #[test]
+    fn parse_legal_purposes_works() {
+        assert_eq!(parse_legal_purposes("A70 Speisung von Teichen"), [(
+            "A70".to_string(),
+            "Speisung von Teichen".to_string()
+        )]);
+        assert_eq!(
+            parse_legal_purposes("A70 Speisung von Teichen E10 Beregnung"),
+            [
+                ("A70".to_string(), "Speisung von Teichen".to_string()),
+                ("E10".to_string(), "Beregnung".to_string())
+            ]
+        );
+        assert_eq!(
+            parse_legal_purposes("A70 Speisung, Fischzucht, E10 Beregnung"),
+            [
+                ("A70".to_string(), "Speisung, Fischzucht".to_string()),
+                ("E10".to_string(), "Beregnung".to_string())
+            ]
+        );
+        assert_eq!(parse_legal_purposes(""), []);
+    }
+
+    #[test]
+    fn strip_annotation_prefix_works() {
+        assert_eq!(strip_annotation_prefix(""), None);
+        assert_eq!(strip_annotation_prefix("Bemerkung:"), None);
+        assert_eq!(
+            strip_annotation_prefix("Bemerkung: Grundwasserentnahme"),
+            Some("Grundwasserentnahme".to_string())
+        );
+        assert_eq!(
+            strip_annotation_prefix("Grundwasserentnahme für die Beregnung"),
+            Some("Grundwasserentnahme für die Beregnung".to_string())
+        );
+    }
+
+    #[test]
+    fn sanitize_removes_stray_chars() {
+        let sanitize = |s: &str| Some(s.to_string()).sanitize();
+        assert_eq!(sanitize("\u{FEFF}Körtke"), Some("Körtke".to_string()));
+        assert_eq!(sanitize(" Kört\u{200B}ke\u{0}"), Some("Körtke".to_string()));
+        assert_eq!(sanitize("Teich\nBokel"), Some("Teich\nBokel".to_string()));
+        assert_eq!(sanitize("\u{FEFF} - "), None);
+        assert_eq!(sanitize("Körtke"), Some("Körtke".to_string()));
+    }
+}
@@ -0,0 +1,83 @@
+//! Canonical names of water bodies ("Gewässer").
+//!
+//! The reports name the same water body in different ways, e.g. `Elbe`,
+//! `Elbe/Labe` or `Elbe (Labe)`.
+//! Names are compared ignoring case and whitespace, unknown names are kept as
+//! they are.
+
+use crate::UsageLocation;
+
+/// Known variants of water body names with their canonical name.
+///
+/// The variants are lowercase and without whitespace, as compared by
+/// [`canonical_water_body`].
+pub const WATER_BODY_ALIASES: &[(&str, &str)] = &[
+    ("elbe", "Elbe"),
+    ("elbe/labe", "Elbe"),
+    ("elbe(labe)", "Elbe"),
+    ("labe", "Elbe"),
+    ("weser", "Weser"),
+    ("weser(unterweser)", "Weser"),
+    ("unterweser", "Weser"),
+    ("ems", "Ems"),
+    ("ems(unterems)", "Ems"),
+    ("unterems", "Ems"),
+    ("aller", "Aller"),
+    ("leine", "Leine"),
+    ("oker", "Oker"),
+    ("hunte", "Hunte"),
+    ("vechte", "Vechte"),
+    ("vechte/overijsselschevecht", "Vechte"),
+    ("ilmenau", "Ilmenau"),
+    ("rhein", "Rhein"),
+    ("rhein/rijn", "Rhein")
+];
+
+/// Canonical name of a water body, `None` if the name is unknown.
+pub fn canonical_water_body(name: &str) -> Option<&'static str> {
+    let variant: String =
+        name.chars().filter(|c| !c.is_whitespace()).flat_map(char::to_lowercase).collect();
+
+    WATER_BODY_ALIASES.iter().find(|(alias, _)| *alias == variant).map(|(_, canonical)| *canonical)
+}
+
+/// Canonical name of a water body, unknown names are returned unchanged.
+pub fn normalize_water_body(name: &str) -> String {
+    canonical_water_body(name).unwrap_or(name).to_string()
+}
+
+/// Fill the normalized water body of a usage location from its raw one.
+pub fn enrich(usage_location: &mut UsageLocation) {
+    usage_location.water_body_normalized =
+        usage_location.water_body.as_deref().map(normalize_water_body);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn normalize_water_body_works() {
+        assert_eq!(normalize_water_body("Elbe"), "Elbe");
+        assert_eq!(normalize_water_body("Elbe/Labe"), "Elbe");
+        assert_eq!(normalize_water_body("Elbe (Labe)"), "Elbe");
+        assert_eq!(normalize_water_body(" elbe / labe "), "Elbe");
+        assert_eq!(canonical_water_body("Mühlenbach"), None);
+        assert_eq!(normalize_water_body("Mühlenbach"), "Mühlenbach");
+    }
+
+    #[test]
+    fn enrich_keeps_raw_water_body() {
+        let mut usage_location = UsageLocation::new();
+        enrich(&mut usage_location);
+        assert_eq!(usage_location.water_body_normalized, None);
+
+        usage_location.water_body = Some("Elbe (Labe)".to_string());
+        enrich(&mut usage_location);
+        assert_eq!(usage_location.water_body.as_deref(), Some("Elbe (Labe)"));
+        assert_eq!(
+            usage_location.water_body_normalized.as_deref(),
+            Some("Elbe")
+        );
+    }
+}
@@ -0,0 +1,118 @@
+use std::collections::HashSet;
+use std::time::SystemTime;
+
+use chrono::{DateTime, SecondsFormat, Utc};
+use nlwkn::{WaterRight, WaterRightNo};
+use serde::Serialize;
+
+/// Where a water right of the combined output comes from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum Source {
+    /// Parsed from the report and enriched by the cadenza table.
+    Enriched,
+
+    /// Parsed from the report only, the cadenza table has no row for it.
+    PdfOnly,
+
+    /// Kept from the reports of `--merge-into` without parsing it again.
+    Merged
+}
+
+/// Water right of the combined output, annotated with its source.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AnnotatedWaterRight<'wr> {
+    pub source: Source,
+
+    /// When the report was fetched, the modification time of its PDF.
+    pub crawled_at: Option<String>,
+
+    /// When the water right was parsed, for merged ones when the reports
+    /// merged into were written.
+    pub parsed_at: Option<String>,
+
+    pub data: &'wr WaterRight
+}
+
+/// Annotate all water rights of a parser run, sorted by their number.
+///
+/// `parsed` are the numbers of the water rights parsed in this run at
+/// `parsed_at`, the other `water_rights` were merged from the reports written
+/// at `merged_at`.
+/// `crawled_at` looks up when the report of a water right was fetched.
+pub fn annotate<'wr>(
+    water_rights: &'wr [WaterRight],
+    pdf_only_water_rights: &'wr [WaterRight],
+    parsed: &HashSet<WaterRightNo>,
+    parsed_at: SystemTime,
+    merged_at: Option<SystemTime>,
+    crawled_at: impl Fn(WaterRightNo) -> Option<SystemTime>
+) -> Vec<AnnotatedWaterRight<'wr>> {
+    let water_rights =
+        water_rights.iter().map(|water_right| match parsed.contains(&water_right.no) {
+            true => (Source::Enriched, Some(parsed_at), water_right),
+            false => (Source::Merged, merged_at, water_right)
+        });
+    let pdf_only_water_rights = pdf_only_water_rights
+        .iter()
+        .map(|water_right| (Source::PdfOnly, Some(parsed_at), water_right));
+
+    let mut annotated: Vec<_> = water_rights
+        .chain(pdf_only_water_rights)
+        .map(|(source, parsed_at, water_right)| AnnotatedWaterRight {
+            source,
+            crawled_at: crawled_at(water_right.no).map(timestamp),
+            parsed_at: parsed_at.map(timestamp),
+            data: water_right
+        })
+        .collect();
+    annotated.sort_by_key(|annotated| annotated.data.no);
+    annotated
+}
+
+/// Format a time as RFC 3339 timestamp in UTC, e.g. `2024-01-01T12:00:00Z`.
+pub fn timestamp(time: SystemTime) -> String {
+    DateTime::<Utc>::from(time).to_rfc3339_opts(SecondsFormat::Secs, true)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    #[test]
+    fn annotate_works() {
+        let parsed_at = SystemTime::UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+        let merged_at = parsed_at - Duration::from_secs(86_400);
+        let crawled_at = |no| (no != 1103).then_some(merged_at - Duration::from_secs(60));
+
+        let water_rights = [WaterRight::new(1101), WaterRight::new(1103)];
+        let pdf_only_water_rights = [WaterRight::new(1102)];
+        let parsed = HashSet::from([1101, 1102]);
+        let annotated = annotate(
+            &water_rights,
+            &pdf_only_water_rights,
+            &parsed,
+            parsed_at,
+            Some(merged_at),
+            crawled_at
+        );
+
+        let json = serde_json::to_value(&annotated).unwrap();
+        assert_eq!(json[0]["source"], "enriched");
+        assert_eq!(json[0]["crawledAt"], "2023-11-13T22:12:20Z");
+        assert_eq!(json[0]["parsedAt"], "2023-11-14T22:13:20Z");
+        assert_eq!(json[0]["data"]["no"], 1101);
+
+        assert_eq!(json[1]["source"], "pdfOnly");
+        assert_eq!(json[1]["parsedAt"], "2023-11-14T22:13:20Z");
+        assert_eq!(json[1]["data"]["no"], 1102);
+
+        assert_eq!(json[2]["source"], "merged");
+        assert_eq!(json[2]["crawledAt"], serde_json::Value::Null);
+        assert_eq!(json[2]["parsedAt"], "2023-11-13T22:13:20Z");
+        assert_eq!(json[2]["data"]["no"], 1103);
+    }
+}
@@ -1,9 +1,11 @@
 use std::iter::Peekable;
 
-use itertools::Itertools;
-
 use crate::intermediate::key_value::{KeyValuePair, KeyValueRepr};
 
+const USAGE_LOCATION_SERIAL_KEY: &str = "Nutzungsort Lfd. Nr.:";
+const USAGE_LOCATION_NAME_KEY: &str = "Bezeichnung:";
+const ANNOTATION_KEY: &str = "Bemerkung:";
+
 #[derive(Debug)]
 pub struct GroupedKeyValueRepr {
     pub root: Vec<KeyValuePair>,
@@ -13,24 +15,7 @@ pub struct GroupedKeyValueRepr {
 
 impl From<KeyValueRepr> for GroupedKeyValueRepr {
     fn from(mut key_value_repr: KeyValueRepr) -> Self {
-        // take the last keys as annotation of the values of them are empty
-        let mut annotation: Vec<String> = Vec::new();
-        for (key, values) in key_value_repr.0.iter().rev() {
-            match values.is_empty() {
-                true => annotation.push(key.clone()),
-                false => break
-            }
-        }
-
-        // remove these keys
-        for _ in annotation.iter() {
-            key_value_repr.0.pop();
-        }
-
-        let annotation = match annotation.is_empty() {
-            true => None,
-            false => Some(annotation.into_iter().rev().join(" "))
-        };
+        let annotation = split_annotation(&mut key_value_repr);
 
         let mut root = Vec::new();
         let mut key_value_repr_iter = key_value_repr.0.into_iter().peekable();
@@ -40,7 +25,21 @@ impl From<KeyValueRepr> for GroupedKeyValueRepr {
             }
         }
 
-        let departments = group_departments(&mut key_value_repr_iter);
+        let mut departments = group_departments(&mut key_value_repr_iter);
+
+        // without any "Abteilung" the usage locations end up in the root, keep them
+        // under a department without name instead of dropping them, the root itself
+        // has no "Bezeichnung" so it also marks a usage location without serial
+        let first_usage_location = root.iter().position(|(key, _)| {
+            key == USAGE_LOCATION_SERIAL_KEY || key == USAGE_LOCATION_NAME_KEY
+        });
+        if let (true, Some(position)) = (departments.is_empty(), first_usage_location) {
+            let mut usage_location_iter = root.split_off(position).into_iter().peekable();
+            departments.push((
+                String::new(),
+                group_usage_locations(&mut usage_location_iter)
+            ));
+        }
 
         Self {
             root,
@@ -50,6 +49,48 @@ impl From<KeyValueRepr> for GroupedKeyValueRepr {
     }
 }
 
+/// Split the annotation off the end of the key value pairs.
+///
+/// The annotation is printed at the end of the report in the font of the
+/// keys, so every line of it ends up as a key without values.
+/// These trailing keys are taken as the lines of the annotation, except a key
+/// ending with a colon that the report already has before, which is a field
+/// left empty, like `"Gewässer:"`.
+/// If the annotation is labeled with `"Bemerkung:"`, the label ends the
+/// annotation and the values read for it are its first lines.
+///
+/// The lines are joined by line breaks, `None` if there are none.
+fn split_annotation(key_value_repr: &mut KeyValueRepr) -> Option<String> {
+    let mut lines = Vec::new();
+    while let Some((key, values)) = key_value_repr.0.last() {
+        let is_field = |key: &str| {
+            let earlier = &key_value_repr.0[..key_value_repr.0.len() - 1];
+            key.ends_with(':') && earlier.iter().any(|(earlier_key, _)| earlier_key == key)
+        };
+        let is_label = match (key.as_str(), values.is_empty()) {
+            (ANNOTATION_KEY, _) => true,
+            (key, true) if !is_field(key) => false,
+            _ => break
+        };
+
+        let (key, values) = key_value_repr.0.pop().expect("checked by last");
+        key_value_repr.1.pop();
+        match is_label {
+            true => {
+                lines.extend(values.into_iter().rev());
+                break;
+            }
+            false => lines.push(key)
+        }
+    }
+
+    let lines: Vec<_> = lines.into_iter().rev().filter(|line| !line.trim().is_empty()).collect();
+    match lines.is_empty() {
+        true => None,
+        false => Some(lines.join("\n"))
+    }
+}
+
 fn group_departments(
     iter: &mut Peekable<impl Iterator<Item = KeyValuePair>>
 ) -> Vec<(String, Vec<Vec<KeyValuePair>>)> {
@@ -75,15 +116,13 @@ fn group_usage_locations(
     let mut usage_location = Vec::new();
 
     while let Some(peek) = iter.peek() {
-        match peek.0.as_str() {
-            "Abteilung:" => break,
-            "Nutzungsort Lfd. Nr.:" => {
-                if !usage_location.is_empty() {
-                    usage_locations.push(usage_location);
-                    usage_location = Vec::new();
-                }
-            }
-            _ => ()
+        if peek.0 == "Abteilung:" {
+            break;
+        }
+
+        if starts_usage_location(&peek.0, &usage_location) {
+            usage_locations.push(usage_location);
+            usage_location = Vec::new();
         }
 
         let next = iter.next().expect("cannot peek if next is none");
@@ -93,3 +132,190 @@ fn group_usage_locations(
     usage_locations.push(usage_location);
     usage_locations
 }
+
+/// Check if `key` begins a new usage location after the open `usage_location`.
+///
+/// Usually every usage location begins with its serial.
+/// If that header is missing or misread, a second "Bezeichnung" in the open
+/// usage location marks the begin of the next one instead of merging both.
+fn starts_usage_location(key: &str, usage_location: &[KeyValuePair]) -> bool {
+    match (key, usage_location.is_empty()) {
+        (_, true) => false,
+        (USAGE_LOCATION_SERIAL_KEY, false) => true,
+        (USAGE_LOCATION_NAME_KEY, false) => {
+            usage_location.iter().any(|(key, _)| key == USAGE_LOCATION_NAME_KEY)
+        }
+        (_, false) => false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pair(key: &str, value: &str) -> KeyValuePair {
+        (key.to_string(), vec![value.to_string()])
+    }
+
+    #[test]
+    fn usage_locations_without_department_are_kept() {
+        let pairs = vec![
+            pair("Wasserrecht Nr.:", "1101"),
+            pair("Rechtsinhaber:", "Körtke"),
+            pair("Nutzungsort Lfd. Nr.:", "1"),
+            pair("Gewässer:", "Aller"),
+            pair("Nutzungsort Lfd. Nr.:", "2"),
+            pair("Gewässer:", "Ise"),
+        ];
+        let pages = vec![0; pairs.len()];
+        let grouped = GroupedKeyValueRepr::from(KeyValueRepr(pairs, pages));
+
+        assert_eq!(grouped.root, [
+            pair("Wasserrecht Nr.:", "1101"),
+            pair("Rechtsinhaber:", "Körtke")
+        ]);
+        assert_eq!(grouped.departments.len(), 1);
+        let (department, usage_locations) = &grouped.departments[0];
+        assert_eq!(department, "");
+        assert_eq!(usage_locations, &[
+            vec![
+                pair("Nutzungsort Lfd. Nr.:", "1"),
+                pair("Gewässer:", "Aller")
+            ],
+            vec![pair("Nutzungsort Lfd. Nr.:", "2"), pair("Gewässer:", "Ise")]
+        ]);
+    }
+
+    #[test]
+    fn usage_locations_without_serial_are_not_merged() {
+        let pairs = vec![
+            pair("Wasserrecht Nr.:", "1101"),
+            pair("Abteilung:", "A"),
+            pair("Nutzungsort Lfd. Nr.:", "1"),
+            pair("Bezeichnung:", "Brunnen 1"),
+            pair("Gewässer:", "Aller"),
+            pair("Bezeichnung:", "Brunnen 2"),
+            pair("Gewässer:", "Ise"),
+            pair("Nutzungsort Lfd. Nr.:", "3"),
+            pair("Bezeichnung:", "Brunnen 3"),
+        ];
+        let pages = vec![0; pairs.len()];
+        let grouped = GroupedKeyValueRepr::from(KeyValueRepr(pairs, pages));
+
+        assert_eq!(grouped.departments.len(), 1);
+        assert_eq!(grouped.departments[0].1, [
+            vec![
+                pair("Nutzungsort Lfd. Nr.:", "1"),
+                pair("Bezeichnung:", "Brunnen 1"),
+                pair("Gewässer:", "Aller")
+            ],
+            vec![pair("Bezeichnung:", "Brunnen 2"), pair("Gewässer:", "Ise")],
+            vec![
+                pair("Nutzungsort Lfd. Nr.:", "3"),
+                pair("Bezeichnung:", "Brunnen 3")
+            ]
+        ]);
+
+        // the first usage location without serial is not kept in the root
+        let pairs = vec![
+            pair("Wasserrecht Nr.:", "1101"),
+            pair("Bezeichnung:", "Brunnen 1"),
+            pair("Gewässer:", "Aller"),
+            pair("Nutzungsort Lfd. Nr.:", "2"),
+            pair("Gewässer:", "Ise"),
+        ];
+        let pages = vec![0; pairs.len()];
+        let grouped = GroupedKeyValueRepr::from(KeyValueRepr(pairs, pages));
+
+        assert_eq!(grouped.root, [pair("Wasserrecht Nr.:", "1101")]);
+        assert_eq!(grouped.departments[0].1, [
+            vec![
+                pair("Bezeichnung:", "Brunnen 1"),
+                pair("Gewässer:", "Aller")
+            ],
+            vec![pair("Nutzungsort Lfd. Nr.:", "2"), pair("Gewässer:", "Ise")]
+        ]);
+    }
+
+    fn annotation_of(pairs: Vec<KeyValuePair>) -> (Vec<KeyValuePair>, Option<String>) {
+        let pages = vec![0; pairs.len()];
+        let mut key_value_repr = KeyValueRepr(pairs, pages);
+        let annotation = split_annotation(&mut key_value_repr);
+        assert_eq!(key_value_repr.0.len(), key_value_repr.1.len());
+        (key_value_repr.0, annotation)
+    }
+
+    fn line(line: &str) -> KeyValuePair {
+        (line.to_string(), Vec::new())
+    }
+
+    #[test]
+    fn split_annotation_works() {
+        let fields = || {
+            vec![
+                pair("Nutzungsort Lfd. Nr.:", "1"),
+                pair("Gewässer:", "Aller"),
+                pair("Nutzungsort Lfd. Nr.:", "2"),
+            ]
+        };
+
+        let (rest, annotation) = annotation_of(fields());
+        assert_eq!(rest, fields(), "no annotation");
+        assert_eq!(annotation, None, "no annotation");
+
+        let mut pairs = fields();
+        pairs.push(line("Grundwasserentnahme für die Beregnung"));
+        let (rest, annotation) = annotation_of(pairs);
+        assert_eq!(rest, fields(), "single line");
+        assert_eq!(
+            annotation.as_deref(),
+            Some("Grundwasserentnahme für die Beregnung"),
+            "single line"
+        );
+
+        let mut pairs = fields();
+        pairs.push(line("Grundwasserentnahme für die Beregnung"));
+        pairs.push(line("landwirtschaftlicher Flächen:"));
+        pairs.push(line("Kartoffeln und Mais"));
+        let (rest, annotation) = annotation_of(pairs);
+        assert_eq!(rest, fields(), "multiple lines");
+        assert_eq!(
+            annotation.as_deref(),
+            Some(
+                "Grundwasserentnahme für die Beregnung\nlandwirtschaftlicher Flächen:\nKartoffeln \
+                 und Mais"
+            ),
+            "multiple lines"
+        );
+
+        let mut pairs = fields();
+        pairs.push(line("Gewässer:"));
+        pairs.push(line("Grundwasserentnahme"));
+        let (rest, annotation) = annotation_of(pairs);
+        let mut expected = fields();
+        expected.push(line("Gewässer:"));
+        assert_eq!(rest, expected, "empty field");
+        assert_eq!(
+            annotation.as_deref(),
+            Some("Grundwasserentnahme"),
+            "empty field"
+        );
+
+        let mut pairs = fields();
+        pairs.push(pair("Bemerkung:", "Grundwasserentnahme für die Beregnung"));
+        pairs.push(line("Kartoffeln und Mais"));
+        let (rest, annotation) = annotation_of(pairs);
+        assert_eq!(rest, fields(), "labeled");
+        assert_eq!(
+            annotation.as_deref(),
+            Some("Grundwasserentnahme für die Beregnung\nKartoffeln und Mais"),
+            "labeled"
+        );
+
+        let mut pairs = fields();
+        pairs.push(line("Bemerkung:"));
+        let (rest, annotation) = annotation_of(pairs);
+        assert_eq!(rest, fields(), "empty label");
+        assert_eq!(annotation, None, "empty label");
+    }
+}
@@ -1,14 +1,17 @@
 use crate::intermediate::text_block::{TextBlock, TextBlockRepr};
 
-pub struct KeyValueRepr(pub Vec<(String, Vec<String>)>);
+/// Key value pairs of a report, the second field holds the zero-based page
+/// index every pair was found on.
+pub struct KeyValueRepr(pub Vec<(String, Vec<String>)>, pub Vec<usize>);
 pub type KeyValuePair = (String, Vec<String>);
 
 impl From<TextBlockRepr> for KeyValueRepr {
     fn from(text_block_repr: TextBlockRepr) -> Self {
         type Pair = (String, Vec<(u32, String)>);
         let mut pairs: Vec<Pair> = Vec::new();
+        let mut pages: Vec<usize> = Vec::new();
 
-        for page in text_block_repr.0.into_iter() {
+        for (page_index, page) in text_block_repr.0.into_iter().enumerate() {
             let mut entry: Option<Pair> = None;
             for text_block in page.into_iter() {
                 let TextBlock {
@@ -45,6 +48,7 @@ impl From<TextBlockRepr> for KeyValueRepr {
                     ("F3" | "F2", Some(entry)) => entry.1.push((x, content)),
                     ("F1", Some(_)) => {
                         pairs.push(entry.take().expect("is some"));
+                        pages.push(page_index);
                         entry = Some((content, Vec::new()))
                     }
                     _ => ()
@@ -53,6 +57,7 @@ impl From<TextBlockRepr> for KeyValueRepr {
 
             if let Some(entry) = entry {
                 pairs.push(entry);
+                pages.push(page_index);
             }
         }
 
@@ -60,7 +65,8 @@ impl From<TextBlockRepr> for KeyValueRepr {
             pairs
                 .into_iter()
                 .map(|(key, values)| (key, values.into_iter().map(|(_, v)| v).collect()))
-                .collect()
+                .collect(),
+            pages
         )
     }
 }
@@ -1,5 +1,7 @@
 use lopdf::content::Operation;
 use lopdf::{Object, StringFormat};
+use nlwkn::util::is_stray_char;
+use unicode_normalization::UnicodeNormalization;
 
 const ENCODING: &str = "WinAnsiEncoding";
 
@@ -95,14 +97,12 @@ fn handle_tf(text_block: &mut TextBlock, operands: &[Object]) {
     }
 
     text_block.font_family = match operands.first() {
-        Some(Object::String(s, StringFormat::Literal)) => {
-            Some(lopdf::Document::decode_text(Some(ENCODING), s))
-        }
+        Some(Object::String(s, StringFormat::Literal)) => Some(decode_text(s)),
         Some(Object::String(_, _)) => {
             eprintln!("warning: cannot handle non-string-literal for 'Tf' operand[0]");
             None
         }
-        Some(Object::Name(n)) => Some(lopdf::Document::decode_text(Some(ENCODING), n)),
+        Some(Object::Name(n)) => Some(decode_text(n)),
         Some(_) => {
             eprintln!("warning: expected string for 'Tf' operand[0]");
             None
@@ -170,7 +170,7 @@ fn handle_tj(text_block: &mut TextBlock, operands: &[Object]) {
     for operand in operands {
         match operand {
             Object::String(s, StringFormat::Literal) => {
-                content.push_str(lopdf::Document::decode_text(Some(ENCODING), s).as_str());
+                content.push_str(decode_text(s).as_str());
             }
             Object::String(_, _) => {
                 eprintln!("warning: expected string literal for 'Tj'");
@@ -191,3 +191,67 @@ fn handle_tj(text_block: &mut TextBlock, operands: &[Object]) {
         (None, false) => None
     };
 }
+
+/// Decode a string of the content stream and [clean](clean_text) it.
+fn decode_text(bytes: &[u8]) -> String {
+    clean_text(&lopdf::Document::decode_text(Some(ENCODING), bytes))
+}
+
+/// Clean up decoded text.
+///
+/// Drops [stray characters](is_stray_char), soft hyphens and replacement
+/// characters of undecodable code units and replaces non-breaking spaces with
+/// spaces.
+/// Umlauts drawn as a letter with a separate diaeresis, which WinAnsi decodes
+/// into a spacing "¨" next to the letter, are joined and everything is
+/// normalized to NFC, so umlauts are always precomposed.
+fn clean_text(text: &str) -> String {
+    let is_umlaut_base = |c: char| matches!(c, 'a' | 'o' | 'u' | 'A' | 'O' | 'U');
+
+    let mut chars = text
+        .chars()
+        .filter(|c| !is_stray_char(*c) && !matches!(c, '\u{AD}' | '\u{FFFD}'))
+        .map(|c| match c {
+            '\u{A0}' => ' ',
+            c => c
+        })
+        .peekable();
+    let mut cleaned = String::with_capacity(text.len());
+    while let Some(c) = chars.next() {
+        match (c, chars.peek()) {
+            ('\u{A8}', Some(&next)) if is_umlaut_base(next) => {
+                cleaned.push(next);
+                cleaned.push('\u{308}');
+                chars.next();
+            }
+            ('\u{A8}', _) if cleaned.ends_with(is_umlaut_base) => cleaned.push('\u{308}'),
+            (c, _) => cleaned.push(c)
+        }
+    }
+
+    cleaned.nfc().collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decode_text_is_clean() {
+        // "Mühle" with the diaeresis drawn separately
+        let bytes = b"M\xA8uhle";
+        assert_eq!(
+            lopdf::Document::decode_text(Some(ENCODING), bytes),
+            "M¨uhle"
+        );
+        assert_eq!(decode_text(bytes), "Mühle");
+        assert_eq!(decode_text(b"Mu\xA8hle"), "Mühle");
+        assert_eq!(decode_text(b"M\xFChle"), "Mühle");
+        assert_eq!(decode_text(b"1\xA8"), "1¨");
+
+        assert_eq!(
+            clean_text("Mu\u{308}h\u{AD}le\u{A0}Nr.\u{FFFD}\u{7}"),
+            "Mühle Nr."
+        );
+    }
+}
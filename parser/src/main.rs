@@ -1,33 +1,51 @@
-use std::collections::{BTreeMap, HashMap};
-use std::fmt::{Display, Formatter};
-use std::fs;
+use std::collections::hash_map::Entry;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::ffi::OsStr;
+use std::fmt::Display;
+use std::num::NonZeroUsize;
 use std::ops::Deref;
 use std::path::{Path, PathBuf};
 use std::process::ExitCode;
-use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::SystemTime;
+use std::{env, fs, io};
 
 use clap::Parser;
-use console::{Color, Style};
-use futures::stream::FuturesUnordered;
-use futures::StreamExt;
+use console::{Color, Term};
 use indicatif::ProgressBar;
-use itertools::Itertools;
 use lazy_static::lazy_static;
 use lopdf::Document;
-use nlwkn::cadenza::CadenzaTable;
-use nlwkn::cli::{progress_message, PROGRESS_STYLE, PROGRESS_UPDATE_INTERVAL, SPINNER_STYLE};
-use nlwkn::util::{zero_is_none, OptionUpdate};
-use nlwkn::{WaterRight, WaterRightNo};
+use nlwkn::cadenza::{BadRow, CadenzaTable, CadenzaTableRow};
+use nlwkn::cli::{self, progress_message, PROGRESS_STYLE, PROGRESS_UPDATE_INTERVAL, SPINNER_STYLE};
+use nlwkn::county::{check_county_coordinates, CountyCheck};
+use nlwkn::disk_space::{check_free_space, SpaceCheck};
+use nlwkn::municipality::{MunicipalityCheck, MunicipalityLookup};
+use nlwkn::util::{
+    check_utm_coordinates, normalize_annotation, normalize_file_reference, parse_legal_purposes,
+    CoordinateCheck, OptionUpdate, UTM_ZERO_POLICY
+};
+use nlwkn::{
+    water_body, LegalDepartment, LegalDepartmentAbbreviation, UsageLocation, WaterRight,
+    WaterRightNo
+};
 use parking_lot::Mutex;
+use rayon::prelude::*;
 use regex::Regex;
 use serde::{Serialize, Serializer};
 use thiserror::Error;
-use tokio::task::JoinHandle;
 
-use crate::parse::parse_document;
+use crate::combined::AnnotatedWaterRight;
+use crate::merge::PriorReports;
+use crate::parse::{parse_document, ParsedDocument, SkippedEntry};
+use crate::provenance::FieldProvenance;
+use crate::report::{Report, ReportEntry, ReportRenderer, Summary, SummaryInputs};
 
+mod combined;
 mod intermediate;
+mod merge;
 mod parse;
+mod provenance;
+mod report;
 
 lazy_static! {
     static ref REPORT_FILE_RE: Regex = Regex::new(r"^rep(?<no>\d+).pdf$").expect("valid regex");
@@ -48,7 +66,128 @@ struct Args {
 
     /// Parse specific water right number report
     #[arg(long = "no")]
-    water_right_no: Option<WaterRightNo>
+    water_right_no: Option<WaterRightNo>,
+
+    /// Print the summary without colors, used automatically if stdout is no
+    /// terminal
+    #[arg(long)]
+    plain_report: bool,
+
+    /// Template for the summary instead of the default format
+    ///
+    /// `{broken}`, `{parsing_issues}`, `{pdf_only}` and `{successful}` are
+    /// replaced by their counts, appending `_file` to them, e.g.
+    /// `{broken_file}`, is replaced by their output file.
+    #[arg(long)]
+    report_template: Option<String>,
+
+    /// Also write the report page every value was extracted from into
+    /// `provenance.json`
+    #[arg(long)]
+    provenance: bool,
+
+    /// Remove coordinates outside of Niedersachsen instead of only warning
+    /// about them
+    #[arg(long)]
+    drop_invalid_coords: bool,
+
+    /// Only parse the fields of the water rights themselves, without legal
+    /// departments and usage locations
+    ///
+    /// Much faster if only a catalog of the water rights is needed.
+    #[arg(long)]
+    root_only: bool,
+
+    /// Previous `reports.json` to merge the results into
+    ///
+    /// Only reports missing in it or modified after it was written are parsed.
+    #[arg(long, value_name = "REPORTS_JSON")]
+    merge_into: Option<PathBuf>,
+
+    /// Fill the official municipality name and district of every usage
+    /// location from the key of its "Gemeindegebiet"
+    ///
+    /// Only district-free cities and Hannover are embedded, other
+    /// municipalities only get their district.
+    #[arg(long)]
+    normalize_municipalities: bool,
+
+    /// CSV with the official municipality keys and names separated by `;`,
+    /// implies `--normalize-municipalities`
+    #[arg(long, value_name = "CSV")]
+    municipality_table: Option<PathBuf>,
+
+    /// Add the canonical name of the "Gewässer" of every usage location,
+    /// keeping the raw name
+    ///
+    /// Common variants like "Elbe/Labe" are mapped by an embedded alias
+    /// table, unknown names are kept as they are.
+    #[arg(long)]
+    normalize_water_bodies: bool,
+
+    /// Warn if the report and the cadenza table have different values for the
+    /// same field of a usage location
+    ///
+    /// Compares the county, river basin, groundwater body and the
+    /// coordinates, the coordinates may differ by up to 10 m.
+    /// The values of the report are kept.
+    #[arg(long)]
+    check_sources: bool,
+
+    /// Warn about usage locations whose coordinates lie outside of their
+    /// county
+    ///
+    /// Coarse check against embedded bounding boxes of the districts, meant
+    /// to find parse errors, not points slightly across a border.
+    #[arg(long)]
+    validate_coordinates_against_county: bool,
+
+    /// Don't fill a missing granting authority with the registering authority
+    ///
+    /// By default a water right registered by an authority is assumed to be
+    /// granted by it too.
+    #[arg(long)]
+    no_infer_granting: bool,
+
+    /// Sort the usage locations of every legal department by their serial
+    ///
+    /// By default they are kept in the order of the report.
+    #[arg(long)]
+    sort_locations: bool,
+
+    /// Abort before saving if less than this many bytes would remain free
+    /// after writing the results
+    #[arg(long, value_name = "BYTES", default_value = "0")]
+    min_free_bytes: u64,
+
+    /// Skip reports larger than this many bytes instead of loading them
+    ///
+    /// Protects against huge or malicious PDFs exhausting the memory, the
+    /// skipped reports are listed in the warnings.
+    #[arg(long, value_name = "BYTES")]
+    max_pdf_bytes: Option<u64>,
+
+    /// Parse the reports on at most N threads
+    ///
+    /// By default one thread per CPU core is used.
+    #[arg(long, value_name = "N")]
+    concurrency: Option<NonZeroUsize>,
+
+    /// Fail instead of warning if a water right ends up in both the reports
+    /// and the pdf-only reports
+    #[arg(long)]
+    strict: bool,
+
+    /// Skip rows of the cadenza table that cannot be read instead of failing
+    ///
+    /// Every skipped row is listed in the warnings with the reason.
+    #[arg(long)]
+    skip_bad_rows: bool,
+
+    /// Also write all water rights into `combined.json`, annotated with their
+    /// source and when their report was fetched and parsed
+    #[arg(long)]
+    combined: bool
 }
 
 #[derive(Debug, Error, Serialize)]
@@ -65,9 +204,21 @@ enum Warning {
     #[error("could not extract water right number from {file_name:?}, will be ignored")]
     CouldNotExtractWaterRightNo { file_name: String },
 
+    #[error("file name {file_name:?} is not valid UTF-8, will be ignored")]
+    NonUtf8FileName { file_name: String },
+
     #[error("could not load {count} reports")]
     CouldNotLoadReports { count: usize },
 
+    #[error(
+        "report {water_right_no} has {size} bytes, more than {max_pdf_bytes}, will be skipped"
+    )]
+    OversizedReport {
+        water_right_no: WaterRightNo,
+        size: u64,
+        max_pdf_bytes: u64
+    },
+
     #[error(
         "could not find usage location no for report {water_right_no}, enrichment may be missing \
          values"
@@ -82,8 +233,105 @@ enum Warning {
         missing_locations: Vec<u64>
     },
 
+    #[error(
+        "legal title of {water_right_no} is {report:?} in the report but {cadenza:?} in the \
+         table, kept both"
+    )]
+    LegalTitleMismatch {
+        water_right_no: WaterRightNo,
+        report: String,
+        cadenza: String
+    },
+
+    #[error("water rights {water_right_nos:?} are in both the reports and the pdf-only reports")]
+    OverlappingReports { water_right_nos: Vec<WaterRightNo> },
+
+    #[error("row {row} of the table could not be read, {error}, will be skipped")]
+    BadTableRow { row: u32, error: String },
+
     #[error("a date in {water_right_no} has an invalid format")]
-    InvalidDateFormat { water_right_no: WaterRightNo }
+    InvalidDateFormat { water_right_no: WaterRightNo },
+
+    #[error(
+        "coordinates ({easting}, {northing}) of usage location {serial:?} in {water_right_no} are \
+         {check} (dropped: {dropped})"
+    )]
+    ImplausibleCoordinates {
+        water_right_no: WaterRightNo,
+        serial: Option<String>,
+        easting: u64,
+        northing: u64,
+        check: CoordinateCheck,
+        dropped: bool
+    },
+
+    #[error(
+        "report {water_right_no} has usage locations without a legal department, stored under an \
+         unknown department"
+    )]
+    MissingDepartment { water_right_no: WaterRightNo },
+
+    #[error("file reference {file_reference:?} of {water_right_no} is malformed, kept as is")]
+    MalformedFileReference {
+        water_right_no: WaterRightNo,
+        file_reference: String
+    },
+
+    #[error("unknown usage location key {key:?} in {water_right_no} with {values:?}, skipped")]
+    UnknownUsageLocationKey {
+        water_right_no: WaterRightNo,
+        key: String,
+        values: Vec<String>
+    },
+
+    #[error("allowance range {range:?} in {water_right_no} cannot be stored, skipped")]
+    UnstorableAllowanceRange {
+        water_right_no: WaterRightNo,
+        range: String
+    },
+
+    #[error(
+        "{field} of usage location {serial:?} in {water_right_no} is {pdf:?} in the report but \
+         {xlsx:?} in the table, kept the report"
+    )]
+    SourceConflict {
+        water_right_no: WaterRightNo,
+        serial: Option<String>,
+        field: &'static str,
+        pdf: String,
+        xlsx: String
+    },
+
+    #[error(
+        "municipal area {key} {name:?} of usage location {serial:?} in {water_right_no} is {check}"
+    )]
+    MunicipalityMismatch {
+        water_right_no: WaterRightNo,
+        serial: Option<String>,
+        key: u64,
+        name: String,
+        #[serde(serialize_with = "serialize_display")]
+        check: MunicipalityCheck
+    },
+
+    #[error(
+        "coordinates ({easting}, {northing}) of usage location {serial:?} in {water_right_no} lie \
+         outside of its county {county:?}"
+    )]
+    CoordinatesOutsideCounty {
+        water_right_no: WaterRightNo,
+        serial: Option<String>,
+        county: String,
+        easting: u64,
+        northing: u64
+    }
+}
+
+fn serialize_display<S>(value: &impl Display, serializer: S) -> Result<S::Ok, S::Error>
+where
+    S: Serializer
+{
+    value.to_string().serialize(serializer)
 }
 
 fn serialize_anyhow_error<S>(error: &anyhow::Error, serializer: S) -> Result<S::Ok, S::Error>
@@ -95,13 +343,40 @@ where
 
 // TODO: add edge case handling input
 
-#[tokio::main]
-async fn main() -> ExitCode {
+fn main() -> ExitCode {
+    if cli::print_completions::<Args, _, _>(
+        env!("CARGO_BIN_NAME"),
+        env::args_os(),
+        &mut io::stdout()
+    ) {
+        return ExitCode::SUCCESS;
+    }
+
     let Args {
         xlsx_path,
         data_path,
-        water_right_no: arg_no
+        water_right_no: arg_no,
+        plain_report,
+        report_template,
+        provenance: with_provenance,
+        drop_invalid_coords,
+        root_only,
+        merge_into,
+        normalize_municipalities,
+        municipality_table,
+        normalize_water_bodies,
+        check_sources,
+        validate_coordinates_against_county,
+        no_infer_granting,
+        sort_locations,
+        min_free_bytes,
+        max_pdf_bytes,
+        concurrency,
+        strict,
+        skip_bad_rows,
+        combined
     } = Args::parse();
+    let parsed_at = SystemTime::now();
 
     let report_dir = {
         let mut path_buf = data_path.clone();
@@ -112,7 +387,22 @@ async fn main() -> ExitCode {
     PROGRESS.set_style(SPINNER_STYLE.clone());
     PROGRESS.enable_steady_tick(PROGRESS_UPDATE_INTERVAL);
 
-    let (reports, broken_reports) = match load_reports(report_dir, arg_no) {
+    let prior_reports = match merge_into.as_ref().map(PriorReports::load).transpose() {
+        Ok(prior_reports) => prior_reports,
+        Err(e) => {
+            progress_message(
+                &PROGRESS,
+                "Error",
+                Color::Red,
+                format!("could not load reports to merge into, {e}")
+            );
+            PROGRESS.finish_and_clear();
+            return ExitCode::FAILURE;
+        }
+    };
+
+    let loaded = load_reports(&report_dir, arg_no, prior_reports.as_ref(), max_pdf_bytes);
+    let (reports, broken_reports, oversized_reports) = match loaded {
         Ok(reports) => reports,
         Err(e) => {
             progress_message(
@@ -126,10 +416,54 @@ async fn main() -> ExitCode {
         }
     };
 
+    let municipality_lookup = match (normalize_municipalities, municipality_table.clone()) {
+        (_, Some(path)) => {
+            let mut lookup = MunicipalityLookup::embedded();
+            let extended = fs::read_to_string(&path)
+                .map_err(anyhow::Error::from)
+                .and_then(|csv| lookup.extend_from_csv(&csv));
+            if let Err(e) = extended {
+                progress_message(
+                    &PROGRESS,
+                    "Error",
+                    Color::Red,
+                    format!("could not load municipality table, {e}")
+                );
+                PROGRESS.finish_and_clear();
+                return ExitCode::FAILURE;
+            }
+            Some(lookup)
+        }
+        (true, None) => Some(MunicipalityLookup::embedded()),
+        (false, None) => None
+    };
+
     PROGRESS.set_style(SPINNER_STYLE.clone());
     PROGRESS.set_message("Parsing table...");
-    let mut cadenza_table = match CadenzaTable::from_path(&xlsx_path) {
-        Ok(table) => table,
+    let cadenza_table = match skip_bad_rows {
+        true => CadenzaTable::from_path_skipping_bad_rows(&xlsx_path),
+        false => CadenzaTable::from_path(&xlsx_path).map(|table| (table, Vec::new()))
+    };
+    let mut cadenza_table = match cadenza_table {
+        Ok((table, bad_rows)) => {
+            if !bad_rows.is_empty() {
+                progress_message(
+                    &PROGRESS,
+                    "Skipped",
+                    Color::Yellow,
+                    format!("{} rows of the table", bad_rows.len())
+                );
+            }
+            for BadRow { row, error } in bad_rows {
+                let warning = Warning::BadTableRow {
+                    row,
+                    error: error.to_string()
+                };
+                progress_message(&PROGRESS, "Warning", Color::Yellow, &warning);
+                WARNINGS.lock().push(warning);
+            }
+            table
+        }
         Err(err) => {
             progress_message(
                 &PROGRESS,
@@ -142,7 +476,6 @@ async fn main() -> ExitCode {
         }
     };
     cadenza_table.sanitize();
-    let cadenza_table = Arc::new(cadenza_table);
 
     PROGRESS.set_style(PROGRESS_STYLE.clone());
     PROGRESS.set_message("Parsing Reports");
@@ -150,42 +483,67 @@ async fn main() -> ExitCode {
     PROGRESS.set_position(0);
     PROGRESS.set_prefix("🚀");
 
-    let mut tasks = FuturesUnordered::new();
-    let reports = reports.into_iter().filter(|(rep_no, _)| match arg_no {
-        Some(arg_no) => *rep_no == arg_no,
-        None => true
-    });
-    for (water_right_no, document) in reports {
-        let cadenza_table = cadenza_table.clone();
-        tasks.push(parsing_task(water_right_no, document, cadenza_table));
+    let mut thread_pool = rayon::ThreadPoolBuilder::new();
+    if let Some(concurrency) = concurrency {
+        thread_pool = thread_pool.num_threads(concurrency.get());
     }
+    let thread_pool = match thread_pool.build() {
+        Ok(thread_pool) => thread_pool,
+        Err(e) => {
+            progress_message(
+                &PROGRESS,
+                "Error",
+                Color::Red,
+                format!("could not start threads, {e}")
+            );
+            PROGRESS.finish_and_clear();
+            return ExitCode::FAILURE;
+        }
+    };
+
+    let reports: Reports = reports
+        .into_iter()
+        .filter(|(rep_no, _)| match arg_no {
+            Some(arg_no) => *rep_no == arg_no,
+            None => true
+        })
+        .collect();
+    let parsed_count = AtomicUsize::default();
+    let parse_results: Vec<ParsingTaskResult> = thread_pool.install(|| {
+        reports
+            .into_par_iter()
+            .map(|(water_right_no, document)| {
+                let parse_res = parse_report(
+                    water_right_no,
+                    document,
+                    &cadenza_table,
+                    drop_invalid_coords,
+                    root_only,
+                    check_sources,
+                    !no_infer_granting
+                );
+                let parsed = parsed_count.fetch_add(1, Ordering::Relaxed) + 1;
+                PROGRESS.set_position(parsed as u64);
+                parse_res
+            })
+            .collect()
+    });
 
     let mut water_rights = Vec::with_capacity(cadenza_table.rows().capacity());
     let mut pdf_only_water_rights = Vec::with_capacity(cadenza_table.rows().capacity());
     let mut parsing_issues = BTreeMap::new();
-    while let Some(task_res) = tasks.next().await {
-        let parse_res = match task_res {
-            Ok(parse_res) => parse_res,
-            Err(err) => {
-                progress_message(
-                    &PROGRESS,
-                    "Error",
-                    Color::Red,
-                    format!("could not join task, {err}")
-                );
-                PROGRESS.inc(1);
-                continue;
-            }
-        };
-
-        let _water_right_no = match parse_res {
-            Ok((water_right, enriched)) => {
+    let mut provenance = BTreeMap::new();
+    for parse_res in parse_results {
+        match parse_res {
+            Ok((water_right, enriched, field_provenance)) => {
                 let no = water_right.no;
+                if with_provenance {
+                    provenance.insert(no, field_provenance);
+                }
                 match enriched {
                     true => water_rights.push(water_right),
                     false => pdf_only_water_rights.push(water_right)
                 }
-                no
             }
 
             Err((water_right_no, error)) => {
@@ -196,11 +554,52 @@ async fn main() -> ExitCode {
                 };
                 progress_message(&PROGRESS, "Warning", Color::Yellow, &warning);
                 WARNINGS.lock().push(warning);
-                water_right_no
             }
-        };
+        }
+    }
 
-        PROGRESS.inc(1);
+    // reports with parsing issues were parsed too, their prior results are
+    // outdated
+    let parsed_nos: HashSet<WaterRightNo> = water_rights
+        .iter()
+        .chain(pdf_only_water_rights.iter())
+        .map(|water_right| water_right.no)
+        .chain(parsing_issues.keys().copied())
+        .collect();
+    let merged_at = prior_reports.as_ref().and_then(PriorReports::written_at);
+    if let Some(prior_reports) = prior_reports {
+        water_rights = prior_reports.merge(water_rights, &parsed_nos);
+    }
+
+    if let Some(lookup) = municipality_lookup.as_ref() {
+        for water_right in water_rights.iter_mut().chain(pdf_only_water_rights.iter_mut()) {
+            normalize_municipalities_of(lookup, water_right);
+        }
+    }
+
+    if validate_coordinates_against_county {
+        for water_right in water_rights.iter().chain(pdf_only_water_rights.iter()) {
+            for warning in coordinates_outside_county(water_right) {
+                progress_message(&PROGRESS, "Warning", Color::Yellow, &warning);
+                WARNINGS.lock().push(warning);
+            }
+        }
+    }
+
+    if sort_locations {
+        water_rights
+            .iter_mut()
+            .chain(pdf_only_water_rights.iter_mut())
+            .flat_map(|water_right| water_right.legal_departments.values_mut())
+            .for_each(LegalDepartment::sort_usage_locations);
+    }
+
+    if normalize_water_bodies {
+        water_rights
+            .iter_mut()
+            .chain(pdf_only_water_rights.iter_mut())
+            .flat_map(WaterRight::usage_locations_mut)
+            .for_each(|(_, usage_location)| water_body::enrich(usage_location));
     }
 
     PROGRESS.set_style(SPINNER_STYLE.clone());
@@ -215,7 +614,9 @@ async fn main() -> ExitCode {
         &water_rights,
         &pdf_only_water_rights,
         &broken_reports,
-        &parsing_issues
+        &parsing_issues,
+        min_free_bytes,
+        strict
     ) {
         Ok(paths) => paths,
         Err(e) => {
@@ -225,24 +626,103 @@ async fn main() -> ExitCode {
         }
     };
 
+    let skipped_reports_path = match save_skipped_reports(&data_path, &oversized_reports) {
+        Ok(path) => path,
+        Err(e) => {
+            progress_message(&PROGRESS, "Error", Color::Red, e);
+            PROGRESS.finish_and_clear();
+            return ExitCode::FAILURE;
+        }
+    };
+
+    if with_provenance {
+        if let Err(e) = save_provenance(&data_path, &provenance) {
+            progress_message(&PROGRESS, "Error", Color::Red, e);
+            PROGRESS.finish_and_clear();
+            return ExitCode::FAILURE;
+        }
+    }
+
+    if combined {
+        let annotated = combined::annotate(
+            &water_rights,
+            &pdf_only_water_rights,
+            &parsed_nos,
+            parsed_at,
+            merged_at,
+            |no| {
+                let report_path = report_dir.join(format!("rep{no}.pdf"));
+                fs::metadata(report_path).and_then(|metadata| metadata.modified()).ok()
+            }
+        );
+        if let Err(e) = save_combined(&data_path, &annotated) {
+            progress_message(&PROGRESS, "Error", Color::Red, e);
+            PROGRESS.finish_and_clear();
+            return ExitCode::FAILURE;
+        }
+    }
+
+    let report = Report {
+        broken: ReportEntry {
+            count: broken_reports.len(),
+            output_file: broken_reports_path
+        },
+        parsing_issues: ReportEntry {
+            count: parsing_issues.len(),
+            output_file: parsing_issues_path
+        },
+        pdf_only: ReportEntry {
+            count: pdf_only_water_rights.len(),
+            output_file: pdf_only_reports_path
+        },
+        successful: ReportEntry {
+            count: water_rights.len(),
+            output_file: reports_path
+        },
+        skipped: ReportEntry {
+            count: oversized_reports.len(),
+            output_file: skipped_reports_path
+        }
+    };
+
+    let summary = report.summary(
+        SummaryInputs {
+            xlsx_path,
+            data_path: data_path.clone(),
+            merge_into,
+            municipality_table
+        },
+        combined::timestamp(parsed_at)
+    );
+    if let Err(e) = save_summary(&data_path, &summary) {
+        progress_message(&PROGRESS, "Error", Color::Red, e);
+        PROGRESS.finish_and_clear();
+        return ExitCode::FAILURE;
+    }
+
     PROGRESS.finish_and_clear();
     eprintln!();
-    print!("{}", Report {
-        broken: (broken_reports.len(), broken_reports_path.display()),
-        parsing_issues: (parsing_issues.len(), parsing_issues_path.display()),
-        pdf_only: (pdf_only_water_rights.len(), pdf_only_reports_path.display()),
-        successful: (water_rights.len(), reports_path.display())
-    });
+    let renderer = match (report_template.as_deref(), plain_report) {
+        (Some(template), _) => ReportRenderer::Template(template),
+        (None, true) => ReportRenderer::Plain,
+        (None, false) if !Term::stdout().is_term() => ReportRenderer::Plain,
+        (None, false) => ReportRenderer::Styled
+    };
+    print!("{}", report.render(renderer));
     ExitCode::SUCCESS
 }
 
 type Reports = Vec<(WaterRightNo, Document)>;
 type BrokenReports = Vec<(WaterRightNo, lopdf::Error)>;
+/// Reports skipped for exceeding `--max-pdf-bytes` with their size.
+type OversizedReports = Vec<(WaterRightNo, u64)>;
 #[inline]
 fn load_reports(
     report_dir: impl AsRef<Path>,
-    selected: Option<WaterRightNo>
-) -> anyhow::Result<(Reports, BrokenReports)> {
+    selected: Option<WaterRightNo>,
+    prior_reports: Option<&PriorReports>,
+    max_pdf_bytes: Option<u64>
+) -> anyhow::Result<(Reports, BrokenReports, OversizedReports)> {
     PROGRESS.set_message("Counting reports...");
     let entry_count = fs::read_dir(&report_dir)?.count();
     let read_dir = fs::read_dir(report_dir)?;
@@ -254,26 +734,44 @@ fn load_reports(
 
     let mut reports = Vec::with_capacity(entry_count);
     let mut broken_reports = Vec::with_capacity(entry_count);
+    let mut oversized_reports = Vec::new();
+    let mut skipped = 0;
 
     for dir_entry in read_dir {
         let dir_entry = dir_entry?;
 
-        let file_name = dir_entry.file_name();
-        let file_name = file_name.to_string_lossy();
-        let Some(captured) = REPORT_FILE_RE.captures(file_name.as_ref())
-        else {
-            let warning = Warning::CouldNotExtractWaterRightNo {
-                file_name: file_name.to_string()
-            };
-            progress_message(&PROGRESS, "Warning", Color::Yellow, &warning);
-            WARNINGS.lock().push(warning);
-            continue;
+        let water_right_no = match extract_water_right_no(&dir_entry.file_name()) {
+            Ok(water_right_no) => water_right_no,
+            Err(warning) => {
+                progress_message(&PROGRESS, "Warning", Color::Yellow, &warning);
+                WARNINGS.lock().push(warning);
+                continue;
+            }
         };
-        let water_right_no: WaterRightNo = captured["no"].parse()?;
         PROGRESS.set_prefix(water_right_no.to_string());
 
-        match selected {
-            Some(selected) if selected != water_right_no => (),
+        if let Some(prior_reports) = prior_reports {
+            let modified = dir_entry.metadata().and_then(|metadata| metadata.modified()).ok();
+            if !prior_reports.needs_parsing(water_right_no, modified) {
+                skipped += 1;
+                PROGRESS.inc(1);
+                continue;
+            }
+        }
+
+        let size = dir_entry.metadata().map(|metadata| metadata.len()).ok();
+        match (selected, size, max_pdf_bytes) {
+            (Some(selected), ..) if selected != water_right_no => (),
+            (_, Some(size), Some(max_pdf_bytes)) if size > max_pdf_bytes => {
+                let warning = Warning::OversizedReport {
+                    water_right_no,
+                    size,
+                    max_pdf_bytes
+                };
+                progress_message(&PROGRESS, "Warning", Color::Yellow, &warning);
+                WARNINGS.lock().push(warning);
+                oversized_reports.push((water_right_no, size));
+            }
             _ => match Document::load(dir_entry.path()) {
                 Ok(document) => reports.push((water_right_no, document)),
                 Err(err) => broken_reports.push((water_right_no, err))
@@ -289,6 +787,22 @@ fn load_reports(
         Color::Green,
         format!("{} reports correctly", reports.len())
     );
+    if skipped > 0 {
+        progress_message(
+            &PROGRESS,
+            "Skipped",
+            Color::Green,
+            format!("{skipped} reports, already parsed")
+        );
+    }
+    if !oversized_reports.is_empty() {
+        progress_message(
+            &PROGRESS,
+            "Skipped",
+            Color::Yellow,
+            format!("{} reports, too large", oversized_reports.len())
+        );
+    }
     if !broken_reports.is_empty() {
         let warning = Warning::CouldNotLoadReports {
             count: broken_reports.len()
@@ -297,162 +811,453 @@ fn load_reports(
         WARNINGS.lock().push(warning);
     }
 
-    Ok((reports, broken_reports))
+    Ok((reports, broken_reports, oversized_reports))
 }
 
-// TODO: this uses tokio for parallelization, tokio is here not the best choice
-// since these       operations are cpu-intensive, rayon would be a better
-// choice
-#[inline]
-fn parsing_task(
+/// Why the broken reports could not be loaded, like an invalid header for
+/// files that are no PDFs or an invalid cross-reference table for truncated
+/// ones.
+fn broken_report_reasons(broken_reports: &BrokenReports) -> BTreeMap<WaterRightNo, String> {
+    broken_reports.iter().map(|(no, err)| (*no, err.to_string())).collect()
+}
+
+/// Extract the water right number from the file name of a report.
+///
+/// File names that are not valid UTF-8 are rejected instead of being converted
+/// lossy, as the replaced characters would otherwise be silently mangled into
+/// a possibly matching name.
+fn extract_water_right_no(file_name: &OsStr) -> Result<WaterRightNo, Warning> {
+    let Some(file_name) = file_name.to_str()
+    else {
+        return Err(Warning::NonUtf8FileName {
+            file_name: file_name.to_string_lossy().to_string()
+        });
+    };
+
+    REPORT_FILE_RE.captures(file_name).and_then(|captured| captured["no"].parse().ok()).ok_or_else(
+        || Warning::CouldNotExtractWaterRightNo {
+            file_name: file_name.to_string()
+        }
+    )
+}
+
+/// Coordinates of the report and the cadenza table differing by at most this
+/// many meters are considered the same.
+const COORDINATE_TOLERANCE: u64 = 10;
+
+/// Differing values of the report and the cadenza table.
+#[derive(Debug, PartialEq, Eq)]
+struct SourceConflict {
+    field: &'static str,
+    pdf: String,
+    xlsx: String
+}
+
+/// Compare the fields of a usage location that both the report and the
+/// cadenza table have.
+///
+/// Fields only one source has a value for are not conflicting, these are
+/// filled by the enrichment.
+fn source_conflicts(usage_location: &UsageLocation, row: &CadenzaTableRow) -> Vec<SourceConflict> {
+    let mut conflicts = Vec::new();
+    let strings = [
+        ("county", &usage_location.county, &row.county),
+        ("riverBasin", &usage_location.river_basin, &row.river_basin),
+        (
+            "groundwaterBody",
+            &usage_location.groundwater_body,
+            &row.groundwater_body
+        )
+    ];
+    for (field, pdf, xlsx) in strings {
+        if let (Some(pdf), Some(xlsx)) = (pdf, xlsx) {
+            if !pdf.trim().eq_ignore_ascii_case(xlsx.trim()) {
+                conflicts.push(SourceConflict {
+                    field,
+                    pdf: pdf.clone(),
+                    xlsx: xlsx.clone()
+                });
+            }
+        }
+    }
+
+    let coordinates = [
+        ("utmEasting", usage_location.utm_easting, row.utm_easting),
+        ("utmNorthing", usage_location.utm_northing, row.utm_northing)
+    ];
+    for (field, pdf, xlsx) in coordinates {
+        if let (Some(pdf), Some(xlsx)) = (pdf, xlsx) {
+            if pdf.abs_diff(xlsx) > COORDINATE_TOLERANCE {
+                conflicts.push(SourceConflict {
+                    field,
+                    pdf: pdf.to_string(),
+                    xlsx: xlsx.to_string()
+                });
+            }
+        }
+    }
+
+    conflicts
+}
+
+type ParsingTaskResult =
+    Result<(WaterRight, bool, Vec<FieldProvenance>), (WaterRightNo, anyhow::Error)>;
+
+/// Fill the official municipality names and districts of a water right, warns
+/// about mismatching names and unknown keys.
+fn normalize_municipalities_of(lookup: &MunicipalityLookup, water_right: &mut WaterRight) {
+    let water_right_no = water_right.no;
+    for (_, usage_location) in water_right.usage_locations_mut() {
+        let check = match lookup.enrich(usage_location) {
+            None | Some(MunicipalityCheck::Matching | MunicipalityCheck::UnknownMunicipality) => {
+                continue
+            }
+            Some(check) => check
+        };
+
+        let (key, name) = usage_location.municipal_area.clone().expect("checked by lookup");
+        let warning = Warning::MunicipalityMismatch {
+            water_right_no,
+            serial: usage_location.serial.clone(),
+            key,
+            name,
+            check
+        };
+        progress_message(&PROGRESS, "Warning", Color::Yellow, &warning);
+        WARNINGS.lock().push(warning);
+    }
+}
+
+/// Warnings for all usage locations of a water right whose coordinates lie
+/// outside of their county, counties unknown to the check are skipped.
+fn coordinates_outside_county(water_right: &WaterRight) -> Vec<Warning> {
+    water_right
+        .usage_locations()
+        .filter_map(|(_, usage_location)| {
+            let county = usage_location.county.as_ref()?;
+            let easting = usage_location.utm_easting?;
+            let northing = usage_location.utm_northing?;
+
+            match check_county_coordinates(county, easting, northing) {
+                CountyCheck::Outside => Some(Warning::CoordinatesOutsideCounty {
+                    water_right_no: water_right.no,
+                    serial: usage_location.serial.clone(),
+                    county: county.clone(),
+                    easting,
+                    northing
+                }),
+                CountyCheck::Inside | CountyCheck::UnknownCounty => None
+            }
+        })
+        .collect()
+}
+
+/// Parse a single report and enrich it with the cadenza table.
+///
+/// Runs on the threads of the rayon pool, so warnings are pushed to the
+/// shared [`WARNINGS`] directly.
+fn parse_report(
     water_right_no: WaterRightNo,
     report_doc: Document,
-    cadenza_table: Arc<CadenzaTable>
-) -> JoinHandle<Result<(WaterRight, bool), (WaterRightNo, anyhow::Error)>> {
-    tokio::spawn(async move {
-        let mut water_right = WaterRight::new(water_right_no);
-        if let Err(e) = parse_document(&mut water_right, report_doc) {
-            return Err((water_right_no, e));
-        }
+    cadenza_table: &CadenzaTable,
+    drop_invalid_coords: bool,
+    root_only: bool,
+    check_sources: bool,
+    infer_granting: bool
+) -> ParsingTaskResult {
+    let mut water_right = WaterRight::new(water_right_no);
+    let ParsedDocument {
+        provenance,
+        skipped_entries
+    } = match parse_document(&mut water_right, report_doc, root_only) {
+        Ok(parsed) => parsed,
+        Err(e) => return Err((water_right_no, e))
+    };
+    for skipped_entry in skipped_entries {
+        let warning = match skipped_entry {
+            SkippedEntry::UnknownKey((key, values)) => Warning::UnknownUsageLocationKey {
+                water_right_no,
+                key,
+                values
+            },
+            SkippedEntry::UnstorableAllowanceRange(range) => Warning::UnstorableAllowanceRange {
+                water_right_no,
+                range
+            }
+        };
+        progress_message(&PROGRESS, "Warning", Color::Yellow, &warning);
+        WARNINGS.lock().push(warning);
+    }
 
-        let mut enriched = false;
-        for row in cadenza_table.rows().iter().filter(|row| row.no == water_right_no) {
-            enriched = true;
-            let wr = &mut water_right;
-            wr.holder.update_if_none_clone(row.rights_holder.as_ref());
-            wr.valid_until.update_if_none_clone(row.valid_until.as_ref());
-            wr.status.update_if_none_clone(row.status.as_ref());
-            wr.valid_from.update_if_none_clone(row.valid_from.as_ref());
-            wr.legal_title.update_if_none_clone(row.legal_title.as_ref());
-            wr.water_authority.update_if_none_clone(row.water_authority.as_ref());
-            wr.granting_authority.update_if_none_clone(row.granting_authority.as_ref());
-            wr.last_change.update_if_none_clone(row.date_of_change.as_ref());
-            wr.file_reference.update_if_none_clone(row.file_reference.as_ref());
-            wr.external_identifier.update_if_none_clone(row.external_identifier.as_ref());
-            wr.address.update_if_none_clone(row.address.as_ref());
+    let mut enriched = false;
+    for row in cadenza_table.rows().iter().filter(|row| row.no == water_right_no) {
+        enriched = true;
+        let wr = &mut water_right;
+        wr.holder.update_if_none_clone(row.rights_holder.as_ref());
+        wr.valid_until.update_if_none_clone(row.valid_until.as_ref());
+        wr.status.update_if_none_clone(row.status.as_ref());
+        wr.valid_from.update_if_none_clone(row.valid_from.as_ref());
+        if let Some(warning) = check_legal_title(wr, row.legal_title.as_ref()) {
+            progress_message(&PROGRESS, "Warning", Color::Yellow, &warning);
+            WARNINGS.lock().push(warning);
         }
+        wr.water_authority.update_if_none_clone(row.water_authority.as_ref());
+        wr.granting_authority.update_if_none_clone(row.granting_authority.as_ref());
+        wr.last_change.update_if_none_clone(row.date_of_change.as_ref());
+        wr.file_reference.update_if_none_clone(row.file_reference.as_ref());
+        wr.external_identifier.update_if_none_clone(row.external_identifier.as_ref());
+        wr.address.update_if_none_clone(row.address.as_ref());
+    }
 
-        let mut relevant_cadenza_rows: HashMap<_, _> = cadenza_table
+    // without usage locations there is nothing to enrich and nothing missing
+    let mut relevant_cadenza_rows: HashMap<_, _> = match root_only {
+        true => HashMap::new(),
+        false => cadenza_table
             .rows()
             .iter()
             .filter(|row| row.no == water_right_no)
             .map(|row| (row.usage_location_no, row))
-            .collect();
+            .collect()
+    };
 
-        for usage_location in water_right
-            .legal_departments
-            .iter_mut()
-            .flat_map(|(_, department)| department.usage_locations.iter_mut())
-        {
-            let usage_location_by_name = relevant_cadenza_rows.values().find(|row| {
-                usage_location.name.is_some() && row.usage_location == usage_location.name
-            });
-            let usage_location_by_coords = relevant_cadenza_rows.values().find(|row| {
-                usage_location.utm_easting.is_some() &&
-                    row.utm_easting == usage_location.utm_easting &&
-                    usage_location.utm_northing.is_some() &&
-                    row.utm_northing == usage_location.utm_northing
-            });
-
-            let usage_location_no = match (usage_location_by_name, usage_location_by_coords) {
-                (Some(usage_location), _) | (None, Some(usage_location)) => {
-                    usage_location.usage_location_no
-                }
-                (None, None) => {
-                    let warning = Warning::CouldNotFindUsageLocation { water_right_no };
-                    progress_message(&PROGRESS, "Warning", Color::Yellow, &warning);
-                    WARNINGS.lock().push(warning);
-                    continue;
-                }
-            };
-
-            let row = relevant_cadenza_rows
-                .remove(&usage_location_no)
-                .expect("we got the no from the that map");
-
-            let ul = usage_location;
-            ul.no.update_if_none(Some(row.usage_location_no));
-            ul.legal_purpose.update_if_none_with(|| {
-                row.legal_purpose.as_ref().and_then(|ls| {
-                    ls.splitn(2, ' ').map(ToString::to_string).collect_tuple::<(String, String)>()
-                })
-            });
-            ul.county.update_if_none_clone(row.county.as_ref());
-            ul.river_basin.update_if_none_clone(row.river_basin.as_ref());
-            ul.groundwater_body.update_if_none_clone(row.groundwater_body.as_ref());
-            ul.flood_area.update_if_none_clone(row.flood_area.as_ref());
-            ul.water_protection_area.update_if_none_clone(row.water_protection_area.as_ref());
-            ul.utm_easting.update_if_none_clone(row.utm_easting.as_ref());
-            ul.utm_northing.update_if_none_clone(row.utm_northing.as_ref());
-
-            // sanitize coordinates
-            ul.utm_easting = ul.utm_easting.and_then(zero_is_none);
-            ul.utm_northing = ul.utm_northing.and_then(zero_is_none);
+    for (_, usage_location) in water_right.usage_locations_mut() {
+        let usage_location_by_name = relevant_cadenza_rows
+            .values()
+            .find(|row| usage_location.name.is_some() && row.usage_location == usage_location.name);
+        let usage_location_by_coords = relevant_cadenza_rows.values().find(|row| {
+            usage_location.utm_easting.is_some() &&
+                row.utm_easting == usage_location.utm_easting &&
+                usage_location.utm_northing.is_some() &&
+                row.utm_northing == usage_location.utm_northing
+        });
+
+        let usage_location_no = match (usage_location_by_name, usage_location_by_coords) {
+            (Some(usage_location), _) | (None, Some(usage_location)) => {
+                usage_location.usage_location_no
+            }
+            (None, None) => {
+                let warning = Warning::CouldNotFindUsageLocation { water_right_no };
+                progress_message(&PROGRESS, "Warning", Color::Yellow, &warning);
+                WARNINGS.lock().push(warning);
+                continue;
+            }
+        };
+
+        let row = relevant_cadenza_rows
+            .remove(&usage_location_no)
+            .expect("we got the no from the that map");
+
+        if check_sources {
+            for conflict in source_conflicts(usage_location, row) {
+                let warning = Warning::SourceConflict {
+                    water_right_no,
+                    serial: usage_location.serial.clone(),
+                    field: conflict.field,
+                    pdf: conflict.pdf,
+                    xlsx: conflict.xlsx
+                };
+                progress_message(&PROGRESS, "Warning", Color::Yellow, &warning);
+                WARNINGS.lock().push(warning);
+            }
         }
 
-        if !relevant_cadenza_rows.is_empty() {
-            let missing_locations = relevant_cadenza_rows.keys().copied().collect::<Vec<_>>();
-            let warning = Warning::MissingLocations {
-                water_right_no,
-                missing_locations
-            };
-            progress_message(&PROGRESS, "Warning", Color::Yellow, &warning);
-            WARNINGS.lock().push(warning);
+        let ul = usage_location;
+        ul.no.update_if_none(Some(row.usage_location_no));
+        if let (true, Some(legal_purpose)) =
+            (ul.legal_purposes.is_empty(), row.legal_purpose.as_ref())
+        {
+            ul.legal_purposes = parse_legal_purposes(legal_purpose);
         }
+        ul.county.update_if_none_clone(row.county.as_ref());
+        ul.river_basin.update_if_none_clone(row.river_basin.as_ref());
+        ul.groundwater_body.update_if_none_clone(row.groundwater_body.as_ref());
+        ul.flood_area.update_if_none_clone(row.flood_area.as_ref());
+        ul.water_protection_area.update_if_none_clone(row.water_protection_area.as_ref());
+        ul.utm_easting.update_if_none_clone(row.utm_easting.as_ref());
+        ul.utm_northing.update_if_none_clone(row.utm_northing.as_ref());
+
+        // sanitize coordinates
+        ul.utm_easting = ul.utm_easting.and_then(|v| UTM_ZERO_POLICY.apply(v));
+        ul.utm_northing = ul.utm_northing.and_then(|v| UTM_ZERO_POLICY.apply(v));
+    }
 
-        // remove "Bemerkung: " from annotations if they begin with that
-        match water_right.annotation.as_ref() {
-            Some(annotation) if annotation == "Bemerkung:" => water_right.annotation = None,
-            Some(annotation) if annotation.starts_with("Bemerkung: ") => {
-                water_right.annotation = annotation
-                    .split_once("Bemerkung: ")
-                    .map(|x| x.1)
-                    .expect("separator already checked")
-                    .to_owned()
-                    .into();
-            }
-            _ => ()
+    if !relevant_cadenza_rows.is_empty() {
+        let missing_locations = relevant_cadenza_rows.keys().copied().collect::<Vec<_>>();
+        let warning = Warning::MissingLocations {
+            water_right_no,
+            missing_locations
+        };
+        progress_message(&PROGRESS, "Warning", Color::Yellow, &warning);
+        WARNINGS.lock().push(warning);
+    }
+
+    if water_right.legal_departments.contains_key(&LegalDepartmentAbbreviation::Unknown) {
+        let warning = Warning::MissingDepartment { water_right_no };
+        progress_message(&PROGRESS, "Warning", Color::Yellow, &warning);
+        WARNINGS.lock().push(warning);
+    }
+
+    for (_, usage_location) in water_right.usage_locations_mut() {
+        let (Some(easting), Some(northing)) =
+            (usage_location.utm_easting, usage_location.utm_northing)
+        else {
+            continue;
+        };
+
+        let check = check_utm_coordinates(easting, northing);
+        if check == CoordinateCheck::Plausible {
+            continue;
         }
 
-        // fill granting authority if registering authority is set but not granting, the
-        // registering authority then also granted
-        if let (Some(register), None) = (
-            water_right.registering_authority.as_ref(),
-            water_right.granting_authority.as_ref()
-        ) {
-            water_right.granting_authority = Some(register.to_string());
+        if drop_invalid_coords {
+            usage_location.utm_easting = None;
+            usage_location.utm_northing = None;
         }
 
-        // normalize dates into ISO form
-        for date_opt in [
-            &mut water_right.valid_until,
-            &mut water_right.valid_from,
-            &mut water_right.initially_granted,
-            &mut water_right.last_change
-        ] {
-            let Some(date) = date_opt.as_ref()
-            else {
-                continue;
-            };
-
-            let mut split = date.split('.');
-            let day = split.next();
-            let month = split.next();
-            let year = split.next();
-            if split.next().is_some() {
-                let warning = Warning::InvalidDateFormat { water_right_no };
+        let warning = Warning::ImplausibleCoordinates {
+            water_right_no,
+            serial: usage_location.serial.clone(),
+            easting,
+            northing,
+            check,
+            dropped: drop_invalid_coords
+        };
+        progress_message(&PROGRESS, "Warning", Color::Yellow, &warning);
+        WARNINGS.lock().push(warning);
+    }
+
+    water_right.annotation = normalize_annotation(water_right.annotation.take());
+
+    // normalize file reference, keep the raw value if it does not fit the pattern
+    if let Some(file_reference) = water_right.file_reference.as_mut() {
+        match normalize_file_reference(file_reference) {
+            Some(normalized) => *file_reference = normalized,
+            None => {
+                let warning = Warning::MalformedFileReference {
+                    water_right_no,
+                    file_reference: file_reference.clone()
+                };
                 progress_message(&PROGRESS, "Warning", Color::Yellow, &warning);
                 WARNINGS.lock().push(warning);
-                continue;
             }
+        }
+    }
+
+    infer_granting_authority(&mut water_right, infer_granting);
+
+    // normalize dates into ISO form
+    for date_opt in [
+        &mut water_right.valid_until,
+        &mut water_right.valid_from,
+        &mut water_right.initially_granted,
+        &mut water_right.last_change
+    ] {
+        let Some(date) = date_opt.as_ref()
+        else {
+            continue;
+        };
+
+        let mut split = date.split('.');
+        let day = split.next();
+        let month = split.next();
+        let year = split.next();
+        if split.next().is_some() {
+            let warning = Warning::InvalidDateFormat { water_right_no };
+            progress_message(&PROGRESS, "Warning", Color::Yellow, &warning);
+            WARNINGS.lock().push(warning);
+            continue;
+        }
 
-            if let (Some(day), Some(month), Some(year)) = (day, month, year) {
-                let _ = date_opt.insert(format!("{year}-{month}-{day}"));
+        if let (Some(day), Some(month), Some(year)) = (day, month, year) {
+            let _ = date_opt.insert(format!("{year}-{month}-{day}"));
+        }
+    }
+
+    Ok((water_right, enriched, provenance))
+}
+
+/// Fill the legal title from the cadenza table or cross-check both.
+///
+/// The reports are not read for a title yet, as their label for it is not
+/// known, until then the title is always taken from the table.
+/// The title of the report is kept, if the table has a different one it is
+/// stored as `cadenza_legal_title` and a warning is returned.
+/// Differences only in case, whitespace or punctuation are ignored.
+fn check_legal_title(water_right: &mut WaterRight, cadenza: Option<&String>) -> Option<Warning> {
+    let cadenza = cadenza?;
+    let Some(report) = water_right.legal_title.as_ref()
+    else {
+        water_right.legal_title = Some(cadenza.clone());
+        return None;
+    };
+
+    if !titles_differ(report, cadenza) {
+        return None;
+    }
+
+    let warning = Warning::LegalTitleMismatch {
+        water_right_no: water_right.no,
+        report: report.clone(),
+        cadenza: cadenza.clone()
+    };
+    water_right.cadenza_legal_title = Some(cadenza.clone());
+    Some(warning)
+}
+
+fn titles_differ(a: &str, b: &str) -> bool {
+    let normalize = |title: &str| -> String {
+        title.chars().filter(|c| c.is_alphanumeric()).flat_map(char::to_lowercase).collect()
+    };
+    normalize(a) != normalize(b)
+}
+
+/// Fill the granting authority if the registering authority is set but not
+/// the granting one, the registering authority then also granted.
+///
+/// Without `infer` the water right is left as it is, see
+/// `--no-infer-granting`.
+fn infer_granting_authority(water_right: &mut WaterRight, infer: bool) {
+    if !infer {
+        return;
+    }
+
+    if let (Some(register), None) = (
+        water_right.registering_authority.as_ref(),
+        water_right.granting_authority.as_ref()
+    ) {
+        water_right.granting_authority = Some(register.to_string());
+    }
+}
+
+/// Collapse identical warnings into a single entry.
+///
+/// Warnings are identical if their serialized form is equal, every entry gets
+/// an additional `occurrences` field counting how often that warning was
+/// emitted.
+/// The order of first occurrence is preserved.
+fn aggregate_warnings(warnings: &[Warning]) -> serde_json::Result<Vec<serde_json::Value>> {
+    let mut aggregated: Vec<(serde_json::Value, usize)> = Vec::new();
+    let mut positions: HashMap<String, usize> = HashMap::new();
+    for warning in warnings {
+        let value = serde_json::to_value(warning)?;
+        match positions.entry(value.to_string()) {
+            Entry::Occupied(position) => aggregated[*position.get()].1 += 1,
+            Entry::Vacant(position) => {
+                position.insert(aggregated.len());
+                aggregated.push((value, 1));
             }
         }
+    }
 
-        Ok((water_right, enriched))
-    })
+    Ok(aggregated
+        .into_iter()
+        .map(|(mut value, occurrences)| {
+            if let serde_json::Value::Object(map) = &mut value {
+                map.insert("occurrences".to_string(), occurrences.into());
+            }
+            value
+        })
+        .collect())
 }
 
 struct ResultPaths {
@@ -461,17 +1266,47 @@ struct ResultPaths {
     pub pdf_only_reports_path: PathBuf,
     pub reports_path: PathBuf
 }
+
+/// Numbers of the water rights in both `water_rights` and
+/// `pdf_only_water_rights`, sorted.
+fn overlapping_water_rights(
+    water_rights: &[WaterRight],
+    pdf_only_water_rights: &[WaterRight]
+) -> Vec<WaterRightNo> {
+    let pdf_only: BTreeSet<WaterRightNo> =
+        pdf_only_water_rights.iter().map(|water_right| water_right.no).collect();
+    let overlapping: BTreeSet<WaterRightNo> = water_rights
+        .iter()
+        .map(|water_right| water_right.no)
+        .filter(|no| pdf_only.contains(no))
+        .collect();
+    overlapping.into_iter().collect()
+}
+
 #[inline]
 fn save_results(
     data_path: &Path,
     water_rights: &[WaterRight],
     pdf_only_water_rights: &[WaterRight],
     broken_reports: &BrokenReports,
-    parsing_issues: &BTreeMap<WaterRightNo, String>
+    parsing_issues: &BTreeMap<WaterRightNo, String>,
+    min_free_bytes: u64,
+    strict: bool
 ) -> Result<ResultPaths, String> {
     // TODO: use multiple smaller functions for clarity
     // TODO: maybe use globals here, could be easier to understand
 
+    // every water right is either enriched or pdf-only, never both
+    let water_right_nos = overlapping_water_rights(water_rights, pdf_only_water_rights);
+    if !water_right_nos.is_empty() {
+        let warning = Warning::OverlappingReports { water_right_nos };
+        if strict {
+            return Err(warning.to_string());
+        }
+        progress_message(&PROGRESS, "Warning", Color::Yellow, &warning);
+        WARNINGS.lock().push(warning);
+    }
+
     // save parsed reports
 
     let reports_json_path = {
@@ -489,10 +1324,6 @@ fn save_results(
         Err(e) => return Err(format!("could not serialize water rights to json, {e}"))
     };
 
-    if let Err(e) = fs::write(&reports_json_path, reports_json) {
-        return Err(format!("could not write reports json, {e}"));
-    }
-
     // save pdf only reports
 
     let pdf_only_reports_json_path = {
@@ -514,18 +1345,33 @@ fn save_results(
         }
     };
 
+    let estimated_output = (reports_json.len() + pdf_only_reports_json.len()) as u64;
+    match check_free_space(data_path, estimated_output, min_free_bytes) {
+        Ok(SpaceCheck::Sufficient) => (),
+        Ok(check) => return Err(format!("could not save results, {check}")),
+        Err(e) => progress_message(
+            &PROGRESS,
+            "Warning",
+            Color::Yellow,
+            format!("could not check free disk space, {e}")
+        )
+    }
+
+    if let Err(e) = fs::write(&reports_json_path, reports_json) {
+        return Err(format!("could not write reports json, {e}"));
+    }
+
     if let Err(e) = fs::write(&pdf_only_reports_json_path, pdf_only_reports_json) {
         return Err(format!("could not write pdf only reports json, {e}"));
     }
 
     // save broken reports
 
-    let broken_reports_json = match serde_json::to_string_pretty(
-        &broken_reports.iter().map(|(no, _)| no).copied().collect::<Vec<WaterRightNo>>()
-    ) {
-        Ok(json) => json,
-        Err(e) => return Err(format!("could not serialize broken reports to json, {e}"))
-    };
+    let broken_reports_json =
+        match serde_json::to_string_pretty(&broken_report_reasons(broken_reports)) {
+            Ok(json) => json,
+            Err(e) => return Err(format!("could not serialize broken reports to json, {e}"))
+        };
 
     let broken_reports_path = {
         let mut path: PathBuf = data_path.into();
@@ -554,7 +1400,9 @@ fn save_results(
         return Err(format!("could not write parsing issues json, {e}"));
     }
 
-    let warnings_json = match serde_json::to_string_pretty(WARNINGS.lock().deref()) {
+    let warnings_json = match aggregate_warnings(WARNINGS.lock().deref())
+        .and_then(|warnings| serde_json::to_string_pretty(&warnings))
+    {
         Ok(json) => json,
         Err(e) => return Err(format!("could not serialize warnings to json, {e}"))
     };
@@ -577,107 +1425,361 @@ fn save_results(
     })
 }
 
-struct Report<T0, T1, T2, T3> {
-    broken: (usize, T0),
-    parsing_issues: (usize, T1),
-    pdf_only: (usize, T2),
-    successful: (usize, T3)
+/// Write the reports skipped for exceeding `--max-pdf-bytes` with their size.
+#[inline]
+fn save_skipped_reports(
+    data_path: &Path,
+    oversized_reports: &OversizedReports
+) -> Result<PathBuf, String> {
+    let skipped_reports: BTreeMap<WaterRightNo, u64> = oversized_reports.iter().copied().collect();
+    let skipped_reports_json = match serde_json::to_string_pretty(&skipped_reports) {
+        Ok(json) => json,
+        Err(e) => return Err(format!("could not serialize skipped reports to json, {e}"))
+    };
+
+    let skipped_reports_path = {
+        let mut path: PathBuf = data_path.into();
+        path.push("skipped-reports.json");
+        path
+    };
+
+    if let Err(e) = fs::write(&skipped_reports_path, skipped_reports_json) {
+        return Err(format!("could not write skipped reports json, {e}"));
+    }
+
+    Ok(skipped_reports_path)
 }
 
-impl<T0, T1, T2, T3> Display for Report<T0, T1, T2, T3>
-where
-    T0: Display,
-    T1: Display,
-    T2: Display,
-    T3: Display
-{
-    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
-        let description_style = Style::new().fg(Color::Yellow);
-        let category_style = Style::new().fg(Color::Magenta);
-        let key_style = Style::new().fg(Color::Cyan);
-        let equal_style = Style::new().fg(Color::White);
-        let num_value_style = Style::new().fg(Color::Magenta).bright();
-        let str_value_style = Style::new().fg(Color::Blue).bright();
-
-        let description_indicator = description_style.apply_to("#");
-        let identifier_open = category_style.apply_to("[");
-        let identifier_close = category_style.apply_to("]");
-        let count_key = key_style.apply_to("count");
-        let output_file_key = key_style.apply_to("output_file");
-        let equal_sign = equal_style.apply_to("=");
-        let string_indicator = str_value_style.apply_to("'");
-
-        let entries: &[(Vec<&str>, &str, usize, &dyn Display)] = &[
-            (
-                vec![
-                    "Broken PDF files which cannot be loaded.",
-                    "Could be due to corrupted or incompatible files.",
-                ],
-                "broken",
-                self.broken.0,
-                &self.broken.1
-            ),
-            (
-                vec![
-                    "Reports with parsing issues.",
-                    "First issue with it's respective water right number.",
-                ],
-                "parsing_issues",
-                self.parsing_issues.0,
-                &self.parsing_issues.1
-            ),
-            (
-                vec![
-                    "Reports where data could only be extracted from the PDF file.",
-                    "XLSX data might be missing.",
-                ],
-                "pdf_only",
-                self.pdf_only.0,
-                &self.pdf_only.1
-            ),
-            (
-                vec!["Reports parsed and enriched with both PDF and XLSX data."],
-                "reports",
-                self.successful.0,
-                &self.successful.1
-            )
-        ];
+#[inline]
+fn save_provenance(
+    data_path: &Path,
+    provenance: &BTreeMap<WaterRightNo, Vec<FieldProvenance>>
+) -> Result<PathBuf, String> {
+    let provenance_json = match serde_json::to_string_pretty(provenance) {
+        Ok(json) => json,
+        Err(e) => return Err(format!("could not serialize provenance to json, {e}"))
+    };
 
-        for (description, identifier, count, output_file) in entries {
-            for description in description {
-                writeln!(
-                    f,
-                    "{} {}",
-                    description_indicator,
-                    description_style.apply_to(description)
-                )?;
-            }
-            writeln!(
-                f,
-                "{}{}{}",
-                identifier_open,
-                category_style.apply_to(identifier),
-                identifier_close
-            )?;
-            writeln!(
-                f,
-                "{} {} {}",
-                count_key,
-                equal_sign,
-                num_value_style.apply_to(count)
-            )?;
-            writeln!(
-                f,
-                "{} {} {}{}{}",
-                output_file_key,
-                equal_sign,
-                string_indicator,
-                str_value_style.apply_to(output_file),
-                string_indicator
-            )?;
-            writeln!(f)?;
+    let provenance_path = {
+        let mut path: PathBuf = data_path.into();
+        path.push("provenance.json");
+        path
+    };
+
+    if let Err(e) = fs::write(&provenance_path, provenance_json) {
+        return Err(format!("could not write provenance json, {e}"));
+    }
+
+    Ok(provenance_path)
+}
+
+fn save_combined(data_path: &Path, annotated: &[AnnotatedWaterRight]) -> Result<PathBuf, String> {
+    #[cfg(debug_assertions)]
+    let combined_json = serde_json::to_string_pretty(annotated);
+    #[cfg(not(debug_assertions))]
+    let combined_json = serde_json::to_string(annotated);
+    let combined_json = match combined_json {
+        Ok(json) => json,
+        Err(e) => {
+            return Err(format!(
+                "could not serialize combined water rights to json, {e}"
+            ))
         }
+    };
+
+    let combined_path = {
+        let mut path: PathBuf = data_path.into();
+        path.push("combined.json");
+        path
+    };
+
+    if let Err(e) = fs::write(&combined_path, combined_json) {
+        return Err(format!("could not write combined json, {e}"));
+    }
+
+    Ok(combined_path)
+}
+
+fn save_summary(data_path: &Path, summary: &Summary) -> Result<PathBuf, String> {
+    let summary_json = match serde_json::to_string_pretty(summary) {
+        Ok(json) => json,
+        Err(e) => return Err(format!("could not serialize summary to json, {e}"))
+    };
+
+    let summary_path = {
+        let mut path: PathBuf = data_path.into();
+        path.push("reports.summary.json");
+        path
+    };
+
+    if let Err(e) = fs::write(&summary_path, summary_json) {
+        return Err(format!("could not write summary json, {e}"));
+    }
+
+    Ok(summary_path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn extract_water_right_no_works() {
+        assert_eq!(
+            extract_water_right_no(OsStr::new("rep1101.pdf")).unwrap(),
+            1101
+        );
+        assert!(matches!(
+            extract_water_right_no(OsStr::new("report.pdf")),
+            Err(Warning::CouldNotExtractWaterRightNo { .. })
+        ));
+    }
 
-        Ok(())
+    #[test]
+    fn bash_completions_are_generated() {
+        let mut completions = Vec::new();
+        assert!(cli::print_completions::<Args, _, _>(
+            "parser",
+            ["parser", "completions", "bash"],
+            &mut completions
+        ));
+        let completions = String::from_utf8(completions).unwrap();
+        assert!(completions.contains("_parser()"));
+        assert!(completions.contains("--max-pdf-bytes"));
+        assert!(!completions.contains("completions"));
+
+        let mut completions = Vec::new();
+        assert!(!cli::print_completions::<Args, _, _>(
+            "parser",
+            ["parser", "cadenza.xlsx"],
+            &mut completions
+        ));
+        assert!(completions.is_empty());
+    }
+
+    #[test]
+    fn infer_granting_authority_works() {
+        let mut water_right = WaterRight::new(1101);
+        water_right.registering_authority = Some("Landkreis Gifhorn".to_string());
+        infer_granting_authority(&mut water_right, false);
+        assert_eq!(water_right.granting_authority, None);
+
+        infer_granting_authority(&mut water_right, true);
+        assert_eq!(
+            water_right.granting_authority.as_deref(),
+            Some("Landkreis Gifhorn")
+        );
+
+        water_right.granting_authority = Some("NLWKN".to_string());
+        infer_granting_authority(&mut water_right, true);
+        assert_eq!(water_right.granting_authority.as_deref(), Some("NLWKN"));
+    }
+
+    #[test]
+    fn check_legal_title_works() {
+        let mut water_right = WaterRight::new(1101);
+        assert!(check_legal_title(&mut water_right, None).is_none());
+        assert!(check_legal_title(&mut water_right, Some(&"Erlaubnis".to_string())).is_none());
+        assert_eq!(water_right.legal_title.as_deref(), Some("Erlaubnis"));
+
+        assert!(check_legal_title(&mut water_right, Some(&"erlaubnis ".to_string())).is_none());
+        assert_eq!(water_right.cadenza_legal_title, None);
+
+        let warning = check_legal_title(&mut water_right, Some(&"Bewilligung".to_string()));
+        assert!(matches!(
+            warning,
+            Some(Warning::LegalTitleMismatch {
+                water_right_no: 1101,
+                ..
+            })
+        ));
+        assert_eq!(water_right.legal_title.as_deref(), Some("Erlaubnis"));
+        assert_eq!(
+            water_right.cadenza_legal_title.as_deref(),
+            Some("Bewilligung")
+        );
+    }
+
+    #[test]
+    fn overlapping_reports_are_detected() {
+        let water_rights: Vec<_> = [1101, 1103, 1102].map(WaterRight::new).into();
+        let pdf_only_water_rights: Vec<_> = [1104, 1102, 1101].map(WaterRight::new).into();
+        assert_eq!(
+            overlapping_water_rights(&water_rights, &pdf_only_water_rights),
+            [1101, 1102]
+        );
+        assert!(overlapping_water_rights(&water_rights, &[WaterRight::new(1104)]).is_empty());
+
+        let data_dir = tempfile::tempdir().unwrap();
+        let data_path = data_dir.path();
+        let err = save_results(
+            data_path,
+            &water_rights,
+            &pdf_only_water_rights,
+            &BrokenReports::new(),
+            &BTreeMap::new(),
+            0,
+            true
+        )
+        .err()
+        .unwrap();
+        assert_eq!(
+            err,
+            "water rights [1101, 1102] are in both the reports and the pdf-only reports"
+        );
+        assert!(!data_path.join("reports.json").exists());
+    }
+
+    #[test]
+    fn broken_reports_keep_reason() {
+        let report_dir = tempfile::tempdir().unwrap();
+        let report_dir = report_dir.path();
+        fs::write(report_dir.join("rep1101.pdf"), "<html>not a pdf</html>").unwrap();
+        fs::write(
+            report_dir.join("rep1102.pdf"),
+            "%PDF-1.4\n1 0 obj\n<< /Type"
+        )
+        .unwrap();
+
+        let (reports, broken_reports, _) = load_reports(report_dir, None, None, None).unwrap();
+        assert!(reports.is_empty());
+
+        let reasons = broken_report_reasons(&broken_reports);
+        assert_eq!(reasons.keys().copied().collect::<Vec<_>>(), [1101, 1102]);
+        assert_eq!(reasons[&1101], "Invalid file header");
+        assert_ne!(reasons[&1102], reasons[&1101]);
+        assert!(!reasons[&1102].is_empty());
+    }
+
+    #[test]
+    fn oversized_reports_are_skipped() {
+        let report_dir = tempfile::tempdir().unwrap();
+        let report_dir = report_dir.path();
+        let mut huge = b"%PDF-1.4\n".to_vec();
+        huge.resize(4096, b'0');
+        fs::write(report_dir.join("rep1101.pdf"), huge).unwrap();
+        fs::write(report_dir.join("rep1102.pdf"), "<html>not a pdf</html>").unwrap();
+
+        let limited = load_reports(report_dir, None, None, Some(1024));
+        let unlimited = load_reports(report_dir, None, None, None);
+
+        let (reports, broken_reports, oversized_reports) = limited.unwrap();
+        assert!(reports.is_empty());
+        assert_eq!(oversized_reports, [(1101, 4096)]);
+        assert_eq!(
+            broken_reports.iter().map(|(no, _)| *no).collect::<Vec<_>>(),
+            [1102]
+        );
+        assert!(WARNINGS.lock().iter().any(|warning| matches!(
+            warning,
+            Warning::OversizedReport {
+                water_right_no: 1101,
+                size: 4096,
+                max_pdf_bytes: 1024
+            }
+        )));
+
+        let (_, mut broken_reports, oversized_reports) = unlimited.unwrap();
+        assert!(oversized_reports.is_empty());
+        broken_reports.sort_by_key(|(no, _)| *no);
+        assert_eq!(
+            broken_reports.iter().map(|(no, _)| *no).collect::<Vec<_>>(),
+            [1101, 1102]
+        );
+    }
+
+    #[test]
+    fn skipped_reports_are_saved() {
+        let data_dir = tempfile::tempdir().unwrap();
+        let path = save_skipped_reports(data_dir.path(), &vec![(1102, 2048), (1101, 4096)]);
+        let path = path.unwrap();
+        assert_eq!(path, data_dir.path().join("skipped-reports.json"));
+
+        let skipped: BTreeMap<WaterRightNo, u64> =
+            serde_json::from_str(&fs::read_to_string(path).unwrap()).unwrap();
+        assert_eq!(skipped, BTreeMap::from([(1101, 4096), (1102, 2048)]));
+    }
+
+    #[test]
+    fn aggregate_warnings_collapses_duplicates() {
+        let warnings = [
+            Warning::CouldNotFindUsageLocation {
+                water_right_no: 1101
+            },
+            Warning::InvalidDateFormat {
+                water_right_no: 1101
+            },
+            Warning::CouldNotFindUsageLocation {
+                water_right_no: 1101
+            },
+            Warning::CouldNotFindUsageLocation {
+                water_right_no: 1102
+            }
+        ];
+
+        let aggregated = aggregate_warnings(&warnings).unwrap();
+        assert_eq!(aggregated, [
+            serde_json::json!({
+                "type": "CouldNotFindUsageLocation",
+                "water_right_no": 1101,
+                "occurrences": 2
+            }),
+            serde_json::json!({
+                "type": "InvalidDateFormat",
+                "water_right_no": 1101,
+                "occurrences": 1
+            }),
+            serde_json::json!({
+                "type": "CouldNotFindUsageLocation",
+                "water_right_no": 1102,
+                "occurrences": 1
+            })
+        ]);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn extract_water_right_no_rejects_non_utf8() {
+        use std::os::unix::ffi::OsStrExt;
+
+        let file_name = OsStr::from_bytes(b"rep11\xFF01.pdf");
+        assert!(matches!(
+            extract_water_right_no(file_name),
+            Err(Warning::NonUtf8FileName { .. })
+        ));
+    }
+
+    #[test]
+    fn source_conflicts_works() {
+        let mut usage_location = UsageLocation::new();
+        usage_location.county = Some("Gifhorn".to_string());
+        usage_location.river_basin = Some("Elbe/Labe".to_string());
+        usage_location.utm_easting = Some(32603873);
+        usage_location.utm_northing = Some(5852015);
+
+        let row: CadenzaTableRow = serde_json::from_value(serde_json::json!({
+            "Wasserrecht Nr.": 1101,
+            "Nutzungsort Nr.": 1,
+            "Rechtsabteilung": "Entnahme von Wasser",
+            "Landkreis": "Celle",
+            "Flussgebiet": "elbe/labe ",
+            "Grundwasserkörper": "Ilmenau Lockergestein links",
+            "UTM-Rechtswert": 32603880,
+            "UTM-Hochwert": 5852115
+        }))
+        .unwrap();
+
+        assert_eq!(source_conflicts(&usage_location, &row), [
+            SourceConflict {
+                field: "county",
+                pdf: "Gifhorn".to_string(),
+                xlsx: "Celle".to_string()
+            },
+            SourceConflict {
+                field: "utmNorthing",
+                pdf: "5852015".to_string(),
+                xlsx: "5852115".to_string()
+            }
+        ]);
     }
 }
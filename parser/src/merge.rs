@@ -0,0 +1,136 @@
+use std::collections::HashSet;
+use std::fs;
+use std::path::Path;
+use std::time::SystemTime;
+
+use nlwkn::{WaterRight, WaterRightNo};
+
+/// Water rights of a previous parser run to merge new results into.
+pub struct PriorReports {
+    water_rights: Vec<WaterRight>,
+    known: HashSet<WaterRightNo>,
+
+    /// When the previous results were written, reports modified afterwards
+    /// are parsed again.
+    written_at: Option<SystemTime>
+}
+
+impl PriorReports {
+    pub fn load(path: impl AsRef<Path>) -> anyhow::Result<Self> {
+        let path = path.as_ref();
+        let water_rights = nlwkn::load_water_rights(path)?;
+        let written_at = fs::metadata(path)?.modified().ok();
+        Ok(PriorReports::new(water_rights, written_at))
+    }
+
+    fn new(water_rights: Vec<WaterRight>, written_at: Option<SystemTime>) -> Self {
+        let known = water_rights.iter().map(|water_right| water_right.no).collect();
+        PriorReports {
+            water_rights,
+            known,
+            written_at
+        }
+    }
+
+    /// When the previous results were written.
+    pub fn written_at(&self) -> Option<SystemTime> {
+        self.written_at
+    }
+
+    /// Whether the report for `water_right_no`, last modified at `modified`,
+    /// has to be parsed.
+    ///
+    /// Unknown reports are always parsed, known ones only if they were
+    /// modified after the previous results were written.
+    pub fn needs_parsing(
+        &self,
+        water_right_no: WaterRightNo,
+        modified: Option<SystemTime>
+    ) -> bool {
+        if !self.known.contains(&water_right_no) {
+            return true;
+        }
+
+        match (modified, self.written_at) {
+            (Some(modified), Some(written_at)) => modified > written_at,
+            _ => false
+        }
+    }
+
+    /// Merge the newly `parsed` water rights into the previous ones.
+    ///
+    /// Previous water rights whose report was parsed again, i.e. whose number
+    /// is in `reparsed`, are dropped, even if the report is now pdf-only or
+    /// could not be parsed.
+    /// The result is sorted by the water right number.
+    pub fn merge(
+        self,
+        parsed: Vec<WaterRight>,
+        reparsed: &HashSet<WaterRightNo>
+    ) -> Vec<WaterRight> {
+        let mut merged: Vec<_> = self
+            .water_rights
+            .into_iter()
+            .filter(|water_right| !reparsed.contains(&water_right.no))
+            .chain(parsed)
+            .collect();
+        merged.sort_by_key(|water_right| water_right.no);
+        merged
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    #[test]
+    fn only_new_reports_are_parsed() {
+        let written_at = SystemTime::UNIX_EPOCH + Duration::from_secs(1_000_000);
+        let mut prior_water_right = WaterRight::new(1101);
+        prior_water_right.holder = Some("prior".to_string());
+        let prior = PriorReports::new(
+            vec![prior_water_right, WaterRight::new(1103)],
+            Some(written_at)
+        );
+
+        let before = Some(written_at - Duration::from_secs(60));
+        let after = Some(written_at + Duration::from_secs(60));
+        assert!(!prior.needs_parsing(1101, before));
+        assert!(!prior.needs_parsing(1101, None));
+        assert!(prior.needs_parsing(1101, after));
+        assert!(prior.needs_parsing(1102, before));
+
+        let mut reparsed = WaterRight::new(1101);
+        reparsed.holder = Some("reparsed".to_string());
+        let merged = prior.merge(
+            vec![WaterRight::new(1102), reparsed],
+            &HashSet::from([1101, 1102])
+        );
+        assert_eq!(
+            merged.iter().map(|water_right| water_right.no).collect::<Vec<_>>(),
+            [1101, 1102, 1103]
+        );
+        assert_eq!(merged[0].holder.as_deref(), Some("reparsed"));
+    }
+
+    #[test]
+    fn reparsed_pdf_only_reports_replace_prior() {
+        let prior = PriorReports::new(
+            vec![
+                WaterRight::new(1101),
+                WaterRight::new(1102),
+                WaterRight::new(1103),
+            ],
+            None
+        );
+
+        // 1101 is now pdf-only and 1102 could not be parsed
+        let merged = prior.merge(Vec::new(), &HashSet::from([1101, 1102]));
+        assert_eq!(
+            merged.iter().map(|water_right| water_right.no).collect::<Vec<_>>(),
+            [1103]
+        );
+    }
+}
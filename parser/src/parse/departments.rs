@@ -1,19 +1,64 @@
 use std::str::FromStr;
 
-use itertools::Itertools;
 use lazy_static::lazy_static;
 use nlwkn::helper_types::{OrFallback, Quantity, Rate, SingleOrPair};
-use nlwkn::util::StringOption;
-use nlwkn::{LandRecord, LegalDepartment, LegalDepartmentAbbreviation, UsageLocation, WaterRight};
+use nlwkn::util::{parse_decimal, parse_legal_purposes, StringOption};
+use nlwkn::{
+    DamTargets, LandRecord, LegalDepartment, LegalDepartmentAbbreviation, RateRecord,
+    UsageLocation, WaterRight
+};
 use regex::Regex;
 
 use crate::intermediate::key_value::KeyValuePair;
+use crate::parse::SkippedEntry;
 
+/// Keys of a usage location the parser understands.
+///
+/// Other keys are skipped and returned by [`parse_departments`], the known keys
+/// still fail the report if their values are malformed.
+const USAGE_LOCATION_KEYS: &[&str] = &[
+    "Nutzungsort Lfd. Nr.:",
+    "Bezeichnung:",
+    "Rechtszweck:",
+    "East und North:",
+    "Top. Karte 1:25.000:",
+    "(ETRS89/UTM 32N)",
+    "Gemeindegebiet:",
+    "Gemarkung, Flur:",
+    "Unterhaltungsverband:",
+    "Flurstück:",
+    "EU-Bearbeitungsgebiet:",
+    "Gewässer:",
+    "Einzugsgebietskennzahl:",
+    "Verordnungszitat:",
+    "Erlaubniswert:"
+];
+
+/// Parse the grouped departments into the `water_right`.
+///
+/// An empty department text marks usage locations without an "Abteilung",
+/// these are stored under [`LegalDepartmentAbbreviation::Unknown`].
+///
+/// Returns the skipped entries of the usage locations.
 pub fn parse_departments(
     items: Vec<(String, Vec<Vec<KeyValuePair>>)>,
     water_right: &mut WaterRight
-) -> anyhow::Result<()> {
+) -> anyhow::Result<Vec<SkippedEntry>> {
+    let mut skipped_entries = Vec::new();
     for (department_text, usage_locations) in items {
+        if department_text.is_empty() {
+            let abbreviation = LegalDepartmentAbbreviation::Unknown;
+            let mut legal_department =
+                LegalDepartment::new(abbreviation, abbreviation.description().to_string());
+            skipped_entries.append(&mut parse_usage_locations(
+                usage_locations,
+                &mut legal_department,
+                abbreviation
+            )?);
+            water_right.legal_departments.insert(abbreviation, legal_department);
+            continue;
+        }
+
         let mut department_text_split = department_text.splitn(3, ' ');
         let abbreviation: LegalDepartmentAbbreviation = department_text_split
             .next()
@@ -26,25 +71,34 @@ pub fn parse_departments(
             .to_string();
 
         let mut legal_department = LegalDepartment::new(abbreviation, description);
-        parse_usage_locations(usage_locations, &mut legal_department, abbreviation)?;
+        skipped_entries.append(&mut parse_usage_locations(
+            usage_locations,
+            &mut legal_department,
+            abbreviation
+        )?);
         water_right.legal_departments.insert(abbreviation, legal_department);
     }
 
-    Ok(())
+    Ok(skipped_entries)
 }
 
 fn parse_usage_locations(
     usage_locations: Vec<Vec<KeyValuePair>>,
     legal_department: &mut LegalDepartment,
     department: LegalDepartmentAbbreviation
-) -> anyhow::Result<()> {
+) -> anyhow::Result<Vec<SkippedEntry>> {
+    let mut skipped_entries = Vec::new();
     for usage_location_items in usage_locations {
         let mut usage_location = UsageLocation::new();
-        parse_usage_location(usage_location_items, &mut usage_location, department)?;
+        skipped_entries.append(&mut parse_usage_location(
+            usage_location_items,
+            &mut usage_location,
+            department
+        )?);
         legal_department.usage_locations.push(usage_location);
     }
 
-    Ok(())
+    Ok(skipped_entries)
 }
 
 lazy_static! {
@@ -52,13 +106,18 @@ lazy_static! {
         Regex::new(r"^(?<ser_no>.*) \((?<active>\w+), (?<real>\w+)\)$").expect("valid regex");
     static ref STRING_NUM_RE: Regex =
         Regex::new(r"^(?<string>\D+)\s*(?<num>\d+)$").expect("valid regex");
+    static ref ALLOWANCE_RANGE_RE: Regex =
+        Regex::new(r"^(?<kind>.+?) (?<min>[\d\.,]+) ?[-–] ?(?<max>[\d\.,]+) (?<unit>\S+)$")
+            .expect("valid regex");
 }
 
+/// Parse the entries of a usage location, returns the skipped ones.
 fn parse_usage_location(
     items: Vec<KeyValuePair>,
     usage_location: &mut UsageLocation,
     department: LegalDepartmentAbbreviation
-) -> anyhow::Result<()> {
+) -> anyhow::Result<Vec<SkippedEntry>> {
+    let mut skipped_entries = Vec::new();
     for (key, values) in items {
         let mut values = values.into_iter();
         let mut first = values.next().sanitize();
@@ -75,10 +134,15 @@ fn parse_usage_location(
             }
             ("Bezeichnung:", v, _) => usage_location.name = v.map(|s| s.replace('\n', " ")),
             ("Rechtszweck:", Some(v), _) => {
-                usage_location.legal_purpose =
-                    v.splitn(2, ' ').map(ToString::to_string).collect_tuple()
+                usage_location.legal_purposes = parse_legal_purposes(&v)
+            }
+            // the northing is usually under the next key, some reports have both here
+            ("East und North:", Some(easting), northing) => {
+                usage_location.utm_easting = Some(easting.parse()?);
+                if let Some(northing) = northing {
+                    usage_location.utm_northing = Some(northing.parse()?);
+                }
             }
-            ("East und North:", Some(v), _) => usage_location.utm_easting = Some(v.parse()?),
             ("Top. Karte 1:25.000:", None, None) => (),
             ("Top. Karte 1:25.000:", Some(num), None) => {
                 usage_location.map_excerpt =
@@ -130,62 +194,150 @@ fn parse_usage_location(
                     Some(SingleOrPair::Pair(num.replace(' ', "").parse()?, s))
             }
             ("Verordnungszitat:", v, _) => usage_location.regulation_citation = v,
-            ("Erlaubniswert:", Some(v), _) => parse_allowance_value(v, usage_location, department)?,
+            ("Erlaubniswert:", Some(v), _) => {
+                if let Some(range) = parse_allowance_value(v, usage_location, department)? {
+                    skipped_entries.push(SkippedEntry::UnstorableAllowanceRange(range));
+                }
+            }
 
-            (key, first, second) => {
+            (key, first, second) if USAGE_LOCATION_KEYS.contains(&key) => {
                 return Err(anyhow::Error::msg(format!(
                     "invalid entry for the usage location, key: {key:?}, first: {first:?}, \
                      second: {second:?}"
                 )));
             }
+            (_, first, second) => {
+                let values = first.into_iter().chain(second).chain(values).collect();
+                skipped_entries.push(SkippedEntry::UnknownKey((key, values)));
+            }
         }
     }
 
-    Ok(())
+    Ok(skipped_entries)
 }
 
+/// Parse an "Erlaubniswert" like `"Entnahmemenge 1500 m³/a"`.
+///
+/// Values may also be given as range like `"Entnahmemenge 100 - 200 m³/d"`,
+/// see [`parse_allowance_range`].
+/// A range that cannot be stored is returned, so it is skipped instead of
+/// failing the whole report.
 fn parse_allowance_value(
     value: String,
     usage_location: &mut UsageLocation,
     department: LegalDepartmentAbbreviation
-) -> anyhow::Result<()> {
+) -> anyhow::Result<Option<String>> {
     use LegalDepartmentAbbreviation::*;
 
+    if let Some(range) = ALLOWANCE_RANGE_RE.captures(&value) {
+        let stored = parse_allowance_range(
+            &range["kind"],
+            (&range["min"], &range["max"]),
+            &range["unit"],
+            usage_location,
+            department
+        );
+        return Ok((!stored).then_some(value));
+    }
+
     let mut split = value.rsplitn(3, ' ');
     let unit = split.next().ok_or(anyhow::Error::msg("'Erlaubniswert' has no unit"))?;
     let value = split.next().ok_or(anyhow::Error::msg("'Erlaubniswert' has no value"))?;
     let kind = split.next().ok_or(anyhow::Error::msg("'Erlaubniswert' has no specifier"))?;
-    let rate = format!("{value} {unit}");
-    let rate = match Rate::from_str(&rate) {
-        Ok(rate) => OrFallback::Expected(rate),
-        Err(_) => OrFallback::Fallback(rate)
-    };
+
+    if let Some(rates) = allowance_rates(kind, usage_location) {
+        let rate = format!("{value} {unit}");
+        let rate = match Rate::from_str(&rate) {
+            Ok(rate) => OrFallback::Expected(rate),
+            Err(_) => OrFallback::Fallback(rate)
+        };
+        rates.insert(rate);
+        return Ok(None);
+    }
 
     match kind {
-        "Entnahmemenge" => {
-            usage_location.withdrawal_rates.insert(rate);
-        }
-        "Förderleistung" => {
-            usage_location.pumping_rates.insert(rate);
-        }
-        "Einleitungsmenge" => {
-            usage_location.injection_rates.insert(rate);
-        }
-        "Stauziel, bezogen auf NN" => {
+        kind if kind.starts_with("Stauziel") => parse_dam_target(
+            kind,
+            (parse_decimal(value)?, unit.to_string()).into(),
+            &mut usage_location.dam_target_levels
+        ),
+        "Beregnungsfläche" => {
             usage_location
-                .dam_target_levels
-                .default
-                .replace((value.parse()?, unit.to_string()).into());
+                .irrigation_area
+                .replace((parse_decimal(value)?, unit.to_string()).into());
         }
-        "Stauziel (Höchststau), bezogen auf NN" => {
-            usage_location.dam_target_levels.max.replace((value.parse()?, unit.to_string()).into());
+        a if matches!(department, A | B | C | F) => {
+            usage_location.injection_limits.push((a.to_string(), Quantity {
+                value: parse_decimal(value)?,
+                unit: unit.to_string()
+            }));
         }
-        "Stauziel (Dauerstau), bezogen auf NN" => {
-            usage_location
-                .dam_target_levels
-                .steady
-                .replace((value.parse()?, unit.to_string()).into());
+        a => return Err(anyhow::Error::msg(format!("unknown allow value: {a:?}")))
+    }
+
+    Ok(None)
+}
+
+/// Parse an "Erlaubniswert" given as range of two values with the same unit,
+/// returns whether the range was stored.
+///
+/// Rates keep both ends, if one of them cannot be read the whole range is
+/// kept as fallback.
+/// Injection limits keep both ends under the same key.
+/// The irrigation area and dam targets only hold a single value, so their
+/// ranges are not stored.
+fn parse_allowance_range(
+    kind: &str,
+    (min, max): (&str, &str),
+    unit: &str,
+    usage_location: &mut UsageLocation,
+    department: LegalDepartmentAbbreviation
+) -> bool {
+    use LegalDepartmentAbbreviation::*;
+
+    if let Some(rates) = allowance_rates(kind, usage_location) {
+        let min_rate = Rate::from_str(&format!("{min} {unit}"));
+        let max_rate = Rate::from_str(&format!("{max} {unit}"));
+        match (min_rate, max_rate) {
+            (Ok(min_rate), Ok(max_rate)) => {
+                rates.insert(OrFallback::Expected(min_rate));
+                rates.insert(OrFallback::Expected(max_rate));
+            }
+            _ => {
+                rates.insert(OrFallback::Fallback(format!("{min} - {max} {unit}")));
+            }
+        }
+        return true;
+    }
+
+    let single_value = kind.starts_with("Stauziel") || kind == "Beregnungsfläche";
+    if single_value || !matches!(department, A | B | C | F) {
+        return false;
+    }
+    match (parse_decimal(min), parse_decimal(max)) {
+        (Ok(min), Ok(max)) => {
+            for value in [min, max] {
+                usage_location.injection_limits.push((kind.to_string(), Quantity {
+                    value,
+                    unit: unit.to_string()
+                }));
+            }
+            true
         }
+        _ => false
+    }
+}
+
+/// The rates of a usage location an "Erlaubniswert" of `kind` belongs to,
+/// `None` if the kind is no rate.
+fn allowance_rates<'ul>(
+    kind: &str,
+    usage_location: &'ul mut UsageLocation
+) -> Option<&'ul mut RateRecord> {
+    Some(match kind {
+        "Entnahmemenge" => &mut usage_location.withdrawal_rates,
+        "Förderleistung" => &mut usage_location.pumping_rates,
+        "Einleitungsmenge" => &mut usage_location.injection_rates,
         "Abwasservolumenstrom, Sekunde" |
         "Abwasservolumenstrom, RW, Sekunde" |
         "Abwasservolumenstrom, Std." |
@@ -193,26 +345,223 @@ fn parse_allowance_value(
         "Abwasservolumenstrom, Tag" |
         "Abwasservolumenstrom, RW, Tag" |
         "Abwasservolumenstrom, Jahr" |
-        "Abwasservolumenstrom, RW, Jahr" => {
-            usage_location.waste_water_flow_volume.insert(rate);
+        "Abwasservolumenstrom, RW, Jahr" => &mut usage_location.waste_water_flow_volume,
+        "Zusatzregen" => &mut usage_location.rain_supplement,
+        "Ableitungsmenge" => &mut usage_location.fluid_discharge,
+        _ => return None
+    })
+}
+
+/// Sorts a "Stauziel" into the [`DamTargets`].
+///
+/// Only targets referenced to "NN" have a dedicated field, every other
+/// reference base is kept with its full description in
+/// [`DamTargets::other`].
+fn parse_dam_target(kind: &str, quantity: Quantity, dam_targets: &mut DamTargets) {
+    match kind {
+        "Stauziel, bezogen auf NN" => {
+            dam_targets.default.replace(quantity);
         }
-        "Beregnungsfläche" => {
-            usage_location.irrigation_area.replace((value.parse()?, unit.to_string()).into());
+        "Stauziel (Höchststau), bezogen auf NN" => {
+            dam_targets.max.replace(quantity);
         }
-        "Zusatzregen" => {
-            usage_location.rain_supplement.insert(rate);
+        "Stauziel (Dauerstau), bezogen auf NN" => {
+            dam_targets.steady.replace(quantity);
         }
-        "Ableitungsmenge" => {
-            usage_location.fluid_discharge.insert(rate);
+        other => dam_targets.other.push((other.to_string(), quantity))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_allowance_value_keeps_repeated_rates() {
+        let mut usage_location = UsageLocation::new();
+        for value in [
+            "Entnahmemenge 1500 m³/a",
+            "Entnahmemenge 2500 m³/a",
+            "Entnahmemenge 1500 m³/a"
+        ] {
+            parse_allowance_value(
+                value.to_string(),
+                &mut usage_location,
+                LegalDepartmentAbbreviation::A
+            )
+            .unwrap();
         }
-        a if matches!(department, A | B | C | F) => {
-            usage_location.injection_limits.push((a.to_string(), Quantity {
-                value: value.parse()?,
-                unit: unit.to_string()
-            }));
+
+        let rates: Vec<String> = usage_location
+            .withdrawal_rates
+            .iter()
+            .map(|rate| match rate {
+                OrFallback::Expected(rate) => format!("{} {}/{}", rate.value, rate.unit, rate.per),
+                OrFallback::Fallback(rate) => rate.clone()
+            })
+            .collect();
+        assert_eq!(rates, ["1500 m³/a", "2500 m³/a"]);
+    }
+
+    #[test]
+    fn parse_allowance_value_reads_decimal_commas() {
+        let mut usage_location = UsageLocation::new();
+        for value in [
+            "Entnahmemenge 0,5 m³/s",
+            "Entnahmemenge 1.200,00 m³/a",
+            "Entnahmemenge 12.5 m³/d",
+            "Stauziel, bezogen auf NN 12,75 m",
+            "Beregnungsfläche 1.234,5 ha",
+            "Härtegrad 7,5 °dH"
+        ] {
+            parse_allowance_value(
+                value.to_string(),
+                &mut usage_location,
+                LegalDepartmentAbbreviation::A
+            )
+            .unwrap();
         }
-        a => return Err(anyhow::Error::msg(format!("unknown allow value: {a:?}")))
+
+        let rates: Vec<String> = usage_location
+            .withdrawal_rates
+            .iter()
+            .map(|rate| match rate {
+                OrFallback::Expected(rate) => format!("{} {}/{}", rate.value, rate.unit, rate.per),
+                OrFallback::Fallback(rate) => rate.clone()
+            })
+            .collect();
+        assert_eq!(rates.len(), 3);
+        assert!(rates.contains(&"0.5 m³/s".to_string()));
+        assert!(rates.contains(&"1200 m³/a".to_string()));
+        assert!(rates.contains(&"12.5 m³/d".to_string()));
+
+        let dam_target = usage_location.dam_target_levels.default.as_ref().unwrap();
+        assert_eq!(dam_target.to_string(), "12.75 m");
+        assert_eq!(
+            usage_location.irrigation_area.as_ref().unwrap().to_string(),
+            "1234.5 ha"
+        );
+        assert_eq!(usage_location.injection_limits[0].1.value, 7.5);
+    }
+
+    #[test]
+    fn parse_allowance_value_reads_ranges() {
+        let mut usage_location = UsageLocation::new();
+        for value in [
+            "Entnahmemenge 100 - 200 m³/d",
+            "Förderleistung 0,5 – 1,5 l/s",
+            "Einleitungsmenge 10 - 20 Ziegen"
+        ] {
+            parse_allowance_value(
+                value.to_string(),
+                &mut usage_location,
+                LegalDepartmentAbbreviation::A
+            )
+            .unwrap();
+        }
+
+        let rates = |rates: &RateRecord| -> Vec<String> {
+            rates
+                .iter()
+                .map(|rate| match rate {
+                    OrFallback::Expected(rate) => {
+                        format!("{} {}/{}", rate.value, rate.unit, rate.per)
+                    }
+                    OrFallback::Fallback(rate) => rate.clone()
+                })
+                .collect()
+        };
+        assert_eq!(rates(&usage_location.withdrawal_rates), [
+            "100 m³/d",
+            "200 m³/d"
+        ]);
+        assert_eq!(rates(&usage_location.pumping_rates), ["0.5 l/s", "1.5 l/s"]);
+        assert_eq!(rates(&usage_location.injection_rates), ["10 - 20 Ziegen"]);
+        assert!(usage_location.injection_limits.is_empty());
+
+        let stored = parse_allowance_value(
+            "Härtegrad 7 - 8 °dH".to_string(),
+            &mut usage_location,
+            LegalDepartmentAbbreviation::A
+        )
+        .unwrap();
+        assert_eq!(stored, None);
+        let limits: Vec<(&str, f64)> = usage_location
+            .injection_limits
+            .iter()
+            .map(|(kind, quantity)| (kind.as_str(), quantity.value))
+            .collect();
+        assert_eq!(limits, [("Härtegrad", 7.0), ("Härtegrad", 8.0)]);
+
+        for department in [
+            LegalDepartmentAbbreviation::A,
+            LegalDepartmentAbbreviation::E
+        ] {
+            for value in [
+                "Beregnungsfläche 10 - 20 ha",
+                "Stauziel, bezogen auf NN 2 - 3 m"
+            ] {
+                let skipped =
+                    parse_allowance_value(value.to_string(), &mut usage_location, department)
+                        .unwrap();
+                assert_eq!(skipped.as_deref(), Some(value));
+            }
+        }
+        assert!(usage_location.irrigation_area.is_none());
+        assert!(usage_location.dam_target_levels.is_empty());
+    }
+
+    #[test]
+    fn parse_allowance_value_sorts_dam_targets() {
+        let mut usage_location = UsageLocation::new();
+        for value in [
+            "Stauziel, bezogen auf NN 12.5 m",
+            "Stauziel (Höchststau), bezogen auf NN 13 m",
+            "Stauziel, bezogen auf Pegel Celle 2.1 m",
+            "Stauziel (Dauerstau), bezogen auf Pegel Celle 1.8 m"
+        ] {
+            parse_allowance_value(
+                value.to_string(),
+                &mut usage_location,
+                LegalDepartmentAbbreviation::B
+            )
+            .unwrap();
+        }
+
+        let dam_targets = &usage_location.dam_target_levels;
+        assert_eq!(dam_targets.default.as_ref().unwrap().to_string(), "12.5 m");
+        assert_eq!(dam_targets.max.as_ref().unwrap().to_string(), "13 m");
+        assert!(dam_targets.steady.is_none());
+        let other: Vec<_> =
+            dam_targets.other.iter().map(|(kind, q)| (kind.as_str(), q.to_string())).collect();
+        assert_eq!(other, [
+            ("Stauziel, bezogen auf Pegel Celle", "2.1 m".to_string()),
+            (
+                "Stauziel (Dauerstau), bezogen auf Pegel Celle",
+                "1.8 m".to_string()
+            )
+        ]);
+        assert!(usage_location.injection_limits.is_empty());
     }
 
-    Ok(())
+    #[test]
+    fn parse_usage_location_reads_combined_coordinates() {
+        let coordinates = |values: &[&str]| {
+            let mut usage_location = UsageLocation::new();
+            let values = values.iter().map(ToString::to_string).collect();
+            parse_usage_location(
+                vec![("East und North:".to_string(), values)],
+                &mut usage_location,
+                LegalDepartmentAbbreviation::A
+            )
+            .unwrap();
+            (usage_location.utm_easting, usage_location.utm_northing)
+        };
+
+        assert_eq!(
+            coordinates(&["32603873", "5852015"]),
+            (Some(32603873), Some(5852015))
+        );
+        assert_eq!(coordinates(&["32603873"]), (Some(32603873), None));
+    }
 }
@@ -2,15 +2,64 @@ use lopdf::Document;
 use nlwkn::WaterRight;
 
 use crate::intermediate::grouped_key_value::GroupedKeyValueRepr;
-use crate::intermediate::key_value::KeyValueRepr;
+use crate::intermediate::key_value::{KeyValuePair, KeyValueRepr};
 use crate::intermediate::text_block::TextBlockRepr;
+use crate::provenance::{field_provenance, FieldProvenance};
 
 mod departments;
 mod root;
 
-pub fn parse_document(water_right: &mut WaterRight, document: Document) -> anyhow::Result<()> {
+/// What [`parse_document`] found besides the water right.
+#[derive(Debug)]
+pub struct ParsedDocument {
+    /// Page every parsed value was found on.
+    pub provenance: Vec<FieldProvenance>,
+
+    /// Entries of usage locations the parser skipped.
+    pub skipped_entries: Vec<SkippedEntry>
+}
+
+/// Entry of a usage location that is skipped instead of failing the report.
+#[derive(Debug, PartialEq)]
+pub enum SkippedEntry {
+    /// Entry with a key the parser does not know.
+    UnknownKey(KeyValuePair),
+
+    /// "Erlaubniswert" with a range the water right cannot store, like the
+    /// range of an irrigation area.
+    UnstorableAllowanceRange(String)
+}
+
+/// Parse the report `document` into the `water_right`.
+///
+/// With `root_only` only the fields of the water right itself are parsed, the
+/// legal departments stay empty.
+pub fn parse_document(
+    water_right: &mut WaterRight,
+    document: Document,
+    root_only: bool
+) -> anyhow::Result<ParsedDocument> {
     let text_block_repr = TextBlockRepr::try_from(document)?;
     let key_value_repr = KeyValueRepr::from(text_block_repr);
+    let mut provenance = field_provenance(&key_value_repr);
+    if root_only {
+        provenance.retain(|field| field.department.is_none() && field.usage_location.is_none());
+    }
+
+    let skipped_entries = parse_key_values(water_right, key_value_repr, root_only)?;
+    Ok(ParsedDocument {
+        provenance,
+        skipped_entries
+    })
+}
+
+/// Parse the key values into the `water_right`, returns the skipped usage
+/// location entries.
+fn parse_key_values(
+    water_right: &mut WaterRight,
+    key_value_repr: KeyValueRepr,
+    root_only: bool
+) -> anyhow::Result<Vec<SkippedEntry>> {
     let GroupedKeyValueRepr {
         root,
         departments,
@@ -18,8 +67,80 @@ pub fn parse_document(water_right: &mut WaterRight, document: Document) -> anyho
     } = key_value_repr.into();
 
     root::parse_root(root, water_right)?;
-    departments::parse_departments(departments, water_right)?;
+    let skipped_entries = match root_only {
+        true => Vec::new(),
+        false => departments::parse_departments(departments, water_right)?
+    };
     water_right.annotation = annotation;
 
-    Ok(())
+    Ok(skipped_entries)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key_value_repr() -> KeyValueRepr {
+        let pairs: Vec<_> = [
+            ("Aktenzeichen:", "6630-01-1610"),
+            ("Betreff:", "Fischteiche"),
+            (
+                "Abteilung:",
+                "A - Entnahme von Wasser aus oberirdischen Gewässern"
+            ),
+            ("Nutzungsort Lfd. Nr.:", "1101-01 (aktiv, real)"),
+            ("Bezeichnung:", "Teich Bokel")
+        ]
+        .into_iter()
+        .map(|(key, value)| (key.to_string(), vec![value.to_string()]))
+        .collect();
+        let pages = vec![0; pairs.len()];
+        KeyValueRepr(pairs, pages)
+    }
+
+    #[test]
+    fn root_only_skips_departments() {
+        let mut water_right = WaterRight::new(1101);
+        parse_key_values(&mut water_right, key_value_repr(), true).unwrap();
+        assert_eq!(water_right.file_reference.as_deref(), Some("6630-01-1610"));
+        assert_eq!(water_right.subject.as_deref(), Some("Fischteiche"));
+        assert!(water_right.legal_departments.is_empty());
+
+        let mut water_right = WaterRight::new(1101);
+        parse_key_values(&mut water_right, key_value_repr(), false).unwrap();
+        assert_eq!(water_right.file_reference.as_deref(), Some("6630-01-1610"));
+        let usage_locations: Vec<_> = water_right
+            .legal_departments
+            .values()
+            .flat_map(|department| department.usage_locations.iter())
+            .collect();
+        assert_eq!(usage_locations.len(), 1);
+        assert_eq!(usage_locations[0].name.as_deref(), Some("Teich Bokel"));
+    }
+
+    #[test]
+    fn unknown_keys_are_skipped() {
+        let KeyValueRepr(mut pairs, mut pages) = key_value_repr();
+        pairs.insert(
+            4,
+            ("Neues Feld:".to_string(), vec![
+                "1".to_string(),
+                "2".to_string(),
+            ])
+        );
+        pages.insert(4, pages[3]);
+
+        let mut water_right = WaterRight::new(1101);
+        let skipped_entries =
+            parse_key_values(&mut water_right, KeyValueRepr(pairs, pages), false).unwrap();
+        assert_eq!(skipped_entries, [SkippedEntry::UnknownKey((
+            "Neues Feld:".to_string(),
+            vec!["1".to_string(), "2".to_string()]
+        ))]);
+
+        let usage_location =
+            &water_right.legal_departments.values().next().unwrap().usage_locations[0];
+        assert_eq!(usage_location.serial.as_deref(), Some("1101-01"));
+        assert_eq!(usage_location.name.as_deref(), Some("Teich Bokel"));
+    }
 }
@@ -7,7 +7,7 @@ pub fn parse_root(items: Vec<KeyValuePair>, water_right: &mut WaterRight) -> any
     for (key, values) in items {
         let mut value = values.into_iter().next().sanitize();
         match (key.as_str(), value.take()) {
-            ("Wasserbuchbehörde", v) => water_right.water_authority = v,
+            ("Wasserbuchbehörde", v) => water_right.water_authority = v.map(join_lines),
             ("Kennziffer", Some(v)) => {
                 let mut split = v.rsplitn(2, ' ');
                 water_right.status =
@@ -15,9 +15,9 @@ pub fn parse_root(items: Vec<KeyValuePair>, water_right: &mut WaterRight) -> any
                 water_right.external_identifier = split.next().map(|ext_id| ext_id.to_string());
             }
             ("erteilt durch /", _) => (),
-            ("eingetragen durch:", v) => water_right.registering_authority = v,
+            ("eingetragen durch:", v) => water_right.registering_authority = v.map(join_lines),
             ("abweichend", _) => (),
-            ("erteilt durch:", v) => water_right.granting_authority = v,
+            ("erteilt durch:", v) => water_right.granting_authority = v.map(join_lines),
             ("erteilt am:", v) => water_right.valid_from = v,
             // TODO: remove this when the reports have their typo fixed
             ("erstmalig erteilt am:" | "erstmalig ertellt am:", v) => {
@@ -26,7 +26,7 @@ pub fn parse_root(items: Vec<KeyValuePair>, water_right: &mut WaterRight) -> any
             ("Aktenzeichen:", v) => water_right.file_reference = v,
             ("Das Recht ist befristet bis", v) => water_right.valid_until = v,
             ("und betrifft Rechtsabteilungen", _) => (),
-            ("Betreff:", v) => water_right.subject = v,
+            ("Betreff:", v) => water_right.subject = v.map(join_lines),
             (key, value) => {
                 return Err(anyhow::Error::msg(format!(
                     "invalid entry for the root, key: {key:?}, value: {value:?}"
@@ -37,3 +37,78 @@ pub fn parse_root(items: Vec<KeyValuePair>, water_right: &mut WaterRight) -> any
 
     Ok(())
 }
+
+/// Join the lines of a free-text value, which the text blocks of the report
+/// split after `;` and abbreviations.
+///
+/// Line breaks are only kept after sentence punctuation.
+fn join_lines(text: String) -> String {
+    if !text.contains('\n') {
+        return text;
+    }
+
+    let mut joined = String::with_capacity(text.len());
+    for line in text.lines().map(str::trim).filter(|line| !line.is_empty()) {
+        match joined.chars().last() {
+            None => (),
+            Some('.' | '!' | '?') if !ends_with_abbreviation(&joined) => joined.push('\n'),
+            Some(_) => joined.push(' ')
+        }
+        joined.push_str(line);
+    }
+
+    joined
+}
+
+/// Abbreviations the free-text fields of the reports break lines after.
+const ABBREVIATIONS: &[&str] = &[
+    "Abs.", "bzw.", "ca.", "d.h.", "Flst.", "gem.", "ggf.", "i.V.m.", "inkl.", "Lkr.", "Nieders.",
+    "Nr.", "Str.", "u.a.", "usw.", "vgl.", "z.B.", "z.T."
+];
+
+/// Check if text ends with an abbreviation like "Nieders." or "gem." instead
+/// of a sentence, see [`ABBREVIATIONS`].
+fn ends_with_abbreviation(text: &str) -> bool {
+    let last_word = text.rsplit(char::is_whitespace).next().unwrap_or_default();
+    ABBREVIATIONS.contains(&last_word)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn join_lines_works() {
+        assert_eq!(join_lines("Fischteiche".to_string()), "Fischteiche");
+        assert_eq!(
+            join_lines("Entnahme aus der Ise;\nSpeisung von Teichen".to_string()),
+            "Entnahme aus der Ise; Speisung von Teichen"
+        );
+        assert_eq!(
+            join_lines("Entnahme von Wasser gem.\n§ 8 WHG. \nSpeisung von Teichen.".to_string()),
+            "Entnahme von Wasser gem. § 8 WHG.\nSpeisung von Teichen."
+        );
+        assert_eq!(
+            join_lines("Nieders.\nLandesbetrieb für Wasserwirtschaft".to_string()),
+            "Nieders. Landesbetrieb für Wasserwirtschaft"
+        );
+        assert_eq!(
+            join_lines("Die Entnahme ist.\nDas Recht wird.\nBefristet".to_string()),
+            "Die Entnahme ist.\nDas Recht wird.\nBefristet"
+        );
+    }
+
+    #[test]
+    fn subject_lines_are_joined() {
+        let items = vec![("Betreff:".to_string(), vec!["Fischteiche \
+                                                        Bokel;\nEntnahme aus der \
+                                                        Ise. \nNur im Winter"
+            .to_string()])];
+        let mut water_right = WaterRight::new(1101);
+        parse_root(items, &mut water_right).unwrap();
+        assert_eq!(
+            water_right.subject.as_deref(),
+            Some("Fischteiche Bokel; Entnahme aus der Ise.\nNur im Winter")
+        );
+    }
+}
@@ -0,0 +1,107 @@
+use serde::Serialize;
+
+use crate::intermediate::key_value::KeyValueRepr;
+
+/// Location of a single parsed value in the original report.
+#[derive(Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FieldProvenance {
+    /// Abbreviation of the legal department the value belongs to, `None` for
+    /// values of the water right itself.
+    pub department: Option<String>,
+
+    /// Serial number of the usage location the value belongs to.
+    pub usage_location: Option<String>,
+
+    /// Key of the value as written in the report.
+    pub field: String,
+
+    /// Page of the report, starting at `1`.
+    pub page: usize
+}
+
+/// Collect the page of every value in the report.
+///
+/// Keys without any values are skipped as they don't point to a value.
+pub fn field_provenance(key_value_repr: &KeyValueRepr) -> Vec<FieldProvenance> {
+    let KeyValueRepr(pairs, pages) = key_value_repr;
+    let mut department = None;
+    let mut usage_location = None;
+    let mut provenance = Vec::new();
+
+    for ((key, values), page) in pairs.iter().zip(pages) {
+        match key.as_str() {
+            "Abteilung:" => {
+                department = values.join("").split(' ').next().map(ToString::to_string);
+                usage_location = None;
+            }
+            "Nutzungsort Lfd. Nr.:" => {
+                usage_location = values
+                    .first()
+                    .and_then(|value| value.split(" (").next())
+                    .map(ToString::to_string);
+            }
+            _ => ()
+        }
+
+        if values.is_empty() {
+            continue;
+        }
+
+        provenance.push(FieldProvenance {
+            department: department.clone(),
+            usage_location: usage_location.clone(),
+            field: key.clone(),
+            page: page + 1
+        });
+    }
+
+    provenance
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::intermediate::text_block::{TextBlock, TextBlockRepr};
+
+    fn text_block(font_family: &str, x: f32, content: &str) -> TextBlock {
+        TextBlock {
+            x: Some(x),
+            font_family: Some(font_family.to_string()),
+            content: Some(content.to_string()),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn field_provenance_captures_page() {
+        let text_block_repr = TextBlockRepr(vec![
+            vec![
+                text_block("F1", 10.0, "Aktenzeichen:"),
+                text_block("F2", 100.0, "6630-01-1610"),
+                text_block("F1", 10.0, "Abteilung:"),
+                text_block("F2", 100.0, "A Entnahme von Wasser"),
+            ],
+            vec![
+                text_block("F1", 10.0, "Nutzungsort Lfd. Nr.:"),
+                text_block("F2", 100.0, "101 (aktiv, real)"),
+                text_block("F1", 10.0, "Gemeindegebiet:"),
+                text_block("F2", 100.0, "Gifhorn"),
+            ],
+        ]);
+        let provenance = field_provenance(&KeyValueRepr::from(text_block_repr));
+
+        assert_eq!(provenance[0], FieldProvenance {
+            department: None,
+            usage_location: None,
+            field: "Aktenzeichen:".to_string(),
+            page: 1
+        });
+        assert_eq!(provenance.last().unwrap(), &FieldProvenance {
+            department: Some("A".to_string()),
+            usage_location: Some("101".to_string()),
+            field: "Gemeindegebiet:".to_string(),
+            page: 2
+        });
+    }
+}
@@ -0,0 +1,303 @@
+use std::fmt::{Display, Formatter};
+use std::path::PathBuf;
+
+use console::{Color, Style};
+use serde::Serialize;
+
+/// Summary of a parser run.
+///
+/// The data is kept separately from its rendering, see [`ReportRenderer`] for
+/// the available renderers.
+pub struct Report {
+    pub broken: ReportEntry,
+    pub parsing_issues: ReportEntry,
+    pub pdf_only: ReportEntry,
+    pub successful: ReportEntry,
+    pub skipped: ReportEntry
+}
+
+pub struct ReportEntry {
+    pub count: usize,
+    pub output_file: PathBuf
+}
+
+/// How a [`Report`] should be rendered.
+pub enum ReportRenderer<'t> {
+    /// TOML-like output with ANSI colors.
+    Styled,
+
+    /// TOML-like output without any ANSI escape codes.
+    Plain,
+
+    /// User provided template, see [`Report::render_template`].
+    Template(&'t str)
+}
+
+impl Report {
+    pub fn render(&self, renderer: ReportRenderer) -> String {
+        match renderer {
+            ReportRenderer::Styled => Toml(self, true).to_string(),
+            ReportRenderer::Plain => Toml(self, false).to_string(),
+            ReportRenderer::Template(template) => self.render_template(template)
+        }
+    }
+
+    /// Render the report from a template string.
+    ///
+    /// The placeholders `{broken}`, `{parsing_issues}`, `{pdf_only}`,
+    /// `{successful}` and `{skipped}` are replaced by their counts, with a
+    /// `_file` suffix
+    /// (e.g. `{broken_file}`) they are replaced by their output file.
+    pub fn render_template(&self, template: &str) -> String {
+        let mut rendered = template.to_string();
+        for (identifier, entry) in self.entries() {
+            rendered =
+                rendered.replace(&format!("{{{identifier}}}"), &entry.count.to_string()).replace(
+                    &format!("{{{identifier}_file}}"),
+                    &entry.output_file.display().to_string()
+                );
+        }
+
+        rendered
+    }
+
+    /// Machine-readable [`Summary`] of the report, written next to the
+    /// results.
+    pub fn summary(&self, inputs: SummaryInputs, parsed_at: String) -> Summary {
+        Summary {
+            counts: SummaryCounts {
+                broken: self.broken.count,
+                parsing_issues: self.parsing_issues.count,
+                pdf_only: self.pdf_only.count,
+                successful: self.successful.count,
+                skipped: self.skipped.count
+            },
+            inputs,
+            parsed_at
+        }
+    }
+
+    fn entries(&self) -> [(&'static str, &ReportEntry); 5] {
+        [
+            ("broken", &self.broken),
+            ("parsing_issues", &self.parsing_issues),
+            ("pdf_only", &self.pdf_only),
+            ("successful", &self.successful),
+            ("skipped", &self.skipped)
+        ]
+    }
+}
+
+/// Counts and inputs of a parser run, serialized into `reports.summary.json`.
+///
+/// The keys follow the placeholders of [`Report::render_template`].
+#[derive(Debug, Serialize)]
+pub struct Summary {
+    pub counts: SummaryCounts,
+    pub inputs: SummaryInputs,
+
+    /// When the parser was started, as RFC 3339 timestamp in UTC.
+    pub parsed_at: String
+}
+
+#[derive(Debug, Serialize)]
+pub struct SummaryCounts {
+    pub broken: usize,
+    pub parsing_issues: usize,
+    pub pdf_only: usize,
+    pub successful: usize,
+
+    /// Reports skipped for exceeding `--max-pdf-bytes`.
+    pub skipped: usize
+}
+
+/// Input paths of a parser run.
+#[derive(Debug, Serialize)]
+pub struct SummaryInputs {
+    pub xlsx_path: PathBuf,
+    pub data_path: PathBuf,
+    pub merge_into: Option<PathBuf>,
+    pub municipality_table: Option<PathBuf>
+}
+
+/// TOML-like renderer for the [`Report`], the flag toggles the styling.
+struct Toml<'r>(&'r Report, bool);
+
+impl Display for Toml<'_> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let Toml(report, styled) = self;
+        let style = |color: Color| match styled {
+            true => Style::new().fg(color),
+            false => Style::new()
+        };
+
+        let description_style = style(Color::Yellow);
+        let category_style = style(Color::Magenta);
+        let key_style = style(Color::Cyan);
+        let equal_style = style(Color::White);
+        let num_value_style = style(Color::Magenta).bright();
+        let str_value_style = style(Color::Blue).bright();
+
+        let description_indicator = description_style.apply_to("#");
+        let identifier_open = category_style.apply_to("[");
+        let identifier_close = category_style.apply_to("]");
+        let count_key = key_style.apply_to("count");
+        let output_file_key = key_style.apply_to("output_file");
+        let equal_sign = equal_style.apply_to("=");
+        let string_indicator = str_value_style.apply_to("'");
+
+        let entries: &[(Vec<&str>, &str, &ReportEntry)] = &[
+            (
+                vec![
+                    "Broken PDF files which cannot be loaded.",
+                    "Could be due to corrupted or incompatible files.",
+                ],
+                "broken",
+                &report.broken
+            ),
+            (
+                vec![
+                    "Reports with parsing issues.",
+                    "First issue with it's respective water right number.",
+                ],
+                "parsing_issues",
+                &report.parsing_issues
+            ),
+            (
+                vec![
+                    "Reports where data could only be extracted from the PDF file.",
+                    "XLSX data might be missing.",
+                ],
+                "pdf_only",
+                &report.pdf_only
+            ),
+            (
+                vec!["Reports parsed and enriched with both PDF and XLSX data."],
+                "reports",
+                &report.successful
+            ),
+            (
+                vec![
+                    "Reports skipped for being larger than the allowed size.",
+                    "Size in bytes with it's respective water right number.",
+                ],
+                "skipped",
+                &report.skipped
+            )
+        ];
+
+        for (description, identifier, entry) in entries {
+            for description in description {
+                writeln!(
+                    f,
+                    "{} {}",
+                    description_indicator,
+                    description_style.apply_to(description)
+                )?;
+            }
+            writeln!(
+                f,
+                "{}{}{}",
+                identifier_open,
+                category_style.apply_to(identifier),
+                identifier_close
+            )?;
+            writeln!(
+                f,
+                "{} {} {}",
+                count_key,
+                equal_sign,
+                num_value_style.apply_to(entry.count)
+            )?;
+            writeln!(
+                f,
+                "{} {} {}{}{}",
+                output_file_key,
+                equal_sign,
+                string_indicator,
+                str_value_style.apply_to(entry.output_file.display()),
+                string_indicator
+            )?;
+            writeln!(f)?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn report() -> Report {
+        let entry = |count, output_file: &str| ReportEntry {
+            count,
+            output_file: output_file.into()
+        };
+
+        Report {
+            broken: entry(1, "data/broken-reports.json"),
+            parsing_issues: entry(2, "data/parsing-issues.json"),
+            pdf_only: entry(3, "data/pdf-only-reports.json"),
+            successful: entry(4, "data/reports.json"),
+            skipped: entry(5, "data/skipped-reports.json")
+        }
+    }
+
+    #[test]
+    fn plain_renderer_works() {
+        let rendered = report().render(ReportRenderer::Plain);
+        assert!(!rendered.contains('\u{1b}'));
+        assert_eq!(rendered.lines().take(5).collect::<Vec<_>>(), [
+            "# Broken PDF files which cannot be loaded.",
+            "# Could be due to corrupted or incompatible files.",
+            "[broken]",
+            "count = 1",
+            "output_file = 'data/broken-reports.json'"
+        ]);
+        assert!(rendered.contains("[reports]\ncount = 4\noutput_file = 'data/reports.json'\n"));
+        assert!(rendered
+            .ends_with("[skipped]\ncount = 5\noutput_file = 'data/skipped-reports.json'\n\n"));
+    }
+
+    #[test]
+    fn template_renderer_works() {
+        let rendered = report().render(ReportRenderer::Template(
+            "broken={broken} ok={successful} ({successful_file}) skipped={skipped} {unknown}"
+        ));
+        assert_eq!(
+            rendered,
+            "broken=1 ok=4 (data/reports.json) skipped=5 {unknown}"
+        );
+    }
+
+    #[test]
+    fn summary_works() {
+        let inputs = SummaryInputs {
+            xlsx_path: "cadenza.xlsx".into(),
+            data_path: "data".into(),
+            merge_into: None,
+            municipality_table: None
+        };
+        let summary = report().summary(inputs, "2024-01-01T12:00:00Z".to_string());
+        assert_eq!(
+            serde_json::to_value(summary).unwrap(),
+            serde_json::json!({
+                "counts": {
+                    "broken": 1,
+                    "parsing_issues": 2,
+                    "pdf_only": 3,
+                    "successful": 4,
+                    "skipped": 5
+                },
+                "inputs": {
+                    "xlsx_path": "cadenza.xlsx",
+                    "data_path": "data",
+                    "merge_into": null,
+                    "municipality_table": null
+                },
+                "parsed_at": "2024-01-01T12:00:00Z"
+            })
+        );
+    }
+}